@@ -0,0 +1,117 @@
+//! 请求体处理管线基准测试
+//!
+//! 对比两种处理方式：
+//! - `per_stage`：每个阶段各自解析、各自序列化（重构前的做法）
+//! - `single_parse`：入口解析一次，所有阶段改写同一个 `MessagesRequest`，最后序列化一次
+//!
+//! 内部模块只在 `bench` feature 下导出：`cargo bench --bench pipeline --features bench`
+
+use std::hint::black_box;
+
+use bytes::Bytes;
+use cc_proxy::bench::{
+    BodyQuirks, CUSTOM_SYSTEM_PROMPT, CachePlanner, Capabilities, CapabilityAdapter,
+    ContentTagFilter, CustomSystemPrompt, HistorySanitizer, ModelOverride, ReadDedup, RequestBody,
+    RequestStats, SystemPromptFilter, ToolDescriptionFilter, ToolResultPolicy,
+    ToolResultTruncation, Transform,
+};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use serde_json::{Value, json};
+
+/// 构造一段长对话：反复读取同一批文件、带 thinking 签名和大段工具输出
+fn synthetic_request(turns: usize) -> Bytes {
+    let file_body = "fn main() {\n    println!(\"hello\");\n}\n".repeat(40);
+    let log_body = "warning: unused variable `x`\n".repeat(200);
+    let mut messages =
+        vec![json!({"role": "user", "content": "<system-reminder>ctx</system-reminder>开始"})];
+    for turn in 0..turns {
+        let id = format!("toolu_{turn}");
+        messages.push(json!({
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "先读文件", "signature": format!("sig{turn}")},
+                {"type": "tool_use", "id": id, "name": "Read", "input": {"file_path": format!("/src/f{}.rs", turn % 8)}}
+            ]
+        }));
+        let output = if turn % 3 == 0 { &log_body } else { &file_body };
+        messages.push(json!({
+            "role": "user",
+            "content": [{"type": "tool_result", "tool_use_id": id, "content": output}]
+        }));
+    }
+    let tools: Vec<Value> = (0..20)
+        .map(|i| {
+            json!({
+                "name": format!("tool_{i}"),
+                "description": "Runs a command. ".repeat(50),
+                "input_schema": {"type": "object", "properties": {"command": {"type": "string"}}}
+            })
+        })
+        .collect();
+    let body = json!({
+        "model": "claude-sonnet-4-5",
+        "max_tokens": 32000,
+        "system": [
+            {"type": "text", "text": "You are Claude Code, Anthropic's official CLI for Claude."},
+            {"type": "text", "text": "Follow the project conventions. ".repeat(200)}
+        ],
+        "tools": tools,
+        "messages": messages,
+        "stream": true
+    });
+    Bytes::from(body.to_string())
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let stats = RequestStats::default();
+    let policy = ToolResultPolicy {
+        max_tokens: Some(500),
+        ..ToolResultPolicy::default()
+    };
+    let capabilities = Capabilities::default();
+    let tool_results = ToolResultTruncation {
+        policy: &policy,
+        stats: &stats,
+    };
+    let stages: [&dyn Transform; 11] = [
+        &CustomSystemPrompt(CUSTOM_SYSTEM_PROMPT),
+        &SystemPromptFilter,
+        &ContentTagFilter,
+        &ReadDedup,
+        &tool_results,
+        &ToolDescriptionFilter,
+        &HistorySanitizer("upstream-a"),
+        &CapabilityAdapter(&capabilities),
+        &CachePlanner,
+        &BodyQuirks(&[]),
+        &ModelOverride("claude-opus-4-1"),
+    ];
+
+    let mut group = c.benchmark_group("request_pipeline");
+    for turns in [20, 200] {
+        let raw = synthetic_request(turns);
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(BenchmarkId::new("per_stage", turns), &raw, |b, raw| {
+            b.iter(|| {
+                let mut bytes = raw.clone();
+                for &stage in &stages {
+                    let mut body = RequestBody::parse(bytes);
+                    body.apply(stage);
+                    bytes = body.into_bytes();
+                }
+                black_box(bytes)
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("single_parse", turns), &raw, |b, raw| {
+            b.iter(|| {
+                let mut body = RequestBody::parse(raw.clone());
+                body.run(&stages);
+                black_box(body.into_bytes())
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_pipeline);
+criterion_main!(benches);
@@ -30,9 +30,56 @@ pub enum Mode {
     /// Claude CLI → `OpenAI` Responses API 格式接口，需要进行请求/响应双向转换
     #[serde(rename = "openai_responses")]
     OpenAIResponses,
-    /// Claude CLI → `OpenAI` Chat Completions API 格式接口（预留）
+    /// Claude CLI → `OpenAI` Chat Completions API 格式接口（含 `DeepSeek` / Kimi / GLM 的 `reasoning_content`）
     #[serde(rename = "openai_chat")]
     OpenAIChat,
+    /// Claude CLI → AWS Bedrock `InvokeModel`，请求使用 `SigV4` 签名，流式响应为 AWS event stream
+    #[serde(rename = "bedrock")]
+    Bedrock,
+    /// Claude CLI → Google Vertex AI `rawPredict`，使用服务账号签发的 OAuth 访问令牌
+    #[serde(rename = "vertex")]
+    Vertex,
+    /// Claude CLI → Google Gemini 原生 `generateContent` 接口，需要进行请求/响应双向转换
+    #[serde(rename = "gemini")]
+    Gemini,
+    /// Claude CLI → Ollama 原生 `/api/chat` 接口（本地模型），流式响应为 NDJSON
+    #[serde(rename = "ollama")]
+    Ollama,
+}
+
+/// 工具 `input_schema` 清洗策略
+///
+/// 不同上游对 JSON Schema 的支持程度差异很大，按上游选择清洗方式。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum SchemaProfile {
+    /// 仅移除 `$schema`，其余原样发送
+    #[serde(rename = "permissive")]
+    #[default]
+    Permissive,
+    /// `OpenAI` strict 函数调用：展开 `$ref`、合并联合类型、只保留 strict 支持的关键字
+    #[serde(rename = "strict-openai")]
+    StrictOpenAI,
+    /// Gemini（含 GLM 等沿用其 schema 子集的上游）：`OpenAPI` 3.0 子集，可空类型改为 `nullable`
+    #[serde(rename = "gemini")]
+    Gemini,
+}
+
+/// 上游提供商的兼容性修补，按 upstream 在 `quirks` 中启用
+///
+/// 每项修补的实现见 `gateway::quirks`。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Quirk {
+    /// Kimi 等开启思考后要求 assistant 消息携带 `reasoning_content`，缺失时补全
+    KimiReasoningContent,
+    /// 移除 Claude CLI 附加的 `?beta=true` 查询参数
+    StripBetaQuery,
+    /// 移除请求中的 `cache_control` 标记
+    NoCacheControl,
+    /// 禁止并行工具调用
+    NoParallelTools,
+    /// 限制 `max_tokens` 上限（`{ max_tokens_cap = 8192 }`）
+    MaxTokensCap(u64),
 }
 
 /// 全局原子配置，支持热重载
@@ -57,6 +104,169 @@ pub struct UpstreamConfig {
     /// 上游模式：直通 Anthropic 或兼容 `OpenAI` Responses
     #[serde(default)]
     pub mode: Mode,
+    /// 是否支持服务端工具（`web_search` / `web_fetch` / `code_execution`）
+    ///
+    /// Anthropic 模式下原样透传，`OpenAI` Responses 模式下映射为内置工具；
+    /// 设为 false 时从请求中移除这些工具
+    #[serde(default = "default_true")]
+    pub server_tools: bool,
+    /// 工具 `input_schema` 的清洗策略（非 Anthropic 模式生效）
+    #[serde(default)]
+    pub schema_profile: SchemaProfile,
+    /// 路由别名：在 `/v1/models` 中额外公布的模型名
+    ///
+    /// 请求中的 model 最终仍会被 `model` 覆盖，别名只用于通过客户端的模型名校验
+    #[serde(default)]
+    pub model_aliases: Vec<String>,
+    /// `openai_chat` 模式下是否把历史 assistant 消息的 thinking 作为 `reasoning_content` 回传
+    ///
+    /// `DeepSeek` 等上游要求不回传（否则报错），Kimi 等开启思考后要求回传，按上游选择
+    #[serde(default)]
+    pub send_reasoning_content: bool,
+    /// 按上游启用的兼容性修补（如 `["kimi_reasoning_content", { max_tokens_cap = 8192 }]`）
+    ///
+    /// 未配置时按 `mode` 使用 [`UpstreamConfig::quirks`] 中的默认值，配置为 `[]` 时全部关闭
+    #[serde(default)]
+    pub quirks: Option<Vec<Quirk>>,
+    /// 上游模型的能力声明，转发前按此调整请求，选择 upstream 时跳过无法处理请求的上游
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    /// `bedrock` 模式的 AWS 区域与凭证（未配置的项从环境变量读取）
+    #[serde(default)]
+    pub bedrock: Option<BedrockConfig>,
+    /// `vertex` 模式的项目、区域与服务账号密钥
+    #[serde(default)]
+    pub vertex: Option<VertexConfig>,
+    /// `ollama` 模式的本地模型选项
+    #[serde(default)]
+    pub ollama: Option<OllamaConfig>,
+    /// Azure `OpenAI` 资源与部署（`openai_responses` / `openai_chat` 模式下配置后按 Azure 的地址与鉴权发送）
+    #[serde(default)]
+    pub azure: Option<AzureConfig>,
+}
+
+/// 上游模型的能力声明（`[upstream.capabilities]`）
+///
+/// 默认全部支持、不设上限。声明不支持的能力后，请求在转发前被调整
+/// （图片替换为文字说明、移除 thinking、收紧 `max_tokens` 等），避免上游返回含糊的 400。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Capabilities {
+    /// 是否支持图片输入；不支持时图片替换为文字说明
+    #[serde(default = "default_true")]
+    pub vision: bool,
+    /// 是否支持 extended thinking；不支持时移除 `thinking` 参数与历史 thinking 块
+    #[serde(default = "default_true")]
+    pub thinking: bool,
+    /// 是否支持工具调用；不支持时移除工具定义，历史工具调用改写为文本
+    #[serde(default = "default_true")]
+    pub tools: bool,
+    /// 是否支持 `tool_choice` 的 `any` / 指定工具；不支持时降级为 `auto`
+    #[serde(default = "default_true")]
+    pub tool_choice_any: bool,
+    /// 是否支持 `stop_sequences`
+    #[serde(default = "default_true")]
+    pub stop_sequences: bool,
+    /// 是否支持提示词缓存；不支持时移除所有 `cache_control`，也不规划缓存断点
+    #[serde(default = "default_true")]
+    pub prompt_caching: bool,
+    /// 工具数量上限，超出时保留对话中用到的工具和排在前面的工具
+    #[serde(default)]
+    pub max_tools: Option<usize>,
+    /// 输出 token 上限（`max_tokens` 随之收紧）
+    #[serde(default)]
+    pub max_output_tokens: Option<u64>,
+    /// 上下文窗口大小（token），估算的输入超出时选择 upstream 会跳过该上游
+    #[serde(default)]
+    pub context_window: Option<u64>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            vision: default_true(),
+            thinking: default_true(),
+            tools: default_true(),
+            tool_choice_any: default_true(),
+            stop_sequences: default_true(),
+            prompt_caching: default_true(),
+            max_tools: None,
+            max_output_tokens: None,
+            context_window: None,
+        }
+    }
+}
+
+/// AWS Bedrock 配置
+///
+/// 未配置的项依次回退到 `AWS_REGION` / `AWS_DEFAULT_REGION`、`AWS_ACCESS_KEY_ID`、
+/// `AWS_SECRET_ACCESS_KEY`、`AWS_SESSION_TOKEN` 环境变量。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BedrockConfig {
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    /// 临时凭证（STS）的会话令牌
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+/// Google Vertex AI 配置
+///
+/// `credentials_file` 未配置时读取 `GOOGLE_APPLICATION_CREDENTIALS`；
+/// `project_id` 未配置时使用密钥文件中的 `project_id`。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VertexConfig {
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// 区域（如 `us-east5`），默认 `global`
+    #[serde(default)]
+    pub region: Option<String>,
+    /// 服务账号 JSON 密钥文件路径
+    #[serde(default)]
+    pub credentials_file: Option<String>,
+    /// OAuth 令牌交换地址，默认使用密钥文件中的 `token_uri`
+    #[serde(default)]
+    pub token_url: Option<String>,
+}
+
+/// Azure `OpenAI` 配置
+///
+/// `endpoint` 留空时使用 `https://{resource}.openai.azure.com`；
+/// `deployment` 未配置时使用 upstream 的 `model`。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AzureConfig {
+    /// 资源名（`{resource}.openai.azure.com` 中的子域名）
+    #[serde(default)]
+    pub resource: Option<String>,
+    /// 部署名
+    #[serde(default)]
+    pub deployment: Option<String>,
+    /// `api-version` 查询参数
+    #[serde(default)]
+    pub api_version: Option<String>,
+}
+
+/// Ollama 配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OllamaConfig {
+    /// 模型是否支持原生函数调用；设为 false 时通过提示词协议模拟工具调用
+    #[serde(default = "default_true")]
+    pub native_tools: bool,
+    /// 上下文窗口大小（`options.num_ctx`），Ollama 默认值较小，会静默截断长提示词
+    #[serde(default)]
+    pub num_ctx: Option<u64>,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            native_tools: default_true(),
+            num_ctx: None,
+        }
+    }
 }
 
 /// 配置结构
@@ -74,6 +284,36 @@ pub struct Config {
     /// 本地优化拦截开关
     #[serde(default)]
     pub optimizations: OptimizationConfig,
+    /// `/v1/models` 模型列表配置
+    #[serde(default)]
+    pub models: ModelsConfig,
+}
+
+/// `/v1/models` 模型列表配置
+///
+/// 模型列表由代理本地生成（upstream 的 `model` 与 `model_aliases`），
+/// 不再转发给轮询到的某个 upstream。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelsConfig {
+    /// 是否合并各 upstream 真实的模型列表（首次请求时拉取并缓存，只适用于 Anthropic 与 `OpenAI` 兼容的 upstream）
+    #[serde(default)]
+    pub merge_upstream: bool,
+    /// 上游模型列表的缓存时长（秒）
+    #[serde(default = "default_models_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for ModelsConfig {
+    fn default() -> Self {
+        Self {
+            merge_upstream: false,
+            cache_ttl_secs: default_models_cache_ttl_secs(),
+        }
+    }
+}
+
+const fn default_models_cache_ttl_secs() -> u64 {
+    600
 }
 
 /// 本地优化配置
@@ -91,6 +331,51 @@ pub struct OptimizationConfig {
     pub enable_suggestion_mode_skip: bool,
     #[serde(default = "default_true")]
     pub enable_filepath_extraction_mock: bool,
+    /// 历史中重复的 `Read` 结果替换为简短说明
+    ///
+    /// 文件内容变化后会改写历史中较早的读取结果，提示词缓存从该消息起失效，默认关闭。
+    #[serde(default)]
+    pub enable_read_dedup: bool,
+    /// 转发给 Anthropic 格式的上游前重新规划 `cache_control` 断点
+    #[serde(default = "default_true")]
+    pub enable_cache_planner: bool,
+    /// 超大 `tool_result` 的截断策略
+    #[serde(default)]
+    pub tool_result: ToolResultPolicy,
+}
+
+/// 超大 `tool_result` 的截断策略（`[optimizations.tool_result]`）
+///
+/// 一次 `cat` 锁文件或一段很长的构建日志会在之后每一轮重复发送。
+/// 超出上限的结果先折叠连续重复的行，仍超出时保留开头与结尾、省略中间部分。
+/// 截断是确定性的，同一结果在后续请求中截断结果一致；但配置了 `old_after_turns` 时，
+/// 结果跨过轮数阈值的那次请求会按 `old_max_tokens` 重新截断，改写较早的消息，
+/// 提示词缓存从该消息起失效。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ToolResultPolicy {
+    /// 单个 `tool_result` 文本的 token 上限，未配置时不截断
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// 是否折叠连续重复的行
+    #[serde(default = "default_true")]
+    pub collapse_repeated_lines: bool,
+    /// 早于该轮数的结果使用 `old_max_tokens`（一轮为一条 user 消息）
+    #[serde(default)]
+    pub old_after_turns: Option<usize>,
+    /// 较早结果的 token 上限（通常小于 `max_tokens`）
+    #[serde(default)]
+    pub old_max_tokens: Option<u64>,
+}
+
+impl Default for ToolResultPolicy {
+    fn default() -> Self {
+        Self {
+            max_tokens: None,
+            collapse_repeated_lines: default_true(),
+            old_after_turns: None,
+            old_max_tokens: None,
+        }
+    }
 }
 
 impl Default for OptimizationConfig {
@@ -102,6 +387,9 @@ impl Default for OptimizationConfig {
             enable_title_generation_skip: default_true(),
             enable_suggestion_mode_skip: default_true(),
             enable_filepath_extraction_mock: default_true(),
+            enable_read_dedup: false,
+            enable_cache_planner: default_true(),
+            tool_result: ToolResultPolicy::default(),
         }
     }
 }
@@ -117,6 +405,35 @@ impl Default for UpstreamConfig {
             model: default_model(),
             api_keys: Vec::new(),
             mode: Mode::AnthropicDirect,
+            server_tools: default_true(),
+            schema_profile: SchemaProfile::default(),
+            model_aliases: Vec::new(),
+            send_reasoning_content: false,
+            quirks: None,
+            capabilities: Capabilities::default(),
+            bedrock: None,
+            vertex: None,
+            ollama: None,
+            azure: None,
+        }
+    }
+}
+
+impl UpstreamConfig {
+    /// 生效的兼容性修补
+    ///
+    /// 未配置 `quirks` 时沿用引入该配置前的固定行为：转发 Claude CLI 请求的 mode
+    /// 移除 `?beta=true`，`anthropic` 模式额外补全 Kimi 的 `reasoning_content`。
+    #[must_use]
+    pub fn quirks(&self) -> &[Quirk] {
+        const ANTHROPIC: &[Quirk] = &[Quirk::StripBetaQuery, Quirk::KimiReasoningContent];
+        const OPENAI: &[Quirk] = &[Quirk::StripBetaQuery];
+
+        match (&self.quirks, self.mode) {
+            (Some(quirks), _) => quirks,
+            (None, Mode::AnthropicDirect) => ANTHROPIC,
+            (None, Mode::OpenAIResponses | Mode::OpenAIChat) => OPENAI,
+            (None, _) => &[],
         }
     }
 }
@@ -223,6 +540,7 @@ impl AtomicConfig {
                 let optimizations_changed = old.optimizations != new_config.optimizations;
                 let log_req_body_changed = old.log_req_body != new_config.log_req_body;
                 let log_res_body_changed = old.log_res_body != new_config.log_res_body;
+                let models_changed = old.models != new_config.models;
                 self.inner.store(Arc::new(new_config.clone()));
 
                 // 更新 Upstream 选择器
@@ -236,6 +554,7 @@ impl AtomicConfig {
                     || optimizations_changed
                     || log_req_body_changed
                     || log_res_body_changed
+                    || models_changed
                 {
                     info!("✅ 配置已更新:");
                     if upstream_changed {
@@ -284,6 +603,16 @@ impl AtomicConfig {
                             old.log_res_body, new_config.log_res_body,
                         );
                     }
+
+                    if models_changed {
+                        info!(
+                            "models: merge_upstream {}→{}, cache_ttl_secs {}→{}",
+                            old.models.merge_upstream,
+                            new_config.models.merge_upstream,
+                            old.models.cache_ttl_secs,
+                            new_config.models.cache_ttl_secs,
+                        );
+                    }
                 } else {
                     info!("ℹ️ 配置文件内容未变化");
                 }
@@ -7,7 +7,7 @@
 
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use super::{Mode, UpstreamConfig};
+use super::{Capabilities, Mode, UpstreamConfig};
 
 /// Upstream 选择器，使用双层 round-robin 策略
 pub struct UpstreamSelector {
@@ -19,6 +19,7 @@ pub struct UpstreamSelector {
 
 impl UpstreamSelector {
     /// 创建新的 Upstream 选择器
+    #[must_use]
     pub fn new(upstreams: Vec<UpstreamConfig>) -> Option<Self> {
         if upstreams.is_empty() {
             return None;
@@ -46,6 +47,17 @@ impl UpstreamSelector {
     ///
     /// 返回 (upstream索引, endpoint, model, `api_key`, `mode`)
     pub fn next(&self) -> Option<(usize, &str, &str, &str, Mode)> {
+        self.next_matching(|_| true)
+    }
+
+    /// 按轮询顺序选择第一个满足 `accept` 的 upstream
+    ///
+    /// 从本轮轮询到的 upstream 开始依次尝试，跳过不满足条件的上游（如不支持图片）；
+    /// 全部不满足时退回本轮轮询到的 upstream，由请求调整兜底。
+    pub fn next_matching(
+        &self,
+        accept: impl Fn(&UpstreamConfig) -> bool,
+    ) -> Option<(usize, &str, &str, &str, Mode)> {
         if self.upstreams.is_empty() {
             return None;
         }
@@ -56,7 +68,18 @@ impl UpstreamSelector {
         let global_idx = self.next_index.fetch_add(1, Ordering::Relaxed);
 
         // 计算 upstream 索引和该 upstream 内的 key 索引
-        let upstream_idx = global_idx % upstream_count;
+        let round_robin_idx = global_idx % upstream_count;
+        let upstream_idx = (0..upstream_count)
+            .map(|offset| (round_robin_idx + offset) % upstream_count)
+            .find(|&idx| accept(&self.upstreams[idx]))
+            .unwrap_or(round_robin_idx);
+        if upstream_idx != round_robin_idx {
+            tracing::info!(
+                "⏭️ Upstream[{}] 无法处理该请求，改用 Upstream[{}]",
+                round_robin_idx,
+                upstream_idx
+            );
+        }
         let upstream = &self.upstreams[upstream_idx];
 
         // 在该 upstream 的 api_keys 中轮询（返回借用，避免克隆）
@@ -77,6 +100,20 @@ impl UpstreamSelector {
             upstream.mode,
         ))
     }
+
+    /// 是否有 upstream 声明了能力限制（没有时无需分析请求内容）
+    #[must_use]
+    pub fn has_capability_limits(&self) -> bool {
+        self.upstreams
+            .iter()
+            .any(|upstream| upstream.capabilities != Capabilities::default())
+    }
+
+    /// 按索引获取 upstream 的完整配置
+    #[must_use]
+    pub fn upstream(&self, idx: usize) -> Option<&UpstreamConfig> {
+        self.upstreams.get(idx)
+    }
 }
 
 #[cfg(test)]
@@ -91,6 +128,7 @@ mod tests {
                 model: "model1".to_string(),
                 api_keys: vec!["key1a".to_string(), "key1b".to_string()],
                 mode: Mode::AnthropicDirect,
+                ..UpstreamConfig::default()
             },
             UpstreamConfig {
                 endpoint: "https://upstream2.example.com".to_string(),
@@ -101,6 +139,7 @@ mod tests {
                     "key2c".to_string(),
                 ],
                 mode: Mode::OpenAIResponses,
+                ..UpstreamConfig::default()
             },
         ]
     }
@@ -158,4 +197,22 @@ mod tests {
         // new() 返回 None 当输入为空时
         assert!(selector.is_none());
     }
+
+    /// 测试跳过不满足条件的 upstream，全部不满足时退回轮询结果
+    #[test]
+    fn test_next_matching_skips_upstreams() {
+        let mut upstreams = create_test_upstreams();
+        upstreams[0].capabilities.vision = false;
+        let selector = UpstreamSelector::new(upstreams).unwrap();
+        assert!(selector.has_capability_limits());
+
+        let vision = |upstream: &UpstreamConfig| upstream.capabilities.vision;
+        let (idx0, ..) = selector.next_matching(vision).unwrap();
+        assert_eq!(idx0, 1);
+        let (idx1, ..) = selector.next_matching(vision).unwrap();
+        assert_eq!(idx1, 1);
+
+        let (idx2, ..) = selector.next_matching(|_| false).unwrap();
+        assert_eq!(idx2, 0);
+    }
 }
@@ -0,0 +1,396 @@
+//! 消息内容与内容块
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+/// 消息内容（也用于 `system` 与 `tool_result.content`）：纯字符串或内容块数组
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentBlock>> for Content {
+    fn from(blocks: Vec<ContentBlock>) -> Self {
+        Self::Blocks(blocks)
+    }
+}
+
+impl Content {
+    /// 内容块（字符串内容没有内容块）
+    #[must_use]
+    pub fn blocks(&self) -> &[ContentBlock] {
+        match self {
+            Self::Text(_) => &[],
+            Self::Blocks(blocks) => blocks,
+        }
+    }
+
+    /// 可修改的内容块（字符串内容没有内容块）
+    pub fn blocks_mut(&mut self) -> &mut [ContentBlock] {
+        match self {
+            Self::Text(_) => &mut [],
+            Self::Blocks(blocks) => blocks,
+        }
+    }
+
+    /// 转为内容块数组后返回，字符串内容转为一个 text 块（空字符串转为空数组）
+    pub fn make_blocks(&mut self) -> &mut Vec<ContentBlock> {
+        if let Self::Text(text) = self {
+            let blocks = if text.is_empty() {
+                Vec::new()
+            } else {
+                vec![ContentBlock::text(std::mem::take(text))]
+            };
+            *self = Self::Blocks(blocks);
+        }
+        match self {
+            Self::Blocks(blocks) => blocks,
+            Self::Text(_) => unreachable!("content was converted to blocks above"),
+        }
+    }
+
+    /// 依次返回字符串内容或各 text 块的文本（不含 thinking 等其他块）
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        let (text, blocks) = match self {
+            Self::Text(text) => (Some(text.as_str()), &[][..]),
+            Self::Blocks(blocks) => (None, blocks.as_slice()),
+        };
+        text.into_iter()
+            .chain(blocks.iter().filter_map(ContentBlock::as_text))
+    }
+
+    /// 拼接后的文本（不含 thinking 等其他块）
+    #[must_use]
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Blocks(_) => Cow::Owned(self.texts().collect()),
+        }
+    }
+}
+
+/// 内容块
+///
+/// 未知类型（`server_tool_use`、`web_search_tool_result`、`search_result`……）
+/// 与字段不完整的块落入 [`ContentBlock::Other`]，原样保留。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text(TextBlock),
+    Image(MediaBlock),
+    Document(MediaBlock),
+    Thinking(ThinkingBlock),
+    RedactedThinking(RedactedThinkingBlock),
+    ToolUse(ToolUseBlock),
+    ToolResult(ToolResultBlock),
+    #[serde(untagged)]
+    Other(OtherBlock),
+}
+
+impl ContentBlock {
+    /// text 块
+    #[must_use]
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text(TextBlock::new(text))
+    }
+
+    /// `tool_use` 块
+    #[must_use]
+    pub fn tool_use(id: impl Into<String>, name: impl Into<String>, input: Value) -> Self {
+        Self::ToolUse(ToolUseBlock {
+            id: id.into(),
+            name: name.into(),
+            input,
+            cache_control: None,
+            extra: Map::new(),
+        })
+    }
+
+    /// `tool_result` 块
+    #[must_use]
+    pub fn tool_result(tool_use_id: impl Into<String>, content: impl Into<Content>) -> Self {
+        Self::ToolResult(ToolResultBlock {
+            tool_use_id: tool_use_id.into(),
+            content: Some(content.into()),
+            is_error: None,
+            cache_control: None,
+            extra: Map::new(),
+        })
+    }
+
+    /// thinking 块
+    #[must_use]
+    pub fn thinking(thinking: impl Into<String>, signature: Option<String>) -> Self {
+        Self::Thinking(ThinkingBlock {
+            thinking: thinking.into(),
+            signature,
+            extra: Map::new(),
+        })
+    }
+
+    /// 未建模类型的块（`server_tool_use`、`web_search_tool_result` 等），
+    /// `fields` 为除 `type` 以外的字段，非对象时忽略
+    #[must_use]
+    pub fn other(kind: impl Into<String>, fields: Value) -> Self {
+        let extra = match fields {
+            Value::Object(extra) => extra,
+            _ => Map::new(),
+        };
+        Self::Other(OtherBlock {
+            kind: kind.into(),
+            cache_control: None,
+            extra,
+        })
+    }
+
+    /// text 块的文本
+    #[must_use]
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(block) => Some(&block.text),
+            _ => None,
+        }
+    }
+
+    /// 块上的 `cache_control`
+    #[must_use]
+    pub const fn cache_control(&self) -> Option<&CacheControl> {
+        match self {
+            Self::Text(TextBlock { cache_control, .. })
+            | Self::Image(MediaBlock { cache_control, .. })
+            | Self::Document(MediaBlock { cache_control, .. })
+            | Self::ToolUse(ToolUseBlock { cache_control, .. })
+            | Self::ToolResult(ToolResultBlock { cache_control, .. })
+            | Self::Other(OtherBlock { cache_control, .. }) => cache_control.as_ref(),
+            Self::Thinking(_) | Self::RedactedThinking(_) => None,
+        }
+    }
+
+    /// 可写入 `cache_control` 的位置；thinking 块不能设置缓存断点，返回 None
+    pub const fn cache_control_mut(&mut self) -> Option<&mut Option<CacheControl>> {
+        match self {
+            Self::Text(TextBlock { cache_control, .. })
+            | Self::Image(MediaBlock { cache_control, .. })
+            | Self::Document(MediaBlock { cache_control, .. })
+            | Self::ToolUse(ToolUseBlock { cache_control, .. })
+            | Self::ToolResult(ToolResultBlock { cache_control, .. })
+            | Self::Other(OtherBlock { cache_control, .. }) => Some(cache_control),
+            Self::Thinking(_) | Self::RedactedThinking(_) => None,
+        }
+    }
+}
+
+/// text 块
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextBlock {
+    /// 部分客户端把文本写成 `{ "text": ".." }` / `{ "value": ".." }` 对象，解析时展开为字符串
+    #[serde(deserialize_with = "lenient_text")]
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl TextBlock {
+    #[must_use]
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            cache_control: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+fn lenient_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => Ok(text),
+        Value::Object(mut object) => match object.remove("text").or_else(|| object.remove("value"))
+        {
+            Some(Value::String(text)) => Ok(text),
+            _ => Err(serde::de::Error::custom(
+                "text object has no string text/value",
+            )),
+        },
+        other => Err(serde::de::Error::custom(format!(
+            "text must be a string, got {other}"
+        ))),
+    }
+}
+
+/// image / document 块
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaBlock {
+    pub source: Source,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl MediaBlock {
+    #[must_use]
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            cache_control: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// 图片 / 文档来源（`base64` / `url` / `text` / `file`……）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Source {
+    /// `{ "type": "base64", media_type, data }`
+    #[must_use]
+    pub fn base64(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            kind: "base64".to_string(),
+            media_type: Some(media_type.into()),
+            data: Some(data.into()),
+            url: None,
+            extra: Map::new(),
+        }
+    }
+
+    /// `{ "type": "url", url }`
+    #[must_use]
+    pub fn url(url: impl Into<String>) -> Self {
+        Self {
+            kind: "url".to_string(),
+            media_type: None,
+            data: None,
+            url: Some(url.into()),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// thinking 块
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThinkingBlock {
+    #[serde(default)]
+    pub thinking: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `redacted_thinking` 块，`data` 是加密后的推理内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactedThinkingBlock {
+    pub data: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `tool_use` 块
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolUseBlock {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "empty_object")]
+    pub input: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn empty_object() -> Value {
+    Value::Object(Map::new())
+}
+
+/// `tool_result` 块
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResultBlock {
+    pub tool_use_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_error: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ToolResultBlock {
+    /// 结果是否标记为错误
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.is_error.unwrap_or(false)
+    }
+}
+
+/// 未建模的内容块
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OtherBlock {
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// 提示词缓存断点
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl CacheControl {
+    /// `{ "type": "ephemeral" }`
+    #[must_use]
+    pub fn ephemeral() -> Self {
+        Self {
+            kind: "ephemeral".to_string(),
+            ttl: None,
+            extra: Map::new(),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Anthropic Messages API 数据模型
+//!
+//! 请求、响应与流式事件的类型化表示，供各处理阶段、本地优化检测与格式转换共用。
+//!
+//! - 各结构只声明代理需要读写的字段，其余字段（包括 API 将来新增的字段）收进
+//!   `extra`，序列化时原样写回
+//! - 未知类型的内容块、增量与流式事件落入 `Other` 变体，同样原样保留
+//! - 已知类型但字段不完整的内容块也会落入 `Other`，不会导致整个请求解析失败
+//! - 数组或内容写成 `null`、整数写成 `1024.0` 等不规范写法按缺省值 / 整数解析
+
+mod content;
+mod request;
+mod response;
+mod stream;
+
+pub use self::{
+    content::{
+        CacheControl, Content, ContentBlock, MediaBlock, Source, TextBlock, ToolResultBlock,
+    },
+    request::{
+        Message, MessagesRequest, Role, ThinkingConfig, ThinkingKind, Tool, ToolChoice,
+        ToolChoiceKind,
+    },
+    response::{ApiError, ErrorResponse, MessagesResponse, Usage, error_type_for_status},
+    stream::{Delta, DeltaUsage, MessageDelta, StreamEvent},
+};
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    /// 测试请求解析后再序列化保留未建模的字段与内容块
+    #[test]
+    fn test_request_round_trip_keeps_unknown_fields() {
+        let body = json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "metadata": {"user_id": "u1"},
+            "system": [{"type": "text", "text": "Be brief.", "cache_control": {"type": "ephemeral", "ttl": "1h"}}],
+            "tools": [
+                {"name": "Read", "input_schema": {"type": "object"}, "x-extension": true},
+                {"type": "web_search_20250305", "name": "web_search", "max_uses": 3}
+            ],
+            "tool_choice": {"type": "auto", "disable_parallel_tool_use": true},
+            "thinking": {"type": "enabled", "budget_tokens": 2048},
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "hmm", "signature": "sig"},
+                    {"type": "server_tool_use", "id": "srv_1", "name": "web_search", "input": {"query": "rust"}},
+                    {"type": "web_search_tool_result", "tool_use_id": "srv_1", "content": []},
+                    {"type": "text", "text": "done", "citations": [{"type": "char_location"}]}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "ok"}], "is_error": false},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}}
+                ]}
+            ]
+        });
+        let request: MessagesRequest = serde_json::from_value(body.clone()).unwrap();
+
+        assert_eq!(request.messages[0].role, Role::User);
+        assert_eq!(request.messages[0].content.text(), "hi");
+        assert_eq!(request.thinking_kind(), Some(&ThinkingKind::Enabled));
+        assert_eq!(
+            request.tools[1].kind.as_deref(),
+            Some("web_search_20250305")
+        );
+        let blocks = request.messages[1].content.blocks();
+        assert_eq!(blocks.len(), 4);
+        assert!(matches!(blocks[0], ContentBlock::Thinking(_)));
+        assert!(
+            matches!(&blocks[2], ContentBlock::Other(other) if other.kind == "web_search_tool_result")
+        );
+        assert!(matches!(blocks[3], ContentBlock::Text(_)));
+        assert!(matches!(blocks[1], ContentBlock::Other(_)));
+        // thinking 块不计入文本
+        assert_eq!(request.messages[1].content.text(), "done");
+        assert_eq!(serde_json::to_value(&request).unwrap(), body);
+    }
+
+    /// 测试字段不完整的已知类型块与对象形式的文本
+    #[test]
+    fn test_lenient_blocks() {
+        let content: Content = serde_json::from_value(json!([
+            {"type": "text", "text": {"value": "wrapped"}},
+            {"type": "tool_use", "name": "Read"},
+            {"no_type": 1}
+        ]))
+        .unwrap();
+        let blocks = content.blocks();
+        assert_eq!(blocks[0].as_text(), Some("wrapped"));
+        assert!(matches!(&blocks[1], ContentBlock::Other(other) if other.kind == "tool_use"));
+        assert_eq!(
+            serde_json::to_value(&blocks[2]).unwrap(),
+            json!({"no_type": 1})
+        );
+
+        let mut content = Content::from("hello");
+        content.make_blocks()[0]
+            .cache_control_mut()
+            .unwrap()
+            .replace(CacheControl::ephemeral());
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!([{"type": "text", "text": "hello", "cache_control": {"type": "ephemeral"}}])
+        );
+    }
+
+    /// 测试 `null` 字段与小数部分为 0 的浮点数按缺省值 / 整数解析
+    #[test]
+    fn test_lenient_request_fields() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024.0,
+            "tools": null,
+            "thinking": {"type": "enabled", "budget_tokens": 2048.0},
+            "messages": [{"role": "user", "content": null}]
+        }))
+        .unwrap();
+        assert_eq!(request.max_tokens, Some(1024));
+        assert!(request.tools.is_empty());
+        assert_eq!(request.thinking.unwrap().budget_tokens, Some(2048));
+        assert_eq!(request.messages[0].content, Content::default());
+
+        for invalid in [json!({"max_tokens": 1.5}), json!({"max_tokens": -1})] {
+            assert!(serde_json::from_value::<MessagesRequest>(invalid).is_err());
+        }
+    }
+
+    /// 测试流式事件的解析、事件名与未知事件的保留
+    #[test]
+    fn test_stream_events() {
+        let events = [
+            json!({"type": "message_start", "message": {
+                "id": "msg_1", "type": "message", "role": "assistant", "model": "m",
+                "content": [], "stop_reason": null, "stop_sequence": null,
+                "usage": {"input_tokens": 10, "output_tokens": 1, "cache_read_input_tokens": 5}
+            }}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "hi"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "citations_delta", "citation": {}}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn", "stop_sequence": null}, "usage": {"output_tokens": 7}}),
+            json!({"type": "message_stop"}),
+            json!({"type": "ping"}),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+            json!({"type": "future_event", "payload": [1, 2]}),
+        ];
+        for event in events {
+            let parsed: StreamEvent = serde_json::from_value(event.clone()).unwrap();
+            assert_eq!(parsed.name(), event["type"].as_str().unwrap());
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), event);
+        }
+
+        let delta: StreamEvent = serde_json::from_value(json!({
+            "type": "content_block_delta", "index": 2,
+            "delta": {"type": "signature_delta", "signature": "sig"}
+        }))
+        .unwrap();
+        assert!(matches!(
+            delta,
+            StreamEvent::ContentBlockDelta { index: 2, delta: Delta::Signature { ref signature } }
+                if signature == "sig"
+        ));
+        let response = MessagesResponse::new("msg_1", "m");
+        assert_eq!(
+            serde_json::to_value(&response).unwrap()["stop_reason"],
+            Value::Null
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Messages 请求
+
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use serde_json::{Map, Number, Value};
+
+use super::content::{CacheControl, Content};
+
+/// `POST /v1/messages`（以及 `count_tokens`）请求体
+///
+/// `metadata`、`output_format`、`context_management` 等代理不关心的字段保留在 `extra` 中。
+/// 客户端常见的不规范写法（数组或内容写成 `null`、整数写成 `1024.0`）按缺省值 / 整数解析。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessagesRequest {
+    #[serde(
+        default,
+        deserialize_with = "null_as_default",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub model: String,
+    #[serde(
+        default,
+        deserialize_with = "null_as_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<Content>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_u64",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "null_as_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub tools: Vec<Tool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_u64",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub top_k: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl MessagesRequest {
+    /// 是否请求流式响应
+    #[must_use]
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+
+    /// thinking 的类型（未设置时为 None）
+    #[must_use]
+    pub fn thinking_kind(&self) -> Option<&ThinkingKind> {
+        self.thinking.as_ref().map(|thinking| &thinking.kind)
+    }
+}
+
+/// `null` 按缺省值处理
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// 非负整数，接受小数部分为 0 的浮点数（如 `1024.0`）
+fn lenient_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let Some(number) = Option::<Number>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    number
+        .as_u64()
+        .or_else(|| {
+            number
+                .as_f64()
+                .and_then(|float| float.to_string().parse().ok())
+        })
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("expected a non-negative integer, got {number}")))
+}
+
+/// 对话消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: Content,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Message {
+    #[must_use]
+    pub fn new(role: Role, content: impl Into<Content>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// 消息角色
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Assistant,
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl Role {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::User => "user",
+            Self::Assistant => "assistant",
+            Self::Other(role) => role,
+        }
+    }
+}
+
+/// 工具定义
+///
+/// 客户端工具只有 `name` / `description` / `input_schema`；
+/// 服务端工具（`web_search_20250305` 等）带有 `type`，其余参数保留在 `extra` 中。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `tool_choice`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolChoice {
+    #[serde(rename = "type")]
+    pub kind: ToolChoiceKind,
+    /// `type = "tool"` 时指定的工具名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disable_parallel_tool_use: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ToolChoice {
+    #[must_use]
+    pub fn new(kind: ToolChoiceKind) -> Self {
+        Self {
+            kind,
+            name: None,
+            disable_parallel_tool_use: None,
+            extra: Map::new(),
+        }
+    }
+
+    /// 强制调用的工具名（`type = "tool"`）
+    #[must_use]
+    pub fn forced_tool(&self) -> Option<&str> {
+        match self.kind {
+            ToolChoiceKind::Tool => self.name.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// `tool_choice.type`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoiceKind {
+    Auto,
+    Any,
+    Tool,
+    None,
+    #[serde(untagged)]
+    Other(String),
+}
+
+/// 扩展思考配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThinkingConfig {
+    #[serde(rename = "type")]
+    pub kind: ThinkingKind,
+    #[serde(
+        default,
+        deserialize_with = "lenient_u64",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub budget_tokens: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `thinking.type`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingKind {
+    Enabled,
+    Disabled,
+    Adaptive,
+    #[serde(untagged)]
+    Other(String),
+}
@@ -0,0 +1,143 @@
+//! Messages 响应与错误响应
+
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{content::ContentBlock, request::Role};
+
+/// 非流式响应体，也是流式 `message_start` 中的 `message`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessagesResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type", default = "message_kind")]
+    pub kind: String,
+    #[serde(default = "assistant_role")]
+    pub role: Role,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+    #[serde(default)]
+    pub usage: Usage,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn message_kind() -> String {
+    "message".to_string()
+}
+
+const fn assistant_role() -> Role {
+    Role::Assistant
+}
+
+impl MessagesResponse {
+    /// 空的 assistant 消息
+    #[must_use]
+    pub fn new(id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            kind: message_kind(),
+            role: Role::Assistant,
+            model: model.into(),
+            content: Vec::new(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage: Usage::default(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// token 用量
+///
+/// `server_tool_use`、`service_tier`、`cache_creation` 明细等保留在 `extra` 中。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Usage {
+    #[must_use]
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            input_tokens,
+            output_tokens,
+            ..Self::default()
+        }
+    }
+}
+
+/// 错误响应体（`{ "type": "error", "error": { "type", "message" } }`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    #[serde(rename = "type", default = "error_kind")]
+    pub kind: String,
+    pub error: ApiError,
+}
+
+fn error_kind() -> String {
+    "error".to_string()
+}
+
+impl ErrorResponse {
+    #[must_use]
+    pub fn new(error_type: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            kind: error_kind(),
+            error: ApiError::new(error_type, message),
+        }
+    }
+}
+
+/// 错误详情
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ApiError {
+    #[must_use]
+    pub fn new(error_type: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            kind: error_type.into(),
+            message: message.into(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// 按状态码推断 Anthropic 错误类型（上游错误响应未携带可映射的错误类型时使用）
+#[must_use]
+pub const fn error_type_for_status(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        _ => "api_error",
+    }
+}
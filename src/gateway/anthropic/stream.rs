@@ -0,0 +1,127 @@
+//! 流式事件
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{
+    content::ContentBlock,
+    response::{ApiError, MessagesResponse, Usage},
+};
+
+/// 流式响应中的一个事件（SSE `data:` 的内容）
+///
+/// 未知事件落入 [`StreamEvent::Other`]，原样保留。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    MessageStart {
+        message: MessagesResponse,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: Delta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: DeltaUsage,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: ApiError,
+    },
+    #[serde(untagged)]
+    Other(Map<String, Value>),
+}
+
+impl StreamEvent {
+    /// SSE `event:` 名称（与 `type` 相同）
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::MessageStart { .. } => "message_start",
+            Self::ContentBlockStart { .. } => "content_block_start",
+            Self::ContentBlockDelta { .. } => "content_block_delta",
+            Self::ContentBlockStop { .. } => "content_block_stop",
+            Self::MessageDelta { .. } => "message_delta",
+            Self::MessageStop => "message_stop",
+            Self::Ping => "ping",
+            Self::Error { .. } => "error",
+            Self::Other(object) => object.get("type").and_then(Value::as_str).unwrap_or(""),
+        }
+    }
+}
+
+/// `content_block_delta` 中的增量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Delta {
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    #[serde(rename = "input_json_delta")]
+    InputJson { partial_json: String },
+    #[serde(rename = "thinking_delta")]
+    Thinking { thinking: String },
+    #[serde(rename = "signature_delta")]
+    Signature { signature: String },
+    /// `citations_delta` 等未建模的增量
+    #[serde(untagged)]
+    Other(Map<String, Value>),
+}
+
+/// `message_delta` 中的消息级增量
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageDelta {
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl MessageDelta {
+    #[must_use]
+    pub fn stop(stop_reason: impl Into<String>) -> Self {
+        Self {
+            stop_reason: Some(stop_reason.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// `message_delta` 中的累计用量，只有 `output_tokens` 必定出现
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaUsage {
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// 非流式 usage 作为 `message_delta` 的累计用量
+impl From<Usage> for DeltaUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            output_tokens: usage.output_tokens,
+            input_tokens: Some(usage.input_tokens),
+            cache_creation_input_tokens: usage.cache_creation_input_tokens,
+            cache_read_input_tokens: usage.cache_read_input_tokens,
+            extra: usage.extra,
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Azure `OpenAI` 上游（`openai_responses` / `openai_chat` 模式配置了 `[upstream.azure]` 时）
+//!
+//! 请求体格式与 `OpenAI` 相同，差异在于地址与鉴权：
+//! - Responses：`{base}/openai/responses?api-version=..`，部署名放在请求体的 `model` 中
+//! - Chat Completions：`{base}/openai/deployments/{deployment}/chat/completions?api-version=..`
+//! - 使用 `api-key` 请求头鉴权（不是 Bearer）
+//! - 没有 token 计数接口，`count_tokens` 请求由代理按本地估算回复，不会发到这里
+//!
+//! 内容过滤拦截的错误响应由 [`crate::gateway::openai_compat::error_body_to_anthropic`] 转换。
+
+use bytes::Bytes;
+use http::Method;
+use http_body_util::Full;
+use hyper::Request as HyperRequest;
+
+use crate::{
+    config::{AzureConfig, Mode, UpstreamConfig},
+    gateway::handler::override_model_in_body,
+};
+
+/// 未配置 `api_version` 时使用的版本（Responses API 需要 preview 版本）
+const DEFAULT_API_VERSION: &str = "2025-04-01-preview";
+
+/// 构建发往 Azure `OpenAI` 的请求
+pub fn build_request(
+    upstream: &UpstreamConfig,
+    azure: &AzureConfig,
+    body: Bytes,
+    api_key: &str,
+) -> Result<HyperRequest<Full<Bytes>>, String> {
+    let deployment = azure
+        .deployment
+        .as_deref()
+        .filter(|deployment| !deployment.is_empty())
+        .unwrap_or(&upstream.model);
+    if deployment.is_empty() {
+        return Err("azure upstream requires deployment or model".to_string());
+    }
+    let url = azure_url(upstream, azure, deployment)?;
+    // Responses API 按请求体中的 model 选择部署
+    let body = if matches!(upstream.mode, Mode::OpenAIResponses) && !body.is_empty() {
+        override_model_in_body(&body, deployment).unwrap_or(body)
+    } else {
+        body
+    };
+    tracing::info!("Proxying to: {} (azure)", url);
+
+    HyperRequest::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .header("content-type", "application/json")
+        .header("api-key", api_key)
+        .body(Full::new(body))
+        .map_err(|e| format!("failed to build request: {e}"))
+}
+
+/// 按 mode 拼接部署地址
+fn azure_url(
+    upstream: &UpstreamConfig,
+    azure: &AzureConfig,
+    deployment: &str,
+) -> Result<String, String> {
+    let base = if upstream.endpoint.is_empty() {
+        let resource = azure
+            .resource
+            .as_deref()
+            .filter(|resource| !resource.is_empty())
+            .ok_or_else(|| "azure upstream requires resource or endpoint".to_string())?;
+        format!("https://{resource}.openai.azure.com")
+    } else {
+        upstream.endpoint.trim_end_matches('/').to_string()
+    };
+    let api_version = azure
+        .api_version
+        .as_deref()
+        .filter(|version| !version.is_empty())
+        .unwrap_or(DEFAULT_API_VERSION);
+    let path = match upstream.mode {
+        Mode::OpenAIChat => format!("openai/deployments/{deployment}/chat/completions"),
+        _ => "openai/responses".to_string(),
+    };
+    Ok(format!("{base}/{path}?api-version={api_version}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+
+    use super::*;
+
+    /// 测试 Responses 与 Chat Completions 的部署地址、`api-key` 鉴权与部署名覆盖
+    #[tokio::test]
+    async fn test_build_request() {
+        let azure = AzureConfig {
+            resource: Some("contoso".to_string()),
+            deployment: Some("gpt-4o-prod".to_string()),
+            api_version: None,
+        };
+        let upstream = UpstreamConfig {
+            mode: Mode::OpenAIResponses,
+            model: "gpt-4o".to_string(),
+            azure: Some(azure.clone()),
+            ..UpstreamConfig::default()
+        };
+        let request = build_request(
+            &upstream,
+            &azure,
+            Bytes::from_static(br#"{"model":"gpt-4o","input":[]}"#),
+            "secret",
+        )
+        .unwrap();
+        assert_eq!(
+            request.uri(),
+            "https://contoso.openai.azure.com/openai/responses?api-version=2025-04-01-preview"
+        );
+        assert_eq!(request.headers()["api-key"], "secret");
+        assert!(request.headers().get("authorization").is_none());
+        let body = request.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["model"], "gpt-4o-prod");
+
+        let upstream = UpstreamConfig {
+            mode: Mode::OpenAIChat,
+            endpoint: "https://gateway.example.com/".to_string(),
+            ..upstream
+        };
+        let azure = AzureConfig {
+            api_version: Some("2024-10-21".to_string()),
+            ..azure
+        };
+        let request = build_request(&upstream, &azure, Bytes::new(), "secret").unwrap();
+        assert_eq!(
+            request.uri(),
+            "https://gateway.example.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-10-21"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! AWS event stream 二进制帧解码（`application/vnd.amazon.eventstream`）
+//!
+//! 帧结构：
+//!
+//! ```text
+//! total_len(u32) | headers_len(u32) | prelude_crc(u32) | headers | payload | message_crc(u32)
+//! ```
+//!
+//! 所有整数为大端序，CRC 为 CRC32（IEEE）。
+
+/// 帧头部的固定长度（`total_len` + `headers_len` + `prelude_crc`）
+const PRELUDE_LEN: usize = 12;
+/// 帧尾 `message_crc` 的长度
+const CRC_LEN: usize = 4;
+/// 单帧最大长度（AWS 限制为 16 MiB）
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// 解码后的一帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventMessage {
+    /// 字符串类型的头部（`:event-type`、`:message-type` 等），其余类型的头部被跳过
+    pub headers: Vec<(String, String)>,
+    pub payload: Vec<u8>,
+}
+
+impl EventMessage {
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// 增量解码器：按任意边界喂入字节，吐出完整的帧
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    /// 喂入字节，返回已完整接收的帧；帧损坏时返回错误，之后的数据不再可信
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<EventMessage>, String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = Vec::new();
+        while self.buffer.len() >= PRELUDE_LEN {
+            let total_len = read_u32(&self.buffer, 0) as usize;
+            if !(PRELUDE_LEN + CRC_LEN..=MAX_MESSAGE_LEN).contains(&total_len) {
+                return Err(format!("invalid event stream frame length: {total_len}"));
+            }
+            if self.buffer.len() < total_len {
+                break;
+            }
+            let frame = self.buffer.drain(..total_len).collect::<Vec<_>>();
+            messages.push(decode_frame(&frame)?);
+        }
+        Ok(messages)
+    }
+
+    /// 是否残留未完整接收的帧
+    #[must_use]
+    pub const fn has_pending(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+}
+
+/// 解码一个完整的帧
+fn decode_frame(frame: &[u8]) -> Result<EventMessage, String> {
+    let headers_len = read_u32(frame, 4) as usize;
+    if read_u32(frame, 8) != crc32fast::hash(&frame[..8]) {
+        return Err("event stream prelude checksum mismatch".to_string());
+    }
+    let crc_offset = frame.len() - CRC_LEN;
+    if read_u32(frame, crc_offset) != crc32fast::hash(&frame[..crc_offset]) {
+        return Err("event stream message checksum mismatch".to_string());
+    }
+    let headers_end = PRELUDE_LEN + headers_len;
+    if headers_end > crc_offset {
+        return Err("event stream headers exceed frame".to_string());
+    }
+    Ok(EventMessage {
+        headers: decode_headers(&frame[PRELUDE_LEN..headers_end])?,
+        payload: frame[headers_end..crc_offset].to_vec(),
+    })
+}
+
+/// 解码头部：`name_len(u8) | name | type(u8) | value`
+fn decode_headers(mut bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let truncated = || "event stream header truncated".to_string();
+    let mut headers = Vec::new();
+    while let Some((&name_len, rest)) = bytes.split_first() {
+        let name_len = usize::from(name_len);
+        let name = rest.get(..name_len).ok_or_else(truncated)?;
+        let (&value_type, rest) = rest[name_len..].split_first().ok_or_else(truncated)?;
+        let value_len = match value_type {
+            // bool true / bool false
+            0 | 1 => 0,
+            // byte
+            2 => 1,
+            // short
+            3 => 2,
+            // int
+            4 => 4,
+            // long / timestamp
+            5 | 8 => 8,
+            // uuid
+            9 => 16,
+            // bytes / string：u16 长度前缀
+            6 | 7 => {
+                let len = rest.get(..2).ok_or_else(truncated)?;
+                2 + usize::from(u16::from_be_bytes([len[0], len[1]]))
+            }
+            other => return Err(format!("unknown event stream header type: {other}")),
+        };
+        let value = rest.get(..value_len).ok_or_else(truncated)?;
+        if value_type == 7 {
+            headers.push((
+                String::from_utf8_lossy(name).into_owned(),
+                String::from_utf8_lossy(&value[2..]).into_owned(),
+            ));
+        }
+        bytes = &rest[value_len..];
+    }
+    Ok(headers)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// 编码一帧（仅字符串头部），供测试构造上游响应
+#[cfg(test)]
+#[must_use]
+pub fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut header_bytes = Vec::new();
+    for (name, value) in headers {
+        header_bytes.push(u8::try_from(name.len()).unwrap_or(u8::MAX));
+        header_bytes.extend_from_slice(name.as_bytes());
+        header_bytes.push(7);
+        header_bytes.extend_from_slice(&u16::try_from(value.len()).unwrap_or(0).to_be_bytes());
+        header_bytes.extend_from_slice(value.as_bytes());
+    }
+    let total_len = PRELUDE_LEN + header_bytes.len() + payload.len() + CRC_LEN;
+    let mut frame = Vec::with_capacity(total_len);
+    frame.extend_from_slice(&u32::try_from(total_len).unwrap_or(0).to_be_bytes());
+    frame.extend_from_slice(&u32::try_from(header_bytes.len()).unwrap_or(0).to_be_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&frame).to_be_bytes());
+    frame.extend_from_slice(&header_bytes);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32fast::hash(&frame).to_be_bytes());
+    frame
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试按任意边界切分的帧能被完整解码
+    #[test]
+    fn test_decode_split_frames() {
+        let mut bytes = encode_frame(
+            &[(":event-type", "chunk"), (":message-type", "event")],
+            b"{\"bytes\":\"e30=\"}",
+        );
+        bytes.extend(encode_frame(&[(":message-type", "event")], b"second"));
+
+        let mut decoder = EventStreamDecoder::default();
+        let mut messages = Vec::new();
+        for chunk in bytes.chunks(7) {
+            messages.extend(decoder.feed(chunk).unwrap());
+        }
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].header(":event-type"), Some("chunk"));
+        assert_eq!(messages[0].payload, b"{\"bytes\":\"e30=\"}");
+        assert_eq!(messages[1].payload, b"second");
+        assert!(!decoder.has_pending());
+    }
+
+    /// 测试校验和不匹配时报错
+    #[test]
+    fn test_decode_rejects_corrupt_frame() {
+        let mut bytes = encode_frame(&[(":message-type", "event")], b"payload");
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xff;
+        assert!(EventStreamDecoder::default().feed(&bytes).is_err());
+    }
+}
@@ -0,0 +1,743 @@
+//! AWS Bedrock 上游（`mode = "bedrock"`）
+//!
+//! - 请求：Anthropic Messages 请求体去掉 `model` / `stream`，加上 `anthropic_version`，
+//!   `anthropic-beta` 请求头改为请求体中的 `anthropic_beta`；
+//!   发往 `/model/{model}/invoke` 或 `/model/{model}/invoke-with-response-stream`，使用 `SigV4` 签名
+//! - `count_tokens`：同样的请求体 base64 编码后包进 `CountTokens` 请求，发往 `/model/{model}/count-tokens`，
+//!   响应 `{ "inputTokens": .. }` 转换为 `{ "input_tokens": .. }`（不产生调用费用）
+//! - 响应：非流式响应体已是 Anthropic 格式；流式响应为 AWS event stream，
+//!   每帧 payload 的 `bytes` 字段是 base64 编码的 Anthropic 流式事件，解码后还原为 SSE
+
+pub mod eventstream;
+pub mod sigv4;
+
+use std::{env, mem};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use http::{HeaderMap, Method, StatusCode};
+use http_body_util::Full;
+use hyper::Request as HyperRequest;
+use serde_json::{Value, json};
+
+use self::{
+    eventstream::{EventMessage, EventStreamDecoder},
+    sigv4::{AwsCredentials, SignableRequest, SigningParams},
+};
+use crate::{
+    config::{BedrockConfig, UpstreamConfig},
+    gateway::{
+        anthropic::{ApiError, MessagesRequest, StreamEvent, error_type_for_status},
+        sse::{StreamConverter, encode_stream_event},
+    },
+};
+
+/// `SigV4` 签名使用的服务名
+const SERVICE: &str = "bedrock";
+/// Bedrock 要求的 Anthropic API 版本
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+/// Bedrock 流式响应的 content-type
+pub const EVENTSTREAM_CONTENT_TYPE: &str = "application/vnd.amazon.eventstream";
+
+/// Anthropic 请求 → Bedrock `InvokeModel` 请求
+///
+/// 返回 (请求体, 模型 id, 是否流式)。
+pub fn anthropic_request_to_bedrock(
+    body: &[u8],
+    anthropic_beta: Option<&str>,
+) -> Result<(Bytes, String, bool), String> {
+    let (request, model, stream) = bedrock_request(body, anthropic_beta)?;
+    let body = serde_json::to_vec(&request).map_err(|e| format!("serialize failed: {e}"))?;
+    Ok((Bytes::from(body), model, stream))
+}
+
+/// Anthropic `count_tokens` 请求 → Bedrock `CountTokens` 请求
+///
+/// `InvokeModel` 请求体以 base64 放在 `input.invokeModel.body` 中；
+/// `count_tokens` 请求没有 `max_tokens`，而 `InvokeModel` 请求体要求该字段，缺失时补 1。
+/// 返回 (请求体, 模型 id)。
+pub fn anthropic_count_tokens_to_bedrock(
+    body: &[u8],
+    anthropic_beta: Option<&str>,
+) -> Result<(Bytes, String), String> {
+    let (mut request, model, _) = bedrock_request(body, anthropic_beta)?;
+    request.max_tokens.get_or_insert(1);
+    let invoke_body = serde_json::to_vec(&request).map_err(|e| format!("serialize failed: {e}"))?;
+    let body = json!({
+        "input": { "invokeModel": { "body": STANDARD.encode(invoke_body) } }
+    });
+    Ok((Bytes::from(body.to_string()), model))
+}
+
+/// Bedrock `CountTokens` 响应 → Anthropic `count_tokens` 响应
+pub fn count_tokens_response_to_anthropic(body: &[u8]) -> Result<Bytes, String> {
+    let input_tokens = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| value.get("inputTokens").and_then(Value::as_u64))
+        .ok_or("CountTokens response has no inputTokens")?;
+    Ok(Bytes::from(
+        json!({ "input_tokens": input_tokens }).to_string(),
+    ))
+}
+
+/// 去掉 `model` / `stream`，加上 `anthropic_version` 与 `anthropic_beta`
+fn bedrock_request(
+    body: &[u8],
+    anthropic_beta: Option<&str>,
+) -> Result<(MessagesRequest, String, bool), String> {
+    let mut request: MessagesRequest =
+        serde_json::from_slice(body).map_err(|e| format!("invalid request body: {e}"))?;
+    let model = mem::take(&mut request.model);
+    if model.is_empty() {
+        return Err("request has no model".to_string());
+    }
+    let stream = request.stream.take().unwrap_or(false);
+    request.extra.insert(
+        "anthropic_version".to_string(),
+        Value::String(BEDROCK_ANTHROPIC_VERSION.to_string()),
+    );
+    let betas = anthropic_beta
+        .into_iter()
+        .flat_map(|header| header.split(','))
+        .map(str::trim)
+        .filter(|beta| !beta.is_empty())
+        .map(|beta| Value::String(beta.to_string()))
+        .collect::<Vec<_>>();
+    if !betas.is_empty() {
+        request
+            .extra
+            .insert("anthropic_beta".to_string(), Value::Array(betas));
+    }
+    Ok((request, model, stream))
+}
+
+/// 构建已签名的 Bedrock 请求；`count_tokens` 为 true 时改为 `CountTokens` 请求
+pub fn build_request(
+    upstream: &UpstreamConfig,
+    body: &[u8],
+    client_headers: &HeaderMap,
+    count_tokens: bool,
+) -> Result<HyperRequest<Full<Bytes>>, String> {
+    let anthropic_beta = client_headers
+        .get("anthropic-beta")
+        .and_then(|value| value.to_str().ok());
+    let (body, model, stream) = if count_tokens {
+        let (body, model) = anthropic_count_tokens_to_bedrock(body, anthropic_beta)?;
+        (body, model, false)
+    } else {
+        anthropic_request_to_bedrock(body, anthropic_beta)?
+    };
+    let (region, credentials) = resolve_credentials(upstream.bedrock.as_ref())?;
+
+    let (scheme, host, base_path) = bedrock_base_url(&upstream.endpoint, &region);
+    let action = if count_tokens {
+        "count-tokens"
+    } else if stream {
+        "invoke-with-response-stream"
+    } else {
+        "invoke"
+    };
+    let path = format!(
+        "{base_path}/model/{}/{action}",
+        sigv4::uri_encode(&model, true)
+    );
+    let url = format!("{scheme}://{host}{path}");
+    let accept = if stream {
+        EVENTSTREAM_CONTENT_TYPE
+    } else {
+        "application/json"
+    };
+    let headers = vec![
+        ("host".to_string(), host),
+        ("content-type".to_string(), "application/json".to_string()),
+        ("accept".to_string(), accept.to_string()),
+    ];
+    let amz_date = sigv4::amz_date_now();
+    let signed = sigv4::sign(
+        &SignableRequest {
+            method: "POST",
+            path: &path,
+            query: "",
+            headers: &headers,
+            payload: &body,
+        },
+        &SigningParams {
+            credentials: &credentials,
+            region: &region,
+            service: SERVICE,
+            amz_date: &amz_date,
+        },
+    );
+    tracing::info!("Proxying to: {} (bedrock, region={})", url, region);
+
+    let mut builder = HyperRequest::builder().method(Method::POST).uri(&url);
+    for (name, value) in headers.iter().chain(&signed) {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(Full::new(body))
+        .map_err(|e| format!("failed to build request: {e}"))
+}
+
+/// Bedrock Runtime 的 (scheme, host, 路径前缀)
+///
+/// `endpoint` 留空时使用区域默认地址，否则使用配置值（VPC endpoint、本地替身服务等）。
+fn bedrock_base_url(endpoint: &str, region: &str) -> (&'static str, String, String) {
+    if endpoint.is_empty() {
+        return (
+            "https",
+            format!("bedrock-runtime.{region}.amazonaws.com"),
+            String::new(),
+        );
+    }
+    let (scheme, rest) = endpoint.strip_prefix("http://").map_or_else(
+        || {
+            (
+                "https",
+                endpoint.strip_prefix("https://").unwrap_or(endpoint),
+            )
+        },
+        |rest| ("http", rest),
+    );
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let path = path.trim_matches('/');
+    let base_path = if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{path}")
+    };
+    (scheme, host.to_string(), base_path)
+}
+
+/// 读取区域与凭证：配置优先，未配置的项从环境变量读取
+fn resolve_credentials(config: Option<&BedrockConfig>) -> Result<(String, AwsCredentials), String> {
+    let pick = |configured: Option<&String>, vars: &[&str]| {
+        configured
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .or_else(|| {
+                vars.iter()
+                    .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+            })
+    };
+    let region = pick(
+        config.and_then(|c| c.region.as_ref()),
+        &["AWS_REGION", "AWS_DEFAULT_REGION"],
+    )
+    .ok_or("bedrock region not configured (bedrock.region or AWS_REGION)")?;
+    let access_key_id = pick(
+        config.and_then(|c| c.access_key_id.as_ref()),
+        &["AWS_ACCESS_KEY_ID"],
+    )
+    .ok_or("bedrock access key not configured (bedrock.access_key_id or AWS_ACCESS_KEY_ID)")?;
+    let secret_access_key = pick(
+        config.and_then(|c| c.secret_access_key.as_ref()),
+        &["AWS_SECRET_ACCESS_KEY"],
+    )
+    .ok_or(
+        "bedrock secret key not configured (bedrock.secret_access_key or AWS_SECRET_ACCESS_KEY)",
+    )?;
+    let session_token = pick(
+        config.and_then(|c| c.session_token.as_ref()),
+        &["AWS_SESSION_TOKEN"],
+    );
+    Ok((
+        region,
+        AwsCredentials {
+            access_key_id,
+            secret_access_key,
+            session_token,
+        },
+    ))
+}
+
+/// AWS 异常类型 → Anthropic 错误类型
+///
+/// 普通响应使用 `ValidationException`，流式异常帧使用 `validationException`，比较时忽略大小写。
+fn anthropic_error_type(exception: &str) -> &'static str {
+    match exception.to_ascii_lowercase().as_str() {
+        "validationexception" => "invalid_request_error",
+        "accessdeniedexception" | "unrecognizedclientexception" => "permission_error",
+        "resourcenotfoundexception" => "not_found_error",
+        "throttlingexception" | "servicequotaexceededexception" => "rate_limit_error",
+        "serviceunavailableexception" | "modelnotreadyexception" => "overloaded_error",
+        _ => "api_error",
+    }
+}
+
+/// Bedrock 错误响应（`{ "message": .. }` + `x-amzn-errortype` 头）→ Anthropic 错误格式
+#[must_use]
+pub fn error_body_to_anthropic(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Bytes {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| {
+            value
+                .get("message")
+                .or_else(|| value.get("Message"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+    // x-amzn-errortype 形如 `ValidationException:http://internal.amazon.com/coral/...`
+    let error_type = headers
+        .get("x-amzn-errortype")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(':').next())
+        .map_or_else(|| error_type_for_status(status), anthropic_error_type);
+    Bytes::from(
+        json!({
+            "type": "error",
+            "error": { "type": error_type, "message": message }
+        })
+        .to_string(),
+    )
+}
+
+/// Bedrock event stream → Anthropic SSE
+#[derive(Debug, Default)]
+pub struct BedrockStreamConverter {
+    decoder: EventStreamDecoder,
+    failed: bool,
+}
+
+impl BedrockStreamConverter {
+    fn handle_message(message: &EventMessage, out: &mut Vec<Bytes>) {
+        let payload = serde_json::from_slice::<Value>(&message.payload).unwrap_or(Value::Null);
+        match message.header(":message-type") {
+            Some("event") if message.header(":event-type") == Some("chunk") => {
+                let Some(event) = payload
+                    .get("bytes")
+                    .and_then(Value::as_str)
+                    .and_then(|encoded| STANDARD.decode(encoded).ok())
+                    .and_then(|decoded| serde_json::from_slice::<StreamEvent>(&decoded).ok())
+                else {
+                    tracing::warn!("⚠️ 无法解析 Bedrock chunk 事件");
+                    return;
+                };
+                out.push(encode_stream_event(&event));
+            }
+            Some("exception" | "error") => {
+                let exception = message
+                    .header(":exception-type")
+                    .or_else(|| message.header(":error-code"))
+                    .unwrap_or("InternalServerException");
+                let text = payload
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .or_else(|| message.header(":error-message"))
+                    .unwrap_or(exception);
+                tracing::warn!("Bedrock 流式异常: {}: {}", exception, text);
+                out.push(error_event(anthropic_error_type(exception), text));
+            }
+            other => tracing::debug!("忽略 Bedrock 事件: {:?}", other),
+        }
+    }
+}
+
+fn error_event(error_type: &str, message: &str) -> Bytes {
+    encode_stream_event(&StreamEvent::Error {
+        error: ApiError::new(error_type, message),
+    })
+}
+
+impl StreamConverter for BedrockStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        if self.failed {
+            return Vec::new();
+        }
+        let mut out = Vec::new();
+        match self.decoder.feed(chunk) {
+            Ok(messages) => {
+                for message in &messages {
+                    Self::handle_message(message, &mut out);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Bedrock event stream 解码失败: {}", e);
+                self.failed = true;
+                out.push(error_event("api_error", &e));
+            }
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        if !self.failed && self.decoder.has_pending() {
+            tracing::warn!("⚠️ Bedrock event stream 在帧中间结束");
+            return vec![error_event(
+                "api_error",
+                "upstream stream ended unexpectedly",
+            )];
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use http_body_util::BodyExt;
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use ring::{digest, hmac};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{eventstream::encode_frame, *};
+    use crate::{config::Mode, gateway::sse::SseDecoder};
+
+    /// 本地监听器收到的原始请求
+    struct ReceivedRequest {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl ReceivedRequest {
+        fn header(&self, name: &str) -> &str {
+            self.headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map_or("", |(_, value)| value.as_str())
+        }
+    }
+
+    /// 接收一个 HTTP/1.1 请求并返回 `{}`
+    async fn receive_one(listener: TcpListener) -> ReceivedRequest {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = Vec::new();
+        let header_end = loop {
+            let mut chunk = [0u8; 4096];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before headers");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos;
+            }
+        };
+        let head = String::from_utf8(buf[..header_end].to_vec()).unwrap();
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap().split(' ');
+        let method = request_line.next().unwrap().to_string();
+        let path = request_line.next().unwrap().to_string();
+        let headers = lines
+            .map(|line| {
+                let (name, value) = line.split_once(':').unwrap();
+                (name.to_ascii_lowercase(), value.trim().to_string())
+            })
+            .collect::<Vec<_>>();
+        let length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map_or(0, |(_, value)| value.parse::<usize>().unwrap());
+        let mut body = buf[header_end + 4..].to_vec();
+        while body.len() < length {
+            let mut chunk = [0u8; 4096];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before body");
+            body.extend_from_slice(&chunk[..n]);
+        }
+        socket
+            .write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}",
+            )
+            .await
+            .unwrap();
+        ReceivedRequest {
+            method,
+            path,
+            headers,
+            body,
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().fold(String::new(), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+    }
+
+    fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+            .as_ref()
+            .to_vec()
+    }
+
+    /// 按接收到的请求独立重算 `SigV4` 签名，返回 (规范 URI, 签名)
+    fn recompute_signature(request: &ReceivedRequest, secret: &str) -> (String, String) {
+        let authorization = request.header("authorization");
+        let field = |name: &str| {
+            authorization
+                .split([' ', ','])
+                .find_map(|part| part.strip_prefix(name))
+                .unwrap()
+                .to_string()
+        };
+        let credential = field("Credential=");
+        let signed_headers = field("SignedHeaders=");
+        let scope = credential.split_once('/').unwrap().1.to_string();
+        let scope_parts = scope.split('/').collect::<Vec<_>>();
+
+        // 路径只含 unreserved 字符与 `%XX`，再编码一次即把 `%` 换成 `%25`
+        let canonical_uri = request.path.replace('%', "%25");
+        let canonical_headers = signed_headers
+            .split(';')
+            .fold(String::new(), |mut out, name| {
+                let _ = writeln!(out, "{name}:{}", request.header(name));
+                out
+            });
+        let canonical_request = format!(
+            "{}\n{canonical_uri}\n\n{canonical_headers}\n{signed_headers}\n{}",
+            request.method,
+            hex(digest::digest(&digest::SHA256, &request.body).as_ref())
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+            request.header("x-amz-date"),
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+        let signing_key = scope_parts
+            .iter()
+            .fold(format!("AWS4{secret}").into_bytes(), |key, part| {
+                hmac_sha256(&key, part)
+            });
+        (
+            canonical_uri,
+            hex(&hmac_sha256(&signing_key, &string_to_sign)),
+        )
+    }
+
+    /// 测试 Anthropic 请求体转换为 Bedrock 请求体
+    #[test]
+    fn test_anthropic_request_to_bedrock() {
+        let request = json!({
+            "model": "us.anthropic.claude-sonnet-4-5-20250929-v1:0",
+            "stream": true,
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let (body, model, stream) = anthropic_request_to_bedrock(
+            request.to_string().as_bytes(),
+            Some("interleaved-thinking-2025-05-14, context-1m-2025-08-07"),
+        )
+        .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(model, "us.anthropic.claude-sonnet-4-5-20250929-v1:0");
+        assert!(stream);
+        assert!(body.get("model").is_none());
+        assert!(body.get("stream").is_none());
+        assert_eq!(body["anthropic_version"], "bedrock-2023-05-31");
+        assert_eq!(body["anthropic_beta"][1], "context-1m-2025-08-07");
+    }
+
+    /// 测试 event stream 帧还原为 Anthropic SSE，异常帧转换为 error 事件
+    #[test]
+    fn test_bedrock_stream_to_anthropic_sse() {
+        let chunk = |event: &Value| {
+            let payload = json!({ "bytes": STANDARD.encode(event.to_string()) });
+            encode_frame(
+                &[
+                    (":event-type", "chunk"),
+                    (":content-type", "application/json"),
+                    (":message-type", "event"),
+                ],
+                payload.to_string().as_bytes(),
+            )
+        };
+        let mut bytes = chunk(&json!({"type": "message_start", "message": {"id": "msg_1"}}));
+        bytes.extend(chunk(&json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": "Hi"}
+        })));
+        bytes.extend(encode_frame(
+            &[
+                (":exception-type", "throttlingException"),
+                (":message-type", "exception"),
+            ],
+            br#"{"message":"Too many requests"}"#,
+        ));
+
+        let mut converter = BedrockStreamConverter::default();
+        let mut out = Vec::new();
+        for part in bytes.chunks(10) {
+            out.extend(converter.convert(part));
+        }
+        out.extend(converter.finish());
+
+        let mut decoder = SseDecoder::default();
+        let events = out
+            .iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].event.as_deref(), Some("message_start"));
+        assert_eq!(events[1].json().unwrap()["delta"]["text"], "Hi");
+        assert_eq!(events[2].event.as_deref(), Some("error"));
+        assert_eq!(
+            events[2].json().unwrap()["error"]["message"],
+            "Too many requests"
+        );
+    }
+
+    /// 测试 Bedrock 错误响应转换为 Anthropic 错误格式
+    #[test]
+    fn test_error_body_to_anthropic() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-amzn-errortype",
+            "ValidationException:http://internal.amazon.com/coral/com.amazon.bedrock/"
+                .parse()
+                .unwrap(),
+        );
+        let body = error_body_to_anthropic(
+            StatusCode::BAD_REQUEST,
+            &headers,
+            br#"{"message":"max_tokens: field required"}"#,
+        );
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(body["error"]["message"], "max_tokens: field required");
+
+        let body = error_body_to_anthropic(StatusCode::FORBIDDEN, &HeaderMap::new(), b"denied");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "permission_error");
+    }
+
+    /// 测试发往本地监听器的请求可按收到的内容重算出相同签名，模型 id 中的冒号在规范 URI 中被二次编码
+    #[tokio::test]
+    async fn test_signed_request_verifies_on_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(receive_one(listener));
+
+        let secret = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        let upstream = UpstreamConfig {
+            mode: Mode::Bedrock,
+            endpoint: format!("http://{addr}"),
+            bedrock: Some(BedrockConfig {
+                region: Some("us-west-2".to_string()),
+                access_key_id: Some("AKIDEXAMPLE".to_string()),
+                secret_access_key: Some(secret.to_string()),
+                session_token: Some("session-token".to_string()),
+            }),
+            ..UpstreamConfig::default()
+        };
+        let body = json!({
+            "model": "us.anthropic.claude-sonnet-4-5-20250929-v1:0",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let mut client_headers = HeaderMap::new();
+        client_headers.insert("anthropic-beta", "context-1m-2025-08-07".parse().unwrap());
+        let request = build_request(
+            &upstream,
+            body.to_string().as_bytes(),
+            &client_headers,
+            false,
+        )
+        .unwrap();
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            "{}"
+        );
+
+        let received = server.await.unwrap();
+        assert_eq!(received.method, "POST");
+        assert_eq!(
+            received.path,
+            "/model/us.anthropic.claude-sonnet-4-5-20250929-v1%3A0/invoke"
+        );
+        assert_eq!(received.header("host"), addr.to_string());
+        let authorization = received.header("authorization");
+        assert!(authorization.contains("/us-west-2/bedrock/aws4_request"));
+        assert!(
+            authorization
+                .contains("SignedHeaders=accept;content-type;host;x-amz-date;x-amz-security-token")
+        );
+
+        let (canonical_uri, signature) = recompute_signature(&received, secret);
+        assert_eq!(
+            canonical_uri,
+            "/model/us.anthropic.claude-sonnet-4-5-20250929-v1%253A0/invoke"
+        );
+        assert!(authorization.ends_with(&format!("Signature={signature}")));
+
+        let sent: Value = serde_json::from_slice(&received.body).unwrap();
+        assert_eq!(sent["anthropic_beta"][0], "context-1m-2025-08-07");
+        assert!(sent.get("model").is_none());
+    }
+
+    /// 测试 `count_tokens` 请求改发 `CountTokens`，签名同样有效，响应转换为 Anthropic 格式
+    #[tokio::test]
+    async fn test_count_tokens_uses_count_tokens_api() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(receive_one(listener));
+
+        let secret = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        let upstream = UpstreamConfig {
+            mode: Mode::Bedrock,
+            endpoint: format!("http://{addr}"),
+            bedrock: Some(BedrockConfig {
+                region: Some("us-east-1".to_string()),
+                access_key_id: Some("AKIDEXAMPLE".to_string()),
+                secret_access_key: Some(secret.to_string()),
+                session_token: None,
+            }),
+            ..UpstreamConfig::default()
+        };
+        let body = json!({
+            "model": "anthropic.claude-sonnet-4-5-20250929-v1:0",
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let request = build_request(
+            &upstream,
+            body.to_string().as_bytes(),
+            &HeaderMap::new(),
+            true,
+        )
+        .unwrap();
+        let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+        client.request(request).await.unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            received.path,
+            "/model/anthropic.claude-sonnet-4-5-20250929-v1%3A0/count-tokens"
+        );
+        let (_, signature) = recompute_signature(&received, secret);
+        assert!(
+            received
+                .header("authorization")
+                .ends_with(&format!("Signature={signature}"))
+        );
+
+        let sent: Value = serde_json::from_slice(&received.body).unwrap();
+        let invoke_body = STANDARD
+            .decode(sent["input"]["invokeModel"]["body"].as_str().unwrap())
+            .unwrap();
+        let invoke_body: Value = serde_json::from_slice(&invoke_body).unwrap();
+        assert_eq!(invoke_body["anthropic_version"], "bedrock-2023-05-31");
+        assert_eq!(invoke_body["max_tokens"], 1);
+        assert_eq!(invoke_body["messages"], body["messages"]);
+        assert!(invoke_body.get("model").is_none());
+
+        let converted = count_tokens_response_to_anthropic(br#"{"inputTokens":42}"#).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&converted).unwrap(),
+            json!({"input_tokens": 42})
+        );
+        assert!(count_tokens_response_to_anthropic(b"{}").is_err());
+    }
+}
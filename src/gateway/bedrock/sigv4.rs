@@ -0,0 +1,236 @@
+//! AWS Signature Version 4
+//!
+//! 只实现 Bedrock 需要的部分：单个请求体一次性签名（不支持分块签名）。
+//! 规范见 <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv-create-signed-request.html>。
+
+use std::fmt::Write as _;
+
+use ring::{digest, hmac};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// AWS 访问凭证
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+/// 待签名的请求
+pub struct SignableRequest<'a> {
+    pub method: &'a str,
+    /// 实际发送的路径（已做过一次 URI 编码）
+    pub path: &'a str,
+    /// 实际发送的查询串（不含 `?`）
+    pub query: &'a str,
+    /// 参与签名的请求头，必须包含 `host`
+    pub headers: &'a [(String, String)],
+    pub payload: &'a [u8],
+}
+
+/// 签名参数
+pub struct SigningParams<'a> {
+    pub credentials: &'a AwsCredentials,
+    pub region: &'a str,
+    pub service: &'a str,
+    /// `YYYYMMDD'T'HHMMSS'Z'` 格式的 UTC 时间
+    pub amz_date: &'a str,
+}
+
+/// 计算签名，返回需要追加到请求上的头部
+///
+/// 返回的头部包括 `x-amz-date`、可选的 `x-amz-security-token` 以及 `authorization`；
+/// 除 `authorization` 外都参与签名。
+#[must_use]
+pub fn sign(request: &SignableRequest<'_>, params: &SigningParams<'_>) -> Vec<(String, String)> {
+    let payload_hash = hex(digest::digest(&digest::SHA256, request.payload).as_ref());
+
+    let mut added = vec![("x-amz-date".to_string(), params.amz_date.to_string())];
+    if let Some(token) = &params.credentials.session_token {
+        added.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+
+    let mut headers = request
+        .headers
+        .iter()
+        .chain(&added)
+        .map(|(name, value)| (name.to_ascii_lowercase(), normalize_header_value(value)))
+        .collect::<Vec<_>>();
+    headers.sort();
+    let canonical_headers = headers
+        .iter()
+        .fold(String::new(), |mut out, (name, value)| {
+            let _ = writeln!(out, "{name}:{value}");
+            out
+        });
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method,
+        canonical_uri(request.path),
+        canonical_query(request.query),
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+
+    let date = params.amz_date.get(..8).unwrap_or(params.amz_date);
+    let scope = format!("{date}/{}/{}/aws4_request", params.region, params.service);
+    let string_to_sign = format!(
+        "{ALGORITHM}\n{}\n{scope}\n{}",
+        params.amz_date,
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+    );
+
+    let secret = format!("AWS4{}", params.credentials.secret_access_key);
+    let signing_key = [date, params.region, params.service, "aws4_request"]
+        .iter()
+        .fold(secret.into_bytes(), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    added.push((
+        "authorization".to_string(),
+        format!(
+            "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            params.credentials.access_key_id
+        ),
+    ));
+    added
+}
+
+/// 当前 UTC 时间，`SigV4` 的 `x-amz-date` 格式
+#[must_use]
+pub fn amz_date_now() -> String {
+    chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// `SigV4` 的 URI 编码：除 unreserved 字符外全部编码为大写 `%XX`
+#[must_use]
+pub fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'_' | b'.' | b'~')
+            || (byte == b'/' && !encode_slash)
+        {
+            out.push(char::from(byte));
+        } else {
+            let _ = write!(out, "%{byte:02X}");
+        }
+    }
+    out
+}
+
+/// 非 S3 服务的规范 URI：对实际发送的路径再编码一次
+fn canonical_uri(path: &str) -> String {
+    if path.is_empty() {
+        "/".to_string()
+    } else {
+        uri_encode(path, false)
+    }
+}
+
+/// 规范查询串：按参数名、参数值排序
+fn canonical_query(query: &str) -> String {
+    let mut pairs = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// 去掉首尾空白并把连续空白压缩为一个空格
+fn normalize_header_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 AWS 文档中的 IAM `ListUsers` 签名示例
+    #[test]
+    fn test_sign_matches_aws_example() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let headers = [
+            (
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+            ),
+            ("Host".to_string(), "iam.amazonaws.com".to_string()),
+        ];
+        let request = SignableRequest {
+            method: "GET",
+            path: "/",
+            query: "Version=2010-05-08&Action=ListUsers",
+            headers: &headers,
+            payload: b"",
+        };
+        let params = SigningParams {
+            credentials: &credentials,
+            region: "us-east-1",
+            service: "iam",
+            amz_date: "20150830T123600Z",
+        };
+        let added = sign(&request, &params);
+        let authorization = &added.last().map(|(_, value)| value.as_str());
+
+        assert_eq!(
+            *authorization,
+            Some(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+                 SignedHeaders=content-type;host;x-amz-date, \
+                 Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+            )
+        );
+    }
+
+    /// 测试 Bedrock 模型 id 中的冒号在规范 URI 中被二次编码
+    #[test]
+    fn test_canonical_uri_double_encodes() {
+        let path = format!(
+            "/model/{}/invoke",
+            uri_encode("anthropic.claude-sonnet-4-5-20250929-v1:0", true)
+        );
+        assert_eq!(
+            path,
+            "/model/anthropic.claude-sonnet-4-5-20250929-v1%3A0/invoke"
+        );
+        assert_eq!(
+            canonical_uri(&path),
+            "/model/anthropic.claude-sonnet-4-5-20250929-v1%253A0/invoke"
+        );
+    }
+}
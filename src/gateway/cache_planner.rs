@@ -0,0 +1,433 @@
+//! `cache_control` 断点规划与缓存命中统计
+//!
+//! 注入的系统提示词带有一个 `cache_control`，之后的过滤会改写请求的其余部分，
+//! tools 与不断增长的消息历史上没有断点。转发给 Anthropic 格式的上游前，按以下位置重新规划
+//! （最多 [`MAX_BREAKPOINTS`] 个，Anthropic 的上限）：
+//! 1. tools 末尾：缓存工具定义
+//! 2. system 末尾：缓存工具定义 + 系统提示词
+//! 3. 最后一条消息：写入整个前缀，供下一轮读取
+//! 4. 倒数第二条 user 消息：读取上一轮写入的前缀（一轮新增的内容块较多时，
+//!    仅靠最后一条消息的断点回溯不到上一轮的位置）
+//!
+//! 客户端自带的标记在名额内保留（优先保留靠后的），超出上限的移除。
+//! 响应中的 usage 记入 [`RequestStats`]，在日志中报告缓存命中率。
+
+use std::sync::{Arc, atomic::Ordering};
+
+use bytes::Bytes;
+
+use crate::gateway::{
+    RequestStats,
+    anthropic::{
+        CacheControl, Content, ContentBlock, DeltaUsage, MessagesRequest, MessagesResponse, Role,
+        StreamEvent, ToolResultBlock, Usage,
+    },
+    pipeline::Transform,
+    sse::{SseDecoder, SseEvent, StreamConverter},
+};
+
+/// Anthropic 单个请求允许的 `cache_control` 断点数
+pub const MAX_BREAKPOINTS: usize = 4;
+
+/// 断点位置，按 tools → system → messages 的前缀顺序排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Slot {
+    Tool(usize),
+    System(usize),
+    /// (消息索引, 内容块索引)
+    Message(usize, usize),
+}
+
+/// 规划请求的 `cache_control` 断点
+pub struct CachePlanner;
+
+impl Transform for CachePlanner {
+    fn name(&self) -> &'static str {
+        "cache_planner"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        plan_cache_breakpoints(body)
+    }
+}
+
+fn plan_cache_breakpoints(request: &mut MessagesRequest) -> bool {
+    let mut changed = strip_nested_markers(request);
+    let existing = existing_markers(request);
+    // 沿用客户端标记的设置（如 ttl），避免混用不同 ttl 时顺序不合法
+    let template = existing
+        .first()
+        .and_then(|&slot| marker(request, slot))
+        .cloned()
+        .unwrap_or_else(CacheControl::ephemeral);
+
+    let planned = planned_slots(request);
+    let extra = existing
+        .iter()
+        .filter(|slot| !planned.contains(slot))
+        .copied()
+        .collect::<Vec<_>>();
+    let removed = extra
+        .len()
+        .saturating_sub(MAX_BREAKPOINTS.saturating_sub(planned.len()));
+    for &slot in &extra[..removed] {
+        if let Some(marker) = marker_mut(request, slot) {
+            *marker = None;
+            changed = true;
+        }
+    }
+    for &slot in &planned {
+        if let Some(marker) = marker_mut(request, slot)
+            && marker.is_none()
+        {
+            *marker = Some(template.clone());
+            changed = true;
+        }
+    }
+
+    if changed {
+        tracing::debug!(
+            "💾 缓存断点: 规划 {} 个, 移除多余标记 {} 个",
+            planned.len(),
+            removed
+        );
+    }
+    changed
+}
+
+/// 规划的断点位置（已排序去重）
+fn planned_slots(request: &MessagesRequest) -> Vec<Slot> {
+    let mut slots = Vec::with_capacity(MAX_BREAKPOINTS);
+    if let Some(last) = request.tools.len().checked_sub(1) {
+        slots.push(Slot::Tool(last));
+    }
+    if let Some(index) = request.system.as_ref().and_then(last_cacheable_block) {
+        slots.push(Slot::System(index));
+    }
+    let messages = &request.messages;
+    let last = messages.len().checked_sub(1);
+    let previous_user = last.and_then(|last| {
+        messages[..last]
+            .iter()
+            .rposition(|message| message.role == Role::User)
+    });
+    for index in [previous_user, last].into_iter().flatten() {
+        if let Some(block) = last_cacheable_block(&messages[index].content) {
+            slots.push(Slot::Message(index, block));
+        }
+    }
+    slots.sort_unstable();
+    slots.dedup();
+    slots
+}
+
+/// 最后一个可以加 `cache_control` 的内容块（thinking 块与空文本不可以）
+///
+/// 字符串形式的内容视为单个文本块，加断点时转换为数组。
+fn last_cacheable_block(content: &Content) -> Option<usize> {
+    match content {
+        Content::Text(text) => (!text.is_empty()).then_some(0),
+        Content::Blocks(blocks) => blocks.iter().rposition(|block| match block {
+            ContentBlock::Thinking(_) | ContentBlock::RedactedThinking(_) => false,
+            ContentBlock::Text(text) => !text.text.is_empty(),
+            _ => true,
+        }),
+    }
+}
+
+/// 已有 `cache_control` 标记的位置（按前缀顺序）
+fn existing_markers(request: &MessagesRequest) -> Vec<Slot> {
+    let marked = |blocks: &[ContentBlock]| {
+        blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.cache_control().is_some())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>()
+    };
+    let mut slots = request
+        .tools
+        .iter()
+        .enumerate()
+        .filter(|(_, tool)| tool.cache_control.is_some())
+        .map(|(index, _)| Slot::Tool(index))
+        .collect::<Vec<_>>();
+    if let Some(system) = &request.system {
+        slots.extend(marked(system.blocks()).into_iter().map(Slot::System));
+    }
+    for (index, message) in request.messages.iter().enumerate() {
+        slots.extend(
+            marked(message.content.blocks())
+                .into_iter()
+                .map(|block| Slot::Message(index, block)),
+        );
+    }
+    slots
+}
+
+/// 移除 `tool_result` 嵌套内容中的标记，断点只放在顶层内容块上
+fn strip_nested_markers(request: &mut MessagesRequest) -> bool {
+    let mut removed = false;
+    for block in request
+        .messages
+        .iter_mut()
+        .flat_map(|message| message.content.blocks_mut())
+    {
+        let ContentBlock::ToolResult(ToolResultBlock {
+            content: Some(content),
+            ..
+        }) = block
+        else {
+            continue;
+        };
+        for inner in content.blocks_mut() {
+            removed |= inner
+                .cache_control_mut()
+                .is_some_and(|marker| marker.take().is_some());
+        }
+    }
+    removed
+}
+
+fn marker(request: &MessagesRequest, slot: Slot) -> Option<&CacheControl> {
+    let (content, index) = match slot {
+        Slot::Tool(index) => return request.tools.get(index)?.cache_control.as_ref(),
+        Slot::System(index) => (request.system.as_ref()?, index),
+        Slot::Message(message, index) => (&request.messages.get(message)?.content, index),
+    };
+    content.blocks().get(index)?.cache_control()
+}
+
+fn marker_mut(request: &mut MessagesRequest, slot: Slot) -> Option<&mut Option<CacheControl>> {
+    let (content, index) = match slot {
+        Slot::Tool(index) => return Some(&mut request.tools.get_mut(index)?.cache_control),
+        Slot::System(index) => (request.system.as_mut()?, index),
+        Slot::Message(message, index) => (&mut request.messages.get_mut(message)?.content, index),
+    };
+    // 字符串内容转换为单个文本块
+    content.make_blocks().get_mut(index)?.cache_control_mut()
+}
+
+/// 记录一次响应的缓存用量并打印命中率
+pub fn record_usage(stats: &RequestStats, usage: &Usage) {
+    let read = usage.cache_read_input_tokens.unwrap_or(0);
+    let creation = usage.cache_creation_input_tokens.unwrap_or(0);
+    let uncached = usage.input_tokens;
+    if read + creation + uncached == 0 {
+        return;
+    }
+    stats
+        .cache_read_input_tokens
+        .fetch_add(read, Ordering::Relaxed);
+    stats
+        .cache_creation_input_tokens
+        .fetch_add(creation, Ordering::Relaxed);
+    stats
+        .uncached_input_tokens
+        .fetch_add(uncached, Ordering::Relaxed);
+
+    let total_read = stats.cache_read_input_tokens.load(Ordering::Relaxed);
+    let total = total_read
+        + stats.cache_creation_input_tokens.load(Ordering::Relaxed)
+        + stats.uncached_input_tokens.load(Ordering::Relaxed);
+    tracing::info!(
+        "💾 缓存 | 本次命中率: {:.1}% (读取 {} / 写入 {} / 未缓存 {}) | 累计命中率: {:.1}%",
+        hit_rate(read, read + creation + uncached),
+        read,
+        creation,
+        uncached,
+        hit_rate(total_read, total)
+    );
+}
+
+fn hit_rate(read: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        read as f64 * 100.0 / total as f64
+    }
+}
+
+/// 记录 Anthropic 非流式响应体中的缓存用量
+pub fn record_response_usage(stats: &RequestStats, body: &[u8]) {
+    if let Ok(response) = serde_json::from_slice::<MessagesResponse>(body) {
+        record_usage(stats, &response.usage);
+    }
+}
+
+/// 旁路读取 Anthropic SSE 流中的 usage，流结束时记录缓存用量；字节原样透传
+pub struct CacheUsageRecorder {
+    decoder: SseDecoder,
+    stats: Arc<RequestStats>,
+    usage: Usage,
+}
+
+impl CacheUsageRecorder {
+    #[must_use]
+    pub fn new(stats: Arc<RequestStats>) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            stats,
+            usage: Usage::default(),
+        }
+    }
+
+    /// `message_start` 带有输入用量，`message_delta` 可能带有最终的累计值
+    fn observe(&mut self, event: &SseEvent) {
+        match event.parse::<StreamEvent>() {
+            Some(StreamEvent::MessageStart { message }) => self.usage = message.usage,
+            Some(StreamEvent::MessageDelta { usage, .. }) => self.merge_delta(&usage),
+            _ => {}
+        }
+    }
+
+    /// 合并 `message_delta` 中非零的累计值
+    fn merge_delta(&mut self, delta: &DeltaUsage) {
+        let positive = |tokens: Option<u64>| tokens.filter(|&tokens| tokens > 0);
+        if let Some(input) = positive(delta.input_tokens) {
+            self.usage.input_tokens = input;
+        }
+        if let Some(creation) = positive(delta.cache_creation_input_tokens) {
+            self.usage.cache_creation_input_tokens = Some(creation);
+        }
+        if let Some(read) = positive(delta.cache_read_input_tokens) {
+            self.usage.cache_read_input_tokens = Some(read);
+        }
+    }
+}
+
+impl StreamConverter for CacheUsageRecorder {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        for event in self.decoder.feed(chunk) {
+            self.observe(&event);
+        }
+        vec![Bytes::copy_from_slice(chunk)]
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        if let Some(event) = self.decoder.finish() {
+            self.observe(&event);
+        }
+        record_usage(&self.stats, &self.usage);
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn plan(body: &Value) -> Value {
+        let mut request = serde_json::from_value(body.clone()).unwrap();
+        assert!(CachePlanner.apply(&mut request));
+        serde_json::to_value(&request).unwrap()
+    }
+
+    fn markers(body: &Value) -> usize {
+        body.to_string().matches("cache_control").count()
+    }
+
+    /// 测试在 tools、system、最后一条消息与上一条 user 消息末尾放置断点
+    #[test]
+    fn test_plan_breakpoints() {
+        let body = json!({
+            "tools": [{"name": "Read"}, {"name": "Bash"}],
+            "system": "You are helpful.",
+            "messages": [
+                {"role": "user", "content": "Fix the bug."},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "Read", "input": {}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]},
+                {"role": "assistant", "content": [{"type": "text", "text": "Done."}, {"type": "thinking", "thinking": "", "signature": "s"}]}
+            ]
+        });
+        let planned = plan(&body);
+        assert_eq!(markers(&planned), 4);
+        assert_eq!(planned["tools"][1]["cache_control"]["type"], "ephemeral");
+        assert!(planned["tools"][0].get("cache_control").is_none());
+        assert_eq!(planned["system"][0]["text"], "You are helpful.");
+        assert!(planned["system"][0].get("cache_control").is_some());
+        assert!(
+            planned["messages"][2]["content"][0]
+                .get("cache_control")
+                .is_some()
+        );
+        // thinking 块不能加断点，放在其前面的文本块上
+        assert!(
+            planned["messages"][3]["content"][0]
+                .get("cache_control")
+                .is_some()
+        );
+        assert!(planned["messages"][0]["content"].is_string());
+
+        // 已经规划过的请求不再修改
+        let mut request: MessagesRequest = serde_json::from_value(planned).unwrap();
+        assert!(!CachePlanner.apply(&mut request));
+    }
+
+    /// 测试超出上限的客户端标记被移除，保留靠后的标记并沿用其 ttl
+    #[test]
+    fn test_remove_extra_client_markers() {
+        let marker = json!({"type": "ephemeral", "ttl": "1h"});
+        let text = |text: &str| json!({"type": "text", "text": text, "cache_control": marker});
+        let body = json!({
+            "system": [text("injected"), text("a"), json!({"type": "text", "text": "b"})],
+            "messages": [
+                {"role": "user", "content": [text("one"), text("two")]},
+                {"role": "assistant", "content": "ok"},
+                {"role": "user", "content": "three"}
+            ]
+        });
+        let planned = plan(&body);
+        assert_eq!(markers(&planned), MAX_BREAKPOINTS);
+        assert!(planned["system"][0].get("cache_control").is_none());
+        assert!(planned["system"][1].get("cache_control").is_none());
+        assert_eq!(planned["system"][2]["cache_control"], marker);
+        // 剩余一个名额保留最靠后的客户端标记
+        assert_eq!(
+            planned["messages"][0]["content"][0]["cache_control"],
+            marker
+        );
+        assert_eq!(
+            planned["messages"][0]["content"][1]["cache_control"],
+            marker
+        );
+        assert_eq!(
+            planned["messages"][2]["content"][0]["cache_control"],
+            marker
+        );
+    }
+
+    /// 测试从流式与非流式响应中统计缓存命中
+    #[test]
+    fn test_record_cache_usage() {
+        let stats = Arc::new(RequestStats::default());
+        let mut recorder = CacheUsageRecorder::new(Arc::clone(&stats));
+        let stream = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":10,\"cache_read_input_tokens\":900,\"cache_creation_input_tokens\":90}}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":5}}\n\n"
+        );
+        let out = stream
+            .as_bytes()
+            .chunks(7)
+            .flat_map(|chunk| recorder.convert(chunk))
+            .collect::<Vec<_>>();
+        assert!(recorder.finish().is_empty());
+        assert_eq!(out.concat(), stream.as_bytes());
+        assert_eq!(stats.cache_read_input_tokens.load(Ordering::Relaxed), 900);
+
+        record_response_usage(
+            &stats,
+            br#"{"usage": {"input_tokens": 100, "cache_read_input_tokens": 0}}"#,
+        );
+        assert_eq!(stats.uncached_input_tokens.load(Ordering::Relaxed), 110);
+        assert_eq!(
+            stats.cache_creation_input_tokens.load(Ordering::Relaxed),
+            90
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//! 按上游能力调整请求（`[upstream.capabilities]`）
+//!
+//! 转发前把 Anthropic 格式的请求体调整为上游能处理的形态：
+//! - 不支持图片：图片块（含 `tool_result` 内的图片）替换为文字说明
+//! - 不支持 thinking：移除 `thinking` 参数与历史 thinking 块
+//! - 不支持工具：移除工具定义，历史 `tool_use` / `tool_result` 改写为文本
+//! - 不支持 `tool_choice: any`：`any` / 指定工具降级为 `auto`
+//! - 不支持 `stop_sequences`：移除该参数
+//! - 不支持提示词缓存：移除所有 `cache_control`
+//! - 工具数量与 `max_tokens` 按上限收紧
+//!
+//! 这些调整会丢失信息，因此选择 upstream 时先按 [`RequestNeeds`] 跳过无法完整处理请求的上游，
+//! 只有所有上游都不满足时才依赖调整兜底。
+
+use std::collections::HashSet;
+
+use crate::{
+    config::Capabilities,
+    gateway::{
+        anthropic::{
+            Content, ContentBlock, MessagesRequest, Role, TextBlock, ToolChoice, ToolChoiceKind,
+            ToolResultBlock,
+        },
+        pipeline::Transform,
+        quirks,
+        service::estimate_request_tokens,
+    },
+};
+
+/// 图片被移除后留下的说明
+const IMAGE_OMITTED_TEXT: &str = "[image omitted: the upstream model does not support image input]";
+/// thinking 块被移除后 assistant 消息为空时的占位文本
+const THINKING_OMITTED_TEXT: &str = "[thinking omitted]";
+
+/// 请求对上游能力的要求
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RequestNeeds {
+    /// 消息中含有图片
+    pub images: bool,
+    /// 请求定义了工具
+    pub tools: bool,
+    /// 估算的输入 token 数
+    pub input_tokens: u64,
+}
+
+impl RequestNeeds {
+    /// 分析 Anthropic 请求
+    #[must_use]
+    pub fn from_request(request: &MessagesRequest) -> Self {
+        Self {
+            images: request
+                .messages
+                .iter()
+                .any(|message| has_image(message.content.blocks())),
+            tools: !request.tools.is_empty(),
+            input_tokens: estimate_request_tokens(request),
+        }
+    }
+
+    /// 上游能否在不丢失信息的情况下处理请求
+    #[must_use]
+    pub fn served_by(&self, capabilities: &Capabilities) -> bool {
+        let vision = capabilities.vision || !self.images;
+        let tools = capabilities.tools || !self.tools;
+        let context = capabilities
+            .context_window
+            .is_none_or(|window| self.input_tokens <= window);
+        vision && tools && context
+    }
+}
+
+/// 内容块中是否含有图片（含 `tool_result` 内的图片）
+fn has_image(content: &[ContentBlock]) -> bool {
+    content.iter().any(|block| match block {
+        ContentBlock::Image(_) => true,
+        ContentBlock::ToolResult(ToolResultBlock {
+            content: Some(inner),
+            ..
+        }) => has_image(inner.blocks()),
+        _ => false,
+    })
+}
+
+/// 按上游能力调整请求体
+pub struct CapabilityAdapter<'a>(pub &'a Capabilities);
+
+impl Transform for CapabilityAdapter<'_> {
+    fn name(&self) -> &'static str {
+        "capability_adapter"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        *self.0 != Capabilities::default() && adapt_request(body, self.0)
+    }
+}
+
+fn adapt_request(request: &mut MessagesRequest, capabilities: &Capabilities) -> bool {
+    let mut adjusted = Vec::new();
+    if !capabilities.vision && strip_images(request) {
+        adjusted.push("images");
+    }
+    if !capabilities.thinking && strip_thinking(request) {
+        adjusted.push("thinking");
+    }
+    if !capabilities.tools && strip_tools(request) {
+        adjusted.push("tools");
+    }
+    if !capabilities.tool_choice_any && relax_tool_choice(request) {
+        adjusted.push("tool_choice");
+    }
+    if !capabilities.stop_sequences && request.stop_sequences.take().is_some() {
+        adjusted.push("stop_sequences");
+    }
+    if let Some(max_tools) = capabilities.max_tools
+        && limit_tools(request, max_tools)
+    {
+        adjusted.push("max_tools");
+    }
+    if let Some(cap) = capabilities.max_output_tokens
+        && quirks::cap_max_tokens(request, cap)
+    {
+        adjusted.push("max_tokens");
+    }
+    if !capabilities.prompt_caching && quirks::strip_cache_control(request) {
+        adjusted.push("cache_control");
+    }
+
+    if adjusted.is_empty() {
+        return false;
+    }
+    tracing::info!("🧩 按上游能力调整请求: {}", adjusted.join(", "));
+    true
+}
+
+/// 遍历所有消息的内容块数组（字符串内容没有需要调整的块）
+fn contents_mut(
+    request: &mut MessagesRequest,
+) -> impl Iterator<Item = (&Role, &mut Vec<ContentBlock>)> {
+    request
+        .messages
+        .iter_mut()
+        .filter_map(|message| match &mut message.content {
+            Content::Blocks(blocks) => Some((&message.role, blocks)),
+            Content::Text(_) => None,
+        })
+}
+
+/// 图片块替换为文字说明（保留其 `cache_control`）
+fn strip_images(request: &mut MessagesRequest) -> bool {
+    fn replace(content: &mut [ContentBlock]) -> bool {
+        let mut changed = false;
+        for block in content {
+            match block {
+                ContentBlock::Image(image) => {
+                    let mut text = TextBlock::new(IMAGE_OMITTED_TEXT);
+                    text.cache_control = image.cache_control.take();
+                    *block = ContentBlock::Text(text);
+                    changed = true;
+                }
+                ContentBlock::ToolResult(ToolResultBlock {
+                    content: Some(inner),
+                    ..
+                }) => {
+                    changed |= replace(inner.blocks_mut());
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+    contents_mut(request).fold(false, |changed, (_, content)| replace(content) || changed)
+}
+
+/// 移除 `thinking` 参数与历史 assistant 消息中的 thinking 块
+fn strip_thinking(request: &mut MessagesRequest) -> bool {
+    let mut changed = request.thinking.take().is_some();
+    for (role, content) in contents_mut(request) {
+        if *role != Role::Assistant {
+            continue;
+        }
+        let before = content.len();
+        content.retain(|block| {
+            !matches!(
+                block,
+                ContentBlock::Thinking(_) | ContentBlock::RedactedThinking(_)
+            )
+        });
+        if content.len() != before {
+            changed = true;
+            if content.is_empty() {
+                content.push(ContentBlock::text(THINKING_OMITTED_TEXT));
+            }
+        }
+    }
+    changed
+}
+
+/// 移除工具定义，历史中的工具调用与结果改写为文本
+fn strip_tools(request: &mut MessagesRequest) -> bool {
+    let mut changed = !std::mem::take(&mut request.tools).is_empty();
+    changed |= request.tool_choice.take().is_some();
+    for (_, content) in contents_mut(request) {
+        for block in content.iter_mut() {
+            let text = match block {
+                ContentBlock::ToolUse(tool_use) => {
+                    format!("[tool call {}: {}]", tool_use.name, tool_use.input)
+                }
+                ContentBlock::ToolResult(result) => {
+                    let label = if result.is_error() {
+                        "tool error"
+                    } else {
+                        "tool result"
+                    };
+                    format!("[{label}]\n{}", tool_result_text(result.content.as_ref()))
+                }
+                _ => continue,
+            };
+            *block = ContentBlock::text(text);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// `tool_result.content`（字符串或块数组）→ 文本
+fn tool_result_text(content: Option<&Content>) -> String {
+    match content {
+        Some(Content::Text(text)) => text.clone(),
+        Some(Content::Blocks(blocks)) => blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text(text) => text.text.as_str(),
+                ContentBlock::Image(_) => "[image]",
+                _ => "",
+            })
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => String::new(),
+    }
+}
+
+/// `tool_choice` 的 `any` / 指定工具降级为 `auto`（保留 `disable_parallel_tool_use`）
+fn relax_tool_choice(request: &mut MessagesRequest) -> bool {
+    let Some(tool_choice) = request.tool_choice.as_mut() else {
+        return false;
+    };
+    if !matches!(tool_choice.kind, ToolChoiceKind::Any | ToolChoiceKind::Tool) {
+        return false;
+    }
+    tool_choice.kind = ToolChoiceKind::Auto;
+    tool_choice.name = None;
+    true
+}
+
+/// 工具数量限制在 `max_tools` 以内：优先保留对话中调用过的工具，其余按原顺序保留
+fn limit_tools(request: &mut MessagesRequest, max_tools: usize) -> bool {
+    if request.tools.len() <= max_tools {
+        return false;
+    }
+    let used = request
+        .messages
+        .iter()
+        .flat_map(|message| message.content.blocks())
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse(tool_use) => Some(tool_use.name.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let tools = &mut request.tools;
+
+    let pinned = tools
+        .iter()
+        .filter(|tool| used.contains(&tool.name))
+        .count();
+    let mut remaining = max_tools.saturating_sub(pinned);
+    tools.retain(|tool| {
+        if used.contains(&tool.name) {
+            return true;
+        }
+        let keep = remaining > 0;
+        remaining = remaining.saturating_sub(1);
+        keep
+    });
+    tools.truncate(max_tools);
+
+    if tools.is_empty() {
+        request.tool_choice = None;
+    } else if let Some(name) = request
+        .tool_choice
+        .as_ref()
+        .and_then(|tool_choice| tool_choice.name.as_deref())
+        && !tools.iter().any(|tool| tool.name == name)
+    {
+        // 指定的工具被移除时退回由模型自行选择
+        request.tool_choice = Some(ToolChoice::new(ToolChoiceKind::Auto));
+    }
+    true
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn adapt(body: &Value, capabilities: &Capabilities) -> Value {
+        let mut request = serde_json::from_value(body.clone()).unwrap();
+        assert!(CapabilityAdapter(capabilities).apply(&mut request));
+        serde_json::to_value(&request).unwrap()
+    }
+
+    fn image() -> Value {
+        json!({"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo"}})
+    }
+
+    /// 测试不支持图片与 thinking 时的调整
+    #[test]
+    fn test_adapt_vision_and_thinking() {
+        let body = json!({
+            "max_tokens": 32000,
+            "thinking": {"type": "enabled", "budget_tokens": 16000},
+            "messages": [
+                {"role": "user", "content": [image(), {"type": "text", "text": "What is this?"}]},
+                {"role": "assistant", "content": [{"type": "thinking", "thinking": "Hmm", "signature": "sig"}, {"type": "tool_use", "id": "t1", "name": "Read", "input": {}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": [image()]}]}
+            ]
+        });
+        let capabilities = Capabilities {
+            vision: false,
+            thinking: false,
+            max_output_tokens: Some(8192),
+            ..Capabilities::default()
+        };
+        let adapted = adapt(&body, &capabilities);
+        assert_eq!(
+            adapted["messages"][0]["content"][0]["text"],
+            IMAGE_OMITTED_TEXT
+        );
+        assert_eq!(
+            adapted["messages"][2]["content"][0]["content"][0]["text"],
+            IMAGE_OMITTED_TEXT
+        );
+        assert!(adapted.get("thinking").is_none());
+        assert_eq!(adapted["messages"][1]["content"][0]["type"], "tool_use");
+        assert_eq!(adapted["max_tokens"], 8192);
+
+        // 能力未受限时不修改
+        let mut request = serde_json::from_value(body).unwrap();
+        assert!(!CapabilityAdapter(&Capabilities::default()).apply(&mut request));
+    }
+
+    /// 测试工具相关的调整：移除工具、降级 `tool_choice`、限制工具数量
+    #[test]
+    fn test_adapt_tools() {
+        let body = json!({
+            "tools": [{"name": "Bash"}, {"name": "Read"}, {"name": "Grep"}, {"name": "Edit"}],
+            "tool_choice": {"type": "tool", "name": "Edit", "disable_parallel_tool_use": true},
+            "stop_sequences": ["END"],
+            "messages": [
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "Grep", "input": {"pattern": "fn"}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "is_error": true, "content": "no matches"}]}
+            ]
+        });
+
+        let adapted = adapt(
+            &body,
+            &Capabilities {
+                tools: false,
+                stop_sequences: false,
+                ..Capabilities::default()
+            },
+        );
+        assert!(adapted.get("tools").is_none());
+        assert!(adapted.get("tool_choice").is_none());
+        assert!(adapted.get("stop_sequences").is_none());
+        assert_eq!(
+            adapted["messages"][0]["content"][0]["text"],
+            r#"[tool call Grep: {"pattern":"fn"}]"#
+        );
+        assert_eq!(
+            adapted["messages"][1]["content"][0]["text"],
+            "[tool error]\nno matches"
+        );
+
+        let adapted = adapt(
+            &body,
+            &Capabilities {
+                tool_choice_any: false,
+                max_tools: Some(2),
+                ..Capabilities::default()
+            },
+        );
+        let names = adapted["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Bash", "Grep"]);
+        assert_eq!(
+            adapted["tool_choice"],
+            json!({"type": "auto", "disable_parallel_tool_use": true})
+        );
+    }
+
+    /// 测试请求需求分析与上游能否处理的判断
+    #[test]
+    fn test_request_needs() {
+        let body = json!({
+            "system": "You are helpful.",
+            "tools": [{"name": "Read"}],
+            "messages": [{"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": [image()]}]}]
+        });
+        let needs = RequestNeeds::from_request(&serde_json::from_value(body).unwrap());
+        assert!(needs.images);
+        assert!(needs.tools);
+
+        assert!(needs.served_by(&Capabilities::default()));
+        assert!(!needs.served_by(&Capabilities {
+            vision: false,
+            ..Capabilities::default()
+        }));
+        assert!(!needs.served_by(&Capabilities {
+            context_window: Some(needs.input_tokens - 1),
+            ..Capabilities::default()
+        }));
+    }
+}
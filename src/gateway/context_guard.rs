@@ -0,0 +1,377 @@
+//! 上下文窗口守卫（`[upstream.capabilities] context_window`）
+//!
+//! Claude CLI 按 200k 的上下文窗口压缩会话，窗口更小的上游会在长会话中途报
+//! "context length exceeded"。转发前按代理自身的 token 估算检查请求，超出时依次裁剪：
+//! 1. 较早的 `tool_result` 内容（从最旧的开始，保留 `tool_use_id` 使调用与结果仍然配对）
+//! 2. 较早 assistant 消息中的 thinking 块（最后一条 assistant 消息保留）
+//! 3. 整轮的早期对话：保留首条 user 消息，成对移除其后的 assistant / user 消息
+//!
+//! 裁剪后仍放不下时不再请求上游，直接返回 Anthropic 格式的 `invalid_request_error`，
+//! 信息与 Anthropic 的 "prompt is too long" 一致，Claude CLI 据此触发会话压缩。
+
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::{
+    config::Capabilities,
+    gateway::{
+        anthropic::{Content, ContentBlock, ErrorResponse, MessagesRequest, Role},
+        quirks,
+        service::{estimate_block_tokens, estimate_message_tokens, estimate_request_tokens},
+    },
+};
+
+/// 被裁剪的 `tool_result` 留下的说明
+const TRIMMED_TOOL_RESULT_TEXT: &str = "[tool result trimmed to fit the context window]";
+/// thinking 块被移除后 assistant 消息为空时的占位文本
+const THINKING_OMITTED_TEXT: &str = "[thinking omitted]";
+/// 内容不超过该 token 数的 `tool_result` 不值得裁剪
+const MIN_TRIM_TOKENS: u64 = 64;
+/// 为首条 user 消息末尾的裁剪说明预留的 token 数
+const NOTE_TOKENS: u64 = 32;
+
+/// 裁剪后仍超出上下文窗口
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContextOverflow {
+    /// 裁剪后估算的输入 token 数
+    pub input_tokens: u64,
+    /// 可用于输入的 token 数（上下文窗口减去为输出预留的部分）
+    pub limit: u64,
+}
+
+impl fmt::Display for ContextOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prompt is too long: {} tokens > {} maximum",
+            self.input_tokens, self.limit
+        )
+    }
+}
+
+impl ContextOverflow {
+    /// Anthropic 格式的错误响应体
+    #[must_use]
+    pub fn error_body(&self) -> Bytes {
+        let error = ErrorResponse::new("invalid_request_error", self.to_string());
+        Bytes::from(serde_json::to_vec(&error).unwrap_or_default())
+    }
+}
+
+/// 各阶段的裁剪数量，用于日志
+#[derive(Default)]
+struct Trimmed {
+    tool_results: usize,
+    thinking: usize,
+    messages: usize,
+}
+
+/// 检查请求是否放得下上游的上下文窗口，必要时裁剪历史
+///
+/// 直接改写请求，返回 `Ok(true)` 表示有裁剪；返回错误时请求已部分裁剪，不应再转发。
+/// 输入与 `max_tokens` 之和超出窗口时 `max_tokens` 随之收紧。
+pub fn fit_context_window(
+    request: &mut MessagesRequest,
+    capabilities: &Capabilities,
+) -> Result<bool, ContextOverflow> {
+    let Some(window) = capabilities.context_window else {
+        return Ok(false);
+    };
+    let before = estimate_request_tokens(request);
+    // 为输出预留 max_tokens，最多预留窗口的四分之一，其余给输入
+    let max_tokens = request.max_tokens.unwrap_or(0);
+    let limit = window - max_tokens.min(window / 4);
+    let mut total = before;
+    let mut trimmed = Trimmed::default();
+    if total > limit {
+        total = trim_tool_results(request, total, limit, &mut trimmed);
+    }
+    if total > limit {
+        total = trim_thinking(request, total, limit, &mut trimmed);
+    }
+    if total > limit {
+        total = trim_early_turns(request, total, limit, &mut trimmed);
+    }
+    if total > limit {
+        tracing::warn!(
+            "✂️ 上下文超出 upstream 窗口 ({} tokens)：裁剪后估算 {} tokens，仍超出 {}",
+            window,
+            total,
+            limit
+        );
+        return Err(ContextOverflow {
+            input_tokens: total,
+            limit,
+        });
+    }
+
+    let trimmed_any = trimmed.tool_results + trimmed.thinking + trimmed.messages > 0;
+    if trimmed_any {
+        tracing::info!(
+            "✂️ 上下文超出 upstream 窗口 ({} tokens)：估算 {} → {} tokens，裁剪 tool_result {} 个、thinking {} 个、早期消息 {} 条",
+            window,
+            before,
+            total,
+            trimmed.tool_results,
+            trimmed.thinking,
+            trimmed.messages
+        );
+    }
+    let capped = total + max_tokens > window && quirks::cap_max_tokens(request, window - total);
+    Ok(trimmed_any || capped)
+}
+
+/// 从最旧的消息开始把 `tool_result` 内容替换为说明（最后一条消息除外）
+fn trim_tool_results(
+    request: &mut MessagesRequest,
+    mut total: u64,
+    limit: u64,
+    trimmed: &mut Trimmed,
+) -> u64 {
+    let last = request.messages.len().saturating_sub(1);
+    for message in &mut request.messages[..last] {
+        if message.role != Role::User {
+            continue;
+        }
+        for block in message.content.blocks_mut() {
+            if total <= limit {
+                return total;
+            }
+            let tokens = estimate_block_tokens(block);
+            let ContentBlock::ToolResult(result) = block else {
+                continue;
+            };
+            if tokens <= MIN_TRIM_TOKENS {
+                continue;
+            }
+            result.content = Some(Content::from(TRIMMED_TOOL_RESULT_TEXT));
+            total = total.saturating_sub(tokens) + estimate_block_tokens(block);
+            trimmed.tool_results += 1;
+        }
+    }
+    total
+}
+
+/// 从最旧的 assistant 消息开始移除 thinking 块（最后一条 assistant 消息保留）
+fn trim_thinking(
+    request: &mut MessagesRequest,
+    mut total: u64,
+    limit: u64,
+    trimmed: &mut Trimmed,
+) -> u64 {
+    let messages = &mut request.messages;
+    let Some(last_assistant) = messages
+        .iter()
+        .rposition(|message| message.role == Role::Assistant)
+    else {
+        return total;
+    };
+    for message in &mut messages[..last_assistant] {
+        if total <= limit {
+            break;
+        }
+        if message.role != Role::Assistant {
+            continue;
+        }
+        let before = estimate_message_tokens(message);
+        let Content::Blocks(content) = &mut message.content else {
+            continue;
+        };
+        let count = content.len();
+        content.retain(|block| {
+            !matches!(
+                block,
+                ContentBlock::Thinking(_) | ContentBlock::RedactedThinking(_)
+            )
+        });
+        if content.len() == count {
+            continue;
+        }
+        trimmed.thinking += count - content.len();
+        if content.is_empty() {
+            content.push(ContentBlock::text(THINKING_OMITTED_TEXT));
+        }
+        total = total.saturating_sub(before) + estimate_message_tokens(message);
+    }
+    total
+}
+
+/// 保留首条 user 消息，成对移除其后最早的 assistant / user 消息
+///
+/// 一对消息中 assistant 的 `tool_use` 与紧随其后 user 消息的 `tool_result` 一起移除，
+/// 剩余消息仍保持 user / assistant 交替且调用与结果配对。
+fn trim_early_turns(
+    request: &mut MessagesRequest,
+    mut total: u64,
+    limit: u64,
+    trimmed: &mut Trimmed,
+) -> u64 {
+    let messages = &mut request.messages;
+    if messages.first().map(|message| &message.role) != Some(&Role::User) {
+        return total;
+    }
+    let mut end = 1;
+    while total + NOTE_TOKENS > limit
+        && end + 2 < messages.len()
+        && messages[end].role == Role::Assistant
+        && messages[end + 1].role == Role::User
+    {
+        total = total.saturating_sub(
+            estimate_message_tokens(&messages[end]) + estimate_message_tokens(&messages[end + 1]),
+        );
+        end += 2;
+    }
+    if end == 1 {
+        return total;
+    }
+    let removed = end - 1;
+    messages.drain(1..end);
+    trimmed.messages += removed;
+
+    // 在首条 user 消息末尾说明有消息被裁剪
+    let first = &mut messages[0];
+    let before = estimate_message_tokens(first);
+    first.content.make_blocks().push(ContentBlock::text(format!(
+        "[{removed} earlier messages were trimmed to fit the context window]"
+    )));
+    total.saturating_sub(before) + estimate_message_tokens(first)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn capabilities(context_window: u64) -> Capabilities {
+        Capabilities {
+            context_window: Some(context_window),
+            ..Capabilities::default()
+        }
+    }
+
+    fn request(body: &Value) -> MessagesRequest {
+        serde_json::from_value(body.clone()).unwrap()
+    }
+
+    fn fit(body: &Value, context_window: u64) -> Result<Option<Value>, ContextOverflow> {
+        let mut request = request(body);
+        fit_context_window(&mut request, &capabilities(context_window))
+            .map(|trimmed| trimmed.then(|| serde_json::to_value(&request).unwrap()))
+    }
+
+    fn tool_turn(id: &str, output: &str, thinking: bool) -> [Value; 2] {
+        let mut assistant = vec![
+            json!({"type": "text", "text": "y".repeat(400)}),
+            json!({"type": "tool_use", "id": id, "name": "Read", "input": {}}),
+        ];
+        if thinking {
+            assistant.insert(
+                0,
+                json!({"type": "thinking", "thinking": "x".repeat(2000), "signature": "sig"}),
+            );
+        }
+        [
+            json!({"role": "assistant", "content": assistant}),
+            json!({"role": "user", "content": [{"type": "tool_result", "tool_use_id": id, "content": output}]}),
+        ]
+    }
+
+    fn conversation(output: &str, thinking: bool) -> Value {
+        let mut messages = vec![json!({"role": "user", "content": "Fix the bug."})];
+        for index in 0..4 {
+            messages.extend(tool_turn(&format!("t{index}"), output, thinking));
+        }
+        json!({"max_tokens": 32000, "messages": messages})
+    }
+
+    /// 测试未超出窗口时不修改请求，未配置窗口时跳过
+    #[test]
+    fn test_fits_unchanged() {
+        let body = conversation("ok", false);
+        assert_eq!(fit(&body, 200_000), Ok(None));
+        assert_eq!(
+            fit_context_window(&mut request(&body), &Capabilities::default()),
+            Ok(false)
+        );
+    }
+
+    /// 测试优先裁剪最旧的 `tool_result`，最后一条消息保留，`max_tokens` 随窗口收紧
+    #[test]
+    fn test_trims_oldest_tool_results_first() {
+        let body = conversation(&"line\n".repeat(2000), true);
+        let total = estimate_request_tokens(&request(&body));
+        let fitted = fit(&body, total).unwrap().unwrap();
+        let messages = fitted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 9);
+        assert_eq!(
+            messages[2]["content"][0]["content"],
+            TRIMMED_TOOL_RESULT_TEXT
+        );
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "t0");
+        assert_ne!(
+            messages[8]["content"][0]["content"],
+            TRIMMED_TOOL_RESULT_TEXT
+        );
+        // thinking 未被裁剪
+        assert_eq!(messages[1]["content"][0]["type"], "thinking");
+        assert!(fitted["max_tokens"].as_u64().unwrap() < 32000);
+    }
+
+    /// 测试 `tool_result` 不足以裁剪时移除旧 thinking，再成对移除早期消息
+    #[test]
+    fn test_trims_thinking_then_early_turns() {
+        let body = conversation("ok", true);
+        let total = estimate_request_tokens(&request(&body));
+        let fitted = fit(&body, total * 4 / 5).unwrap().unwrap();
+        let messages = fitted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 9);
+        assert_eq!(messages[1]["content"][0]["type"], "text");
+        assert_eq!(messages[7]["content"][0]["type"], "thinking");
+
+        let fitted = fit(&body, total / 2).unwrap().unwrap();
+        let messages = fitted["messages"].as_array().unwrap();
+        assert!(messages.len() < 9);
+        assert_eq!(messages.len() % 2, 1);
+        assert_eq!(messages[0]["content"][0]["text"], "Fix the bug.");
+        assert!(
+            messages[0]["content"][1]["text"]
+                .as_str()
+                .unwrap()
+                .contains("earlier messages were trimmed")
+        );
+        // 剩余的 tool_use 与 tool_result 仍然配对
+        for pair in messages[1..].chunks(2) {
+            let id = pair[0]["content"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|block| block["type"] == "tool_use")
+                .unwrap()["id"]
+                .clone();
+            assert_eq!(pair[1]["content"][0]["tool_use_id"], id);
+        }
+    }
+
+    /// 测试裁剪后仍超出窗口时返回 Anthropic 格式的错误
+    #[test]
+    fn test_overflow_error() {
+        let body = json!({
+            "max_tokens": 1000,
+            "messages": [{"role": "user", "content": "x".repeat(40_000)}]
+        });
+        let overflow = fit(&body, 8000).unwrap_err();
+        assert_eq!(overflow.limit, 7000);
+        let error: Value = serde_json::from_slice(&overflow.error_body()).unwrap();
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["error"]["type"], "invalid_request_error");
+        assert!(
+            error["error"]["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("prompt is too long: ")
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Google Gemini 原生 API 与 Anthropic Claude API 格式双向转换（`mode = "gemini"`）
+//!
+//! 功能：
+//! - Claude CLI 请求 → Gemini `generateContent` 请求
+//! - Gemini 响应 → Claude CLI 响应
+//! - Gemini `streamGenerateContent` SSE 事件 → Claude CLI 流式事件
+//!
+//! 请求发往 `{endpoint}/models/{model}:generateContent`（流式为 `:streamGenerateContent?alt=sse`），
+//! 使用 `x-goog-api-key` 鉴权；错误响应与 Vertex AI 同为 Google 风格。
+
+mod request;
+mod response;
+mod stream;
+
+use bytes::Bytes;
+use http::Method;
+use http_body_util::Full;
+use hyper::Request as HyperRequest;
+
+pub use self::{
+    request::anthropic_request_to_gemini, response::gemini_response_to_anthropic,
+    stream::GeminiStreamConverter,
+};
+use crate::{config::UpstreamConfig, gateway::openai_compat::ToolAliases};
+
+/// `endpoint` 留空时使用的 Gemini API 地址
+const DEFAULT_ENDPOINT: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// 转换请求体并构建发往 Gemini 的请求
+///
+/// 返回请求与工具名别名表（响应转换时还原函数名）。
+pub fn build_request(
+    upstream: &UpstreamConfig,
+    body: &[u8],
+    api_key: &str,
+) -> Result<(HyperRequest<Full<Bytes>>, ToolAliases), String> {
+    let converted = anthropic_request_to_gemini(body, upstream)?;
+    let body = serde_json::to_vec(&converted.body)
+        .map_err(|e| format!("Failed to serialize request: {e}"))?;
+    tracing::debug!("🔄 请求体格式转换: Claude → Gemini ({} bytes)", body.len());
+
+    let base = if upstream.endpoint.is_empty() {
+        DEFAULT_ENDPOINT
+    } else {
+        upstream.endpoint.trim_end_matches('/')
+    };
+    let action = if converted.stream {
+        "streamGenerateContent?alt=sse"
+    } else {
+        "generateContent"
+    };
+    let url = format!("{base}/models/{}:{action}", converted.model);
+    let host = base
+        .strip_prefix("https://")
+        .or_else(|| base.strip_prefix("http://"))
+        .unwrap_or(base)
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    tracing::info!("Proxying to: {} (gemini)", url);
+
+    let request = HyperRequest::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .header("host", host)
+        .header("content-type", "application/json")
+        .header("x-goog-api-key", api_key)
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|e| format!("failed to build request: {e}"))?;
+    Ok((request, converted.aliases))
+}
@@ -0,0 +1,453 @@
+//! 请求格式转换
+//!
+//! Anthropic Claude 请求 → Gemini `generateContent` 请求
+//!
+//! 主要转换：
+//! - system → `systemInstruction`
+//! - messages[] → contents[]（assistant → `model`），内容块 → parts
+//! - `tool_use` → `functionCall`，`tool_result` → `functionResponse`
+//! - image / document → `inlineData`（URL 图片 → `fileData`）
+//! - tools → `functionDeclarations`，`tool_choice` → `toolConfig`
+//! - `max_tokens` / `temperature` 等 → `generationConfig`，thinking → `thinkingConfig`
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    config::{SchemaProfile, UpstreamConfig},
+    gateway::{
+        anthropic::{
+            Content, ContentBlock, MediaBlock, Message, MessagesRequest, Role, ThinkingKind, Tool,
+            ToolChoice, ToolChoiceKind, ToolResultBlock,
+        },
+        openai_compat::{ToolAliases, is_server_tool, sanitize_tool_schema},
+    },
+};
+
+/// 转换结果
+pub struct GeminiRequest {
+    pub body: Value,
+    pub model: String,
+    pub stream: bool,
+    pub aliases: ToolAliases,
+}
+
+/// Anthropic Claude 请求 → Gemini `generateContent` 请求
+pub fn anthropic_request_to_gemini(
+    body: &[u8],
+    upstream: &UpstreamConfig,
+) -> Result<GeminiRequest, String> {
+    let request: MessagesRequest = serde_json::from_slice(body)
+        .map_err(|_| "Request body must be a JSON object.".to_string())?;
+    if request.model.is_empty() {
+        return Err("Request must include model.".to_string());
+    }
+    if request.messages.is_empty() {
+        return Err("Request must include messages.".to_string());
+    }
+
+    let aliases = ToolAliases::from_request(&request);
+    let tool_names = collect_tool_use_names(&request.messages);
+    let contents = request
+        .messages
+        .iter()
+        .filter_map(|message| claude_message_to_content(message, &aliases, &tool_names))
+        .collect::<Vec<_>>();
+
+    let mut out = Map::new();
+    out.insert("contents".to_string(), Value::Array(contents));
+    if let Some(text) = request.system.as_ref().and_then(claude_system_to_text) {
+        out.insert(
+            "systemInstruction".to_string(),
+            json!({ "parts": [{ "text": text }] }),
+        );
+    }
+    // Gemini 只接受 OpenAPI 子集，未单独配置时按 gemini profile 清洗
+    let profile = match upstream.schema_profile {
+        SchemaProfile::Permissive => SchemaProfile::Gemini,
+        profile => profile,
+    };
+    let declarations = map_tools(&request.tools, &aliases, profile);
+    if !declarations.is_empty() {
+        out.insert(
+            "tools".to_string(),
+            json!([{ "functionDeclarations": declarations }]),
+        );
+        if let Some(tool_config) = request
+            .tool_choice
+            .as_ref()
+            .and_then(|choice| map_tool_choice(choice, &aliases))
+        {
+            out.insert("toolConfig".to_string(), tool_config);
+        }
+    }
+    out.insert(
+        "generationConfig".to_string(),
+        Value::Object(generation_config(&request)),
+    );
+
+    Ok(GeminiRequest {
+        body: Value::Object(out),
+        stream: request.is_stream(),
+        model: request.model,
+        aliases,
+    })
+}
+
+/// `tool_use_id` → 工具名（`functionResponse` 需要函数名而不是调用 id）
+fn collect_tool_use_names(messages: &[Message]) -> HashMap<&str, &str> {
+    messages
+        .iter()
+        .flat_map(|message| message.content.blocks())
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse(tool_use) => Some((tool_use.id.as_str(), tool_use.name.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn claude_message_to_content(
+    message: &Message,
+    aliases: &ToolAliases,
+    tool_names: &HashMap<&str, &str>,
+) -> Option<Value> {
+    let role = match message.role {
+        Role::Assistant => "model",
+        Role::User => "user",
+        Role::Other(_) => return None,
+    };
+    let parts = match &message.content {
+        Content::Text(text) => vec![json!({ "text": text })],
+        Content::Blocks(blocks) => claude_blocks_to_parts(blocks, aliases, tool_names),
+    };
+    if parts.is_empty() {
+        return None;
+    }
+    Some(json!({ "role": role, "parts": parts }))
+}
+
+/// Claude 内容块 → Gemini parts
+///
+/// thinking 块的正文不回传（Gemini 不接受思考内容作为输入），
+/// 其签名作为 `thoughtSignature` 挂到紧随其后的 part 上，与响应转换时的拆分方式对应。
+fn claude_blocks_to_parts(
+    blocks: &[ContentBlock],
+    aliases: &ToolAliases,
+    tool_names: &HashMap<&str, &str>,
+) -> Vec<Value> {
+    let mut parts = Vec::new();
+    let mut pending_signature: Option<&str> = None;
+    for block in blocks {
+        let mut part = match block {
+            ContentBlock::Text(text) if !text.text.is_empty() => json!({ "text": text.text }),
+            ContentBlock::Image(media) | ContentBlock::Document(media) => {
+                match media_block_to_part(media) {
+                    Some(part) => part,
+                    None => continue,
+                }
+            }
+            ContentBlock::Thinking(thinking) => {
+                pending_signature = thinking
+                    .signature
+                    .as_deref()
+                    .filter(|signature| !signature.is_empty());
+                continue;
+            }
+            ContentBlock::ToolUse(tool_use) => json!({
+                "functionCall": {
+                    "name": aliases.alias(&tool_use.name),
+                    "args": tool_use.input
+                }
+            }),
+            ContentBlock::ToolResult(result) => {
+                let tool_use_id = result.tool_use_id.as_str();
+                let name = tool_names.get(tool_use_id).copied().unwrap_or(tool_use_id);
+                let (response, media) = tool_result_to_response(result);
+                parts.push(json!({
+                    "functionResponse": { "name": aliases.alias(name), "response": response }
+                }));
+                // functionResponse 只能承载 JSON，工具返回的图片作为同一轮的额外 part
+                parts.extend(media);
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(signature) = pending_signature.take()
+            && let Some(object) = part.as_object_mut()
+        {
+            object.insert(
+                "thoughtSignature".to_string(),
+                Value::String(signature.to_string()),
+            );
+        }
+        parts.push(part);
+    }
+    // 签名位于最后（流式响应中签名常随最后一个空 part 到达）时挂到最后一个 part 上
+    if let Some(signature) = pending_signature
+        && let Some(Value::Object(last)) = parts.last_mut()
+    {
+        last.insert(
+            "thoughtSignature".to_string(),
+            Value::String(signature.to_string()),
+        );
+    }
+    parts
+}
+
+/// Claude 图片 / 文档块 → `inlineData`（base64）或 `fileData`（URL）
+fn media_block_to_part(block: &MediaBlock) -> Option<Value> {
+    let source = &block.source;
+    match source.kind.as_str() {
+        "base64" => {
+            let media_type = source
+                .media_type
+                .as_deref()
+                .unwrap_or("application/octet-stream");
+            let data = source.data.as_deref()?;
+            Some(json!({ "inlineData": { "mimeType": media_type, "data": data } }))
+        }
+        "url" => {
+            let url = source.url.as_deref()?;
+            Some(json!({ "fileData": { "mimeType": guess_mime_type(url), "fileUri": url } }))
+        }
+        _ => None,
+    }
+}
+
+/// 按 URL 扩展名推断 `fileData` 必填的 `mimeType`
+fn guess_mime_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        _ => "image/png",
+    }
+}
+
+/// `tool_result` → (`functionResponse.response`, 附带的媒体 parts)
+///
+/// 文本拼接为 `content`（出错时为 `error`），图片与文档另行返回。
+fn tool_result_to_response(result: &ToolResultBlock) -> (Value, Vec<Value>) {
+    let mut texts = Vec::new();
+    let mut media = Vec::new();
+    match &result.content {
+        Some(Content::Text(text)) => texts.push(text.clone()),
+        Some(Content::Blocks(items)) => {
+            for item in items {
+                match item {
+                    ContentBlock::Text(text) => texts.push(text.text.clone()),
+                    ContentBlock::Image(block) | ContentBlock::Document(block) => {
+                        if let Some(part) = media_block_to_part(block) {
+                            media.push(part);
+                        }
+                    }
+                    _ => texts.push(serde_json::to_string(item).unwrap_or_default()),
+                }
+            }
+        }
+        None => {}
+    }
+    let key = if result.is_error() {
+        "error"
+    } else {
+        "content"
+    };
+    (json!({ key: texts.join("\n") }), media)
+}
+
+fn claude_system_to_text(system: &Content) -> Option<String> {
+    let text = system
+        .texts()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Anthropic tools → `functionDeclarations`
+///
+/// 服务端工具（`web_search` 等）与 Gemini 的内置工具返回格式差异较大，暂不映射。
+fn map_tools(tools: &[Tool], aliases: &ToolAliases, profile: SchemaProfile) -> Vec<Value> {
+    tools
+        .iter()
+        .filter(|tool| !is_server_tool(tool) && !tool.name.is_empty())
+        .map(|tool| {
+            let mut declaration = Map::new();
+            declaration.insert(
+                "name".to_string(),
+                Value::String(aliases.alias(&tool.name).into_owned()),
+            );
+            if let Some(description) = &tool.description {
+                declaration.insert(
+                    "description".to_string(),
+                    Value::String(description.clone()),
+                );
+            }
+            // 无参数工具不能携带空的 properties，省略 parameters
+            if let Some(schema) = tool.input_schema.as_ref().filter(|schema| {
+                schema
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .is_some_and(|properties| !properties.is_empty())
+            }) {
+                declaration.insert(
+                    "parameters".to_string(),
+                    sanitize_tool_schema(schema, profile),
+                );
+            }
+            Value::Object(declaration)
+        })
+        .collect()
+}
+
+/// Anthropic `tool_choice` → `toolConfig.functionCallingConfig`
+fn map_tool_choice(choice: &ToolChoice, aliases: &ToolAliases) -> Option<Value> {
+    let config = match &choice.kind {
+        ToolChoiceKind::Auto => json!({ "mode": "AUTO" }),
+        ToolChoiceKind::Any => json!({ "mode": "ANY" }),
+        ToolChoiceKind::None => json!({ "mode": "NONE" }),
+        ToolChoiceKind::Tool => {
+            let name = choice.name.as_deref()?;
+            json!({ "mode": "ANY", "allowedFunctionNames": [aliases.alias(name)] })
+        }
+        ToolChoiceKind::Other(_) => return None,
+    };
+    Some(json!({ "functionCallingConfig": config }))
+}
+
+/// 采样参数与 thinking → `generationConfig`
+fn generation_config(request: &MessagesRequest) -> Map<String, Value> {
+    let mut config = Map::new();
+    if let Some(max_tokens) = request.max_tokens {
+        config.insert("maxOutputTokens".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = request.temperature {
+        config.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = request.top_p {
+        config.insert("topP".to_string(), json!(top_p));
+    }
+    if let Some(top_k) = request.top_k {
+        config.insert("topK".to_string(), json!(top_k));
+    }
+    if let Some(stop_sequences) = &request.stop_sequences {
+        config.insert("stopSequences".to_string(), json!(stop_sequences));
+    }
+    let budget = request
+        .thinking
+        .as_ref()
+        .and_then(|thinking| match thinking.kind {
+            ThinkingKind::Enabled => thinking
+                .budget_tokens
+                .and_then(|budget| i64::try_from(budget).ok()),
+            // 自适应思考对应 Gemini 的动态预算
+            ThinkingKind::Adaptive => Some(-1),
+            _ => None,
+        });
+    if let Some(budget) = budget {
+        config.insert(
+            "thinkingConfig".to_string(),
+            json!({ "thinkingBudget": budget, "includeThoughts": true }),
+        );
+    }
+    config
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn convert(request: &Value) -> Value {
+        anthropic_request_to_gemini(request.to_string().as_bytes(), &UpstreamConfig::default())
+            .unwrap()
+            .body
+    }
+
+    /// 测试 system、消息、图片、工具调用与工具结果的转换
+    #[test]
+    fn test_messages_map_to_contents() {
+        let body = convert(&json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "Be brief."}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "AAAA"}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "Let me look.", "signature": "sig_1"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "a.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "fn main() {}"}]}
+                ]}
+            ]
+        }));
+
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief.");
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 1024);
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[0]["parts"][1]["inlineData"]["mimeType"],
+            "image/jpeg"
+        );
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(contents[1]["parts"].as_array().unwrap().len(), 1);
+        assert_eq!(contents[1]["parts"][0]["functionCall"]["name"], "Read");
+        assert_eq!(contents[1]["parts"][0]["thoughtSignature"], "sig_1");
+        assert_eq!(
+            contents[2]["parts"][0]["functionResponse"],
+            json!({"name": "Read", "response": {"content": "fn main() {}"}})
+        );
+    }
+
+    /// 测试工具声明、强制工具选择与 thinking 预算的转换
+    #[test]
+    fn test_tools_and_thinking_config() {
+        let body = convert(&json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 1024,
+            "thinking": {"type": "enabled", "budget_tokens": 2048},
+            "tools": [
+                {"name": "Bash", "description": "Run", "input_schema": {
+                    "$schema": "http://json-schema.org/draft-07/schema#",
+                    "type": "object",
+                    "properties": {"command": {"type": "string"}},
+                    "additionalProperties": false
+                }},
+                {"name": "Now", "input_schema": {"type": "object", "properties": {}}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "tool_choice": {"type": "tool", "name": "Bash"},
+            "messages": [{"role": "user", "content": "hi"}]
+        }));
+
+        let declarations = body["tools"][0]["functionDeclarations"].as_array().unwrap();
+        assert_eq!(declarations.len(), 2);
+        assert!(declarations[0]["parameters"].get("$schema").is_none());
+        assert!(
+            declarations[0]["parameters"]
+                .get("additionalProperties")
+                .is_none()
+        );
+        assert!(declarations[1].get("parameters").is_none());
+        assert_eq!(
+            body["toolConfig"]["functionCallingConfig"],
+            json!({"mode": "ANY", "allowedFunctionNames": ["Bash"]})
+        );
+        assert_eq!(
+            body["generationConfig"]["thinkingConfig"],
+            json!({"thinkingBudget": 2048, "includeThoughts": true})
+        );
+    }
+}
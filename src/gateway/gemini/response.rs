@@ -0,0 +1,275 @@
+//! 响应格式转换
+//!
+//! Gemini `generateContent` 响应 → Anthropic Claude 响应
+//!
+//! 主要转换：
+//! - `candidates[0].content.parts` → content 块（`thought` → thinking，`functionCall` → `tool_use`）
+//! - `thoughtSignature` → thinking 块的 `signature`
+//! - `finishReason` → `stop_reason`，`usageMetadata` → usage
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+use crate::gateway::{
+    anthropic::{ContentBlock, MessagesResponse, Usage},
+    hash::stable_hash_hex,
+    openai_compat::ToolAliases,
+};
+
+/// Gemini 响应 → Claude 响应
+pub fn gemini_response_to_anthropic(
+    body: &[u8],
+    model_hint: Option<&str>,
+    aliases: &ToolAliases,
+) -> Result<Bytes, String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("invalid response body: {e}"))?;
+    let id = message_id(&value);
+    let model = value
+        .get("modelVersion")
+        .and_then(Value::as_str)
+        .or(model_hint)
+        .unwrap_or("unknown");
+    let candidate = value.pointer("/candidates/0");
+
+    let mut content = ContentBuilder::default();
+    for (index, part) in candidate
+        .and_then(|candidate| candidate.pointer("/content/parts"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        content.push_part(part, aliases, &id, index);
+    }
+    let has_tool_uses = content.has_tool_uses;
+    let content = content.finish();
+
+    let finish_reason = candidate
+        .and_then(|candidate| candidate.get("finishReason"))
+        .and_then(Value::as_str);
+    // 提示词被拦截时没有候选，只有 promptFeedback.blockReason
+    let blocked = candidate.is_none() && value.pointer("/promptFeedback/blockReason").is_some();
+    let stop_reason = if blocked {
+        "refusal"
+    } else {
+        stop_reason(finish_reason, has_tool_uses)
+    };
+    let usage = value
+        .get("usageMetadata")
+        .and_then(Value::as_object)
+        .map(map_usage)
+        .unwrap_or_default();
+
+    let message = MessagesResponse {
+        content,
+        stop_reason: Some(stop_reason.to_string()),
+        usage,
+        ..MessagesResponse::new(id.as_str(), model)
+    };
+    serde_json::to_vec(&message)
+        .map(Bytes::from)
+        .map_err(|e| format!("Failed to serialize response: {e}"))
+}
+
+/// 按 parts 顺序拼装 content 块
+#[derive(Default)]
+struct ContentBuilder {
+    blocks: Vec<ContentBlock>,
+    thinking: String,
+    has_tool_uses: bool,
+}
+
+impl ContentBuilder {
+    fn push_part(&mut self, part: &Value, aliases: &ToolAliases, id: &str, index: usize) {
+        let signature = part.get("thoughtSignature").and_then(Value::as_str);
+        if is_thought(part) {
+            if let Some(text) = part.get("text").and_then(Value::as_str) {
+                self.thinking.push_str(text);
+            }
+            if let Some(signature) = signature {
+                self.flush_thinking(Some(signature));
+            }
+            return;
+        }
+        // 签名挂在普通 part 上时，拆为该 part 之前的 thinking 块
+        self.flush_thinking(signature);
+        if let Some(call) = part.get("functionCall").and_then(Value::as_object) {
+            self.has_tool_uses = true;
+            self.blocks
+                .push(function_call_to_tool_use(call, aliases, id, index));
+        } else if let Some(text) = part.get("text").and_then(Value::as_str) {
+            match self.blocks.last_mut() {
+                Some(ContentBlock::Text(last)) => last.text.push_str(text),
+                _ => self.blocks.push(ContentBlock::text(text)),
+            }
+        }
+    }
+
+    fn flush_thinking(&mut self, signature: Option<&str>) {
+        if self.thinking.is_empty() && signature.is_none() {
+            return;
+        }
+        self.blocks.push(ContentBlock::thinking(
+            std::mem::take(&mut self.thinking),
+            Some(signature.unwrap_or("").to_string()),
+        ));
+    }
+
+    fn finish(mut self) -> Vec<ContentBlock> {
+        self.flush_thinking(None);
+        self.blocks
+    }
+}
+
+/// part 是否为思考内容
+#[must_use]
+pub fn is_thought(part: &Value) -> bool {
+    part.get("thought").and_then(Value::as_bool) == Some(true)
+}
+
+/// 消息 id：使用 `responseId`，缺失时生成占位 id
+#[must_use]
+pub fn message_id(response: &Value) -> String {
+    response
+        .get("responseId")
+        .and_then(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .map_or_else(|| "msg_gemini".to_string(), |id| format!("msg_{id}"))
+}
+
+/// `functionCall` → `tool_use`
+///
+/// 较新的模型会返回调用 id，否则按消息 id 与 part 位置生成稳定的 id。
+#[must_use]
+pub fn function_call_to_tool_use(
+    call: &Map<String, Value>,
+    aliases: &ToolAliases,
+    message_id: &str,
+    index: usize,
+) -> ContentBlock {
+    let name = call.get("name").and_then(Value::as_str).unwrap_or("");
+    let id = call
+        .get("id")
+        .and_then(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .map_or_else(
+            || {
+                let index = index.to_string();
+                format!(
+                    "toolu_{}",
+                    stable_hash_hex(&[message_id.as_bytes(), index.as_bytes()])
+                )
+            },
+            |id| format!("toolu_{id}"),
+        );
+    ContentBlock::tool_use(
+        id,
+        aliases.original(name),
+        call.get("args").cloned().unwrap_or_else(|| json!({})),
+    )
+}
+
+/// Gemini `finishReason` → Anthropic `stop_reason`
+#[must_use]
+pub fn stop_reason(finish_reason: Option<&str>, has_tool_uses: bool) -> &'static str {
+    match finish_reason {
+        _ if has_tool_uses => "tool_use",
+        Some("MAX_TOKENS") => "max_tokens",
+        Some(
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY",
+        ) => "refusal",
+        _ => "end_turn",
+    }
+}
+
+/// Gemini `usageMetadata` → Anthropic usage
+///
+/// `promptTokenCount` 包含命中缓存的部分，命中部分单独记为 `cache_read_input_tokens`；
+/// 思考 token 单独计数（`thoughtsTokenCount`），合并到 `output_tokens`。
+#[must_use]
+pub fn map_usage(usage: &Map<String, Value>) -> Usage {
+    let count = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+    let prompt_tokens = count("promptTokenCount");
+    let cached_tokens = count("cachedContentTokenCount");
+    Usage {
+        cache_creation_input_tokens: Some(0),
+        cache_read_input_tokens: Some(cached_tokens),
+        ..Usage::new(
+            prompt_tokens.saturating_sub(cached_tokens),
+            count("candidatesTokenCount") + count("thoughtsTokenCount"),
+        )
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试思考、签名、文本与函数调用转换为 Anthropic 响应
+    #[test]
+    fn test_gemini_response_to_anthropic() {
+        let response = json!({
+            "responseId": "abc",
+            "modelVersion": "gemini-2.5-pro",
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "Thinking about files.", "thought": true},
+                    {"text": "Let me read it.", "thoughtSignature": "sig_1"},
+                    {"functionCall": {"name": "Read", "args": {"path": "a.rs"}}}
+                ]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 100,
+                "cachedContentTokenCount": 40,
+                "candidatesTokenCount": 20,
+                "thoughtsTokenCount": 5
+            }
+        });
+        let converted: Value = serde_json::from_slice(
+            &gemini_response_to_anthropic(
+                response.to_string().as_bytes(),
+                None,
+                &ToolAliases::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(converted["id"], "msg_abc");
+        assert_eq!(converted["model"], "gemini-2.5-pro");
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 3);
+        assert_eq!(
+            content[0],
+            json!({"type": "thinking", "thinking": "Thinking about files.", "signature": "sig_1"})
+        );
+        assert_eq!(content[1]["text"], "Let me read it.");
+        assert_eq!(content[2]["name"], "Read");
+        assert_eq!(content[2]["input"]["path"], "a.rs");
+        assert_eq!(converted["stop_reason"], "tool_use");
+        assert_eq!(converted["usage"]["input_tokens"], 60);
+        assert_eq!(converted["usage"]["cache_read_input_tokens"], 40);
+        assert_eq!(converted["usage"]["output_tokens"], 25);
+    }
+
+    /// 测试提示词被拦截时返回 refusal
+    #[test]
+    fn test_blocked_prompt_is_refusal() {
+        let response = json!({"promptFeedback": {"blockReason": "SAFETY"}});
+        let converted: Value = serde_json::from_slice(
+            &gemini_response_to_anthropic(
+                response.to_string().as_bytes(),
+                Some("gemini-2.5-flash"),
+                &ToolAliases::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(converted["stop_reason"], "refusal");
+        assert_eq!(converted["model"], "gemini-2.5-flash");
+        assert_eq!(converted["content"], json!([]));
+    }
+}
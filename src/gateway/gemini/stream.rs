@@ -0,0 +1,255 @@
+//! 流式响应转换
+//!
+//! Gemini `streamGenerateContent?alt=sse` 事件 → Anthropic Messages SSE 事件
+//!
+//! 每个 SSE 事件都是一个完整的 `GenerateContentResponse` 片段：
+//! - 首个片段 → `message_start`
+//! - `thought` part → thinking 块的 `thinking_delta`，`thoughtSignature` → `signature_delta`
+//! - 普通文本 part → text 块的 `text_delta`
+//! - `functionCall` part（总是完整下发）→ 完整的 `tool_use` 块
+//! - 带 `finishReason` 的片段 → `message_delta` + `message_stop`
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use super::response;
+use crate::gateway::{
+    anthropic::DeltaUsage,
+    openai_compat::ToolAliases,
+    sse::{AnthropicSseWriter, SseDecoder, SseEvent, StreamConverter},
+};
+
+/// Gemini SSE → Anthropic SSE 的有状态转换器
+pub struct GeminiStreamConverter {
+    decoder: SseDecoder,
+    writer: AnthropicSseWriter,
+    aliases: ToolAliases,
+    message_id: String,
+    /// 已处理的 part 数，用于为没有 id 的函数调用生成稳定 id
+    part_index: usize,
+    has_tool_uses: bool,
+    usage: Option<Map<String, Value>>,
+}
+
+impl GeminiStreamConverter {
+    #[must_use]
+    pub fn new(model_hint: Option<&str>, aliases: ToolAliases) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            writer: AnthropicSseWriter::new("msg_gemini", model_hint.unwrap_or("unknown")),
+            aliases,
+            message_id: "msg_gemini".to_string(),
+            part_index: 0,
+            has_tool_uses: false,
+            usage: None,
+        }
+    }
+
+    fn handle_event(&mut self, event: &SseEvent) {
+        let Some(data) = event.json() else {
+            return;
+        };
+        if let Some(error) = data.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("Upstream stream error.");
+            self.writer.error("api_error", message);
+            return;
+        }
+
+        if let Some(usage) = data.get("usageMetadata").and_then(Value::as_object) {
+            self.usage = Some(usage.clone());
+        }
+        self.start(&data);
+
+        let candidate = data.pointer("/candidates/0");
+        for part in candidate
+            .and_then(|candidate| candidate.pointer("/content/parts"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            self.handle_part(part);
+        }
+
+        let finish_reason = candidate
+            .and_then(|candidate| candidate.get("finishReason"))
+            .and_then(Value::as_str);
+        let blocked = candidate.is_none() && data.pointer("/promptFeedback/blockReason").is_some();
+        if blocked {
+            self.writer.finish("refusal", self.output_usage());
+        } else if finish_reason.is_some() {
+            let stop_reason = response::stop_reason(finish_reason, self.has_tool_uses);
+            self.writer.finish(stop_reason, self.output_usage());
+        }
+    }
+
+    fn start(&mut self, data: &Value) {
+        if data.get("responseId").is_some() {
+            self.message_id = response::message_id(data);
+        }
+        self.writer.set_message_meta(
+            Some(&self.message_id),
+            data.get("modelVersion").and_then(Value::as_str),
+        );
+        self.writer
+            .start(self.usage.as_ref().map(response::map_usage));
+    }
+
+    fn handle_part(&mut self, part: &Value) {
+        let index = self.part_index;
+        self.part_index += 1;
+        let signature = part.get("thoughtSignature").and_then(Value::as_str);
+        if response::is_thought(part) {
+            if let Some(text) = part.get("text").and_then(Value::as_str) {
+                self.writer.thinking_delta(text);
+            }
+            if let Some(signature) = signature {
+                self.writer.signature_delta(signature);
+            }
+            return;
+        }
+        if let Some(signature) = signature {
+            self.writer.signature_delta(signature);
+        }
+        if let Some(call) = part.get("functionCall").and_then(Value::as_object) {
+            self.has_tool_uses = true;
+            let tool_use =
+                response::function_call_to_tool_use(call, &self.aliases, &self.message_id, index);
+            self.writer.full_block(tool_use);
+        } else if let Some(text) = part.get("text").and_then(Value::as_str) {
+            self.writer.text_delta(text);
+        }
+    }
+
+    fn output_usage(&self) -> Option<DeltaUsage> {
+        self.usage
+            .as_ref()
+            .map(|usage| response::map_usage(usage).into())
+    }
+}
+
+impl StreamConverter for GeminiStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event);
+        }
+        self.writer.take()
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event);
+        }
+        // 上游没有发送 finishReason 就断开时，补齐收尾事件
+        if !self.writer.is_finished() {
+            let stop_reason = response::stop_reason(None, self.has_tool_uses);
+            self.writer.finish(stop_reason, self.output_usage());
+        }
+        self.writer.take()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::gateway::sse::encode_data;
+
+    fn run(converter: &mut GeminiStreamConverter, chunks: &[Value]) -> Vec<Value> {
+        let input = chunks
+            .iter()
+            .flat_map(|chunk| encode_data(&chunk.to_string()).to_vec())
+            .collect::<Vec<_>>();
+        // 按 7 字节切片输入，模拟任意的网络分包
+        let mut out = input
+            .chunks(7)
+            .flat_map(|chunk| converter.convert(chunk))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+        let mut decoder = SseDecoder::default();
+        out.iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .filter_map(|event| event.json())
+            .collect()
+    }
+
+    /// 测试思考、签名、文本与函数调用转换为 Anthropic 流式事件
+    #[test]
+    fn test_gemini_stream_to_anthropic_events() {
+        let parts =
+            |parts: Value| json!({"candidates": [{"content": {"role": "model", "parts": parts}}]});
+        let chunks = [
+            json!({
+                "responseId": "r1",
+                "modelVersion": "gemini-2.5-pro",
+                "candidates": [{"content": {"role": "model", "parts": [{"text": "Plan", "thought": true}]}}],
+                "usageMetadata": {"promptTokenCount": 12}
+            }),
+            parts(json!([{"text": "Hello", "thoughtSignature": "sig_1"}])),
+            parts(json!([{"text": " world"}])),
+            json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"functionCall": {"name": "Read", "args": {"path": "a.rs"}}}]},
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 8, "thoughtsTokenCount": 3}
+            }),
+        ];
+
+        let mut converter = GeminiStreamConverter::new(Some("hint"), ToolAliases::default());
+        let events = run(&mut converter, &chunks);
+        let types = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0]["message"]["id"], "msg_r1");
+        assert_eq!(events[0]["message"]["model"], "gemini-2.5-pro");
+        assert_eq!(events[0]["message"]["usage"]["input_tokens"], 12);
+        assert_eq!(events[2]["delta"]["thinking"], "Plan");
+        assert_eq!(events[3]["delta"]["signature"], "sig_1");
+        assert_eq!(events[7]["delta"]["text"], " world");
+        assert_eq!(events[9]["content_block"]["name"], "Read");
+        assert_eq!(events[12]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[12]["usage"]["output_tokens"], 11);
+    }
+
+    /// 测试流中的错误对象转换为 error 事件
+    #[test]
+    fn test_gemini_stream_error() {
+        let mut converter = GeminiStreamConverter::new(None, ToolAliases::default());
+        let events = run(
+            &mut converter,
+            &[
+                json!({"error": {"code": 503, "message": "The model is overloaded.", "status": "UNAVAILABLE"}}),
+            ],
+        );
+        assert_eq!(events.last().unwrap()["type"], "error");
+        assert_eq!(
+            events.last().unwrap()["error"]["message"],
+            "The model is overloaded."
+        );
+    }
+}
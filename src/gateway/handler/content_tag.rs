@@ -1,4 +1,7 @@
-use serde_json::{Value, from_slice, to_vec};
+use crate::gateway::{
+    anthropic::{Content, MessagesRequest},
+    pipeline::Transform,
+};
 
 /// 需要从 messages[].content[] 中移除的标签（成对匹配）
 const CONTENT_TAG_FILTERS: &[(&str, &str)] = &[
@@ -28,46 +31,47 @@ fn should_remove_content(text: &str) -> bool {
 /// - <command-name>...</command-name>
 /// - <local-command-caveat>...</local-command-caveat>
 ///
-/// 这些内容占用大量 tokens 但对模型无用，此函数将其移除。
-pub fn filter_messages_content(body_bytes: &[u8]) -> Option<bytes::Bytes> {
-    let mut json = from_slice::<Value>(body_bytes).ok()?;
+/// 这些内容占用大量 tokens 但对模型无用，此阶段将其移除。
+pub struct ContentTagFilter;
 
-    let messages = json.get_mut("messages")?.as_array_mut()?;
+impl Transform for ContentTagFilter {
+    fn name(&self) -> &'static str {
+        "content_tag_filter"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        filter_messages_content(body)
+    }
+}
 
+fn filter_messages_content(request: &mut MessagesRequest) -> bool {
     let mut total_removed = 0usize;
     let mut total_chars = 0usize;
 
-    for message in messages.iter_mut() {
-        let Some(content) = message.get_mut("content").and_then(|c| c.as_array_mut()) else {
+    for message in &mut request.messages {
+        let Content::Blocks(content) = &mut message.content else {
             continue;
         };
 
-        // 统计移除前的信息
-        for item in content.iter() {
-            if let Some(text) = item.get("text").and_then(|t| t.as_str())
-                && should_remove_content(text)
-            {
+        // 过滤掉需要移除的内容，同时统计移除的信息
+        content.retain(|item| match item.as_text() {
+            Some(text) if should_remove_content(text) => {
                 total_removed += 1;
                 total_chars += text.len();
+                false
             }
-        }
-
-        // 过滤掉需要移除的内容
-        content.retain(|item| {
-            item.get("text")
-                .and_then(|t| t.as_str())
-                .is_none_or(|text| !should_remove_content(text))
+            _ => true,
         });
     }
 
-    if total_removed > 0 {
-        tracing::info!(
-            "🧹 已过滤 messages.content: 移除 {} 项, 节省约 {} 字符 (~{} tokens)",
-            total_removed,
-            total_chars,
-            total_chars / 4
-        );
+    if total_removed == 0 {
+        return false;
     }
-
-    to_vec(&json).ok().map(Into::into)
+    tracing::info!(
+        "🧹 已过滤 messages.content: 移除 {} 项, 节省约 {} 字符 (~{} tokens)",
+        total_removed,
+        total_chars,
+        total_chars / 4
+    );
+    true
 }
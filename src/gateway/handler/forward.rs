@@ -0,0 +1,933 @@
+//! 上游转发
+//!
+//! 接收 Anthropic 格式的请求体，完成 upstream 选择、按 mode 的格式转换、
+//! token 统计与请求发送，返回 Anthropic 格式的响应（完整 JSON 或 SSE 流）。
+//! Claude CLI 入口与 `OpenAI` 兼容入口共用这一流程。
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::{StreamExt, stream::BoxStream};
+use http::{HeaderMap, Method};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request as HyperRequest, Response as HyperResponse, body::Incoming};
+use salvo::prelude::StatusCode;
+
+use crate::{
+    config::{AtomicConfig, Mode, Quirk, UpstreamConfig, selector::UpstreamSelector},
+    gateway::{
+        HttpClient, RequestStats,
+        anthropic::{ErrorResponse, MessagesRequest},
+        azure, bedrock, cache_planner, capabilities, context_guard, gemini,
+        handler::{
+            request::{ModelOverride, make_proxy_url, override_model_in_body},
+            response::{decompress_gzip_if_needed, sse_data_stream},
+            tool_desc::ServerToolFilter,
+        },
+        ollama, openai_compat,
+        pipeline::RequestBody,
+        quirks,
+        service::{calculate_tokens, estimate_request_tokens, log_full_body, log_full_response},
+        signature,
+        sse::{StreamConverter, convert_stream},
+        vertex,
+    },
+};
+
+/// 发往上游的 Anthropic 请求
+pub struct ForwardRequest<'a> {
+    pub method: &'a Method,
+    /// Anthropic 风格的请求路径（如 `/claude/v1/messages`）
+    pub path: &'a str,
+    pub query: &'a str,
+    /// 需要透传给上游的请求头（host / authorization / content-length 会被替换）
+    pub headers: &'a HeaderMap,
+    /// 解析后的 Anthropic 请求体
+    pub body: RequestBody,
+    /// 入站请求本身就是 Responses 格式时的原始请求体
+    ///
+    /// 选中 `openai_responses` upstream 时直接发送（仅覆盖 model），响应也原样返回，
+    /// 避免 Responses → Anthropic → Responses 的往返转换丢失信息。
+    /// 上下文裁剪或 [`prepare_upstream_body`] 中 model 覆盖以外的阶段改写了请求时，
+    /// 原始请求体已不能代表要发送的内容，改为从 Anthropic 请求体转换。
+    pub native_responses: Option<Bytes>,
+}
+
+impl ForwardRequest<'_> {
+    /// 是否为 `/v1/messages/count_tokens` 请求
+    #[must_use]
+    pub fn is_count_tokens(&self) -> bool {
+        self.path.trim_end_matches('/').ends_with("/count_tokens")
+    }
+}
+
+/// 上游响应体（已转换为 Anthropic 格式）
+pub enum UpstreamBody {
+    /// Anthropic SSE 字节流
+    Stream(BoxStream<'static, Bytes>),
+    /// Anthropic JSON 响应体（已解压）
+    Full(Bytes),
+}
+
+/// 上游响应
+pub struct UpstreamResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: UpstreamBody,
+    /// 响应体为上游原始格式（未转换为 Anthropic 格式）
+    pub passthrough: bool,
+}
+
+/// 选择 upstream 并转发请求
+///
+/// 失败时返回应回复给客户端的状态码。
+pub async fn forward_to_upstream(
+    config: &Arc<AtomicConfig>,
+    stats: &Arc<RequestStats>,
+    client: &Arc<HttpClient>,
+    mut request: ForwardRequest<'_>,
+) -> Result<UpstreamResponse, StatusCode> {
+    let cfg = config.get();
+
+    // 选择 upstream 和 api_key
+    let Some(selector) = config.get_upstream_selector() else {
+        tracing::error!("UpstreamSelector not initialized");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let Some((upstream_idx, upstream, api_key)) = select_upstream(&selector, request.body.value())
+    else {
+        tracing::error!("No upstream configured");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let endpoint = upstream.endpoint.as_str();
+    let selected_model = upstream.model.as_str();
+    let mode = upstream.mode;
+
+    // 打印选择的 upstream 和 api_key（脱敏显示）
+    tracing::info!(
+        "🔄 选中的 Upstream[{}]: endpoint={}, model={}, api_key: {}***, mode={:?}",
+        upstream_idx,
+        endpoint,
+        selected_model,
+        api_key.chars().take(8).collect::<String>(),
+        mode
+    );
+
+    let mut body = std::mem::take(&mut request.body);
+    if let Some(response) = local_reply(&request, &body, &upstream) {
+        return Ok(response);
+    }
+
+    // 超出 upstream 的上下文窗口时裁剪历史，裁剪后仍放不下则不再请求上游
+    let trimmed = match body
+        .try_update(|request| context_guard::fit_context_window(request, &upstream.capabilities))
+    {
+        Ok(trimmed) => trimmed,
+        Err(overflow) => return Ok(error_response(overflow.error_body())),
+    };
+    let rewritten =
+        prepare_upstream_body(&mut body, &upstream, cfg.optimizations.enable_cache_planner);
+    // 原始 Responses 请求体不经过裁剪与各处理阶段，只要其中之一改写了请求，
+    // 就改为从处理后的 Anthropic 请求体转换
+    let native = request
+        .native_responses
+        .take()
+        .filter(|_| matches!(mode, Mode::OpenAIResponses) && !trimmed && !rewritten);
+    let passthrough = native.is_some();
+    // token 统计基于 Anthropic 请求体（透传时为转换得到的请求体）
+    if let Some(request) = body.value() {
+        calculate_tokens(stats, request);
+    }
+    let (body_bytes, mut conversion_context) = native.map_or_else(
+        || encode_upstream_body(body, &upstream),
+        |native| {
+            tracing::debug!("🔄 Responses 请求直接透传给 OpenAI Responses upstream");
+            let native = if selected_model.is_empty() {
+                native
+            } else {
+                override_model_in_body(&native, selected_model).unwrap_or(native)
+            };
+            (native, openai_compat::ConversionContext::default())
+        },
+    );
+    if cfg.log_req_body
+        && let Ok(body_str) = std::str::from_utf8(&body_bytes)
+    {
+        log_full_body(body_str);
+    }
+
+    // 构建代理请求
+    let proxy_req = build_upstream_request(
+        &request,
+        &upstream,
+        &api_key,
+        body_bytes,
+        client,
+        &mut conversion_context,
+    )
+    .await;
+    let proxy_req = match proxy_req {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!("Failed to build proxy request: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // 使用共享的 HTTP 客户端发送请求
+    let proxy_resp: HyperResponse<Incoming> = match client.request(proxy_req).await {
+        Ok(proxy_resp) => proxy_resp,
+        Err(e) => {
+            tracing::error!("Proxy request failed: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+    if passthrough {
+        return read_passthrough_response(proxy_resp, cfg.log_res_body).await;
+    }
+    let model_hint = Some(selected_model).filter(|m| !m.is_empty());
+    let upstream_res = read_upstream_response(
+        proxy_resp,
+        mode,
+        model_hint,
+        conversion_context,
+        cfg.log_res_body,
+    )
+    .await?;
+    let upstream_res = tag_thinking_signatures(upstream_res, signature::upstream_tag(&upstream));
+    Ok(record_cache_usage(upstream_res, stats))
+}
+
+/// 轮询选择 upstream 与 `api_key`
+///
+/// 有上游声明了能力限制时，跳过无法完整处理该请求的上游（如请求含图片）。
+fn select_upstream(
+    selector: &UpstreamSelector,
+    body: Option<&MessagesRequest>,
+) -> Option<(usize, UpstreamConfig, String)> {
+    let needs = body
+        .filter(|_| selector.has_capability_limits())
+        .map(capabilities::RequestNeeds::from_request);
+    let selected = needs.as_ref().map_or_else(
+        || selector.next(),
+        |needs| selector.next_matching(|upstream| needs.served_by(&upstream.capabilities)),
+    );
+    selected.and_then(|(idx, _, _, key, _)| {
+        selector
+            .upstream(idx)
+            .map(|upstream| (idx, upstream.clone(), key.to_owned()))
+    })
+}
+
+/// 不需要请求上游、由代理直接回复的情况
+fn local_reply(
+    request: &ForwardRequest<'_>,
+    body: &RequestBody,
+    upstream: &UpstreamConfig,
+) -> Option<UpstreamResponse> {
+    if let Some(response) = invalid_body_response(body, upstream.mode) {
+        return Some(response);
+    }
+    // Azure OpenAI 没有 token 计数接口，count_tokens 按本地估算回复，不发往部署的生成接口
+    (request.is_count_tokens() && upstream.azure.is_some())
+        .then(|| local_count_tokens_response(body))
+}
+
+/// 请求体不符合 Messages 请求结构、而 upstream 需要格式转换时回复的错误响应
+///
+/// Anthropic 上游仍转发原始请求体（只覆盖 model），由上游给出具体的校验错误。
+fn invalid_body_response(body: &RequestBody, mode: Mode) -> Option<UpstreamResponse> {
+    let error = body.parse_error()?;
+    if matches!(mode, Mode::AnthropicDirect) {
+        return None;
+    }
+    tracing::warn!("请求体无法转换为 {:?} 格式: {}", mode, error);
+    let error = ErrorResponse::new(
+        "invalid_request_error",
+        format!("invalid request body: {error}"),
+    );
+    Some(error_response(Bytes::from(
+        serde_json::to_vec(&error).unwrap_or_default(),
+    )))
+}
+
+/// 不请求上游、直接回复的 Anthropic 错误响应（400）
+fn error_response(body: Bytes) -> UpstreamResponse {
+    local_response(StatusCode::BAD_REQUEST, body)
+}
+
+/// 按本地估算的输入 token 数回复 `count_tokens` 请求
+fn local_count_tokens_response(body: &RequestBody) -> UpstreamResponse {
+    let input_tokens = body.value().map_or(0, estimate_request_tokens);
+    local_response(
+        StatusCode::OK,
+        Bytes::from(serde_json::json!({ "input_tokens": input_tokens }).to_string()),
+    )
+}
+
+/// 不请求上游、直接回复的 JSON 响应
+fn local_response(status: StatusCode, body: Bytes) -> UpstreamResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/json"),
+    );
+    UpstreamResponse {
+        status,
+        headers,
+        body: UpstreamBody::Full(body),
+        passthrough: false,
+    }
+}
+
+/// 为响应中的 thinking 签名加上来源上游的标记，供下次请求清洗历史时识别
+fn tag_thinking_signatures(mut upstream_res: UpstreamResponse, tag: String) -> UpstreamResponse {
+    if !upstream_res.status.is_success() {
+        return upstream_res;
+    }
+    upstream_res.body = match upstream_res.body {
+        UpstreamBody::Full(body) => UpstreamBody::Full(signature::tag_response_body(body, &tag)),
+        UpstreamBody::Stream(stream) => UpstreamBody::Stream(
+            convert_stream(stream, signature::SignatureTagger::new(tag)).boxed(),
+        ),
+    };
+    upstream_res
+}
+
+/// 从响应的 usage 统计缓存命中（流式响应在流结束时记录）
+fn record_cache_usage(
+    mut upstream_res: UpstreamResponse,
+    stats: &Arc<RequestStats>,
+) -> UpstreamResponse {
+    if !upstream_res.status.is_success() {
+        return upstream_res;
+    }
+    upstream_res.body = match upstream_res.body {
+        UpstreamBody::Full(body) => {
+            cache_planner::record_response_usage(stats, &body);
+            UpstreamBody::Full(body)
+        }
+        UpstreamBody::Stream(stream) => UpstreamBody::Stream(
+            convert_stream(
+                stream,
+                cache_planner::CacheUsageRecorder::new(Arc::clone(stats)),
+            )
+            .boxed(),
+        ),
+    };
+    upstream_res
+}
+
+/// 按 mode 构建发往上游的请求，需要在响应转换时回查的信息记入 `conversion_context`
+async fn build_upstream_request(
+    request: &ForwardRequest<'_>,
+    upstream: &UpstreamConfig,
+    api_key: &str,
+    body_bytes: Bytes,
+    client: &Arc<HttpClient>,
+    conversion_context: &mut openai_compat::ConversionContext,
+) -> Result<HyperRequest<Full<Bytes>>, String> {
+    match upstream.mode {
+        Mode::OpenAIResponses | Mode::OpenAIChat if let Some(azure) = &upstream.azure => {
+            azure::build_request(upstream, azure, body_bytes, api_key)
+        }
+        Mode::Bedrock => {
+            conversion_context.count_tokens = request.is_count_tokens();
+            bedrock::build_request(
+                upstream,
+                &body_bytes,
+                request.headers,
+                conversion_context.count_tokens,
+            )
+        }
+        Mode::Vertex => {
+            vertex::build_request(
+                upstream,
+                &body_bytes,
+                request.headers,
+                client,
+                request.is_count_tokens(),
+            )
+            .await
+        }
+        Mode::Gemini => {
+            gemini::build_request(upstream, &body_bytes, api_key).map(|(proxy_req, aliases)| {
+                conversion_context.tool_aliases = aliases;
+                proxy_req
+            })
+        }
+        Mode::Ollama => ollama::build_request(upstream, &body_bytes, api_key).map(
+            |(proxy_req, prompt_tools)| {
+                conversion_context.prompt_tools = prompt_tools;
+                proxy_req
+            },
+        ),
+        _ => build_proxy_request(request, upstream, api_key, body_bytes),
+    }
+}
+
+/// 构建发往 Anthropic / `OpenAI` 兼容上游的请求：透传客户端请求头并注入 Bearer 鉴权
+fn build_proxy_request(
+    request: &ForwardRequest<'_>,
+    upstream: &UpstreamConfig,
+    api_key: &str,
+    body_bytes: Bytes,
+) -> Result<HyperRequest<Full<Bytes>>, String> {
+    let query = quirks::apply_query_quirks(request.query, upstream.quirks());
+    let (upstream_url, host) =
+        make_proxy_url(&upstream.endpoint, upstream.mode, request.path, &query);
+
+    let mut proxy_req_builder = HyperRequest::builder()
+        .method(request.method)
+        .uri(&upstream_url);
+
+    // 复制请求头（跳过 host、authorization 和 content-length，会重新计算）
+    for (name, value) in request.headers {
+        let name_str = name.as_str();
+        if name_str != "host" && name_str != "authorization" && name_str != "content-length" {
+            proxy_req_builder = proxy_req_builder.header(name, value);
+        }
+    }
+
+    // 注入 Authorization
+    proxy_req_builder = proxy_req_builder.header("Authorization", format!("Bearer {api_key}"));
+    proxy_req_builder = proxy_req_builder.header("host", host.as_ref());
+
+    // Content-Length 由 hyper 自动设置，无需手动设置
+    proxy_req_builder
+        .body(Full::new(body_bytes))
+        .map_err(|e| e.to_string())
+}
+
+/// 按 upstream 配置改写 Anthropic 请求体：覆盖 model、清洗签名、移除不支持的服务端工具、
+/// 按能力调整、规划缓存断点、应用兼容性修补
+///
+/// 返回 model 覆盖以外的阶段是否改写了请求体。
+fn prepare_upstream_body(
+    body: &mut RequestBody,
+    upstream: &UpstreamConfig,
+    plan_cache: bool,
+) -> bool {
+    // 使用选中 upstream 的 model 覆盖请求体中的 model 字段
+    // （未能解析为 Messages 请求的请求体在原始 JSON 上覆盖）
+    if !upstream.model.is_empty() {
+        body.apply(&ModelOverride(&upstream.model));
+        body.rewrite_raw(|raw| override_model_in_body(raw, &upstream.model));
+    }
+
+    // 移除其他上游签发的 thinking 块，还原本上游的签名
+    let mut rewritten = body.apply(&signature::HistorySanitizer(&signature::upstream_tag(
+        upstream,
+    )));
+
+    // upstream 不支持服务端工具时，移除 web_search / web_fetch / code_execution
+    if !upstream.server_tools {
+        rewritten |= body.apply(&ServerToolFilter);
+    }
+
+    // 按 upstream 声明的能力调整请求体（移除图片 / thinking、收紧 max_tokens 等）
+    rewritten |= body.apply(&capabilities::CapabilityAdapter(&upstream.capabilities));
+
+    // Anthropic 格式的上游支持提示词缓存时，重新规划 cache_control 断点
+    let cacheable = matches!(
+        upstream.mode,
+        Mode::AnthropicDirect | Mode::Bedrock | Mode::Vertex
+    ) && upstream.capabilities.prompt_caching
+        && !upstream.quirks().contains(&Quirk::NoCacheControl);
+    if plan_cache && cacheable {
+        rewritten |= body.apply(&cache_planner::CachePlanner);
+    }
+
+    // 按 upstream 启用的兼容性修补改写请求体（作用于 Anthropic 格式，先于格式转换）
+    rewritten |= body.apply(&quirks::BodyQuirks(upstream.quirks()));
+    rewritten
+}
+
+/// 生成发往上游的请求体
+///
+/// `OpenAI` 兼容模式直接从解析后的请求体转换为 Responses / Chat Completions 格式；
+/// 其余 mode 序列化 Anthropic 请求体（没有任何阶段修改时使用原始字节）。
+fn encode_upstream_body(
+    body: RequestBody,
+    upstream: &UpstreamConfig,
+) -> (Bytes, openai_compat::ConversionContext) {
+    let converted = match (upstream.mode, body.value()) {
+        (Mode::OpenAIResponses, Some(request)) => Some((
+            "OpenAI Responses",
+            openai_compat::anthropic_request_to_responses(request, upstream),
+        )),
+        (Mode::OpenAIChat, Some(request)) => Some((
+            "OpenAI Chat",
+            openai_compat::chat::anthropic_request_to_chat(request, upstream),
+        )),
+        _ => None,
+    };
+    match converted {
+        Some((target, Ok((converted, context)))) => {
+            tracing::debug!(
+                "🔄 请求体格式转换: Claude → {} ({} bytes)",
+                target,
+                converted.len()
+            );
+            (converted, context)
+        }
+        Some((_, Err(e))) => {
+            tracing::warn!("请求体格式转换失败: {}，使用原始请求体", e);
+            (
+                body.into_bytes(),
+                openai_compat::ConversionContext::default(),
+            )
+        }
+        None => (
+            body.into_bytes(),
+            openai_compat::ConversionContext::default(),
+        ),
+    }
+}
+
+/// 读取上游响应，不做格式转换
+async fn read_passthrough_response(
+    proxy_resp: HyperResponse<Incoming>,
+    log_res_body: bool,
+) -> Result<UpstreamResponse, StatusCode> {
+    let (parts, body) = proxy_resp.into_parts();
+    if is_event_stream(&parts.headers) {
+        return Ok(UpstreamResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: UpstreamBody::Stream(sse_data_stream(body, log_res_body).boxed()),
+            passthrough: true,
+        });
+    }
+    let body_bytes = match BodyExt::collect(body).await {
+        Ok(b) => b.to_bytes(),
+        Err(e) => {
+            tracing::error!("Failed to collect response body: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let content_encoding = parts
+        .headers
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok());
+    let body_bytes = decompress_gzip_if_needed(&body_bytes, content_encoding);
+    if log_res_body {
+        log_full_response(&String::from_utf8_lossy(&body_bytes));
+    }
+    Ok(UpstreamResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body: UpstreamBody::Full(body_bytes),
+        passthrough: true,
+    })
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    has_content_type(headers, "text/event-stream")
+}
+
+fn has_content_type(headers: &HeaderMap, content_type: &str) -> bool {
+    headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains(content_type))
+}
+
+/// 非 SSE 的流式响应（Bedrock event stream / Ollama NDJSON）→ Claude SSE 流
+fn converted_stream_response(
+    mut parts: http::response::Parts,
+    body: Incoming,
+    converter: impl StreamConverter,
+    log_res_body: bool,
+) -> UpstreamResponse {
+    // 原始数据不是 SSE，记录转换后的 SSE
+    let stream = convert_stream(sse_data_stream(body, false), converter).inspect(move |chunk| {
+        if log_res_body && let Ok(s) = std::str::from_utf8(chunk) {
+            tracing::info!("{}", s);
+        }
+    });
+    parts.headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("text/event-stream"),
+    );
+    UpstreamResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body: UpstreamBody::Stream(stream.boxed()),
+        passthrough: false,
+    }
+}
+
+/// 读取上游响应并转换为 Anthropic 格式
+async fn read_upstream_response(
+    proxy_resp: HyperResponse<Incoming>,
+    mode: Mode,
+    model_hint: Option<&str>,
+    conversion_context: openai_compat::ConversionContext,
+    log_res_body: bool,
+) -> Result<UpstreamResponse, StatusCode> {
+    let (parts, body) = proxy_resp.into_parts();
+    let status = parts.status;
+
+    // Bedrock 流式响应：AWS event stream 二进制帧 → Claude 流式事件
+    if matches!(mode, Mode::Bedrock)
+        && has_content_type(&parts.headers, bedrock::EVENTSTREAM_CONTENT_TYPE)
+    {
+        tracing::info!("=== SSE 流式响应开始 (Bedrock event stream) ===");
+        let converter = bedrock::BedrockStreamConverter::default();
+        return Ok(converted_stream_response(
+            parts,
+            body,
+            converter,
+            log_res_body,
+        ));
+    }
+
+    // Ollama 流式响应：NDJSON → Claude 流式事件
+    if matches!(mode, Mode::Ollama) && has_content_type(&parts.headers, ollama::NDJSON_CONTENT_TYPE)
+    {
+        tracing::info!("=== SSE 流式响应开始 (Ollama NDJSON) ===");
+        let converter =
+            ollama::OllamaStreamConverter::new(model_hint, conversion_context.prompt_tools);
+        return Ok(converted_stream_response(
+            parts,
+            body,
+            converter,
+            log_res_body,
+        ));
+    }
+
+    // 在 collect() 之前判断是否为 SSE，避免将整个流缓冲到内存
+    let is_sse = is_event_stream(&parts.headers);
+
+    if is_sse {
+        // SSE：流式透传（或格式转换）+ 实时日志（仅在配置启用时）
+        tracing::info!("=== SSE 流式响应开始 ===");
+        let stream = sse_data_stream(body, log_res_body);
+        let stream = if matches!(mode, Mode::OpenAIResponses) {
+            // OpenAI Responses 流式事件 → Claude 流式事件
+            let converter =
+                openai_compat::ResponsesStreamConverter::new(model_hint, conversion_context);
+            convert_stream(stream, converter).boxed()
+        } else if matches!(mode, Mode::OpenAIChat) {
+            // OpenAI Chat Completions 流式 chunk → Claude 流式事件
+            let converter =
+                openai_compat::chat::ChatStreamConverter::new(model_hint, conversion_context);
+            convert_stream(stream, converter).boxed()
+        } else if matches!(mode, Mode::Gemini) {
+            // Gemini 流式事件 → Claude 流式事件
+            let converter =
+                gemini::GeminiStreamConverter::new(model_hint, conversion_context.tool_aliases);
+            convert_stream(stream, converter).boxed()
+        } else {
+            stream.boxed()
+        };
+        return Ok(UpstreamResponse {
+            status,
+            headers: parts.headers,
+            body: UpstreamBody::Stream(stream),
+            passthrough: false,
+        });
+    }
+
+    // 非 SSE：收集完整响应体后处理
+    let body_bytes = match BodyExt::collect(body).await {
+        Ok(b) => b.to_bytes(),
+        Err(e) => {
+            tracing::error!("Failed to collect response body: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // 检查并解压 gzip 编码的响应体
+    let content_encoding = parts
+        .headers
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok());
+    let body_bytes = decompress_gzip_if_needed(&body_bytes, content_encoding);
+
+    // 记录原始上游响应（用于调试）
+    if matches!(
+        mode,
+        Mode::OpenAIResponses | Mode::OpenAIChat | Mode::Gemini | Mode::Ollama
+    ) && !body_bytes.is_empty()
+        && log_res_body
+    {
+        let raw_body_str = String::from_utf8_lossy(&body_bytes);
+        tracing::info!("=== 原始上游响应 (转换前) ===");
+        tracing::info!("{}", raw_body_str);
+        tracing::info!("=== 原始上游响应结束 ===");
+    }
+
+    let body_bytes = convert_full_body(
+        mode,
+        status,
+        &parts.headers,
+        body_bytes,
+        model_hint,
+        &conversion_context,
+    );
+
+    // 记录响应体
+    if log_res_body {
+        log_full_response(&String::from_utf8_lossy(&body_bytes));
+    }
+
+    Ok(UpstreamResponse {
+        status,
+        headers: parts.headers,
+        body: UpstreamBody::Full(body_bytes),
+        passthrough: false,
+    })
+}
+
+/// 非流式响应体转换为 Anthropic 格式（含错误响应）
+fn convert_full_body(
+    mode: Mode,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body_bytes: Bytes,
+    model_hint: Option<&str>,
+    conversion_context: &openai_compat::ConversionContext,
+) -> Bytes {
+    if matches!(mode, Mode::OpenAIResponses | Mode::OpenAIChat) && !status.is_success() {
+        // OpenAI / Azure OpenAI 错误响应（含内容过滤）→ Claude 错误格式
+        openai_compat::error_body_to_anthropic(status, &body_bytes)
+    } else if matches!(mode, Mode::OpenAIResponses) && !body_bytes.is_empty() {
+        // 如果 oai_api 启用，转换响应体格式：OpenAI Responses → Claude
+        match openai_compat::responses_response_to_anthropic(
+            &body_bytes,
+            model_hint,
+            conversion_context,
+        ) {
+            Ok(converted) => {
+                tracing::debug!(
+                    "🔄 响应体格式转换: OpenAI Responses → Claude ({} bytes → {} bytes)",
+                    body_bytes.len(),
+                    converted.len()
+                );
+                converted
+            }
+            Err(e) => {
+                tracing::warn!("响应体格式转换失败: {}，使用原始响应体", e);
+                body_bytes
+            }
+        }
+    } else if matches!(mode, Mode::OpenAIChat) && !body_bytes.is_empty() {
+        // OpenAI Chat Completions 响应 → Claude 响应
+        match openai_compat::chat::chat_response_to_anthropic(
+            &body_bytes,
+            model_hint,
+            conversion_context,
+        ) {
+            Ok(converted) => converted,
+            Err(e) => {
+                tracing::warn!("响应体格式转换失败: {}，使用原始响应体", e);
+                body_bytes
+            }
+        }
+    } else if matches!(mode, Mode::Bedrock) && !status.is_success() {
+        // Bedrock 错误响应 → Claude 错误格式
+        bedrock::error_body_to_anthropic(status, headers, &body_bytes)
+    } else if matches!(mode, Mode::Bedrock) && conversion_context.count_tokens {
+        // Bedrock CountTokens 响应 → Claude count_tokens 响应
+        bedrock::count_tokens_response_to_anthropic(&body_bytes).unwrap_or_else(|e| {
+            tracing::warn!("响应体格式转换失败: {}，使用原始响应体", e);
+            body_bytes
+        })
+    } else if matches!(mode, Mode::Vertex | Mode::Gemini) && !status.is_success() {
+        // Vertex AI / Gemini 的 Google 风格错误响应 → Claude 错误格式
+        vertex::error_body_to_anthropic(status, &body_bytes)
+    } else if matches!(mode, Mode::Ollama) && !status.is_success() {
+        // Ollama 错误响应 → Claude 错误格式
+        ollama::error_body_to_anthropic(status, &body_bytes)
+    } else if matches!(mode, Mode::Gemini) && !body_bytes.is_empty() {
+        // Gemini 响应 → Claude 响应
+        match gemini::gemini_response_to_anthropic(
+            &body_bytes,
+            model_hint,
+            &conversion_context.tool_aliases,
+        ) {
+            Ok(converted) => converted,
+            Err(e) => {
+                tracing::warn!("响应体格式转换失败: {}，使用原始响应体", e);
+                body_bytes
+            }
+        }
+    } else if matches!(mode, Mode::Ollama) && !body_bytes.is_empty() {
+        // Ollama 响应 → Claude 响应
+        match ollama::ollama_response_to_anthropic(
+            &body_bytes,
+            model_hint,
+            conversion_context.prompt_tools,
+        ) {
+            Ok(converted) => converted,
+            Err(e) => {
+                tracing::warn!("响应体格式转换失败: {}，使用原始响应体", e);
+                body_bytes
+            }
+        }
+    } else {
+        body_bytes
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn responses_upstream() -> UpstreamConfig {
+        UpstreamConfig {
+            mode: Mode::OpenAIResponses,
+            model: "gpt-test".to_string(),
+            ..UpstreamConfig::default()
+        }
+    }
+
+    fn body(request: &serde_json::Value) -> RequestBody {
+        RequestBody::from_request(serde_json::from_value(request.clone()).unwrap())
+    }
+
+    /// 测试只有 model 覆盖时不算改写，原始 Responses 请求体可以直接透传
+    #[test]
+    fn test_model_override_alone_keeps_passthrough() {
+        let mut body = body(&json!({
+            "model": "claude",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}]
+        }));
+        assert!(!prepare_upstream_body(
+            &mut body,
+            &responses_upstream(),
+            true
+        ));
+        assert_eq!(body.value().unwrap().model, "gpt-test");
+    }
+
+    /// 测试服务端工具过滤与 quirks 改写请求时放弃透传
+    #[test]
+    fn test_rewriting_stages_disable_passthrough() {
+        let request = json!({
+            "model": "claude",
+            "max_tokens": 100,
+            "tools": [{"type": "web_search_20250305", "name": "web_search"}],
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+
+        let upstream = UpstreamConfig {
+            server_tools: false,
+            ..responses_upstream()
+        };
+        assert!(prepare_upstream_body(&mut body(&request), &upstream, true));
+
+        let upstream = UpstreamConfig {
+            quirks: Some(vec![Quirk::MaxTokensCap(10)]),
+            ..responses_upstream()
+        };
+        let mut capped = body(&request);
+        assert!(prepare_upstream_body(&mut capped, &upstream, true));
+        assert_eq!(capped.value().unwrap().max_tokens, Some(10));
+    }
+
+    /// 测试 `null` 字段与浮点数 `max_tokens` 的请求仍经过 model 覆盖与格式转换
+    #[test]
+    fn test_loosely_typed_body_is_overridden_and_converted() {
+        let raw = json!({
+            "model": "claude",
+            "max_tokens": 1024.0,
+            "tools": null,
+            "messages": [
+                {"role": "user", "content": null},
+                {"role": "user", "content": "hi"}
+            ]
+        });
+        let mut body = RequestBody::parse(Bytes::from(raw.to_string()));
+        assert!(body.parse_error().is_none());
+        prepare_upstream_body(&mut body, &responses_upstream(), true);
+        assert!(invalid_body_response(&body, Mode::OpenAIResponses).is_none());
+
+        let (bytes, _) = encode_upstream_body(body, &responses_upstream());
+        let converted: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(converted["model"], "gpt-test");
+        assert_eq!(converted["max_output_tokens"], 1024);
+        assert!(converted["input"].is_array());
+        assert!(converted.get("messages").is_none());
+    }
+
+    /// 测试不符合 Messages 请求结构的请求体：Anthropic 上游覆盖 model 后转发，转换型上游回复 400
+    #[test]
+    fn test_invalid_body_overrides_model_or_is_rejected() {
+        let raw = json!({
+            "model": "claude",
+            "max_tokens": 100,
+            "messages": [{"content": "no role"}]
+        });
+        let upstream = UpstreamConfig {
+            model: "claude-override".to_string(),
+            ..UpstreamConfig::default()
+        };
+        let mut body = RequestBody::parse(Bytes::from(raw.to_string()));
+        assert!(invalid_body_response(&body, Mode::AnthropicDirect).is_none());
+        prepare_upstream_body(&mut body, &upstream, true);
+        let sent: serde_json::Value = serde_json::from_slice(&body.into_bytes()).unwrap();
+        assert_eq!(sent["model"], "claude-override");
+        assert_eq!(sent["messages"], raw["messages"]);
+
+        let body = RequestBody::parse(Bytes::from(raw.to_string()));
+        for mode in [Mode::OpenAIResponses, Mode::OpenAIChat, Mode::Bedrock] {
+            let response = invalid_body_response(&body, mode).unwrap();
+            assert_eq!(response.status, StatusCode::BAD_REQUEST);
+            let UpstreamBody::Full(error) = response.body else {
+                panic!("expected a full error body");
+            };
+            let error: serde_json::Value = serde_json::from_slice(&error).unwrap();
+            assert_eq!(error["error"]["type"], "invalid_request_error");
+            assert!(
+                error["error"]["message"]
+                    .as_str()
+                    .unwrap()
+                    .contains("missing field `role`")
+            );
+        }
+    }
+
+    /// 测试本地估算回复 `count_tokens`
+    #[test]
+    fn test_local_count_tokens_response() {
+        let raw = json!({
+            "model": "claude",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hello world, count these tokens"}]
+        });
+        let body = RequestBody::parse(Bytes::from(raw.to_string()));
+        let expected = estimate_request_tokens(body.value().unwrap());
+        assert!(expected > 0);
+        let response = local_count_tokens_response(&body);
+        assert_eq!(response.status, StatusCode::OK);
+        let UpstreamBody::Full(count) = response.body else {
+            panic!("expected a full count body");
+        };
+        let count: serde_json::Value = serde_json::from_slice(&count).unwrap();
+        assert_eq!(count, json!({"input_tokens": expected}));
+    }
+
+    /// 测试按请求路径识别 `count_tokens`
+    #[test]
+    fn test_is_count_tokens() {
+        let headers = HeaderMap::new();
+        let request = |path| ForwardRequest {
+            method: &Method::POST,
+            path,
+            query: "",
+            headers: &headers,
+            body: RequestBody::default(),
+            native_responses: None,
+        };
+        assert!(request("/v1/messages/count_tokens").is_count_tokens());
+        assert!(request("/claude/v1/messages/count_tokens/").is_count_tokens());
+        assert!(!request("/v1/messages").is_count_tokens());
+    }
+}
@@ -0,0 +1,158 @@
+//! `GET /v1/models` 本地模型列表
+//!
+//! Claude CLI 入口返回 Anthropic 格式，`OpenAI` 兼容入口返回 `OpenAI` 格式。
+
+use std::sync::Arc;
+
+use http::{HeaderValue, Method, header};
+use salvo::prelude::*;
+use serde_json::{Value, json};
+
+use crate::{
+    config::AtomicConfig,
+    gateway::{
+        HttpClient,
+        handler::utils::setup_handler_state,
+        models::{
+            ModelCatalogCache, ModelEntry, anthropic_model, anthropic_models_body, list_models,
+            openai_model, openai_models_body,
+        },
+    },
+};
+
+/// Anthropic 模型列表接口的路径后缀
+const MODELS_PATH: &str = "/v1/models";
+
+/// Claude CLI 入口的 `GET /claude/v1/models[/{id}]`
+///
+/// 命中时写入响应并返回 true。
+pub async fn try_serve_anthropic_models(
+    req: &Request,
+    depot: &Depot,
+    res: &mut Response,
+    config: &Arc<AtomicConfig>,
+    client: &Arc<HttpClient>,
+) -> bool {
+    if req.method() != Method::GET {
+        return false;
+    }
+    let Some(path) = parse_models_path(req.uri().path()) else {
+        return false;
+    };
+    let Ok(cache) = depot.obtain::<Arc<ModelCatalogCache>>() else {
+        tracing::error!("ModelCatalogCache not found in depot");
+        return false;
+    };
+
+    let models = list_models(&config.get(), client, cache).await;
+    tracing::info!("✅ 本地生成模型列表: {} 个模型", models.len());
+    let (status, body) = match path {
+        ModelsPath::List => (StatusCode::OK, anthropic_models_body(&models)),
+        ModelsPath::Model(id) => find_model(&models, id).map_or_else(
+            || {
+                let message = format!("model: {id}");
+                let error = json!({ "type": "not_found_error", "message": message });
+                (
+                    StatusCode::NOT_FOUND,
+                    json!({ "type": "error", "error": error }),
+                )
+            },
+            |model| (StatusCode::OK, anthropic_model(model)),
+        ),
+    };
+    res.status_code(status);
+    render_json(res, &body);
+    true
+}
+
+/// `GET /openai/v1/models[/{id}]`
+#[handler]
+pub async fn openai_models(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let (config, _, client) = match setup_handler_state(depot) {
+        Ok(v) => v,
+        Err(e) => {
+            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Failed to get dependencies from depot: {e}");
+            return;
+        }
+    };
+    let Ok(cache) = depot.obtain::<Arc<ModelCatalogCache>>() else {
+        res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+        tracing::error!("ModelCatalogCache not found in depot");
+        return;
+    };
+
+    let models = list_models(&config.get(), client, cache).await;
+    let (status, body) = req.param::<String>("id").map_or_else(
+        || (StatusCode::OK, openai_models_body(&models)),
+        |id| {
+            find_model(&models, &id).map_or_else(
+                || {
+                    let message = format!("The model `{id}` does not exist.");
+                    let error = json!({
+                        "message": message,
+                        "type": "invalid_request_error",
+                        "param": null,
+                        "code": "model_not_found"
+                    });
+                    (StatusCode::NOT_FOUND, json!({ "error": error }))
+                },
+                |model| (StatusCode::OK, openai_model(model)),
+            )
+        },
+    );
+    res.status_code(status);
+    render_json(res, &body);
+}
+
+/// 模型接口路径
+#[derive(Debug, PartialEq, Eq)]
+enum ModelsPath<'a> {
+    /// `/v1/models`
+    List,
+    /// `/v1/models/{id}`
+    Model(&'a str),
+}
+
+fn parse_models_path(path: &str) -> Option<ModelsPath<'_>> {
+    let index = path.find(MODELS_PATH)?;
+    let rest = &path[index + MODELS_PATH.len()..];
+    if rest.is_empty() || rest == "/" {
+        return Some(ModelsPath::List);
+    }
+    rest.strip_prefix('/')
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+        .map(ModelsPath::Model)
+}
+
+fn find_model<'a>(models: &'a [ModelEntry], id: &str) -> Option<&'a ModelEntry> {
+    models.iter().find(|model| model.id == id)
+}
+
+fn render_json(res: &mut Response, body: &Value) {
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res.body(body.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试识别模型列表与单个模型路径
+    #[test]
+    fn test_parse_models_path() {
+        assert_eq!(
+            parse_models_path("/claude/v1/models"),
+            Some(ModelsPath::List)
+        );
+        assert_eq!(
+            parse_models_path("/claude/v1/models/glm-4.7"),
+            Some(ModelsPath::Model("glm-4.7"))
+        );
+        assert_eq!(parse_models_path("/claude/v1/messages"), None);
+        assert_eq!(parse_models_path("/claude/v1/models/a/b"), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! `OpenAI` 兼容入口
+//!
+//! 请求转换为 Anthropic 格式后交给 [`forward_to_upstream`]，
+//! 与 Claude CLI 入口共用 upstream 选择、mode 转换与 token 统计。
+
+use futures_util::StreamExt;
+use http::{HeaderMap, HeaderValue, Method, header};
+use salvo::{http::ResBody, prelude::*};
+
+use crate::gateway::{
+    handler::{
+        forward::{ForwardRequest, UpstreamBody, forward_to_upstream},
+        request::{get_req_body, log_request_meta},
+        utils::setup_handler_state,
+    },
+    openai_compat::inbound::{
+        chat::{anthropic_response_to_chat, chat_request_to_anthropic},
+        chat_stream::ChatStreamConverter,
+        openai_error_body,
+        responses::{anthropic_response_to_responses, responses_request_to_anthropic},
+        responses_stream::ResponsesEventStreamConverter,
+    },
+    pipeline::RequestBody,
+    sse::convert_stream,
+};
+
+/// 转发给上游时使用的 Anthropic 接口路径
+const ANTHROPIC_MESSAGES_PATH: &str = "/v1/messages";
+/// 转发给 Anthropic 上游时附带的 API 版本
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// `POST /openai/v1/chat/completions`
+#[handler]
+pub async fn openai_chat_completions(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let (config, stats, client) = match setup_handler_state(depot) {
+        Ok(v) => v,
+        Err(e) => {
+            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Failed to get dependencies from depot: {e}");
+            return;
+        }
+    };
+
+    log_request_meta(
+        req.method().as_str(),
+        req.uri().to_string().as_str(),
+        req.headers(),
+    );
+
+    let body_bytes = match get_req_body(req).await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("{e}");
+            render_openai_error(res, StatusCode::BAD_REQUEST, &e.to_string());
+            return;
+        }
+    };
+
+    let (anthropic_body, info) = match chat_request_to_anthropic(&body_bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Chat 请求转换失败: {}", e);
+            render_openai_error(res, StatusCode::BAD_REQUEST, &e);
+            return;
+        }
+    };
+    tracing::debug!(
+        "🔄 请求体格式转换: OpenAI Chat → Claude ({} bytes)",
+        body_bytes.len()
+    );
+
+    let headers = anthropic_request_headers(info.stream);
+    let request = ForwardRequest {
+        method: &Method::POST,
+        path: ANTHROPIC_MESSAGES_PATH,
+        query: "",
+        headers: &headers,
+        body: RequestBody::from_request(anthropic_body),
+        native_responses: None,
+    };
+    let upstream_res = match forward_to_upstream(config, stats, client, request).await {
+        Ok(v) => v,
+        Err(status) => {
+            render_openai_error(res, status, "Upstream request failed.");
+            return;
+        }
+    };
+
+    res.status_code(upstream_res.status);
+    match upstream_res.body {
+        UpstreamBody::Stream(stream) => {
+            set_content_type(res, "text/event-stream");
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            let stream = convert_stream(stream, ChatStreamConverter::new(info));
+            res.body(ResBody::stream(
+                stream.map(Ok::<bytes::Bytes, std::convert::Infallible>),
+            ));
+        }
+        UpstreamBody::Full(body) if upstream_res.status.is_success() => {
+            set_content_type(res, "application/json");
+            match anthropic_response_to_chat(&body, &info) {
+                Ok(converted) => {
+                    res.body(converted.to_vec());
+                }
+                Err(e) => {
+                    tracing::warn!("响应体格式转换失败: {}", e);
+                    render_openai_error(res, StatusCode::BAD_GATEWAY, &e);
+                }
+            }
+        }
+        UpstreamBody::Full(body) => {
+            set_content_type(res, "application/json");
+            res.body(openai_error_body(&body, "api_error").to_vec());
+        }
+    }
+}
+
+/// `POST /openai/v1/responses`
+///
+/// 选中 `openai_responses` upstream 时原样透传，其余 mode 经 Anthropic 格式中转。
+#[handler]
+pub async fn openai_responses(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let (config, stats, client) = match setup_handler_state(depot) {
+        Ok(v) => v,
+        Err(e) => {
+            res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Failed to get dependencies from depot: {e}");
+            return;
+        }
+    };
+
+    log_request_meta(
+        req.method().as_str(),
+        req.uri().to_string().as_str(),
+        req.headers(),
+    );
+
+    let body_bytes = match get_req_body(req).await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("{e}");
+            render_openai_error(res, StatusCode::BAD_REQUEST, &e.to_string());
+            return;
+        }
+    };
+
+    let (anthropic_body, info) = match responses_request_to_anthropic(&body_bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Responses 请求转换失败: {}", e);
+            render_openai_error(res, StatusCode::BAD_REQUEST, &e);
+            return;
+        }
+    };
+    tracing::debug!(
+        "🔄 请求体格式转换: OpenAI Responses → Claude ({} bytes)",
+        body_bytes.len()
+    );
+
+    let headers = anthropic_request_headers(info.stream);
+    let request = ForwardRequest {
+        method: &Method::POST,
+        path: ANTHROPIC_MESSAGES_PATH,
+        query: "",
+        headers: &headers,
+        body: RequestBody::from_request(anthropic_body),
+        native_responses: Some(body_bytes),
+    };
+    let upstream_res = match forward_to_upstream(config, stats, client, request).await {
+        Ok(v) => v,
+        Err(status) => {
+            render_openai_error(res, status, "Upstream request failed.");
+            return;
+        }
+    };
+
+    res.status_code(upstream_res.status);
+    if upstream_res.passthrough {
+        if let Some(content_type) = upstream_res.headers.get(header::CONTENT_TYPE) {
+            res.headers_mut()
+                .insert(header::CONTENT_TYPE, content_type.clone());
+        }
+        match upstream_res.body {
+            UpstreamBody::Stream(stream) => {
+                res.headers_mut()
+                    .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+                res.body(ResBody::stream(
+                    stream.map(Ok::<bytes::Bytes, std::convert::Infallible>),
+                ));
+            }
+            UpstreamBody::Full(body) => {
+                res.body(body.to_vec());
+            }
+        }
+        return;
+    }
+
+    match upstream_res.body {
+        UpstreamBody::Stream(stream) => {
+            set_content_type(res, "text/event-stream");
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            let stream = convert_stream(stream, ResponsesEventStreamConverter::new(info));
+            res.body(ResBody::stream(
+                stream.map(Ok::<bytes::Bytes, std::convert::Infallible>),
+            ));
+        }
+        UpstreamBody::Full(body) if upstream_res.status.is_success() => {
+            set_content_type(res, "application/json");
+            match anthropic_response_to_responses(&body, &info) {
+                Ok(converted) => {
+                    res.body(converted.to_vec());
+                }
+                Err(e) => {
+                    tracing::warn!("响应体格式转换失败: {}", e);
+                    render_openai_error(res, StatusCode::BAD_GATEWAY, &e);
+                }
+            }
+        }
+        UpstreamBody::Full(body) => {
+            set_content_type(res, "application/json");
+            res.body(openai_error_body(&body, "api_error").to_vec());
+        }
+    }
+}
+
+/// 转发给上游的请求头
+fn anthropic_request_headers(stream: bool) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        "anthropic-version",
+        HeaderValue::from_static(ANTHROPIC_VERSION),
+    );
+    if stream {
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("text/event-stream"),
+        );
+    }
+    headers
+}
+
+fn set_content_type(res: &mut Response, content_type: &'static str) {
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+}
+
+/// 以 `OpenAI` 错误格式回复
+fn render_openai_error(res: &mut Response, status: StatusCode, message: &str) {
+    let error_type = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "api_error"
+    };
+    let body = serde_json::json!({ "error": { "message": message } }).to_string();
+    res.status_code(status);
+    set_content_type(res, "application/json");
+    res.body(openai_error_body(body.as_bytes(), error_type).to_vec());
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::gateway::{
+    anthropic::{Content, ContentBlock, MessagesRequest, Role},
+    pipeline::Transform,
+};
+
+/// Claude CLI 读取文件的工具名
+const READ_TOOL: &str = "Read";
+
+/// 一次 `Read` 调用的读取范围
+struct ReadRange {
+    path: String,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl ReadRange {
+    fn from_input(input: &Value) -> Option<Self> {
+        Some(Self {
+            path: input.get("file_path")?.as_str()?.to_string(),
+            offset: input.get("offset").and_then(Value::as_u64),
+            limit: input.get("limit").and_then(Value::as_u64),
+        })
+    }
+
+    const fn is_full(&self) -> bool {
+        self.offset.is_none() && self.limit.is_none()
+    }
+
+    /// 本次读取是否覆盖了 `earlier` 读取的范围（同一文件的完整读取或相同范围）
+    fn covers(&self, earlier: &Self) -> bool {
+        self.path == earlier.path
+            && (self.is_full() || (self.offset == earlier.offset && self.limit == earlier.limit))
+    }
+}
+
+/// 将历史中重复的 `Read` 结果替换为简短说明
+///
+/// Agent 会话经常多次读取同一文件，每份内容都留在历史中并随每次请求重复发送。
+/// - 内容与之前一次覆盖同一范围的读取相同：替换较新的结果为
+///   `[content identical to earlier read of <path>]`，较早的消息保持不变，不影响提示词缓存
+/// - 被后续覆盖同一范围、内容不同的读取取代：替换较早的结果为
+///   `[superseded by later read of <path>]`。这会改写历史中较早的消息，
+///   提示词缓存从该消息起失效，因此默认关闭
+///
+/// 覆盖指同一文件的完整读取，或 `offset` / `limit` 相同的读取。
+pub struct ReadDedup;
+
+impl Transform for ReadDedup {
+    fn name(&self) -> &'static str {
+        "read_dedup"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        dedupe_file_reads(body)
+    }
+}
+
+/// 历史中的一次 `Read` 结果
+struct ReadResult<'a> {
+    message: usize,
+    block: usize,
+    range: &'a ReadRange,
+    /// 序列化后的结果内容
+    content: String,
+}
+
+fn dedupe_file_reads(request: &mut MessagesRequest) -> bool {
+    let messages = &mut request.messages;
+
+    // tool_use_id → 读取范围
+    let mut reads = HashMap::new();
+    for block in messages
+        .iter()
+        .filter(|message| message.role == Role::Assistant)
+        .flat_map(|message| message.content.blocks())
+    {
+        if let ContentBlock::ToolUse(tool_use) = block
+            && tool_use.name == READ_TOOL
+            && let Some(range) = ReadRange::from_input(&tool_use.input)
+        {
+            reads.insert(tool_use.id.clone(), range);
+        }
+    }
+    if reads.len() < 2 {
+        return false;
+    }
+
+    // 按时间顺序收集 Read 结果
+    let mut results = Vec::new();
+    for (message_index, message) in messages.iter().enumerate() {
+        if message.role != Role::User {
+            continue;
+        }
+        for (block_index, block) in message.content.blocks().iter().enumerate() {
+            let ContentBlock::ToolResult(result) = block else {
+                continue;
+            };
+            if result.is_error() {
+                continue;
+            }
+            let Some(range) = reads.get(&result.tool_use_id) else {
+                continue;
+            };
+            let Some(content) = result
+                .content
+                .as_ref()
+                .and_then(|content| serde_json::to_string(content).ok())
+            else {
+                continue;
+            };
+            results.push(ReadResult {
+                message: message_index,
+                block: block_index,
+                range,
+                content,
+            });
+        }
+    }
+
+    let mut replaced = 0usize;
+    let mut saved_chars = 0usize;
+    for (index, read) in results.iter().enumerate() {
+        let Some(stub) = stub_for(read, &results[..index], &results[index + 1..]) else {
+            continue;
+        };
+        if stub.len() >= read.content.len() {
+            continue;
+        }
+        if let ContentBlock::ToolResult(result) =
+            &mut messages[read.message].content.blocks_mut()[read.block]
+        {
+            saved_chars += read.content.len() - stub.len();
+            result.content = Some(Content::Text(stub));
+            replaced += 1;
+        }
+    }
+
+    if replaced == 0 {
+        return false;
+    }
+    tracing::info!(
+        "🧹 已去重文件读取: 替换 {} 个重复的 Read 结果, 节省约 {} 字符 (~{} tokens)",
+        replaced,
+        saved_chars,
+        saved_chars / 4
+    );
+    true
+}
+
+/// 一次读取结果应替换成的说明，保留原内容时返回 None
+fn stub_for(
+    read: &ReadResult<'_>,
+    earlier: &[ReadResult<'_>],
+    later: &[ReadResult<'_>],
+) -> Option<String> {
+    let path = &read.range.path;
+    if later
+        .iter()
+        .any(|newer| newer.range.covers(read.range) && newer.content != read.content)
+    {
+        return Some(format!("[superseded by later read of {path}]"));
+    }
+    // 只与最近一次覆盖该范围的读取比较：文件在两次读取之间变化过时不算重复
+    earlier
+        .iter()
+        .rev()
+        .find(|older| older.range.covers(read.range))
+        .filter(|older| older.content == read.content)
+        .map(|_| format!("[content identical to earlier read of {path}]"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn read(id: &str, input: &Value, output: &str) -> [Value; 2] {
+        [
+            json!({"role": "assistant", "content": [{"type": "tool_use", "id": id, "name": "Read", "input": input}]}),
+            json!({"role": "user", "content": [{"type": "tool_result", "tool_use_id": id, "content": output}]}),
+        ]
+    }
+
+    fn dedupe(messages: &[Value]) -> Option<Vec<Value>> {
+        let mut body = serde_json::from_value(json!({ "messages": messages })).unwrap();
+        ReadDedup.apply(&mut body).then(|| {
+            serde_json::to_value(&body.messages)
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .clone()
+        })
+    }
+
+    fn result(messages: &[Value], index: usize) -> &str {
+        messages[index]["content"][0]["content"].as_str().unwrap()
+    }
+
+    /// 测试内容变化时较早的读取被替换，内容相同时替换较新的读取
+    #[test]
+    fn test_dedupe_same_path() {
+        let v1 = "1\tfn main() {}\n".repeat(20);
+        let v2 = "1\tfn main() { run(); }\n".repeat(20);
+        let path = json!({"file_path": "/repo/src/main.rs"});
+        let messages = [
+            read("t1", &path, &v1),
+            read("t2", &path, &v1),
+            read("t3", &path, &v2),
+        ]
+        .concat();
+        let deduped = dedupe(&messages).unwrap();
+        assert_eq!(
+            result(&deduped, 1),
+            "[superseded by later read of /repo/src/main.rs]"
+        );
+        assert_eq!(
+            result(&deduped, 3),
+            "[superseded by later read of /repo/src/main.rs]"
+        );
+        assert_eq!(result(&deduped, 5), v2);
+
+        let messages = [read("t1", &path, &v1), read("t2", &path, &v1)].concat();
+        let deduped = dedupe(&messages).unwrap();
+        assert_eq!(result(&deduped, 1), v1);
+        assert_eq!(
+            result(&deduped, 3),
+            "[content identical to earlier read of /repo/src/main.rs]"
+        );
+
+        // 文件改回原内容：只与最近一次读取比较，不算重复
+        let messages = [
+            read("t1", &path, &v1),
+            read("t2", &path, &v2),
+            read("t3", &path, &v1),
+        ]
+        .concat();
+        let deduped = dedupe(&messages).unwrap();
+        assert!(result(&deduped, 1).starts_with("[superseded"));
+        assert!(result(&deduped, 3).starts_with("[superseded"));
+        assert_eq!(result(&deduped, 5), v1);
+    }
+
+    /// 测试重复读取相同内容时较早的消息保持不变，已发送的前缀仍可命中缓存
+    #[test]
+    fn test_identical_reads_keep_prefix_stable() {
+        let text = "1\tfn main() {}\n".repeat(20);
+        let path = json!({"file_path": "/repo/src/main.rs"});
+        let mut messages = [read("t1", &path, &text), read("t2", &path, &text)].concat();
+        let first = dedupe(&messages).unwrap();
+
+        messages.extend(read("t3", &path, &text));
+        let second = dedupe(&messages).unwrap();
+        assert_eq!(second[..first.len()], first[..]);
+        assert!(result(&second, 5).starts_with("[content identical"));
+    }
+
+    /// 测试不同范围的读取与不同文件不被替换，完整读取取代内容不同的部分读取
+    #[test]
+    fn test_dedupe_ranges() {
+        let text = "x".repeat(500);
+        let head = json!({"file_path": "/a.rs", "offset": 1, "limit": 100});
+        let tail = json!({"file_path": "/a.rs", "offset": 100, "limit": 100});
+        let other = json!({"file_path": "/b.rs"});
+        let messages = [
+            read("t1", &head, &text),
+            read("t2", &tail, &text),
+            read("t3", &other, &text),
+        ]
+        .concat();
+        assert!(dedupe(&messages).is_none());
+
+        // 部分读取不能代替之后的完整读取
+        let full = json!({"file_path": "/a.rs"});
+        let mut messages = messages;
+        messages.extend(read("t4", &full, &text));
+        assert!(dedupe(&messages).is_none());
+
+        let changed = "y".repeat(500);
+        messages.extend(read("t5", &full, &changed));
+        let deduped = dedupe(&messages).unwrap();
+        assert!(result(&deduped, 1).starts_with("[superseded"));
+        assert!(result(&deduped, 3).starts_with("[superseded"));
+        assert_eq!(result(&deduped, 5), text);
+        assert!(result(&deduped, 7).starts_with("[superseded"));
+        assert_eq!(result(&deduped, 9), changed);
+    }
+}
@@ -7,17 +7,21 @@ use http::HeaderMap;
 use http_body_util::BodyExt;
 use hyper::header::{HeaderName, HeaderValue};
 use salvo::prelude::*;
-use serde_json::{Value, from_slice, json, to_vec};
+use serde_json::{Map, Value, from_slice, to_vec};
 use tracing::info;
 
 use crate::{
-    config::{Config, Mode},
+    config::{Config, Mode, OptimizationConfig},
     gateway::{
+        RequestStats,
+        anthropic::MessagesRequest,
         handler::{
-            content_tag::filter_messages_content, system_prompt::filter_system_prompts,
-            tool_desc::filter_tools_by_description,
+            content_tag::ContentTagFilter, read_dedup::ReadDedup,
+            system_prompt::SystemPromptFilter, tool_desc::ToolDescriptionFilter,
+            tool_result::ToolResultTruncation,
         },
         optimization::try_local_optimization,
+        pipeline::{RequestBody, Transform},
         service::log_full_response,
     },
 };
@@ -33,56 +37,66 @@ pub async fn get_req_body(req: &mut Request) -> Result<Bytes> {
     Ok(body_bytes)
 }
 
-pub async fn filter_req_body(body_bytes: &[u8]) -> Result<Bytes> {
-    let mut current = Bytes::copy_from_slice(body_bytes);
-
+/// Claude CLI 请求的过滤阶段（必须在注入系统提示词之后执行）
+pub fn filter_req_body(
+    body: &mut RequestBody,
+    optimizations: &OptimizationConfig,
+    stats: &RequestStats,
+) {
+    let tool_results = ToolResultTruncation {
+        policy: &optimizations.tool_result,
+        stats,
+    };
     // 过滤 system 数组中占用大量 tokens 的提示词
-    if !current.is_empty()
-        && let Some(filtered) = filter_system_prompts(&current)
-    {
-        current = filtered;
-    }
-
+    body.apply(&SystemPromptFilter);
     // 过滤 messages.content 中占用大量 tokens 的无用标签
-    if !current.is_empty()
-        && let Some(filtered) = filter_messages_content(&current)
-    {
-        current = filtered;
+    body.apply(&ContentTagFilter);
+    // 历史中被后续读取覆盖的文件内容替换为简短说明
+    if optimizations.enable_read_dedup {
+        body.apply(&ReadDedup);
     }
-
+    // 按策略截断超大的 tool_result
+    body.apply(&tool_results);
     // 过滤 tools.description 命中关键词的工具定义
-    if !current.is_empty()
-        && let Some(filtered) = filter_tools_by_description(&current)
-    {
-        current = filtered;
-    }
-
-    Ok(current)
+    body.apply(&ToolDescriptionFilter);
 }
 
-/// 尝试覆盖请求体中的 model 字段
-pub fn override_model_in_body(body_bytes: &[u8], model: &str) -> Option<Bytes> {
-    let json = from_slice::<Value>(body_bytes).ok()?;
-    let original_model = json.get("model").and_then(|m| m.as_str());
+/// 使用选中 upstream 的 model 覆盖请求体中的 model 字段
+pub struct ModelOverride<'a>(pub &'a str);
 
-    if let Some(original) = original_model {
-        info!("原始 model: {} -> 覆盖为: {}", original, model);
+impl Transform for ModelOverride<'_> {
+    fn name(&self) -> &'static str {
+        "model_override"
     }
 
-    let mut modified = json;
-    modified["model"] = json!(model);
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        if body.model == self.0 {
+            return false;
+        }
+        if !body.model.is_empty() {
+            info!("原始 model: {} -> 覆盖为: {}", body.model, self.0);
+        }
+        self.0.clone_into(&mut body.model);
+        true
+    }
+}
 
-    to_vec(&modified).ok().map(Into::into)
+/// 尝试覆盖请求体中的 model 字段（用于不经过 Anthropic 管线的原始请求体，如 `OpenAI` 格式）
+#[must_use]
+pub fn override_model_in_body(body_bytes: &[u8], model: &str) -> Option<Bytes> {
+    let mut json = from_slice::<Map<String, Value>>(body_bytes).ok()?;
+    json.insert("model".to_string(), Value::String(model.to_string()));
+    to_vec(&json).ok().map(Into::into)
 }
 
 pub fn req_local_intercept(
     req: &Request,
     res: &mut Response,
-    body_bytes: &Bytes,
+    body: &RequestBody,
     config: &Guard<Arc<Config>>,
 ) -> bool {
     if let Some(local_response) = try_local_optimization(
-        body_bytes,
+        body.value(),
         req.uri().to_string().as_str(),
         &config.optimizations,
     ) {
@@ -111,7 +125,13 @@ pub fn req_local_intercept(
     false
 }
 
-pub fn make_proxy_url<'a>(endpoint: &'a str, mode: Mode, req: &Request) -> (String, Cow<'a, str>) {
+#[must_use]
+pub fn make_proxy_url<'a>(
+    endpoint: &'a str,
+    mode: Mode,
+    original_path: &str,
+    query: &str,
+) -> (String, Cow<'a, str>) {
     // 解析 endpoint
     let host_str = endpoint
         .strip_prefix("https://")
@@ -121,8 +141,6 @@ pub fn make_proxy_url<'a>(endpoint: &'a str, mode: Mode, req: &Request) -> (Stri
     let (host, base_path) = host_str.split_once('/').unwrap_or((host_str, ""));
 
     // 构建上游 URL
-    let original_path = req.uri().path();
-    let query = req.uri().query().unwrap_or("");
     let query_str = if query.is_empty() {
         String::new()
     } else {
@@ -147,11 +165,12 @@ pub fn make_proxy_url<'a>(endpoint: &'a str, mode: Mode, req: &Request) -> (Stri
     };
 
     let mut upstream_url = format!("{host}{new_path}");
-    upstream_url = upstream_url.replace("?beta=true", "");
 
     // 只有当 oai_api=true 时才将 messages 替换为 responses
     if matches!(mode, Mode::OpenAIResponses) {
         upstream_url = upstream_url.replace("messages", "responses");
+    } else if matches!(mode, Mode::OpenAIChat) {
+        upstream_url = upstream_url.replace("messages", "chat/completions");
     }
     upstream_url = upstream_url.replace("claude/", "");
     while upstream_url.contains("//") {
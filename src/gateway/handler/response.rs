@@ -2,11 +2,15 @@ use std::io::Read;
 
 use bytes::Bytes;
 use flate2::read::GzDecoder;
+use futures_util::{Stream, StreamExt};
+use http_body_util::BodyStream;
+use hyper::body::Incoming;
 
 /// 尝试解压 gzip 编码的响应体
 ///
 /// 检查 content-encoding 头部，如果是 gzip 则自动解压。
 /// 返回解压后的字节和是否进行了解压的标志。
+#[must_use]
 pub fn decompress_gzip_if_needed(body_bytes: &Bytes, content_encoding: Option<&str>) -> Bytes {
     // 检查是否为 gzip 编码
     let is_gzip = content_encoding.is_some_and(|enc| enc.to_lowercase().contains("gzip"));
@@ -33,3 +37,26 @@ pub fn decompress_gzip_if_needed(body_bytes: &Bytes, content_encoding: Option<&s
         }
     }
 }
+
+/// 把上游 SSE 响应体转为字节流，并按配置实时记录每个分片
+pub fn sse_data_stream(body: Incoming, log_body: bool) -> impl Stream<Item = Bytes> + Send {
+    BodyStream::new(body)
+        .inspect(move |frame| {
+            if log_body
+                && let Ok(f) = frame
+                && let Some(data) = f.data_ref()
+                && let Ok(s) = std::str::from_utf8(data)
+            {
+                tracing::info!("{}", s);
+            }
+        })
+        .filter_map(|frame| async move {
+            match frame {
+                Ok(f) => f.into_data().ok(),
+                Err(e) => {
+                    tracing::error!("SSE 流读取错误: {}", e);
+                    None
+                }
+            }
+        })
+}
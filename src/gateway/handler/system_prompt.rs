@@ -1,4 +1,7 @@
-use serde_json::{Value, from_slice, to_vec};
+use crate::gateway::{
+    anthropic::{CacheControl, Content, ContentBlock, MessagesRequest, TextBlock},
+    pipeline::Transform,
+};
 
 pub const CUSTOM_SYSTEM_PROMPT: &str = "You are an interactive CLI tool that helps users with software engineering tasks. Use the instructions below and the tools available to you to assist the user.
 
@@ -47,52 +50,73 @@ const SYSTEM_PROMPT_FILTER_MARKERS: &[&str] = &[
 /// 过滤请求体中的 system 数组，移除包含特定文本的元素
 ///
 /// Claude CLI 发送的请求中，system 数组包含很长的提示词文本，
-/// 这些文本会占用大量 tokens。此函数移除包含任意标记文本的元素。
-pub fn filter_system_prompts(body_bytes: &[u8]) -> Option<bytes::Bytes> {
-    let mut json = from_slice::<Value>(body_bytes).ok()?;
+/// 这些文本会占用大量 tokens。此阶段移除包含任意标记文本的元素。
+pub struct SystemPromptFilter;
 
+impl Transform for SystemPromptFilter {
+    fn name(&self) -> &'static str {
+        "system_prompt_filter"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        filter_system_prompts(body)
+    }
+}
+
+fn filter_system_prompts(request: &mut MessagesRequest) -> bool {
     // 获取 system 数组
-    let system = json.get_mut("system")?.as_array_mut()?;
+    let Some(Content::Blocks(system)) = request.system.as_mut() else {
+        return false;
+    };
 
     let original_len = system.len();
 
     // 过滤掉包含任意标记文本的元素
     system.retain(|item| {
-        item.get("text")
-            .and_then(|t| t.as_str())
-            .is_none_or(|text| {
-                !SYSTEM_PROMPT_FILTER_MARKERS
-                    .iter()
-                    .any(|marker| text.contains(marker))
-            })
+        item.as_text().is_none_or(|text| {
+            !SYSTEM_PROMPT_FILTER_MARKERS
+                .iter()
+                .any(|marker| text.contains(marker))
+        })
     });
 
     // 如果有元素被移除，记录日志
-    if system.len() < original_len {
-        tracing::info!(
-            "🧹 已过滤 system 数组: {} 个元素 → {} 个元素 (移除了 {} 个)",
-            original_len,
-            system.len(),
-            original_len - system.len()
-        );
+    if system.len() == original_len {
+        return false;
     }
-
-    to_vec(&json).ok().map(Into::into)
+    tracing::info!(
+        "🧹 已过滤 system 数组: {} 个元素 → {} 个元素 (移除了 {} 个)",
+        original_len,
+        system.len(),
+        original_len - system.len()
+    );
+    true
 }
 
 /// 插入自定义系统提示词到 system 数组
 ///
 /// 将自定义提示词插入到请求体的 system 数组开头，确保自定义提示优先被模型处理。
-/// 如果请求中没有 system 字段，会创建一个新的 system 数组。
+/// 如果请求中没有 system 字段，会创建一个新的 system 数组；字符串形式的 system 转为数组。
 ///
-/// 此函数还会从原始 system 数组中提取 <env>...</env> 标签内的环境信息，
+/// 此阶段还会从原始 system 数组中提取 <env>...</env> 标签内的环境信息，
 /// 并追加到自定义提示词的末尾。
-pub fn insert_custom_system_prompt(body_bytes: &[u8], custom_prompt: &str) -> Option<bytes::Bytes> {
-    let mut json = from_slice::<Value>(body_bytes).ok()?;
+pub struct CustomSystemPrompt(pub &'static str);
 
+impl Transform for CustomSystemPrompt {
+    fn name(&self) -> &'static str {
+        "custom_system_prompt"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        insert_custom_system_prompt(body, self.0);
+        true
+    }
+}
+
+fn insert_custom_system_prompt(request: &mut MessagesRequest, custom_prompt: &str) {
     // 从原始 system 数组中提取环境信息
-    let (env_info, has_env) =
-        extract_env_info(&json).map_or((None, false), |info| (Some(info), true));
+    let env_info = request.system.as_ref().and_then(extract_env_info);
+    let has_env = env_info.is_some();
 
     // 如果有环境信息，追加到自定义提示词末尾
     let final_prompt = env_info.map_or_else(
@@ -101,53 +125,39 @@ pub fn insert_custom_system_prompt(body_bytes: &[u8], custom_prompt: &str) -> Op
     );
 
     // 创建自定义提示词的元素
-    let prompt_obj = serde_json::json!({
-        "cache_control": {
-            "type": "ephemeral"
-        },
-        "text": final_prompt,
-        "type": "text"
-    });
-
-    // 确保 system 字段存在
-    if !json.as_object()?.contains_key("system") {
-        json.as_object_mut()?
-            .insert("system".to_string(), Value::Array(vec![]));
-    }
+    let mut prompt_block = TextBlock::new(final_prompt);
+    prompt_block.cache_control = Some(CacheControl::ephemeral());
 
-    // 获取 system 数组并插入自定义提示词
-    let system = json.get_mut("system")?.as_array_mut()?;
-    system.insert(0, prompt_obj);
+    // 获取 system 数组（不存在时创建）并插入自定义提示词
+    let system = request
+        .system
+        .get_or_insert_with(|| Content::Blocks(Vec::new()))
+        .make_blocks();
+    system.insert(0, ContentBlock::Text(prompt_block));
 
     tracing::info!(
         "✅ 已插入自定义系统提示词，当前 system 数组长度: {}{}",
         system.len(),
         if has_env { " (包含环境信息)" } else { "" }
     );
-
-    to_vec(&json).ok().map(Into::into)
 }
 
-/// 从 JSON 中的 system 数组提取 <env>...</env> 标签内的环境信息
+/// 从 system 中提取 <env>...</env> 标签内的环境信息
 ///
-/// 遍历 system 数组中每个元素的 text 字段，查找 <env> 标签并提取其内容。
+/// 遍历 system 中每段文本，查找 <env> 标签并提取其内容。
 /// 如果找到多个 <env> 标签，只返回第一个。
-fn extract_env_info(json: &Value) -> Option<String> {
-    let system = json.get("system")?.as_array()?;
-
-    for item in system {
-        if let Some(text) = item.get("text")?.as_str() {
-            // 查找 <env> 标签内容
-            if let Some(start) = text.find("<env>") {
-                let start = start + 5; // 跳过 "<env>"
-                if let Some(end) = text[start..].find("</env>") {
-                    let env_content = &text[start..start + end];
-                    // 构建完整的格式化文本
-                    return Some(format!(
-                        "Here is useful information about the environment you are running in:\n<env>\n{}\n</env>",
-                        env_content.trim()
-                    ));
-                }
+fn extract_env_info(system: &Content) -> Option<String> {
+    for text in system.texts() {
+        // 查找 <env> 标签内容
+        if let Some(start) = text.find("<env>") {
+            let start = start + 5; // 跳过 "<env>"
+            if let Some(end) = text[start..].find("</env>") {
+                let env_content = &text[start..start + end];
+                // 构建完整的格式化文本
+                return Some(format!(
+                    "Here is useful information about the environment you are running in:\n<env>\n{}\n</env>",
+                    env_content.trim()
+                ));
             }
         }
     }
@@ -1,4 +1,8 @@
-use serde_json::{Value, from_slice, to_vec};
+use crate::gateway::{
+    anthropic::{MessagesRequest, ToolChoice, ToolChoiceKind},
+    openai_compat::is_server_tool,
+    pipeline::Transform,
+};
 
 /// 需要从 tools[].description 中过滤的关键词
 const TOOLS_DESCRIPTION_FILTER_KEYWORDS: &[&str] = &[
@@ -20,26 +24,88 @@ fn should_remove_tool_by_description(description: &str) -> bool {
 }
 
 /// 过滤 tools 数组中 description 命中关键词的元素
-pub fn filter_tools_by_description(body_bytes: &[u8]) -> Option<bytes::Bytes> {
-    let mut json = from_slice::<Value>(body_bytes).ok()?;
+pub struct ToolDescriptionFilter;
 
-    let tools = json.get_mut("tools")?.as_array_mut()?;
+impl Transform for ToolDescriptionFilter {
+    fn name(&self) -> &'static str {
+        "tool_description_filter"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        filter_tools_by_description(body)
+    }
+}
+
+fn filter_tools_by_description(request: &mut MessagesRequest) -> bool {
+    let tools = &mut request.tools;
     let original_len = tools.len();
 
     tools.retain(|tool| {
-        tool.get("description")
-            .and_then(|d| d.as_str())
+        tool.description
+            .as_deref()
             .is_none_or(|description| !should_remove_tool_by_description(description))
     });
 
-    if tools.len() < original_len {
-        tracing::info!(
-            "🧹 已过滤 tools 数组: {} 个元素 → {} 个元素 (移除了 {} 个)",
-            original_len,
-            tools.len(),
-            original_len - tools.len()
-        );
+    if tools.len() == original_len {
+        return false;
     }
+    tracing::info!(
+        "🧹 已过滤 tools 数组: {} 个元素 → {} 个元素 (移除了 {} 个)",
+        original_len,
+        tools.len(),
+        original_len - tools.len()
+    );
+    true
+}
 
-    to_vec(&json).ok().map(Into::into)
+/// 移除 tools 数组中的 Anthropic 服务端工具
+///
+/// 用于不支持 `web_search` / `web_fetch` / `code_execution` 的 upstream。
+/// 若 `tool_choice` 强制指定了被移除的工具，则回退为 `auto`。
+pub struct ServerToolFilter;
+
+impl Transform for ServerToolFilter {
+    fn name(&self) -> &'static str {
+        "server_tool_filter"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        filter_server_tools(body)
+    }
+}
+
+fn filter_server_tools(request: &mut MessagesRequest) -> bool {
+    let tools = &mut request.tools;
+    let original_len = tools.len();
+    let mut removed_names = Vec::new();
+
+    tools.retain(|tool| {
+        if is_server_tool(tool) {
+            removed_names.push(tool.name.clone());
+            return false;
+        }
+        true
+    });
+
+    if tools.len() == original_len {
+        return false;
+    }
+
+    tracing::info!(
+        "🧹 已移除服务端工具: {} 个 ({})",
+        original_len - tools.len(),
+        removed_names.join(", ")
+    );
+
+    if tools.is_empty() {
+        request.tool_choice = None;
+    } else if request
+        .tool_choice
+        .as_ref()
+        .and_then(|choice| choice.name.as_deref())
+        .is_some_and(|name| removed_names.iter().any(|removed| removed == name))
+    {
+        request.tool_choice = Some(ToolChoice::new(ToolChoiceKind::Auto));
+    }
+    true
 }
@@ -0,0 +1,294 @@
+use std::sync::atomic::Ordering;
+
+use crate::{
+    config::ToolResultPolicy,
+    gateway::{
+        RequestStats,
+        anthropic::{Content, ContentBlock, MessagesRequest, Role, ToolResultBlock},
+        pipeline::Transform,
+        service::estimate_tokens,
+    },
+};
+
+/// 连续重复达到该行数时折叠
+const MIN_REPEATED_LINES: usize = 3;
+
+/// 按策略截断超大的 `tool_result` 文本
+///
+/// Claude CLI 每一轮都会重新发送完整的历史，单个超大的工具结果（锁文件、构建日志）
+/// 会在之后的每次请求中重复占用大量 tokens：
+/// - 连续重复的行折叠为一行加说明
+/// - 仍超出上限时保留开头与结尾，中间替换为省略说明
+/// - 早于 `old_after_turns` 轮的结果使用更严格的 `old_max_tokens`
+///   （结果跨过阈值时会改写较早的消息，提示词缓存从该消息起失效）
+///
+/// 每次截断记入 [`RequestStats`]。
+pub struct ToolResultTruncation<'a> {
+    pub policy: &'a ToolResultPolicy,
+    pub stats: &'a RequestStats,
+}
+
+impl Transform for ToolResultTruncation<'_> {
+    fn name(&self) -> &'static str {
+        "tool_result_truncation"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        truncate_tool_results(body, self.policy, self.stats)
+    }
+}
+
+fn truncate_tool_results(
+    request: &mut MessagesRequest,
+    policy: &ToolResultPolicy,
+    stats: &RequestStats,
+) -> bool {
+    if policy.max_tokens.is_none() && policy.old_max_tokens.is_none() {
+        return false;
+    }
+
+    let mut truncated = 0u64;
+    let mut saved = 0u64;
+    // 从最新的消息往前遍历，age 为其后 user 消息的条数
+    let mut age = 0usize;
+    for message in request.messages.iter_mut().rev() {
+        if message.role != Role::User {
+            continue;
+        }
+        let limit = limit_for_age(policy, age);
+        age += 1;
+        let Some(limit) = limit else {
+            continue;
+        };
+        for block in message.content.blocks_mut() {
+            let ContentBlock::ToolResult(ToolResultBlock {
+                content: Some(content),
+                ..
+            }) = block
+            else {
+                continue;
+            };
+            for text in tool_result_texts(content) {
+                if let Some(shortened) = truncate_text(text, limit, policy.collapse_repeated_lines)
+                {
+                    truncated += 1;
+                    saved += estimate_tokens(text).saturating_sub(estimate_tokens(&shortened));
+                    *text = shortened;
+                }
+            }
+        }
+    }
+
+    if truncated == 0 {
+        return false;
+    }
+    stats
+        .tool_results_truncated
+        .fetch_add(truncated, Ordering::Relaxed);
+    stats
+        .tool_result_tokens_saved
+        .fetch_add(saved, Ordering::Relaxed);
+    tracing::info!(
+        "✂️ 已截断 tool_result: {} 个, 节省约 {} tokens (累计 {} 个, ~{} tokens)",
+        truncated,
+        saved,
+        stats.tool_results_truncated.load(Ordering::Relaxed),
+        stats.tool_result_tokens_saved.load(Ordering::Relaxed)
+    );
+    true
+}
+
+/// 按结果的轮数选择 token 上限
+fn limit_for_age(policy: &ToolResultPolicy, age: usize) -> Option<u64> {
+    let old = policy
+        .old_after_turns
+        .is_some_and(|turns| age >= turns)
+        .then_some(policy.old_max_tokens)
+        .flatten();
+    match (policy.max_tokens, old) {
+        (Some(max), Some(old)) => Some(max.min(old)),
+        (max, old) => max.or(old),
+    }
+}
+
+/// `tool_result.content` 中的文本（字符串或 text 块）
+fn tool_result_texts(content: &mut Content) -> Vec<&mut String> {
+    match content {
+        Content::Text(text) => vec![text],
+        Content::Blocks(items) => items
+            .iter_mut()
+            .filter_map(|item| match item {
+                ContentBlock::Text(block) => Some(&mut block.text),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+/// 超出上限时返回截断后的文本
+fn truncate_text(text: &str, limit: u64, collapse: bool) -> Option<String> {
+    if estimate_tokens(text) <= limit {
+        return None;
+    }
+    let collapsed = if collapse {
+        collapse_repeated_lines(text)
+    } else {
+        text.to_string()
+    };
+    if estimate_tokens(&collapsed) <= limit {
+        return Some(collapsed);
+    }
+    Some(keep_head_and_tail(&collapsed, limit))
+}
+
+/// 连续重复的行折叠为一行加重复次数说明
+fn collapse_repeated_lines(text: &str) -> String {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut out = Vec::with_capacity(lines.len());
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        let run = lines[index..]
+            .iter()
+            .take_while(|other| other.trim_end() == line.trim_end())
+            .count();
+        out.push(line.to_string());
+        if run >= MIN_REPEATED_LINES {
+            out.push(format!(
+                "[... previous line repeated {} more times]",
+                run - 1
+            ));
+        } else {
+            out.extend(std::iter::repeat_n(line.to_string(), run - 1));
+        }
+        index += run;
+    }
+    out.join("\n")
+}
+
+/// 保留开头约 2/3 与结尾约 1/3 的预算，尽量在行边界处截断
+fn keep_head_and_tail(text: &str, limit: u64) -> String {
+    // estimate_tokens 约为 len / 3.5，反推可保留的字节数
+    let budget = usize::try_from(limit.saturating_mul(7) / 2).unwrap_or(usize::MAX);
+    let head_len = floor_char_boundary(text, budget * 2 / 3);
+    let head_len = text[..head_len].rfind('\n').map_or(head_len, |pos| pos + 1);
+    let tail_start = ceil_char_boundary(text, text.len().saturating_sub(budget / 3).max(head_len));
+    let tail_start = text[tail_start..]
+        .find('\n')
+        .map_or(tail_start, |pos| tail_start + pos + 1);
+    let (head, middle, tail) = (
+        &text[..head_len],
+        &text[head_len..tail_start],
+        &text[tail_start..],
+    );
+    format!(
+        "{head}\n[... {} lines (~{} tokens) omitted by cc-proxy ...]\n{tail}",
+        middle.lines().count(),
+        estimate_tokens(middle)
+    )
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn policy(max_tokens: u64) -> ToolResultPolicy {
+        ToolResultPolicy {
+            max_tokens: Some(max_tokens),
+            ..ToolResultPolicy::default()
+        }
+    }
+
+    fn tool_result(text: &str) -> Value {
+        json!({"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t", "content": text}]})
+    }
+
+    fn truncate(body: &Value, policy: &ToolResultPolicy) -> Option<Value> {
+        let stats = RequestStats::default();
+        let mut body = serde_json::from_value(body.clone()).unwrap();
+        truncate_tool_results(&mut body, policy, &stats)
+            .then(|| serde_json::to_value(&body).unwrap())
+    }
+
+    /// 测试超出上限时保留开头与结尾并记入统计
+    #[test]
+    fn test_keep_head_and_tail() {
+        let log = (0..2000)
+            .map(|line| format!("line {line}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = json!({"messages": [tool_result(&log)]});
+        let stats = RequestStats::default();
+        let mut request = serde_json::from_value(body.clone()).unwrap();
+        assert!(truncate_tool_results(&mut request, &policy(300), &stats));
+        let truncated = serde_json::to_value(&request).unwrap();
+        let text = truncated["messages"][0]["content"][0]["content"]
+            .as_str()
+            .unwrap();
+        assert!(text.starts_with("line 0\nline 1\n"));
+        assert!(text.ends_with("line 1999"));
+        assert!(text.contains("omitted by cc-proxy"));
+        assert!(estimate_tokens(text) < 400);
+        assert_eq!(stats.tool_results_truncated.load(Ordering::Relaxed), 1);
+        assert!(stats.tool_result_tokens_saved.load(Ordering::Relaxed) > 3000);
+
+        // 未超出上限或未启用时不修改
+        assert!(truncate(&body, &policy(100_000)).is_none());
+        assert!(truncate(&body, &ToolResultPolicy::default()).is_none());
+    }
+
+    /// 测试连续重复的行被折叠
+    #[test]
+    fn test_collapse_repeated_lines() {
+        let log = "start\n".to_string() + &"Downloading...\n".repeat(500) + "done";
+        let truncated = truncate(&json!({"messages": [tool_result(&log)]}), &policy(50)).unwrap();
+        assert_eq!(
+            truncated["messages"][0]["content"][0]["content"],
+            "start\nDownloading...\n[... previous line repeated 499 more times]\ndone"
+        );
+    }
+
+    /// 测试较早的结果使用更严格的上限
+    #[test]
+    fn test_stricter_limit_for_old_results() {
+        let output = "x".repeat(2000);
+        let body = json!({"messages": [
+            tool_result(&output),
+            {"role": "assistant", "content": [{"type": "text", "text": "ok"}]},
+            tool_result(&output)
+        ]});
+        let policy = ToolResultPolicy {
+            max_tokens: Some(1000),
+            old_after_turns: Some(1),
+            old_max_tokens: Some(100),
+            ..ToolResultPolicy::default()
+        };
+        let truncated = truncate(&body, &policy).unwrap();
+        assert!(
+            truncated["messages"][0]["content"][0]["content"]
+                .as_str()
+                .unwrap()
+                .contains("omitted by cc-proxy")
+        );
+        assert_eq!(truncated["messages"][2]["content"][0]["content"], output);
+    }
+}
@@ -0,0 +1,30 @@
+//! 稳定哈希
+//!
+//! 标准库的 `DefaultHasher` 不保证跨版本/跨进程稳定，缓存 key、别名等需要
+//! 在重启后保持一致的场景统一使用这里的 FNV-1a 实现。
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 对多段字节计算 64 位 FNV-1a 哈希
+///
+/// 段与段之间插入分隔字节，避免 `["ab", "c"]` 与 `["a", "bc"]` 冲突。
+#[must_use]
+pub fn stable_hash(parts: &[&[u8]]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for part in parts {
+        for byte in *part {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// 以 16 位十六进制字符串返回 [`stable_hash`] 结果
+#[must_use]
+pub fn stable_hash_hex(parts: &[&[u8]]) -> String {
+    format!("{:016x}", stable_hash(parts))
+}
@@ -1,7 +1,22 @@
+pub mod anthropic;
+pub mod azure;
+pub mod bedrock;
+pub mod cache_planner;
+pub mod capabilities;
+pub mod context_guard;
+pub mod gemini;
 pub mod handler;
+pub mod hash;
+pub mod models;
+pub mod ollama;
 pub mod openai_compat;
 pub mod optimization;
+pub mod pipeline;
+pub mod quirks;
 pub mod service;
+pub mod signature;
+pub mod sse;
+pub mod vertex;
 
 use std::sync::{Arc, atomic::AtomicU64};
 
@@ -13,6 +28,8 @@ use hyper_util::{
     rt::TokioExecutor,
 };
 
+use self::models::ModelCatalogCache;
+
 /// Token 统计
 pub struct RequestStats {
     pub total_tokens: AtomicU64,
@@ -21,6 +38,16 @@ pub struct RequestStats {
     pub assistant_tokens: AtomicU64,
     pub system_tokens: AtomicU64,
     pub request_count: AtomicU64,
+    /// 被截断的 `tool_result` 数量
+    pub tool_results_truncated: AtomicU64,
+    /// 截断 `tool_result` 节省的 token 数（估算）
+    pub tool_result_tokens_saved: AtomicU64,
+    /// 响应 usage 中从缓存读取的输入 token 数
+    pub cache_read_input_tokens: AtomicU64,
+    /// 响应 usage 中写入缓存的输入 token 数
+    pub cache_creation_input_tokens: AtomicU64,
+    /// 响应 usage 中未命中缓存的输入 token 数
+    pub uncached_input_tokens: AtomicU64,
 }
 
 impl Default for RequestStats {
@@ -32,6 +59,11 @@ impl Default for RequestStats {
             assistant_tokens: AtomicU64::new(0),
             system_tokens: AtomicU64::new(0),
             request_count: AtomicU64::new(0),
+            tool_results_truncated: AtomicU64::new(0),
+            tool_result_tokens_saved: AtomicU64::new(0),
+            cache_read_input_tokens: AtomicU64::new(0),
+            cache_creation_input_tokens: AtomicU64::new(0),
+            uncached_input_tokens: AtomicU64::new(0),
         }
     }
 }
@@ -43,9 +75,17 @@ pub type HttpClient = Client<hyper_rustls::HttpsConnector<HttpConnector>, Full<B
 pub struct GatewayHandler {
     pub stats: Arc<RequestStats>,
     pub client: Arc<HttpClient>,
+    pub models: Arc<ModelCatalogCache>,
+}
+
+impl Default for GatewayHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GatewayHandler {
+    #[must_use]
     pub fn new() -> Self {
         // 创建支持 HTTP 和 HTTPS 的连接器
         // 使用 webpki-roots 内置证书，不依赖系统证书，提高跨平台稳定性
@@ -60,14 +100,22 @@ impl GatewayHandler {
         Self {
             stats: Arc::new(RequestStats::default()),
             client: Arc::new(client),
+            models: Arc::new(ModelCatalogCache::default()),
         }
     }
 
+    #[must_use]
     pub const fn stats(&self) -> &Arc<RequestStats> {
         &self.stats
     }
 
+    #[must_use]
     pub const fn client(&self) -> &Arc<HttpClient> {
         &self.client
     }
+
+    #[must_use]
+    pub const fn models(&self) -> &Arc<ModelCatalogCache> {
+        &self.models
+    }
 }
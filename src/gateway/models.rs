@@ -0,0 +1,441 @@
+//! 本地生成的 `/v1/models` 模型列表
+//!
+//! 客户端（如 Claude Code）通过 `GET /v1/models` 校验模型名。
+//! 代理按 upstream 配置的 `model` 与 `model_aliases` 生成列表，
+//! 可选合并各 upstream 真实的模型列表（拉取后按 `cache_ttl_secs` 缓存）。
+//! 只有 Anthropic 与 `OpenAI` 兼容的 upstream 提供 `GET /v1/models`，其余 mode 只使用配置的模型名。
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures_util::future::join_all;
+use http::Method;
+use http_body_util::{BodyExt, Full};
+use hyper::Request as HyperRequest;
+use serde_json::{Value, json};
+
+use crate::{
+    config::{Config, Mode, UpstreamConfig},
+    gateway::{HttpClient, handler::make_proxy_url},
+};
+
+/// 拉取上游模型列表的超时时间
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Anthropic 模型对象要求的创建时间，本地生成的条目没有真实值
+const UNKNOWN_CREATED_AT: &str = "1970-01-01T00:00:00Z";
+
+/// 模型列表中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelEntry {
+    pub id: String,
+    /// 提供该模型的 upstream 主机名
+    pub owned_by: String,
+}
+
+/// 上游模型列表缓存（按 upstream endpoint 区分）
+#[derive(Default)]
+pub struct ModelCatalogCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<String>)>>,
+}
+
+impl ModelCatalogCache {
+    fn get(&self, endpoint: &str, ttl: Duration) -> Option<Vec<String>> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(endpoint)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+            .map(|(_, ids)| ids.clone())
+    }
+
+    fn insert(&self, endpoint: &str, ids: Vec<String>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(endpoint.to_string(), (Instant::now(), ids));
+        }
+    }
+}
+
+/// 按配置生成模型列表：各 upstream 的 `model` 与 `model_aliases`，按出现顺序去重
+#[must_use]
+pub fn configured_models(config: &Config) -> Vec<ModelEntry> {
+    let mut models: Vec<ModelEntry> = Vec::new();
+    for upstream in &config.upstream {
+        let owned_by = endpoint_host(&upstream.endpoint);
+        for id in std::iter::once(&upstream.model).chain(&upstream.model_aliases) {
+            if !id.is_empty() && !models.iter().any(|model| &model.id == id) {
+                models.push(ModelEntry {
+                    id: id.clone(),
+                    owned_by: owned_by.to_string(),
+                });
+            }
+        }
+    }
+    models
+}
+
+/// 生成模型列表；`merge_upstream` 开启时合并各 upstream 的真实模型列表
+pub async fn list_models(
+    config: &Config,
+    client: &Arc<HttpClient>,
+    cache: &ModelCatalogCache,
+) -> Vec<ModelEntry> {
+    let mut models = configured_models(config);
+    if !config.models.merge_upstream {
+        return models;
+    }
+
+    let ttl = Duration::from_secs(config.models.cache_ttl_secs);
+    let upstreams = config
+        .upstream
+        .iter()
+        .filter(|upstream| models_url(upstream).is_some())
+        .collect::<Vec<_>>();
+    let fetched = join_all(
+        upstreams
+            .iter()
+            .map(|upstream| upstream_model_ids(client, cache, upstream, ttl)),
+    )
+    .await;
+    for (upstream, ids) in upstreams.into_iter().zip(fetched) {
+        let owned_by = endpoint_host(&upstream.endpoint);
+        for id in ids {
+            if !models.iter().any(|model| model.id == id) {
+                models.push(ModelEntry {
+                    id,
+                    owned_by: owned_by.to_string(),
+                });
+            }
+        }
+    }
+    models
+}
+
+/// 缓存中或从 upstream 拉取的模型 id，失败时返回空列表
+async fn upstream_model_ids(
+    client: &Arc<HttpClient>,
+    cache: &ModelCatalogCache,
+    upstream: &UpstreamConfig,
+    ttl: Duration,
+) -> Vec<String> {
+    if let Some(ids) = cache.get(&upstream.endpoint, ttl) {
+        return ids;
+    }
+    match fetch_upstream_models(client, upstream).await {
+        Ok(ids) => {
+            tracing::info!(
+                "📋 已拉取上游模型列表: endpoint={}, {} 个模型",
+                upstream.endpoint,
+                ids.len()
+            );
+            cache.insert(&upstream.endpoint, ids.clone());
+            ids
+        }
+        Err(e) => {
+            tracing::warn!(
+                "拉取上游模型列表失败: endpoint={}, {}",
+                upstream.endpoint,
+                e
+            );
+            // 失败结果同样缓存，避免每次请求都等待不可用的上游
+            cache.insert(&upstream.endpoint, Vec::new());
+            Vec::new()
+        }
+    }
+}
+
+/// upstream 的模型列表地址与主机名
+///
+/// 只有 Anthropic 与 `OpenAI` 兼容接口提供 `GET /v1/models`；Bedrock、Vertex AI、Gemini、
+/// Ollama 与 Azure `OpenAI` 的模型列表接口与鉴权方式各不相同，未配置 endpoint 时也无从请求，均返回 None。
+fn models_url(upstream: &UpstreamConfig) -> Option<(String, Cow<'_, str>)> {
+    let compatible = matches!(
+        upstream.mode,
+        Mode::AnthropicDirect | Mode::OpenAIResponses | Mode::OpenAIChat
+    ) && upstream.azure.is_none();
+    (compatible && !upstream.endpoint.is_empty())
+        .then(|| make_proxy_url(&upstream.endpoint, upstream.mode, "/v1/models", ""))
+}
+
+/// 请求 upstream 的 `/v1/models`，返回其中的模型 id
+async fn fetch_upstream_models(
+    client: &Arc<HttpClient>,
+    upstream: &UpstreamConfig,
+) -> Result<Vec<String>, String> {
+    let Some(api_key) = upstream.api_keys.first() else {
+        return Err("no api_key configured".to_string());
+    };
+    let Some((url, host)) = models_url(upstream) else {
+        return Err("upstream has no compatible models endpoint".to_string());
+    };
+    let builder = HyperRequest::builder()
+        .method(Method::GET)
+        .uri(&url)
+        .header("host", host.as_ref());
+    // Anthropic 使用 x-api-key，OpenAI 兼容接口使用 Bearer
+    let builder = if matches!(upstream.mode, Mode::AnthropicDirect) {
+        builder
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+    } else {
+        builder.header("Authorization", format!("Bearer {api_key}"))
+    };
+    let request = builder
+        .body(Full::new(Bytes::new()))
+        .map_err(|e| format!("failed to build request: {e}"))?;
+
+    let response = tokio::time::timeout(FETCH_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| "request timed out".to_string())?
+        .map_err(|e| format!("request failed: {e}"))?;
+    let status = response.status();
+    let body = BodyExt::collect(response.into_body())
+        .await
+        .map_err(|e| format!("failed to read body: {e}"))?
+        .to_bytes();
+    if !status.is_success() {
+        return Err(format!("status {status}"));
+    }
+    Ok(parse_model_ids(&body))
+}
+
+/// 解析 Anthropic / `OpenAI` 模型列表响应（二者均为 `{ "data": [{ "id": .. }] }`）
+fn parse_model_ids(body: &[u8]) -> Vec<String> {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| value.get("data").and_then(Value::as_array).cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|model| model.get("id").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+fn endpoint_host(endpoint: &str) -> &str {
+    let without_scheme = endpoint
+        .strip_prefix("https://")
+        .or_else(|| endpoint.strip_prefix("http://"))
+        .unwrap_or(endpoint);
+    without_scheme
+        .split_once('/')
+        .map_or(without_scheme, |(host, _)| host)
+}
+
+/// Anthropic 格式的单个模型对象
+#[must_use]
+pub fn anthropic_model(model: &ModelEntry) -> Value {
+    json!({
+        "type": "model",
+        "id": model.id,
+        "display_name": model.id,
+        "created_at": UNKNOWN_CREATED_AT
+    })
+}
+
+/// Anthropic 格式的模型列表
+#[must_use]
+pub fn anthropic_models_body(models: &[ModelEntry]) -> Value {
+    json!({
+        "data": models.iter().map(anthropic_model).collect::<Vec<_>>(),
+        "has_more": false,
+        "first_id": models.first().map(|model| &model.id),
+        "last_id": models.last().map(|model| &model.id)
+    })
+}
+
+/// `OpenAI` 格式的单个模型对象
+#[must_use]
+pub fn openai_model(model: &ModelEntry) -> Value {
+    json!({
+        "id": model.id,
+        "object": "model",
+        "created": 0,
+        "owned_by": model.owned_by
+    })
+}
+
+/// `OpenAI` 格式的模型列表
+#[must_use]
+pub fn openai_models_body(models: &[ModelEntry]) -> Value {
+    json!({
+        "object": "list",
+        "data": models.iter().map(openai_model).collect::<Vec<_>>()
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::Barrier,
+    };
+
+    use super::*;
+    use crate::{config::AzureConfig, gateway::GatewayHandler};
+
+    fn upstream(mode: Mode, endpoint: &str) -> UpstreamConfig {
+        UpstreamConfig {
+            mode,
+            endpoint: endpoint.to_string(),
+            api_keys: vec!["sk-test".to_string()],
+            ..UpstreamConfig::default()
+        }
+    }
+
+    /// 接受一个请求，等所有监听器都收到请求后才回复模型列表，返回收到的请求头（小写）
+    async fn serve_models(listener: TcpListener, barrier: Arc<Barrier>, id: &str) -> String {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            assert_eq!(socket.read(&mut byte).await.unwrap(), 1);
+            head.push(byte[0]);
+        }
+        barrier.wait().await;
+        let body = json!({"data": [{"id": id}]}).to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(head).unwrap().to_ascii_lowercase()
+    }
+
+    /// 测试按 upstream 的 model 与别名生成去重后的模型列表
+    #[test]
+    fn test_configured_models() {
+        let config = Config {
+            log_req_body: false,
+            log_res_body: false,
+            upstream: vec![
+                UpstreamConfig {
+                    endpoint: "https://open.bigmodel.cn/api/anthropic".to_string(),
+                    model: "glm-4.7".to_string(),
+                    model_aliases: vec![
+                        "claude-sonnet-4-5".to_string(),
+                        "claude-haiku-4-5".to_string(),
+                    ],
+                    ..UpstreamConfig::default()
+                },
+                UpstreamConfig {
+                    endpoint: "http://127.0.0.1:8080".to_string(),
+                    model_aliases: vec!["claude-sonnet-4-5".to_string()],
+                    ..UpstreamConfig::default()
+                },
+            ],
+            optimizations: crate::config::OptimizationConfig::default(),
+            models: crate::config::ModelsConfig::default(),
+        };
+        let models = configured_models(&config);
+
+        let ids = models
+            .iter()
+            .map(|model| model.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["glm-4.7", "claude-sonnet-4-5", "claude-haiku-4-5"]);
+        assert_eq!(models[0].owned_by, "open.bigmodel.cn");
+
+        let anthropic = anthropic_models_body(&models);
+        assert_eq!(anthropic["data"][1]["type"], "model");
+        assert_eq!(anthropic["first_id"], "glm-4.7");
+        assert_eq!(anthropic["last_id"], "claude-haiku-4-5");
+
+        let openai = openai_models_body(&models);
+        assert_eq!(openai["object"], "list");
+        assert_eq!(openai["data"][2]["owned_by"], "open.bigmodel.cn");
+    }
+
+    /// 测试解析上游模型列表响应
+    #[test]
+    fn test_parse_model_ids() {
+        let body = json!({"data": [{"id": "gpt-5"}, {"id": "gpt-5-mini"}, {"object": "model"}]});
+        assert_eq!(
+            parse_model_ids(body.to_string().as_bytes()),
+            ["gpt-5", "gpt-5-mini"]
+        );
+        assert!(parse_model_ids(b"not json").is_empty());
+    }
+
+    /// 测试只有 Anthropic 与 `OpenAI` 兼容的 upstream 拉取模型列表
+    #[test]
+    fn test_models_url_by_mode() {
+        let url = |upstream: &UpstreamConfig| models_url(upstream).map(|(url, _)| url);
+        assert_eq!(
+            url(&upstream(
+                Mode::AnthropicDirect,
+                "https://api.anthropic.com"
+            ))
+            .as_deref(),
+            Some("https://api.anthropic.com/v1/models")
+        );
+        assert_eq!(
+            url(&upstream(Mode::OpenAIChat, "https://api.deepseek.com")).as_deref(),
+            Some("https://api.deepseek.com/v1/models")
+        );
+        for mode in [Mode::Bedrock, Mode::Vertex, Mode::Gemini, Mode::Ollama] {
+            assert!(url(&upstream(mode, "https://example.com")).is_none());
+        }
+        assert!(url(&upstream(Mode::OpenAIResponses, "")).is_none());
+        let azure = UpstreamConfig {
+            azure: Some(AzureConfig::default()),
+            ..upstream(Mode::OpenAIResponses, "https://contoso.openai.azure.com")
+        };
+        assert!(url(&azure).is_none());
+    }
+
+    /// 测试并发拉取各 upstream 的模型列表，按 mode 使用对应的鉴权头，跳过没有兼容接口的 upstream
+    #[tokio::test]
+    async fn test_list_models_fetches_concurrently() {
+        let anthropic = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let openai = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config {
+            log_req_body: false,
+            log_res_body: false,
+            upstream: vec![
+                upstream(
+                    Mode::AnthropicDirect,
+                    &format!("http://{}", anthropic.local_addr().unwrap()),
+                ),
+                upstream(Mode::Bedrock, ""),
+                upstream(
+                    Mode::OpenAIChat,
+                    &format!("http://{}", openai.local_addr().unwrap()),
+                ),
+            ],
+            optimizations: crate::config::OptimizationConfig::default(),
+            models: crate::config::ModelsConfig::default(),
+        };
+        config.models.merge_upstream = true;
+
+        // 两个监听器都收到请求后才回复：逐个拉取时第一个请求会一直等到超时
+        let barrier = Arc::new(Barrier::new(2));
+        let anthropic = tokio::spawn(serve_models(
+            anthropic,
+            Arc::clone(&barrier),
+            "claude-opus-4-1",
+        ));
+        let openai = tokio::spawn(serve_models(openai, barrier, "deepseek-chat"));
+
+        let client = GatewayHandler::new().client;
+        let cache = ModelCatalogCache::default();
+        let models = list_models(&config, &client, &cache).await;
+        let ids = models
+            .iter()
+            .map(|model| model.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["claude-opus-4-1", "deepseek-chat"]);
+
+        let anthropic = anthropic.await.unwrap();
+        assert!(anthropic.starts_with("get /v1/models "));
+        assert!(anthropic.contains("x-api-key: sk-test"));
+        assert!(!anthropic.contains("authorization"));
+        let openai = openai.await.unwrap();
+        assert!(openai.contains("authorization: bearer sk-test"));
+        assert!(!openai.contains("x-api-key"));
+    }
+}
@@ -0,0 +1,124 @@
+//! Ollama 原生 API 与 Anthropic Claude API 格式双向转换（`mode = "ollama"`）
+//!
+//! 功能：
+//! - Claude CLI 请求 → Ollama `/api/chat` 请求
+//! - Ollama 响应 → Claude CLI 响应
+//! - Ollama NDJSON 流 → Claude CLI 流式事件
+//!
+//! 请求发往 `{endpoint}/api/chat`（llama.cpp 等兼容该接口的服务同样适用）；
+//! 本地服务通常无需鉴权，配置了 `api_key` 时以 Bearer 方式发送（如经反向代理暴露的实例）。
+//! 模型不支持函数调用时（`[upstream.ollama] native_tools = false`），
+//! 工具调用通过提示词协议模拟，见 [`prompt_tools`]。
+
+mod prompt_tools;
+mod request;
+mod response;
+mod stream;
+
+use bytes::Bytes;
+use http::{Method, StatusCode};
+use http_body_util::Full;
+use hyper::Request as HyperRequest;
+use serde_json::{Value, json};
+
+pub use self::{
+    request::anthropic_request_to_ollama, response::ollama_response_to_anthropic,
+    stream::OllamaStreamConverter,
+};
+use crate::{config::UpstreamConfig, gateway::anthropic::error_type_for_status};
+
+/// 流式响应的 content-type
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// `endpoint` 留空时使用的本地 Ollama 地址
+const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+
+/// 转换请求体并构建发往 Ollama 的请求
+///
+/// 返回请求与是否启用了提示词工具协议（响应转换时需解析文本中的工具调用）。
+pub fn build_request(
+    upstream: &UpstreamConfig,
+    body: &[u8],
+    api_key: &str,
+) -> Result<(HyperRequest<Full<Bytes>>, bool), String> {
+    let converted = anthropic_request_to_ollama(body, upstream)?;
+    let body = serde_json::to_vec(&converted.body)
+        .map_err(|e| format!("Failed to serialize request: {e}"))?;
+    tracing::debug!(
+        "🔄 请求体格式转换: Claude → Ollama ({} bytes, stream={}, prompt_tools={})",
+        body.len(),
+        converted.stream,
+        converted.prompt_tools
+    );
+
+    let base = if upstream.endpoint.is_empty() {
+        DEFAULT_ENDPOINT
+    } else {
+        upstream.endpoint.trim_end_matches('/')
+    };
+    let url = format!("{base}/api/chat");
+    let host = base
+        .strip_prefix("https://")
+        .or_else(|| base.strip_prefix("http://"))
+        .unwrap_or(base)
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    tracing::info!("Proxying to: {} (ollama)", url);
+
+    let mut builder = HyperRequest::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .header("host", host)
+        .header("content-type", "application/json");
+    if !api_key.is_empty() {
+        builder = builder.header("Authorization", format!("Bearer {api_key}"));
+    }
+    let request = builder
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|e| format!("failed to build request: {e}"))?;
+    Ok((request, converted.prompt_tools))
+}
+
+/// Ollama 错误响应（`{ "error": "..." }`）→ Anthropic 错误格式
+#[must_use]
+pub fn error_body_to_anthropic(status: StatusCode, body: &[u8]) -> Bytes {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|value| {
+            value
+                .get("error")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+    Bytes::from(
+        json!({
+            "type": "error",
+            "error": { "type": error_type_for_status(status), "message": message }
+        })
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试 Ollama 错误响应转换为 Anthropic 错误格式
+    #[test]
+    fn test_error_body_to_anthropic() {
+        let converted: Value = serde_json::from_slice(&error_body_to_anthropic(
+            StatusCode::NOT_FOUND,
+            br#"{"error":"model \"qwen9\" not found, try pulling it first"}"#,
+        ))
+        .unwrap();
+        assert_eq!(converted["error"]["type"], "not_found_error");
+        assert_eq!(
+            converted["error"]["message"],
+            "model \"qwen9\" not found, try pulling it first"
+        );
+    }
+}
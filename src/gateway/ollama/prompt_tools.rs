@@ -0,0 +1,271 @@
+//! 提示词模拟的工具调用
+//!
+//! 不支持函数调用的本地模型（`native_tools = false`）无法接收 `tools` 字段，
+//! 改为在系统提示词中描述工具与调用格式，由模型在回复文本中输出：
+//!
+//! ```text
+//! <tool_call>
+//! {"name": "Read", "arguments": {"file_path": "src/main.rs"}}
+//! </tool_call>
+//! ```
+//!
+//! 历史中的 `tool_use` / `tool_result` 按同一协议渲染为文本，
+//! 响应文本由 [`ToolCallParser`] 解析回 `tool_use` 块。
+
+use serde_json::{Map, Value};
+
+use crate::gateway::{
+    anthropic::{Tool, ToolChoice, ToolChoiceKind},
+    openai_compat::{is_server_tool, parse_tool_arguments},
+};
+
+const CALL_OPEN: &str = "<tool_call>";
+const CALL_CLOSE: &str = "</tool_call>";
+
+/// 生成追加到系统提示词的工具说明
+///
+/// 没有可用工具（或 `tool_choice` 为 none）时返回 `None`。
+#[must_use]
+pub fn tool_prompt(tools: &[Tool], tool_choice: Option<&ToolChoice>) -> Option<String> {
+    let choice = tool_choice.map(|choice| &choice.kind);
+    if choice == Some(&ToolChoiceKind::None) {
+        return None;
+    }
+    let descriptions = tools
+        .iter()
+        .filter(|tool| !is_server_tool(tool) && !tool.name.is_empty())
+        .map(|tool| {
+            let description = tool.description.as_deref().unwrap_or("");
+            let schema = tool
+                .input_schema
+                .as_ref()
+                .map_or_else(|| "{}".to_string(), Value::to_string);
+            format!(
+                "## {}\n{}\nParameters (JSON Schema): {schema}",
+                tool.name,
+                description.trim()
+            )
+        })
+        .collect::<Vec<_>>();
+    if descriptions.is_empty() {
+        return None;
+    }
+
+    let requirement = match choice {
+        Some(ToolChoiceKind::Any) => "\nYou MUST call at least one tool in your reply.".to_string(),
+        Some(ToolChoiceKind::Tool) => tool_choice
+            .and_then(ToolChoice::forced_tool)
+            .map(|name| format!("\nYou MUST call the `{name}` tool in your reply."))
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    Some(format!(
+        "# Tools\n\n\
+         You can call the tools listed below. To call a tool, write a block in exactly this form:\n\
+         {CALL_OPEN}\n\
+         {{\"name\": \"<tool name>\", \"arguments\": {{<arguments as a JSON object>}}}}\n\
+         {CALL_CLOSE}\n\
+         You may make several calls in one reply. After your calls, stop and wait: \
+         the results are returned in <tool_result> blocks in the next user message. \
+         Never write <tool_result> blocks yourself.{requirement}\n\n\
+         {}",
+        descriptions.join("\n\n")
+    ))
+}
+
+/// `tool_use` 块 → 调用文本
+///
+/// 手动拼接以保证 `name` 在前，与提示词中的示例一致。
+#[must_use]
+pub fn render_tool_call(name: &str, input: &Value) -> String {
+    format!(
+        "{CALL_OPEN}\n{{\"name\": {}, \"arguments\": {input}}}\n{CALL_CLOSE}",
+        Value::String(name.to_string())
+    )
+}
+
+/// `tool_result` 块 → 结果文本
+#[must_use]
+pub fn render_tool_result(name: &str, content: &str, is_error: bool) -> String {
+    let status = if is_error { " error=\"true\"" } else { "" };
+    format!("<tool_result name=\"{name}\"{status}>\n{content}\n</tool_result>")
+}
+
+/// 解析出的回复片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    ToolCall { name: String, input: Value },
+}
+
+/// 增量解析回复文本中的 `<tool_call>` 块
+///
+/// 流式输出时标签可能被拆到多个片段中，解析器会暂存可能是标签开头的尾部文本；
+/// 调用块前后的空白一并丢弃，避免产生只含换行的 text 块。
+/// 无法解析为调用的块按原样作为文本输出。
+#[derive(Debug, Default)]
+pub struct ToolCallParser {
+    buffer: String,
+    in_call: bool,
+    /// 刚结束一个调用块，后续文本需去掉开头的空白
+    trim_start: bool,
+}
+
+impl ToolCallParser {
+    /// 追加一段文本，返回已能确定的片段
+    pub fn feed(&mut self, text: &str) -> Vec<Segment> {
+        self.buffer.push_str(text);
+        let mut segments = Vec::new();
+        loop {
+            if self.in_call {
+                let Some(end) = self.buffer.find(CALL_CLOSE) else {
+                    break;
+                };
+                let body = self.buffer[..end].to_string();
+                self.buffer.drain(..end + CALL_CLOSE.len());
+                self.in_call = false;
+                self.trim_start = true;
+                segments.push(parse_call(&body));
+            } else if let Some(start) = self.buffer.find(CALL_OPEN) {
+                let text = self.buffer[..start].to_string();
+                self.push_text(&mut segments, text.trim_end());
+                self.buffer.drain(..start + CALL_OPEN.len());
+                self.in_call = true;
+            } else {
+                // 保留可能是 `<tool_call>` 开头的后缀，以及其前的空白
+                let partial = partial_tag_start(&self.buffer);
+                let keep = self.buffer[..partial].trim_end().len();
+                let text = self.buffer[..keep].to_string();
+                self.buffer.drain(..keep);
+                self.push_text(&mut segments, &text);
+                break;
+            }
+        }
+        segments
+    }
+
+    /// 文本结束，输出剩余内容
+    ///
+    /// 模型常常省略结束标签，未闭合的调用块也尝试解析。
+    pub fn finish(&mut self) -> Vec<Segment> {
+        let rest = std::mem::take(&mut self.buffer);
+        let mut segments = Vec::new();
+        if std::mem::take(&mut self.in_call) {
+            segments.push(parse_call(&rest));
+        } else {
+            self.push_text(&mut segments, rest.trim_end());
+        }
+        segments
+    }
+
+    fn push_text(&mut self, segments: &mut Vec<Segment>, text: &str) {
+        let text = if self.trim_start {
+            text.trim_start()
+        } else {
+            text
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.trim_start = false;
+        match segments.last_mut() {
+            Some(Segment::Text(last)) => last.push_str(text),
+            _ => segments.push(Segment::Text(text.to_string())),
+        }
+    }
+}
+
+/// 文本末尾可能是 `<tool_call>` 前缀的起始位置（没有则为文本长度）
+fn partial_tag_start(text: &str) -> usize {
+    (1..CALL_OPEN.len())
+        .rev()
+        .find(|&len| text.ends_with(&CALL_OPEN[..len]))
+        .map_or(text.len(), |len| text.len() - len)
+}
+
+/// 解析调用块内容：`{"name": .., "arguments": {..}}`
+///
+/// 兼容 Markdown 代码块包裹、`parameters` / `input` 字段名与字符串形式的参数。
+fn parse_call(body: &str) -> Segment {
+    let parsed = parse_tool_arguments(body).ok().and_then(|(call, _)| {
+        let name = call.get("name").and_then(Value::as_str)?.to_string();
+        let input = match ["arguments", "parameters", "input"]
+            .iter()
+            .find_map(|key| call.get(*key))
+        {
+            Some(Value::String(raw)) => Value::Object(
+                parse_tool_arguments(raw)
+                    .map(|(arguments, _)| arguments)
+                    .ok()?,
+            ),
+            Some(Value::Object(arguments)) => Value::Object(arguments.clone()),
+            None | Some(Value::Null) => Value::Object(Map::new()),
+            Some(_) => return None,
+        };
+        Some(Segment::ToolCall { name, input })
+    });
+    parsed.unwrap_or_else(|| Segment::Text(format!("{CALL_OPEN}{body}{CALL_CLOSE}")))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 测试按任意分包解析文本与工具调用，并丢弃调用块前后的空白
+    #[test]
+    fn test_parser_handles_split_tags() {
+        let reply = "Let me check.\n<tool_call>\n{\"name\": \"Read\", \"arguments\": {\"file_path\": \"a.rs\"}}\n</tool_call>\n\nDone <tool_x>";
+        let mut parser = ToolCallParser::default();
+        let mut segments = Vec::new();
+        for chunk in reply.as_bytes().chunks(3) {
+            segments.extend(parser.feed(std::str::from_utf8(chunk).unwrap()));
+        }
+        segments.extend(parser.finish());
+
+        let mut merged: Vec<Segment> = Vec::new();
+        for segment in segments {
+            match (merged.last_mut(), segment) {
+                (Some(Segment::Text(last)), Segment::Text(text)) => last.push_str(&text),
+                (_, segment) => merged.push(segment),
+            }
+        }
+        assert_eq!(
+            merged,
+            [
+                Segment::Text("Let me check.".to_string()),
+                Segment::ToolCall {
+                    name: "Read".to_string(),
+                    input: json!({"file_path": "a.rs"})
+                },
+                Segment::Text("Done <tool_x>".to_string()),
+            ]
+        );
+    }
+
+    /// 测试未闭合、参数为字符串的调用块与无法解析的调用块
+    #[test]
+    fn test_parser_fallbacks() {
+        let mut parser = ToolCallParser::default();
+        let mut segments = parser.feed(
+            "<tool_call>{\"name\": \"Bash\", \"parameters\": \"{\\\"command\\\": \\\"ls\\\"}\"}",
+        );
+        segments.extend(parser.finish());
+        assert_eq!(
+            segments,
+            [Segment::ToolCall {
+                name: "Bash".to_string(),
+                input: json!({"command": "ls"})
+            }]
+        );
+
+        let mut parser = ToolCallParser::default();
+        let segments = parser.feed("<tool_call>not json</tool_call>");
+        assert_eq!(
+            segments,
+            [Segment::Text("<tool_call>not json</tool_call>".to_string())]
+        );
+    }
+}
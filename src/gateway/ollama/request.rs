@@ -0,0 +1,418 @@
+//! 请求格式转换
+//!
+//! Anthropic Claude 请求 → Ollama `/api/chat` 请求
+//!
+//! 主要转换：
+//! - system → 首条 `system` 消息
+//! - 内容块 → `content` 字符串，base64 图片 → `images`，thinking → `thinking`
+//! - `tool_use` → `tool_calls`，`tool_result` → `tool` 消息（带 `tool_name`）
+//! - tools → `tools[].function`
+//! - `max_tokens` / `temperature` 等 → `options`，thinking → `think`
+//!
+//! 模型不支持函数调用时，工具改由系统提示词描述，见 [`super::prompt_tools`]。
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+use super::prompt_tools;
+use crate::{
+    config::UpstreamConfig,
+    gateway::{
+        anthropic::{
+            Content, ContentBlock, MediaBlock, Message, MessagesRequest, Role, ThinkingKind, Tool,
+            ToolChoiceKind, ToolResultBlock,
+        },
+        openai_compat::{is_server_tool, sanitize_tool_schema},
+    },
+};
+
+/// 转换结果
+pub struct OllamaRequest {
+    pub body: Value,
+    pub stream: bool,
+    /// 工具调用通过提示词协议模拟，响应文本需解析出工具调用
+    pub prompt_tools: bool,
+}
+
+/// Anthropic Claude 请求 → Ollama `/api/chat` 请求
+pub fn anthropic_request_to_ollama(
+    body: &[u8],
+    upstream: &UpstreamConfig,
+) -> Result<OllamaRequest, String> {
+    let request: MessagesRequest = serde_json::from_slice(body)
+        .map_err(|_| "Request body must be a JSON object.".to_string())?;
+    if request.model.is_empty() {
+        return Err("Request must include model.".to_string());
+    }
+    if request.messages.is_empty() {
+        return Err("Request must include messages.".to_string());
+    }
+    // Ollama 默认流式输出，需要显式指定
+    let stream = request.is_stream();
+    let options = upstream.ollama.clone().unwrap_or_default();
+
+    let tool_choice = request.tool_choice.as_ref();
+    let tool_prompt = if options.native_tools {
+        None
+    } else {
+        prompt_tools::tool_prompt(&request.tools, tool_choice)
+    };
+    let prompt_tools = tool_prompt.is_some();
+
+    let mut system = request
+        .system
+        .as_ref()
+        .and_then(claude_system_to_text)
+        .unwrap_or_default();
+    if let Some(tool_prompt) = tool_prompt {
+        if !system.is_empty() {
+            system.push_str("\n\n");
+        }
+        system.push_str(&tool_prompt);
+    }
+    let mut out_messages = Vec::new();
+    if !system.is_empty() {
+        out_messages.push(json!({ "role": "system", "content": system }));
+    }
+    let tool_names = collect_tool_use_names(&request.messages);
+    for message in &request.messages {
+        // 不支持函数调用的模型无法接收 tool_calls / tool 消息，历史始终渲染为文本
+        append_message(
+            message,
+            &tool_names,
+            !options.native_tools,
+            &mut out_messages,
+        );
+    }
+
+    let mut out = Map::new();
+    out.insert("model".to_string(), Value::String(request.model.clone()));
+    out.insert("messages".to_string(), Value::Array(out_messages));
+    out.insert("stream".to_string(), Value::Bool(stream));
+    let none_choice = tool_choice.is_some_and(|choice| choice.kind == ToolChoiceKind::None);
+    if options.native_tools && !none_choice {
+        let functions = map_tools(&request.tools, upstream);
+        if !functions.is_empty() {
+            out.insert("tools".to_string(), Value::Array(functions));
+        }
+    }
+    if let Some(kind) = request.thinking_kind() {
+        out.insert(
+            "think".to_string(),
+            Value::Bool(*kind != ThinkingKind::Disabled),
+        );
+    }
+    let mut model_options = sampling_options(&request);
+    if let Some(num_ctx) = options.num_ctx {
+        model_options.insert("num_ctx".to_string(), json!(num_ctx));
+    }
+    if !model_options.is_empty() {
+        out.insert("options".to_string(), Value::Object(model_options));
+    }
+
+    Ok(OllamaRequest {
+        body: Value::Object(out),
+        stream,
+        prompt_tools,
+    })
+}
+
+/// `tool_use_id` → 工具名（`tool` 消息按函数名而不是调用 id 关联）
+fn collect_tool_use_names(messages: &[Message]) -> HashMap<&str, &str> {
+    messages
+        .iter()
+        .flat_map(|message| message.content.blocks())
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse(tool_use) => Some((tool_use.id.as_str(), tool_use.name.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 单条消息中逐步收集的内容
+#[derive(Default)]
+struct MessageParts {
+    texts: Vec<String>,
+    images: Vec<Value>,
+    thinking: String,
+    tool_calls: Vec<Value>,
+}
+
+impl MessageParts {
+    fn into_message(self, role: &str) -> Option<Value> {
+        if self.texts.is_empty()
+            && self.images.is_empty()
+            && self.thinking.is_empty()
+            && self.tool_calls.is_empty()
+        {
+            return None;
+        }
+        let mut message = Map::new();
+        message.insert("role".to_string(), Value::String(role.to_string()));
+        message.insert(
+            "content".to_string(),
+            Value::String(self.texts.join("\n\n")),
+        );
+        if !self.images.is_empty() {
+            message.insert("images".to_string(), Value::Array(self.images));
+        }
+        if !self.thinking.is_empty() {
+            message.insert("thinking".to_string(), Value::String(self.thinking));
+        }
+        if !self.tool_calls.is_empty() {
+            message.insert("tool_calls".to_string(), Value::Array(self.tool_calls));
+        }
+        Some(Value::Object(message))
+    }
+}
+
+/// Claude 消息 → 一条或多条 Ollama 消息
+///
+/// 原生工具模式下 `tool_result` 拆为独立的 `tool` 消息，排在同一轮其余内容之前；
+/// 提示词模式下工具调用与结果渲染为文本。
+fn append_message(
+    message: &Message,
+    tool_names: &HashMap<&str, &str>,
+    text_tools: bool,
+    out: &mut Vec<Value>,
+) {
+    let role = match message.role {
+        Role::User | Role::Assistant => message.role.as_str(),
+        Role::Other(_) => return,
+    };
+    let blocks = match &message.content {
+        Content::Text(text) => {
+            out.push(json!({ "role": role, "content": text }));
+            return;
+        }
+        Content::Blocks(blocks) => blocks,
+    };
+
+    let mut parts = MessageParts::default();
+    for block in blocks {
+        match block {
+            ContentBlock::Text(text) => parts.texts.push(text.text.clone()),
+            ContentBlock::Image(image) => parts.images.extend(base64_image(image)),
+            ContentBlock::Document(document) => parts.texts.extend(document_text(document)),
+            ContentBlock::Thinking(thinking) => parts.thinking.push_str(&thinking.thinking),
+            ContentBlock::ToolUse(tool_use) => {
+                if text_tools {
+                    parts.texts.push(prompt_tools::render_tool_call(
+                        &tool_use.name,
+                        &tool_use.input,
+                    ));
+                } else {
+                    parts.tool_calls.push(json!({
+                        "function": { "name": tool_use.name, "arguments": tool_use.input }
+                    }));
+                }
+            }
+            ContentBlock::ToolResult(result) => {
+                let tool_use_id = result.tool_use_id.as_str();
+                let name = tool_names.get(tool_use_id).copied().unwrap_or(tool_use_id);
+                let (content, images) = tool_result_content(result);
+                if text_tools {
+                    parts.texts.push(prompt_tools::render_tool_result(
+                        name,
+                        &content,
+                        result.is_error(),
+                    ));
+                    parts.images.extend(images);
+                } else {
+                    let mut tool_message =
+                        json!({ "role": "tool", "tool_name": name, "content": content });
+                    if !images.is_empty() {
+                        tool_message["images"] = Value::Array(images);
+                    }
+                    out.push(tool_message);
+                }
+            }
+            _ => {}
+        }
+    }
+    out.extend(parts.into_message(role));
+}
+
+/// base64 图片块 → `images` 元素（Ollama 不拉取 URL 图片）
+fn base64_image(block: &MediaBlock) -> Option<Value> {
+    let source = &block.source;
+    (source.kind == "base64")
+        .then(|| source.data.clone().map(Value::String))
+        .flatten()
+}
+
+/// 纯文本文档块 → 文本（其余文档类型本地模型无法读取，忽略）
+fn document_text(block: &MediaBlock) -> Option<String> {
+    let source = &block.source;
+    (source.kind == "text")
+        .then(|| source.data.clone())
+        .flatten()
+}
+
+/// `tool_result` → (文本内容, 图片)
+fn tool_result_content(result: &ToolResultBlock) -> (String, Vec<Value>) {
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    match &result.content {
+        Some(Content::Text(text)) => texts.push(text.clone()),
+        Some(Content::Blocks(items)) => {
+            for item in items {
+                match item {
+                    ContentBlock::Text(text) => texts.push(text.text.clone()),
+                    ContentBlock::Image(image) => images.extend(base64_image(image)),
+                    _ => texts.push(serde_json::to_string(item).unwrap_or_default()),
+                }
+            }
+        }
+        None => {}
+    }
+    (texts.join("\n"), images)
+}
+
+fn claude_system_to_text(system: &Content) -> Option<String> {
+    let text = system
+        .texts()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Anthropic tools → Ollama `tools[].function`（服务端工具无法在本地执行，跳过）
+fn map_tools(tools: &[Tool], upstream: &UpstreamConfig) -> Vec<Value> {
+    tools
+        .iter()
+        .filter(|tool| !is_server_tool(tool) && !tool.name.is_empty())
+        .map(|tool| {
+            let parameters = tool.input_schema.as_ref().map_or_else(
+                || json!({ "type": "object", "properties": {} }),
+                |schema| sanitize_tool_schema(schema, upstream.schema_profile),
+            );
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description.as_deref().unwrap_or(""),
+                    "parameters": parameters
+                }
+            })
+        })
+        .collect()
+}
+
+/// 采样参数 → `options`
+fn sampling_options(request: &MessagesRequest) -> Map<String, Value> {
+    let mut options = Map::new();
+    if let Some(max_tokens) = request.max_tokens {
+        options.insert("num_predict".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = request.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = request.top_p {
+        options.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(top_k) = request.top_k {
+        options.insert("top_k".to_string(), json!(top_k));
+    }
+    if let Some(stop_sequences) = &request.stop_sequences {
+        options.insert("stop".to_string(), json!(stop_sequences));
+    }
+    options
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+    use crate::config::OllamaConfig;
+
+    fn history() -> Value {
+        json!({
+            "model": "qwen3:8b",
+            "max_tokens": 1024,
+            "system": "Be brief.",
+            "thinking": {"type": "enabled", "budget_tokens": 512},
+            "tools": [{"name": "Read", "description": "Read a file", "input_schema": {
+                "type": "object", "properties": {"path": {"type": "string"}}
+            }}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "Let me look.", "signature": ""},
+                    {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "a.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"},
+                    {"type": "text", "text": "Explain it."}
+                ]}
+            ]
+        })
+    }
+
+    /// 测试原生工具模式下消息、图片、工具调用与采样参数的转换
+    #[test]
+    fn test_native_tools_request() {
+        let request = anthropic_request_to_ollama(
+            history().to_string().as_bytes(),
+            &UpstreamConfig::default(),
+        )
+        .unwrap();
+        let body = request.body;
+        assert!(!request.prompt_tools);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["think"], true);
+        assert_eq!(body["options"], json!({"num_predict": 1024}));
+        assert_eq!(body["tools"][0]["function"]["name"], "Read");
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "What is this?", "images": ["AAAA"]},
+                {"role": "assistant", "content": "", "thinking": "Let me look.", "tool_calls": [
+                    {"function": {"name": "Read", "arguments": {"path": "a.rs"}}}
+                ]},
+                {"role": "tool", "tool_name": "Read", "content": "fn main() {}"},
+                {"role": "user", "content": "Explain it."}
+            ])
+        );
+    }
+
+    /// 测试禁用原生工具时改用提示词描述工具并以文本渲染历史调用
+    #[test]
+    fn test_prompt_tools_request() {
+        let upstream = UpstreamConfig {
+            ollama: Some(OllamaConfig {
+                native_tools: false,
+                num_ctx: Some(32768),
+            }),
+            ..UpstreamConfig::default()
+        };
+        let request =
+            anthropic_request_to_ollama(history().to_string().as_bytes(), &upstream).unwrap();
+        let body = request.body;
+        assert!(request.prompt_tools);
+        assert!(body.get("tools").is_none());
+        assert_eq!(body["options"]["num_ctx"], 32768);
+
+        let messages = body["messages"].as_array().unwrap();
+        let system = messages[0]["content"].as_str().unwrap();
+        assert!(system.starts_with("Be brief.\n\n# Tools"));
+        assert!(system.contains("## Read\nRead a file"));
+        assert!(
+            messages[2]["content"]
+                .as_str()
+                .unwrap()
+                .contains("<tool_call>\n{\"name\": \"Read\", \"arguments\": {\"path\":\"a.rs\"}}")
+        );
+        assert_eq!(
+            messages[3]["content"],
+            "<tool_result name=\"Read\">\nfn main() {}\n</tool_result>\n\nExplain it."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! 响应格式转换
+//!
+//! Ollama `/api/chat` 响应 → Anthropic Claude 响应
+//!
+//! 主要转换：
+//! - `message.thinking` → thinking 块，`message.content` → text 块
+//! - `message.tool_calls` → `tool_use` 块（提示词模式下从文本中解析）
+//! - `done_reason` → `stop_reason`，`prompt_eval_count` / `eval_count` → usage
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+use super::prompt_tools::{Segment, ToolCallParser};
+use crate::gateway::{
+    anthropic::{ContentBlock, MessagesResponse, Usage},
+    hash::stable_hash_hex,
+};
+
+/// Ollama 响应 → Claude 响应
+pub fn ollama_response_to_anthropic(
+    body: &[u8],
+    model_hint: Option<&str>,
+    prompt_tools: bool,
+) -> Result<Bytes, String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("invalid response body: {e}"))?;
+    let id = message_id(&value);
+    let model = value
+        .get("model")
+        .and_then(Value::as_str)
+        .or(model_hint)
+        .unwrap_or("unknown");
+    let message = value.get("message");
+    let field = |key: &str| {
+        message
+            .and_then(|message| message.get(key))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+    };
+
+    let mut content = Vec::new();
+    let thinking = field("thinking");
+    if !thinking.is_empty() {
+        content.push(ContentBlock::thinking(thinking, Some(String::new())));
+    }
+    let text = field("content");
+    if prompt_tools {
+        let mut parser = ToolCallParser::default();
+        let mut segments = parser.feed(text);
+        segments.extend(parser.finish());
+        for segment in segments {
+            content.push(segment_to_block(segment, &id, content.len()));
+        }
+    } else if !text.is_empty() {
+        content.push(ContentBlock::text(text));
+    }
+    for call in message
+        .and_then(|message| message.get("tool_calls"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        content.extend(tool_call_to_tool_use(call, &id, content.len()));
+    }
+
+    let has_tool_uses = content
+        .iter()
+        .any(|block| matches!(block, ContentBlock::ToolUse(_)));
+    let stop_reason = stop_reason(
+        value.get("done_reason").and_then(Value::as_str),
+        has_tool_uses,
+    );
+    let message = MessagesResponse {
+        content,
+        stop_reason: Some(stop_reason.to_string()),
+        usage: map_usage(&value),
+        ..MessagesResponse::new(id, model)
+    };
+    serde_json::to_vec(&message)
+        .map(Bytes::from)
+        .map_err(|e| format!("Failed to serialize response: {e}"))
+}
+
+/// 消息 id：Ollama 不返回 id，按 `created_at` 生成
+#[must_use]
+pub fn message_id(response: &Value) -> String {
+    let created_at = response
+        .get("created_at")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    format!("msg_{}", stable_hash_hex(&[created_at.as_bytes()]))
+}
+
+/// 按消息 id 与 block 位置生成稳定的 `tool_use` id
+fn tool_use_id(message_id: &str, index: usize) -> String {
+    let index = index.to_string();
+    format!(
+        "toolu_{}",
+        stable_hash_hex(&[message_id.as_bytes(), index.as_bytes()])
+    )
+}
+
+/// 原生 `tool_calls` 元素 → `tool_use`
+#[must_use]
+pub fn tool_call_to_tool_use(call: &Value, message_id: &str, index: usize) -> Option<ContentBlock> {
+    let function = call.get("function")?;
+    let name = function.get("name").and_then(Value::as_str)?;
+    // 部分模型模板会把参数输出为字符串
+    let input = match function.get("arguments") {
+        Some(Value::Object(arguments)) => Value::Object(arguments.clone()),
+        Some(Value::String(raw)) => serde_json::from_str::<Map<String, Value>>(raw)
+            .map_or_else(|_| json!({}), Value::Object),
+        _ => json!({}),
+    };
+    Some(ContentBlock::tool_use(
+        tool_use_id(message_id, index),
+        name,
+        input,
+    ))
+}
+
+/// 提示词协议解析出的片段 → content 块
+#[must_use]
+pub fn segment_to_block(segment: Segment, message_id: &str, index: usize) -> ContentBlock {
+    match segment {
+        Segment::Text(text) => ContentBlock::text(text),
+        Segment::ToolCall { name, input } => {
+            ContentBlock::tool_use(tool_use_id(message_id, index), name, input)
+        }
+    }
+}
+
+/// Ollama `done_reason` → Anthropic `stop_reason`
+#[must_use]
+pub fn stop_reason(done_reason: Option<&str>, has_tool_uses: bool) -> &'static str {
+    match done_reason {
+        _ if has_tool_uses => "tool_use",
+        Some("length") => "max_tokens",
+        _ => "end_turn",
+    }
+}
+
+/// `prompt_eval_count` / `eval_count` → Anthropic usage
+#[must_use]
+pub fn map_usage(response: &Value) -> Usage {
+    let count = |key: &str| response.get(key).and_then(Value::as_u64).unwrap_or(0);
+    Usage::new(count("prompt_eval_count"), count("eval_count"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn convert(response: &Value, prompt_tools: bool) -> Value {
+        serde_json::from_slice(
+            &ollama_response_to_anthropic(response.to_string().as_bytes(), None, prompt_tools)
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// 测试思考、文本与原生工具调用转换为 Anthropic 响应
+    #[test]
+    fn test_ollama_response_to_anthropic() {
+        let converted = convert(
+            &json!({
+                "model": "qwen3:8b",
+                "created_at": "2025-06-01T10:00:00Z",
+                "message": {
+                    "role": "assistant",
+                    "content": "Reading it.",
+                    "thinking": "Need the file.",
+                    "tool_calls": [{"function": {"name": "Read", "arguments": {"path": "a.rs"}}}]
+                },
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 42,
+                "eval_count": 7
+            }),
+            false,
+        );
+        assert_eq!(converted["model"], "qwen3:8b");
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(
+            content[0],
+            json!({"type": "thinking", "thinking": "Need the file.", "signature": ""})
+        );
+        assert_eq!(content[1]["text"], "Reading it.");
+        assert_eq!(content[2]["name"], "Read");
+        assert_eq!(content[2]["input"], json!({"path": "a.rs"}));
+        assert!(content[2]["id"].as_str().unwrap().starts_with("toolu_"));
+        assert_eq!(converted["stop_reason"], "tool_use");
+        assert_eq!(
+            converted["usage"],
+            json!({"input_tokens": 42, "output_tokens": 7})
+        );
+    }
+
+    /// 测试提示词模式下从文本中解析工具调用
+    #[test]
+    fn test_prompt_tool_calls_in_text() {
+        let converted = convert(
+            &json!({
+                "model": "llama3",
+                "message": {
+                    "role": "assistant",
+                    "content": "Sure.\n<tool_call>\n{\"name\": \"Bash\", \"arguments\": {\"command\": \"ls\"}}\n</tool_call>"
+                },
+                "done_reason": "stop"
+            }),
+            true,
+        );
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[0], json!({"type": "text", "text": "Sure."}));
+        assert_eq!(content[1]["name"], "Bash");
+        assert_eq!(content[1]["input"], json!({"command": "ls"}));
+        assert_eq!(converted["stop_reason"], "tool_use");
+    }
+}
@@ -0,0 +1,275 @@
+//! 流式响应转换
+//!
+//! Ollama NDJSON 流 → Anthropic Messages SSE 事件
+//!
+//! 每行是一个完整的 JSON 对象（不是 SSE）：
+//! - 首行 → `message_start`
+//! - `message.thinking` → thinking 块的 `thinking_delta`
+//! - `message.content` → text 块的 `text_delta`（提示词模式下先解析工具调用）
+//! - `message.tool_calls`（总是完整下发）→ 完整的 `tool_use` 块
+//! - `done: true` 的末行 → `message_delta` + `message_stop`
+//! - `{"error": ..}` 行 → `error` 事件
+
+use bytes::Bytes;
+use serde_json::Value;
+
+use super::{
+    prompt_tools::{Segment, ToolCallParser},
+    response,
+};
+use crate::gateway::{
+    anthropic::ContentBlock,
+    sse::{AnthropicSseWriter, StreamConverter},
+};
+
+/// Ollama NDJSON → Anthropic SSE 的有状态转换器
+pub struct OllamaStreamConverter {
+    /// 尚未凑成完整行的字节
+    buffer: Vec<u8>,
+    writer: AnthropicSseWriter,
+    message_id: Option<String>,
+    /// 提示词模式下的工具调用解析器
+    parser: Option<ToolCallParser>,
+    /// 已输出的 block 数，用于生成稳定的 `tool_use` id
+    block_index: usize,
+    has_tool_uses: bool,
+}
+
+impl OllamaStreamConverter {
+    #[must_use]
+    pub fn new(model_hint: Option<&str>, prompt_tools: bool) -> Self {
+        Self {
+            buffer: Vec::new(),
+            writer: AnthropicSseWriter::new("msg_ollama", model_hint.unwrap_or("unknown")),
+            message_id: None,
+            parser: prompt_tools.then(ToolCallParser::default),
+            block_index: 0,
+            has_tool_uses: false,
+        }
+    }
+
+    fn handle_line(&mut self, line: &[u8]) {
+        let Ok(data) = serde_json::from_slice::<Value>(line) else {
+            return;
+        };
+        if let Some(error) = data.get("error") {
+            let message = error.as_str().unwrap_or("Upstream stream error.");
+            self.writer.error("api_error", message);
+            return;
+        }
+
+        let message_id = self
+            .message_id
+            .get_or_insert_with(|| response::message_id(&data))
+            .clone();
+        self.writer
+            .set_message_meta(Some(&message_id), data.get("model").and_then(Value::as_str));
+        self.writer.start(None);
+
+        let message = data.get("message");
+        let field = |key: &str| {
+            message
+                .and_then(|message| message.get(key))
+                .and_then(Value::as_str)
+                .unwrap_or("")
+        };
+        self.writer.thinking_delta(field("thinking"));
+        let content = field("content").to_string();
+        if let Some(parser) = self.parser.as_mut() {
+            let segments = parser.feed(&content);
+            self.write_segments(segments);
+        } else {
+            self.writer.text_delta(&content);
+        }
+        for call in message
+            .and_then(|message| message.get("tool_calls"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(tool_use) =
+                response::tool_call_to_tool_use(call, &message_id, self.block_index)
+            {
+                self.push_block(tool_use);
+            }
+        }
+
+        if data.get("done").and_then(Value::as_bool) == Some(true) {
+            self.finish_message(&data);
+        }
+    }
+
+    fn write_segments(&mut self, segments: Vec<Segment>) {
+        let message_id = self.message_id.clone().unwrap_or_default();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => self.writer.text_delta(&text),
+                segment @ Segment::ToolCall { .. } => {
+                    let block = response::segment_to_block(segment, &message_id, self.block_index);
+                    self.push_block(block);
+                }
+            }
+        }
+    }
+
+    fn push_block(&mut self, tool_use: ContentBlock) {
+        self.has_tool_uses = true;
+        self.block_index += 1;
+        self.writer.full_block(tool_use);
+    }
+
+    fn finish_message(&mut self, data: &Value) {
+        if let Some(segments) = self.parser.as_mut().map(ToolCallParser::finish) {
+            self.write_segments(segments);
+        }
+        let stop_reason = response::stop_reason(
+            data.get("done_reason").and_then(Value::as_str),
+            self.has_tool_uses,
+        );
+        // message_start 时还没有 token 统计，在 message_delta 中补齐输入 token
+        self.writer
+            .finish(stop_reason, Some(response::map_usage(data).into()));
+    }
+}
+
+impl StreamConverter for OllamaStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line = self.buffer.drain(..=end).collect::<Vec<_>>();
+            self.handle_line(&line);
+        }
+        self.writer.take()
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        let rest = std::mem::take(&mut self.buffer);
+        if !rest.trim_ascii().is_empty() {
+            self.handle_line(&rest);
+        }
+        // 上游没有发送 done 行就断开时，补齐收尾事件
+        if !self.writer.is_finished() {
+            self.finish_message(&Value::Null);
+        }
+        self.writer.take()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::gateway::sse::SseDecoder;
+
+    fn run(converter: &mut OllamaStreamConverter, lines: &[Value]) -> Vec<Value> {
+        let input = lines
+            .iter()
+            .map(|line| line.to_string() + "\n")
+            .collect::<String>();
+        // 按 7 字节切片输入，模拟任意的网络分包
+        let mut out = input
+            .as_bytes()
+            .chunks(7)
+            .flat_map(|chunk| converter.convert(chunk))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+        let mut decoder = SseDecoder::default();
+        out.iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .filter_map(|event| event.json())
+            .collect()
+    }
+
+    fn types(events: &[Value]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect()
+    }
+
+    /// 测试思考、文本与原生工具调用转换为 Anthropic 流式事件
+    #[test]
+    fn test_ollama_stream_to_anthropic_events() {
+        let chunk = |message: Value| json!({"model": "qwen3:8b", "created_at": "t1", "message": message, "done": false});
+        let lines = [
+            chunk(json!({"role": "assistant", "content": "", "thinking": "Plan"})),
+            chunk(json!({"role": "assistant", "content": "Hello"})),
+            chunk(json!({"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "Read", "arguments": {"path": "a.rs"}}}
+            ]})),
+            json!({
+                "model": "qwen3:8b", "created_at": "t2",
+                "message": {"role": "assistant", "content": ""},
+                "done": true, "done_reason": "stop",
+                "prompt_eval_count": 30, "eval_count": 9
+            }),
+        ];
+        let mut converter = OllamaStreamConverter::new(Some("hint"), false);
+        let events = run(&mut converter, &lines);
+        assert_eq!(
+            types(&events),
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0]["message"]["model"], "qwen3:8b");
+        assert_eq!(events[2]["delta"]["thinking"], "Plan");
+        assert_eq!(events[5]["delta"]["text"], "Hello");
+        assert_eq!(events[7]["content_block"]["name"], "Read");
+        assert_eq!(events[10]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[10]["usage"]["input_tokens"], 30);
+        assert_eq!(events[10]["usage"]["output_tokens"], 9);
+    }
+
+    /// 测试提示词模式下跨行拆分的工具调用被解析为 `tool_use` 块
+    #[test]
+    fn test_ollama_stream_prompt_tools() {
+        let chunk = |content: &str| json!({"model": "llama3", "created_at": "t1", "message": {"role": "assistant", "content": content}, "done": false});
+        let lines = [
+            chunk("Checking.\n<tool"),
+            chunk("_call>{\"name\": \"Bash\", "),
+            chunk("\"arguments\": {\"command\": \"ls\"}}</tool_call>"),
+            json!({"model": "llama3", "created_at": "t2", "message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "stop"}),
+        ];
+        let mut converter = OllamaStreamConverter::new(None, true);
+        let events = run(&mut converter, &lines);
+        let text = events
+            .iter()
+            .filter_map(|event| event["delta"]["text"].as_str())
+            .collect::<String>();
+        assert_eq!(text, "Checking.");
+        let tool_use = events
+            .iter()
+            .find(|event| event["content_block"]["type"] == "tool_use")
+            .unwrap();
+        assert_eq!(tool_use["content_block"]["name"], "Bash");
+        let input = events
+            .iter()
+            .find_map(|event| event["delta"]["partial_json"].as_str())
+            .unwrap();
+        assert_eq!(input, r#"{"command":"ls"}"#);
+        assert_eq!(events[events.len() - 2]["delta"]["stop_reason"], "tool_use");
+    }
+
+    /// 测试流中的错误行转换为 error 事件
+    #[test]
+    fn test_ollama_stream_error() {
+        let mut converter = OllamaStreamConverter::new(None, false);
+        let events = run(&mut converter, &[json!({"error": "model 'x' not found"})]);
+        assert_eq!(types(&events), ["error"]);
+        assert_eq!(events[0]["error"]["message"], "model 'x' not found");
+    }
+}
@@ -0,0 +1,162 @@
+//! 工具名可逆别名
+//!
+//! `OpenAI` 及多数兼容上游要求函数名匹配 `^[a-zA-Z0-9_-]{1,64}$`，
+//! 而 MCP 工具名（如 `mcp__server__tool`）可能超长或包含 `.`、`:` 等字符。
+//!
+//! 每个请求建立一张别名表：不合规的名称被清洗、截断并追加原名的稳定哈希，
+//! 发往上游时使用别名，响应（包括流式响应）中的函数名再还原为原名，
+//! 客户端始终只看到原始工具名。
+
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::gateway::{
+    anthropic::{ContentBlock, MessagesRequest},
+    hash::stable_hash_hex,
+};
+
+/// 函数名最大长度
+const MAX_NAME_LEN: usize = 64;
+/// 别名中哈希后缀的长度（含分隔的 `_`）
+const HASH_SUFFIX_LEN: usize = 9;
+
+/// 单个请求内的工具名别名表
+#[derive(Debug, Clone, Default)]
+pub struct ToolAliases {
+    to_alias: HashMap<String, String>,
+    to_original: HashMap<String, String>,
+}
+
+impl ToolAliases {
+    /// 从请求的 tools、`tool_choice` 和历史 `tool_use` 中收集需要别名的工具名
+    #[must_use]
+    pub fn from_request(request: &MessagesRequest) -> Self {
+        let mut aliases = Self::default();
+
+        for tool in &request.tools {
+            aliases.register(&tool.name);
+        }
+        if let Some(name) = request
+            .tool_choice
+            .as_ref()
+            .and_then(|choice| choice.name.as_deref())
+        {
+            aliases.register(name);
+        }
+        for block in request
+            .messages
+            .iter()
+            .flat_map(|message| message.content.blocks())
+        {
+            if let ContentBlock::ToolUse(tool_use) = block {
+                aliases.register(&tool_use.name);
+            }
+        }
+
+        if !aliases.to_alias.is_empty() {
+            tracing::debug!("🏷️ 工具名别名: {:?}", aliases.to_alias);
+        }
+        aliases
+    }
+
+    fn register(&mut self, name: &str) {
+        if is_valid_function_name(name) || self.to_alias.contains_key(name) {
+            return;
+        }
+        let alias = make_alias(name);
+        self.to_original.insert(alias.clone(), name.to_string());
+        self.to_alias.insert(name.to_string(), alias);
+    }
+
+    /// 原名 → 发往上游的名称
+    #[must_use]
+    pub fn alias<'a>(&'a self, name: &'a str) -> Cow<'a, str> {
+        self.to_alias
+            .get(name)
+            .map_or(Cow::Borrowed(name), |alias| Cow::Borrowed(alias.as_str()))
+    }
+
+    /// 上游返回的名称 → 原名
+    #[must_use]
+    pub fn original<'a>(&'a self, name: &'a str) -> &'a str {
+        self.to_original.get(name).map_or(name, String::as_str)
+    }
+}
+
+fn is_valid_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// 清洗非法字符、截断，并追加原名哈希保证唯一与确定性
+fn make_alias(name: &str) -> String {
+    let sanitized = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_NAME_LEN - HASH_SUFFIX_LEN)
+        .collect::<String>();
+    let hash = stable_hash_hex(&[name.as_bytes()]);
+    format!("{sanitized}_{}", &hash[..HASH_SUFFIX_LEN - 1])
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn parse(request: &Value) -> MessagesRequest {
+        serde_json::from_value(request.clone()).unwrap()
+    }
+
+    /// 合规名称保持不变
+    #[test]
+    fn test_valid_names_are_untouched() {
+        let request = parse(&json!({
+            "tools": [{"name": "mcp__playwright__browser_navigate"}, {"name": "Read"}]
+        }));
+        let aliases = ToolAliases::from_request(&request);
+        assert_eq!(aliases.alias("Read"), "Read");
+        assert_eq!(
+            aliases.alias("mcp__playwright__browser_navigate"),
+            "mcp__playwright__browser_navigate"
+        );
+    }
+
+    /// 超长与含非法字符的名称可往返还原
+    #[test]
+    fn test_long_and_invalid_names_round_trip() {
+        let long_name = format!("mcp__{}__do_something", "very_long_server_name".repeat(4));
+        let dotted = "mcp__docs.server__search";
+        let request = parse(&json!({
+            "tools": [{"name": long_name}],
+            "messages": [{
+                "role": "assistant",
+                "content": [{"type": "tool_use", "id": "t1", "name": dotted, "input": {}}]
+            }]
+        }));
+        let aliases = ToolAliases::from_request(&request);
+
+        let long_alias = aliases.alias(&long_name).into_owned();
+        assert!(long_alias.len() <= MAX_NAME_LEN);
+        assert!(is_valid_function_name(&long_alias));
+        assert_eq!(aliases.original(&long_alias), long_name);
+
+        let dotted_alias = aliases.alias(dotted).into_owned();
+        assert!(is_valid_function_name(&dotted_alias));
+        assert_eq!(aliases.original(&dotted_alias), dotted);
+
+        // 同一原名在不同请求中得到相同别名
+        let again = ToolAliases::from_request(&request);
+        assert_eq!(again.alias(dotted), dotted_alias);
+    }
+}
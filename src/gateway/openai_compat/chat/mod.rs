@@ -0,0 +1,19 @@
+//! `OpenAI` Chat Completions 上游（`mode = "openai_chat"`）
+//!
+//! 面向 `DeepSeek` / Kimi / GLM 等只提供 Chat Completions 接口的服务：
+//! - Claude CLI 请求 → Chat Completions 请求
+//! - Chat Completions 响应 → Claude CLI 响应
+//! - Chat Completions 流式 chunk → Claude CLI 流式事件
+//!
+//! 这些服务把思考内容放在 `reasoning_content` 字段中，与 Anthropic thinking 块双向转换。
+//! 历史 thinking 是否回传由 upstream 的 `send_reasoning_content` 决定：
+//! `DeepSeek` 要求不回传，Kimi 开启思考后要求回传。
+
+mod request;
+mod response;
+mod stream;
+
+pub use self::{
+    request::anthropic_request_to_chat, response::chat_response_to_anthropic,
+    stream::ChatStreamConverter,
+};
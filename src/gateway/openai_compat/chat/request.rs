@@ -0,0 +1,350 @@
+//! 请求格式转换
+//!
+//! Anthropic Claude 请求 → `OpenAI` Chat Completions 请求
+//!
+//! 主要转换：
+//! - system → 首条 system 消息
+//! - text / image 块 → `content`（纯文本时为字符串，否则为 parts 数组）
+//! - `tool_use` → assistant 消息的 `tool_calls`
+//! - `tool_result` → `role: "tool"` 消息（排在同一条 user 消息的其余内容之前）
+//! - thinking 块 → assistant 消息的 `reasoning_content`（仅 `send_reasoning_content` 开启时）
+
+use bytes::Bytes;
+use rayon::prelude::*;
+use serde_json::{Map, Value, json};
+
+use super::super::{
+    ConversionContext,
+    alias::ToolAliases,
+    media,
+    request::{
+        claude_content_to_blocks, claude_system_to_text, claude_tool_result_content_to_parts,
+        collect_tool_schemas,
+    },
+    tools,
+};
+use crate::{
+    config::UpstreamConfig,
+    gateway::anthropic::{ContentBlock, Message, MessagesRequest, Role},
+};
+
+/// Anthropic Claude 请求 → `OpenAI` Chat Completions 请求
+pub fn anthropic_request_to_chat(
+    request: &MessagesRequest,
+    upstream: &UpstreamConfig,
+) -> Result<(Bytes, ConversionContext), String> {
+    if request.model.is_empty() {
+        return Err("Request must include model.".to_string());
+    }
+    if request.messages.is_empty() {
+        return Err("Request must include messages.".to_string());
+    }
+    let stream = request.is_stream();
+    let max_tokens = request
+        .max_tokens
+        .filter(|value| *value > 0)
+        .unwrap_or(4096);
+
+    let aliases = ToolAliases::from_request(request);
+    let mut chat_messages = Vec::with_capacity(request.messages.len() + 1);
+    if let Some(system) = request.system.as_ref().and_then(claude_system_to_text)
+        && !system.trim().is_empty()
+    {
+        chat_messages.push(json!({ "role": "system", "content": system }));
+    }
+    let per_message: Vec<Vec<Value>> = request
+        .messages
+        .par_iter()
+        .map(|message| {
+            claude_message_to_chat_messages(message, &aliases, upstream.send_reasoning_content)
+        })
+        .collect();
+    chat_messages.extend(per_message.into_iter().flatten());
+
+    let mut out = Map::new();
+    out.insert("model".to_string(), json!(request.model));
+    out.insert("max_tokens".to_string(), json!(max_tokens));
+    out.insert("stream".to_string(), Value::Bool(stream));
+    if stream {
+        // 默认不返回 usage，需显式请求最后一个 chunk 携带 usage
+        out.insert(
+            "stream_options".to_string(),
+            json!({ "include_usage": true }),
+        );
+    }
+    out.insert("messages".to_string(), Value::Array(chat_messages));
+
+    if let Some(temperature) = request.temperature {
+        out.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = request.top_p {
+        out.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(stop) =
+        tools::map_anthropic_stop_sequences_to_openai_stop(request.stop_sequences.as_deref())
+    {
+        out.insert("stop".to_string(), stop);
+    }
+    if !request.tools.is_empty() {
+        let mapped = tools::map_anthropic_tools_to_chat(&request.tools, upstream, &aliases);
+        if mapped.as_array().is_some_and(|tools| !tools.is_empty()) {
+            out.insert("tools".to_string(), mapped);
+            let (tool_choice, parallel_tool_calls) =
+                tools::map_anthropic_tool_choice_to_chat(request.tool_choice.as_ref(), &aliases);
+            if let Some(tool_choice) = tool_choice {
+                out.insert("tool_choice".to_string(), tool_choice);
+            }
+            if let Some(parallel_tool_calls) = parallel_tool_calls {
+                out.insert(
+                    "parallel_tool_calls".to_string(),
+                    Value::Bool(parallel_tool_calls),
+                );
+            }
+        }
+    }
+
+    let context = ConversionContext {
+        tool_schemas: collect_tool_schemas(&request.tools),
+        tool_aliases: aliases,
+        ..ConversionContext::default()
+    };
+    serde_json::to_vec(&Value::Object(out))
+        .map(|bytes| (Bytes::from(bytes), context))
+        .map_err(|err| format!("Failed to serialize request: {err}"))
+}
+
+/// 一条 Anthropic 消息 → 一条或多条 Chat 消息
+fn claude_message_to_chat_messages(
+    message: &Message,
+    aliases: &ToolAliases,
+    send_reasoning_content: bool,
+) -> Vec<Value> {
+    let blocks = claude_content_to_blocks(&message.content);
+    match &message.role {
+        Role::Assistant => vec![claude_assistant_to_chat(
+            &blocks,
+            aliases,
+            send_reasoning_content,
+        )],
+        Role::Other(role) if role == "system" => Vec::new(),
+        _ => claude_user_to_chat(&blocks),
+    }
+}
+
+/// assistant 消息：文本 → `content`，thinking → `reasoning_content`，`tool_use` → `tool_calls`
+fn claude_assistant_to_chat(
+    blocks: &[ContentBlock],
+    aliases: &ToolAliases,
+    send_reasoning_content: bool,
+) -> Value {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text(block) => text.push_str(&block.text),
+            ContentBlock::Thinking(block) => reasoning.push_str(&block.thinking),
+            ContentBlock::ToolUse(tool_use) => {
+                let id = if tool_use.id.is_empty() {
+                    "call_proxy"
+                } else {
+                    tool_use.id.as_str()
+                };
+                tool_calls.push(json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {
+                        "name": aliases.alias(&tool_use.name),
+                        "arguments": serde_json::to_string(&tool_use.input).unwrap_or_else(|_| "{}".to_string())
+                    }
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("role".to_string(), json!("assistant"));
+    // 只有工具调用时 content 为 null（部分上游拒绝空字符串）
+    let content = if text.is_empty() && !tool_calls.is_empty() {
+        Value::Null
+    } else {
+        Value::String(text)
+    };
+    out.insert("content".to_string(), content);
+    if send_reasoning_content && !reasoning.is_empty() {
+        out.insert("reasoning_content".to_string(), Value::String(reasoning));
+    }
+    if !tool_calls.is_empty() {
+        out.insert("tool_calls".to_string(), Value::Array(tool_calls));
+    }
+    Value::Object(out)
+}
+
+/// user 消息：`tool_result` → tool 消息，其余内容 → 随后的 user 消息
+///
+/// tool 消息只能承载文本，工具返回的图片附在随后的 user 消息中。
+fn claude_user_to_chat(blocks: &[ContentBlock]) -> Vec<Value> {
+    let mut out = Vec::new();
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text(block) => {
+                parts.push(json!({ "type": "text", "text": block.text }));
+            }
+            ContentBlock::Image(image) => {
+                if let Some(part) = media::claude_image_block_to_input_image_part(image) {
+                    parts.push(input_image_to_chat_part(&part));
+                }
+            }
+            ContentBlock::ToolResult(result) => {
+                let call_id = result.tool_use_id.as_str();
+                let (text, images) = claude_tool_result_content_to_parts(result.content.as_ref());
+                let text = if result.is_error() && !text.is_empty() {
+                    format!("[ERROR] {text}")
+                } else if text.is_empty() && !images.is_empty() {
+                    format!(
+                        "[{} image(s) returned, attached in the next message]",
+                        images.len()
+                    )
+                } else {
+                    text.into_owned()
+                };
+                out.push(json!({ "role": "tool", "tool_call_id": call_id, "content": text }));
+                if !images.is_empty() {
+                    parts.push(json!({
+                        "type": "text",
+                        "text": format!("Images returned by tool call {call_id}:")
+                    }));
+                    parts.extend(images.iter().map(input_image_to_chat_part));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if parts.is_empty() {
+        return out;
+    }
+    // 纯文本内容合并为字符串，兼容不接受 parts 数组的上游
+    let content = if parts.iter().all(|part| part["type"] == "text") {
+        Value::String(
+            parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    } else {
+        Value::Array(parts)
+    };
+    out.push(json!({ "role": "user", "content": content }));
+    out
+}
+
+/// Responses `input_image` → Chat `image_url` part
+fn input_image_to_chat_part(part: &Value) -> Value {
+    json!({
+        "type": "image_url",
+        "image_url": { "url": part.get("image_url").cloned().unwrap_or(Value::Null) }
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn convert(request: &Value, send_reasoning_content: bool) -> Value {
+        let upstream = UpstreamConfig {
+            send_reasoning_content,
+            ..UpstreamConfig::default()
+        };
+        let request = serde_json::from_value(request.clone()).unwrap();
+        let (body, _) = anthropic_request_to_chat(&request, &upstream).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// 测试工具调用、工具结果与 thinking 回传为 `reasoning_content`
+    #[test]
+    fn test_anthropic_request_to_chat() {
+        let request = json!({
+            "model": "deepseek-reasoner",
+            "max_tokens": 1024,
+            "stream": true,
+            "system": [{"type": "text", "text": "You are helpful."}],
+            "tools": [
+                {"name": "Read", "description": "Read a file", "input_schema": {"type": "object", "properties": {"path": {"type": "string"}}}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "tool_choice": {"type": "tool", "name": "Read", "disable_parallel_tool_use": true},
+            "messages": [
+                {"role": "user", "content": "Open a.rs"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "Need to read it.", "signature": ""},
+                    {"type": "tool_use", "id": "call_1", "name": "Read", "input": {"path": "a.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "call_1", "content": "fn main() {}"},
+                    {"type": "text", "text": "Explain it."}
+                ]}
+            ]
+        });
+        let body = convert(&request, true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tools"][0]["function"]["name"], "Read");
+        assert_eq!(body["tool_choice"]["function"]["name"], "Read");
+        assert_eq!(body["parallel_tool_calls"], false);
+
+        let messages = body["messages"].as_array().unwrap();
+        let roles = messages
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, ["system", "user", "assistant", "tool", "user"]);
+        assert_eq!(messages[1]["content"], "Open a.rs");
+        assert_eq!(messages[2]["content"], Value::Null);
+        assert_eq!(messages[2]["reasoning_content"], "Need to read it.");
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            r#"{"path":"a.rs"}"#
+        );
+        assert_eq!(messages[3]["tool_call_id"], "call_1");
+        assert_eq!(messages[3]["content"], "fn main() {}");
+        assert_eq!(messages[4]["content"], "Explain it.");
+
+        // 未开启 send_reasoning_content 时不回传 thinking
+        let body = convert(&request, false);
+        assert!(body["messages"][2].get("reasoning_content").is_none());
+    }
+
+    /// 测试图片转换为 `image_url` part，工具返回的图片附在随后的 user 消息中
+    #[test]
+    fn test_images_to_chat_parts() {
+        let image = json!({"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}});
+        let request = json!({
+            "model": "kimi-k2",
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "Look"}, image]},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "call_2", "name": "Shot", "input": {}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "call_2", "content": [image]}]}
+            ]
+        });
+        let body = convert(&request, false);
+        let messages = body["messages"].as_array().unwrap();
+        assert!(body.get("stream_options").is_none());
+        assert_eq!(messages[0]["content"][1]["type"], "image_url");
+        assert_eq!(
+            messages[0]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,AAAA"
+        );
+        assert_eq!(messages[2]["role"], "tool");
+        assert_eq!(
+            messages[2]["content"],
+            "[1 image(s) returned, attached in the next message]"
+        );
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(messages[3]["content"][1]["type"], "image_url");
+    }
+}
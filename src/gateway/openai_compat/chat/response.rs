@@ -0,0 +1,202 @@
+//! 响应格式转换
+//!
+//! `OpenAI` Chat Completions 响应 → Anthropic Claude API 响应
+//!
+//! 主要转换：
+//! - `message.reasoning_content`（`DeepSeek` / Kimi / GLM）→ thinking 块
+//! - `message.content` → text 块
+//! - `message.tool_calls` → `tool_use` 块（参数修复与校验同 Responses）
+//! - `finish_reason` → `stop_reason`
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use super::super::{ConversionContext, response};
+use crate::gateway::anthropic::{ContentBlock, MessagesResponse};
+
+/// `OpenAI` Chat Completions 响应 → Anthropic 响应
+pub fn chat_response_to_anthropic(
+    body: &Bytes,
+    model_hint: Option<&str>,
+    context: &ConversionContext,
+) -> Result<Bytes, String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|_| "Upstream response must be JSON.".to_string())?;
+    let Some(object) = value.as_object() else {
+        return Err("Upstream response must be a JSON object.".to_string());
+    };
+
+    let id = object
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or("msg_proxy");
+    let model = object
+        .get("model")
+        .and_then(Value::as_str)
+        .or(model_hint)
+        .unwrap_or("unknown");
+    let choice = object
+        .get("choices")
+        .and_then(Value::as_array)
+        .and_then(|choices| choices.first());
+    let message = choice.and_then(|choice| choice.get("message"));
+    let field = |key: &str| {
+        message
+            .and_then(|message| message.get(key))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+    };
+
+    let mut content = Vec::new();
+    let reasoning = field("reasoning_content");
+    if !reasoning.trim().is_empty() {
+        content.push(ContentBlock::thinking(reasoning, Some(String::new())));
+    }
+    let mut text = field("content").to_string();
+    let mut tool_uses = Vec::new();
+    for (index, call) in message
+        .and_then(|message| message.get("tool_calls"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let item = tool_call_to_function_call(call, id, index);
+        match response::responses_function_call_to_tool_use(&item, context) {
+            Some(Ok(tool_use)) => tool_uses.push(tool_use),
+            Some(Err(explanation)) => {
+                if !text.is_empty() {
+                    text.push_str("\n\n");
+                }
+                text.push_str(&explanation);
+            }
+            None => {}
+        }
+    }
+    if !text.trim().is_empty() || tool_uses.is_empty() {
+        content.push(ContentBlock::text(text));
+    }
+    let stop_reason = stop_reason(
+        choice
+            .and_then(|choice| choice.get("finish_reason"))
+            .and_then(Value::as_str),
+        !tool_uses.is_empty(),
+    );
+    content.extend(tool_uses);
+
+    let usage = object
+        .get("usage")
+        .and_then(Value::as_object)
+        .map(response::map_openai_usage_to_anthropic_usage)
+        .unwrap_or_default();
+    let out = MessagesResponse {
+        content,
+        stop_reason: Some(stop_reason.to_string()),
+        usage,
+        ..MessagesResponse::new(id, model)
+    };
+    serde_json::to_vec(&out)
+        .map(Bytes::from)
+        .map_err(|err| format!("Failed to serialize response: {err}"))
+}
+
+/// Chat `tool_calls[]` 项 → Responses `function_call` 形态，复用其参数修复与校验
+///
+/// 部分上游不返回调用 id，此时按响应 id 与序号生成。
+#[must_use]
+pub fn tool_call_to_function_call(
+    call: &Value,
+    message_id: &str,
+    index: usize,
+) -> Map<String, Value> {
+    let function = call.get("function");
+    let text = |value: Option<&Value>, key: &str| {
+        value
+            .and_then(|value| value.get(key))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string()
+    };
+    let mut id = text(Some(call), "id");
+    if id.is_empty() {
+        id = format!("call_{message_id}_{index}");
+    }
+    let mut item = Map::new();
+    item.insert("call_id".to_string(), Value::String(id));
+    item.insert("name".to_string(), Value::String(text(function, "name")));
+    item.insert(
+        "arguments".to_string(),
+        Value::String(text(function, "arguments")),
+    );
+    item
+}
+
+/// Chat `finish_reason` → Anthropic `stop_reason`
+#[must_use]
+pub fn stop_reason(finish_reason: Option<&str>, has_tool_uses: bool) -> &'static str {
+    match finish_reason {
+        Some("length") => "max_tokens",
+        Some("content_filter") => "refusal",
+        _ if has_tool_uses => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 测试 `reasoning_content`、文本与工具调用转换为 Anthropic content
+    #[test]
+    fn test_chat_response_to_anthropic() {
+        let body = json!({
+            "id": "chatcmpl-1",
+            "model": "deepseek-reasoner",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "reasoning_content": "The user wants the file.",
+                    "content": "Reading it.",
+                    "tool_calls": [{"id": "call_9", "type": "function", "function": {"name": "Read", "arguments": "{\"path\": \"a.rs\"}"}}]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": {"prompt_tokens": 100, "completion_tokens": 20, "prompt_tokens_details": {"cached_tokens": 60}}
+        });
+        let converted: Value = serde_json::from_slice(
+            &chat_response_to_anthropic(
+                &Bytes::from(body.to_string()),
+                None,
+                &ConversionContext::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(converted["content"][0]["type"], "thinking");
+        assert_eq!(
+            converted["content"][0]["thinking"],
+            "The user wants the file."
+        );
+        assert_eq!(converted["content"][1]["text"], "Reading it.");
+        assert_eq!(converted["content"][2]["type"], "tool_use");
+        assert_eq!(converted["content"][2]["id"], "call_9");
+        assert_eq!(converted["content"][2]["input"]["path"], "a.rs");
+        assert_eq!(converted["stop_reason"], "tool_use");
+        assert_eq!(converted["usage"]["input_tokens"], 40);
+        assert_eq!(converted["usage"]["cache_read_input_tokens"], 60);
+    }
+
+    /// 测试 `finish_reason` 映射
+    #[test]
+    fn test_stop_reason() {
+        assert_eq!(stop_reason(Some("stop"), false), "end_turn");
+        assert_eq!(stop_reason(Some("length"), true), "max_tokens");
+        assert_eq!(stop_reason(Some("content_filter"), false), "refusal");
+        assert_eq!(stop_reason(Some("tool_calls"), true), "tool_use");
+        assert_eq!(stop_reason(None, false), "end_turn");
+    }
+}
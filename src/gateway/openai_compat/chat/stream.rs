@@ -0,0 +1,326 @@
+//! 流式响应转换
+//!
+//! `OpenAI` Chat Completions SSE → Anthropic Messages SSE 事件
+//!
+//! 主要转换：
+//! - 首个 chunk → `message_start`
+//! - `delta.reasoning_content` → thinking 块的 `thinking_delta`
+//! - `delta.content` → text 块的 `text_delta`
+//! - `delta.tool_calls`（按 `index` 分片下发）→ 完整的 `tool_use` 块
+//! - `finish_reason` + usage chunk / `[DONE]` → `message_delta` + `message_stop`
+//!
+//! 与 Responses 一样，工具调用参数收齐后才整体下发，以便还原别名、修复并校验参数。
+//! `stream_options.include_usage` 时 usage 位于 `finish_reason` 之后单独的 chunk，
+//! 因此收到 `finish_reason` 后先记下，等 usage 或流结束再收尾。
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use super::{super::ConversionContext, super::response as responses, response};
+use crate::gateway::{
+    anthropic::DeltaUsage,
+    sse::{AnthropicSseWriter, SseDecoder, SseEvent, StreamConverter},
+};
+
+/// 正在累积的工具调用
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Chat Completions SSE → Anthropic SSE 的有状态转换器
+pub struct ChatStreamConverter {
+    decoder: SseDecoder,
+    writer: AnthropicSseWriter,
+    context: ConversionContext,
+    message_id: String,
+    /// `tool_calls[].index` → 累积中的工具调用
+    tool_calls: BTreeMap<u64, PendingToolCall>,
+    /// 已收到的 `finish_reason`，等待 usage 后收尾
+    stop_reason: Option<&'static str>,
+    has_tool_uses: bool,
+}
+
+impl ChatStreamConverter {
+    #[must_use]
+    pub fn new(model_hint: Option<&str>, context: ConversionContext) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            writer: AnthropicSseWriter::new("msg_proxy", model_hint.unwrap_or("unknown")),
+            context,
+            message_id: "msg_proxy".to_string(),
+            tool_calls: BTreeMap::new(),
+            stop_reason: None,
+            has_tool_uses: false,
+        }
+    }
+
+    fn handle_event(&mut self, event: &SseEvent) {
+        if event.data.trim() == "[DONE]" {
+            self.finish_message(None);
+            return;
+        }
+        let Some(data) = event.json() else {
+            return;
+        };
+        if let Some(error) = data.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .or_else(|| error.as_str())
+                .unwrap_or("Upstream stream error.");
+            self.writer.error("api_error", message);
+            return;
+        }
+
+        let id = data.get("id").and_then(Value::as_str);
+        if let Some(id) = id {
+            id.clone_into(&mut self.message_id);
+        }
+        self.writer
+            .set_message_meta(id, data.get("model").and_then(Value::as_str));
+        self.writer.start(None);
+
+        let choice = data
+            .get("choices")
+            .and_then(Value::as_array)
+            .and_then(|choices| choices.first());
+        if let Some(delta) = choice.and_then(|choice| choice.get("delta")) {
+            let field = |key: &str| delta.get(key).and_then(Value::as_str).unwrap_or("");
+            self.writer.thinking_delta(field("reasoning_content"));
+            self.writer.text_delta(field("content"));
+            for call in delta
+                .get("tool_calls")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                self.push_tool_call_delta(call);
+            }
+        }
+        if let Some(finish_reason) = choice
+            .and_then(|choice| choice.get("finish_reason"))
+            .and_then(Value::as_str)
+        {
+            self.flush_tool_calls();
+            self.stop_reason = Some(response::stop_reason(
+                Some(finish_reason),
+                self.has_tool_uses,
+            ));
+        }
+        // usage 可能与 finish_reason 同一 chunk，也可能在其后单独下发
+        if let Some(usage) = data.get("usage").and_then(Value::as_object)
+            && self.stop_reason.is_some()
+        {
+            self.finish_message(Some(
+                responses::map_openai_usage_to_anthropic_usage(usage).into(),
+            ));
+        }
+    }
+
+    fn push_tool_call_delta(&mut self, call: &Value) {
+        let index = call.get("index").and_then(Value::as_u64).unwrap_or(0);
+        let pending = self.tool_calls.entry(index).or_default();
+        let function = call.get("function");
+        if let Some(id) = call.get("id").and_then(Value::as_str) {
+            id.clone_into(&mut pending.id);
+        }
+        if let Some(name) = function
+            .and_then(|function| function.get("name"))
+            .and_then(Value::as_str)
+        {
+            pending.name.push_str(name);
+        }
+        if let Some(arguments) = function
+            .and_then(|function| function.get("arguments"))
+            .and_then(Value::as_str)
+        {
+            pending.arguments.push_str(arguments);
+        }
+    }
+
+    /// 把累积完成的工具调用作为完整的 `tool_use` 块输出
+    fn flush_tool_calls(&mut self) {
+        let tool_calls = std::mem::take(&mut self.tool_calls);
+        for (index, call) in tool_calls {
+            let mut item = Map::new();
+            item.insert("id".to_string(), Value::String(call.id));
+            item.insert(
+                "function".to_string(),
+                serde_json::json!({ "name": call.name, "arguments": call.arguments }),
+            );
+            let item = response::tool_call_to_function_call(
+                &Value::Object(item),
+                &self.message_id,
+                usize::try_from(index).unwrap_or_default(),
+            );
+            match responses::responses_function_call_to_tool_use(&item, &self.context) {
+                Some(Ok(tool_use)) => {
+                    self.has_tool_uses = true;
+                    self.writer.full_block(tool_use);
+                }
+                Some(Err(explanation)) => {
+                    self.writer.close_block();
+                    self.writer.text_delta(&explanation);
+                    self.writer.close_block();
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn finish_message(&mut self, usage: Option<DeltaUsage>) {
+        if self.writer.is_finished() {
+            return;
+        }
+        self.flush_tool_calls();
+        let stop_reason = self
+            .stop_reason
+            .unwrap_or_else(|| response::stop_reason(None, self.has_tool_uses));
+        self.writer.finish(stop_reason, usage);
+    }
+}
+
+impl StreamConverter for ChatStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event);
+        }
+        self.writer.take()
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event);
+        }
+        // 上游没有发送 usage / [DONE] 就断开时，补齐收尾事件
+        self.finish_message(None);
+        self.writer.take()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn run(converter: &mut ChatStreamConverter, chunks: &[Value]) -> Vec<Value> {
+        let mut input = chunks
+            .iter()
+            .map(|chunk| "data: ".to_string() + &chunk.to_string() + "\n\n")
+            .collect::<String>();
+        input.push_str("data: [DONE]\n\n");
+        // 按 11 字节切片输入，模拟任意的网络分包
+        let mut out = input
+            .as_bytes()
+            .chunks(11)
+            .flat_map(|chunk| converter.convert(chunk))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+        let mut decoder = SseDecoder::default();
+        out.iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .filter_map(|event| event.json())
+            .collect()
+    }
+
+    fn chunk(delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": "chatcmpl-7",
+            "model": "deepseek-reasoner",
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+        })
+    }
+
+    /// 测试 `reasoning_content`、文本与分片工具调用转换为 Anthropic 流式事件
+    #[test]
+    fn test_chat_stream_to_anthropic_events() {
+        let chunks = [
+            chunk(
+                &json!({"role": "assistant", "reasoning_content": "Think"}),
+                None,
+            ),
+            chunk(&json!({"reasoning_content": "ing."}), None),
+            chunk(&json!({"content": "Sure."}), None),
+            chunk(
+                &json!({"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "Read", "arguments": ""}}]}),
+                None,
+            ),
+            chunk(
+                &json!({"tool_calls": [{"index": 0, "function": {"arguments": "{\"path\":"}}]}),
+                None,
+            ),
+            chunk(
+                &json!({"tool_calls": [{"index": 0, "function": {"arguments": " \"a.rs\"}"}}]}),
+                None,
+            ),
+            chunk(&json!({}), Some("tool_calls")),
+            json!({"id": "chatcmpl-7", "choices": [], "usage": {"prompt_tokens": 50, "completion_tokens": 12}}),
+        ];
+        let mut converter = ChatStreamConverter::new(Some("hint"), ConversionContext::default());
+        let events = run(&mut converter, &chunks);
+        let types = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0]["message"]["id"], "chatcmpl-7");
+        assert_eq!(events[1]["content_block"]["type"], "thinking");
+        assert_eq!(events[3]["delta"]["thinking"], "ing.");
+        assert_eq!(events[6]["delta"]["text"], "Sure.");
+        assert_eq!(events[8]["content_block"]["id"], "call_1");
+        assert_eq!(events[9]["delta"]["partial_json"], r#"{"path":"a.rs"}"#);
+        assert_eq!(events[11]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[11]["usage"]["input_tokens"], 50);
+        assert_eq!(events[11]["usage"]["output_tokens"], 12);
+    }
+
+    /// 测试没有 usage chunk 时在 `[DONE]` 收尾，错误 chunk 转换为 error 事件
+    #[test]
+    fn test_chat_stream_done_and_error() {
+        let mut converter = ChatStreamConverter::new(None, ConversionContext::default());
+        let events = run(
+            &mut converter,
+            &[
+                chunk(&json!({"content": "Hi"}), None),
+                chunk(&json!({}), Some("length")),
+            ],
+        );
+        assert_eq!(
+            events[events.len() - 2]["delta"]["stop_reason"],
+            "max_tokens"
+        );
+        assert_eq!(events[events.len() - 1]["type"], "message_stop");
+
+        let mut converter = ChatStreamConverter::new(None, ConversionContext::default());
+        let events = run(
+            &mut converter,
+            &[json!({"error": {"message": "Rate limit reached", "type": "rate_limit_error"}})],
+        );
+        assert_eq!(events[0]["type"], "error");
+        assert_eq!(events[0]["error"]["message"], "Rate limit reached");
+    }
+}
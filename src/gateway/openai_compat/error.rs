@@ -0,0 +1,128 @@
+//! 错误响应转换
+//!
+//! `OpenAI` / Azure `OpenAI` 错误响应 → Anthropic 错误格式
+//!
+//! Azure 的内容过滤在提示词被拦截时返回 400，`code` 为 `content_filter`，
+//! 命中的类别位于 `innererror.content_filter_result`（或 `error.content_filters`）。
+//! 这里把命中的类别附在错误信息中；生成内容被拦截的情况见响应转换中的 `refusal`。
+
+use bytes::Bytes;
+use http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::gateway::anthropic::error_type_for_status;
+
+/// `OpenAI` 风格错误响应（`{ "error": { "message", "type", "code" } }`）→ Anthropic 错误格式
+#[must_use]
+pub fn error_body_to_anthropic(status: StatusCode, body: &[u8]) -> Bytes {
+    let value = serde_json::from_slice::<Value>(body).unwrap_or(Value::Null);
+    if value.get("type").and_then(Value::as_str) == Some("error") {
+        return Bytes::copy_from_slice(body);
+    }
+    let error = value.get("error");
+    let mut message = error
+        .and_then(|error| error.get("message"))
+        .and_then(Value::as_str)
+        .map_or_else(
+            || String::from_utf8_lossy(body).into_owned(),
+            str::to_string,
+        );
+    let code = error
+        .and_then(|error| error.get("code"))
+        .and_then(Value::as_str);
+    if code == Some("content_filter") {
+        let categories = error.map(filtered_categories).unwrap_or_default();
+        if !categories.is_empty() {
+            message = format!("{message} (filtered: {})", categories.join(", "));
+        }
+    }
+    let error_type = match code {
+        Some("content_filter" | "context_length_exceeded") => "invalid_request_error",
+        Some("rate_limit_exceeded") => "rate_limit_error",
+        _ => error_type_for_status(status),
+    };
+    Bytes::from(
+        json!({
+            "type": "error",
+            "error": { "type": error_type, "message": message }
+        })
+        .to_string(),
+    )
+}
+
+/// 收集内容过滤命中的类别（`filtered: true` 的项）
+fn filtered_categories(error: &Value) -> Vec<String> {
+    let results = error
+        .pointer("/innererror/content_filter_result")
+        .into_iter()
+        .chain(
+            error
+                .get("content_filters")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|filter| filter.get("content_filter_results")),
+        );
+    let mut categories = Vec::new();
+    for (category, result) in results.filter_map(Value::as_object).flatten() {
+        if result.get("filtered").and_then(Value::as_bool) == Some(true)
+            && !categories.contains(category)
+        {
+            categories.push(category.clone());
+        }
+    }
+    categories
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试 Azure 内容过滤错误转换为带命中类别的 Anthropic 错误
+    #[test]
+    fn test_azure_content_filter_error() {
+        let body = json!({
+            "error": {
+                "message": "The response was filtered due to the prompt triggering Azure OpenAI's content management policy.",
+                "type": null,
+                "param": "prompt",
+                "code": "content_filter",
+                "status": 400,
+                "innererror": {
+                    "code": "ResponsibleAIPolicyViolation",
+                    "content_filter_result": {
+                        "hate": {"filtered": false, "severity": "safe"},
+                        "jailbreak": {"filtered": true, "detected": true},
+                        "violence": {"filtered": true, "severity": "medium"}
+                    }
+                }
+            }
+        });
+        let converted: Value = serde_json::from_slice(&error_body_to_anthropic(
+            StatusCode::BAD_REQUEST,
+            body.to_string().as_bytes(),
+        ))
+        .unwrap();
+        assert_eq!(converted["type"], "error");
+        assert_eq!(converted["error"]["type"], "invalid_request_error");
+        assert!(
+            converted["error"]["message"]
+                .as_str()
+                .unwrap()
+                .ends_with("policy. (filtered: jailbreak, violence)")
+        );
+    }
+
+    /// 测试普通 `OpenAI` 错误按状态码映射
+    #[test]
+    fn test_openai_error() {
+        let converted: Value = serde_json::from_slice(&error_body_to_anthropic(
+            StatusCode::UNAUTHORIZED,
+            br#"{"error":{"message":"Incorrect API key provided.","type":"invalid_request_error","code":"invalid_api_key"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(converted["error"]["type"], "authentication_error");
+        assert_eq!(converted["error"]["message"], "Incorrect API key provided.");
+    }
+}
@@ -0,0 +1,438 @@
+//! `OpenAI` Chat Completions ↔ Anthropic Messages
+//!
+//! 请求：
+//! - system / developer 消息 → system
+//! - user 消息的 `image_url` → image 块
+//! - assistant 消息的 `tool_calls` → `tool_use` 块
+//! - tool 消息 → user 消息中的 `tool_result` 块（连续的 tool 消息合并为一条）
+//!
+//! 响应：
+//! - text 块 → `message.content`，thinking 块 → `message.reasoning_content`
+//! - `tool_use` 块 → `message.tool_calls`
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+use super::{
+    DEFAULT_MAX_TOKENS, UsageTotals, chat_finish_reason, push_blocks, thinking_for_effort,
+    unix_timestamp,
+};
+use crate::gateway::{
+    anthropic::{
+        Content, ContentBlock, Message, MessagesRequest, MessagesResponse, Role, Tool, ToolChoice,
+        ToolChoiceKind,
+    },
+    openai_compat::{json_repair, media},
+};
+
+/// 响应转换需要的请求信息
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequestInfo {
+    pub model: String,
+    pub stream: bool,
+    /// `stream_options.include_usage`：流结束前是否发送 usage 块
+    pub include_usage: bool,
+}
+
+/// Chat Completions 请求 → Anthropic Messages 请求
+pub fn chat_request_to_anthropic(
+    body: &[u8],
+) -> Result<(MessagesRequest, ChatRequestInfo), String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|_| "Request body must be JSON.".to_string())?;
+    let Some(object) = value.as_object() else {
+        return Err("Request body must be a JSON object.".to_string());
+    };
+    let model = object
+        .get("model")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "Request must include model.".to_string())?;
+    let Some(messages) = object.get("messages").and_then(Value::as_array) else {
+        return Err("Request must include messages.".to_string());
+    };
+
+    let stream = object
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let info = ChatRequestInfo {
+        model: model.to_string(),
+        stream,
+        include_usage: object
+            .get("stream_options")
+            .and_then(|options| options.get("include_usage"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    };
+
+    let (system, messages) = chat_messages_to_anthropic(messages);
+    let max_tokens = object
+        .get("max_completion_tokens")
+        .or_else(|| object.get("max_tokens"))
+        .and_then(Value::as_u64)
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let stop_sequences = match object.get("stop") {
+        Some(Value::String(stop)) => Some(vec![stop.clone()]),
+        Some(Value::Array(stops)) if !stops.is_empty() => Some(
+            stops
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        ),
+        _ => None,
+    };
+    let mut out = MessagesRequest {
+        model: model.to_string(),
+        messages,
+        system: (!system.is_empty()).then(|| Content::Text(system.join("\n\n"))),
+        max_tokens: Some(max_tokens),
+        stream: Some(stream),
+        stop_sequences,
+        temperature: object.get("temperature").and_then(Value::as_f64),
+        top_p: object.get("top_p").and_then(Value::as_f64),
+        thinking: object
+            .get("reasoning_effort")
+            .and_then(Value::as_str)
+            .and_then(|effort| thinking_for_effort(effort, max_tokens)),
+        ..MessagesRequest::default()
+    };
+    if let Some(user) = object.get("user").and_then(Value::as_str) {
+        out.extra
+            .insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+    if let Some(format) = object.get("response_format").and_then(chat_response_format) {
+        out.extra.insert("output_format".to_string(), format);
+    }
+    apply_chat_tools(object, &mut out);
+
+    Ok((out, info))
+}
+
+/// Chat messages → (system 文本, Anthropic messages)
+fn chat_messages_to_anthropic(messages: &[Value]) -> (Vec<String>, Vec<Message>) {
+    let mut system = Vec::new();
+    let mut out = Vec::new();
+    for message in messages {
+        let content = message.get("content");
+        match message.get("role").and_then(Value::as_str).unwrap_or("") {
+            "system" | "developer" => {
+                let text = content_text(content);
+                if !text.is_empty() {
+                    system.push(text);
+                }
+            }
+            "user" => push_blocks(&mut out, Role::User, chat_content_to_blocks(content)),
+            "assistant" => {
+                let mut blocks = chat_content_to_blocks(content);
+                if let Some(tool_calls) = message.get("tool_calls").and_then(Value::as_array) {
+                    blocks.extend(tool_calls.iter().filter_map(tool_call_to_tool_use));
+                }
+                push_blocks(&mut out, Role::Assistant, blocks);
+            }
+            "tool" => {
+                let tool_use_id = message
+                    .get("tool_call_id")
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let block = ContentBlock::tool_result(tool_use_id, content_text(content));
+                push_blocks(&mut out, Role::User, vec![block]);
+            }
+            other => tracing::debug!("⚠️ 忽略未知角色的消息: {}", other),
+        }
+    }
+    (system, out)
+}
+
+/// Chat 消息 content（字符串或 parts 数组）→ Anthropic 内容块
+fn chat_content_to_blocks(content: Option<&Value>) -> Vec<ContentBlock> {
+    match content {
+        Some(Value::String(text)) if !text.is_empty() => vec![ContentBlock::text(text.as_str())],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(Value::as_str)? {
+                "text" => {
+                    let text = part.get("text").and_then(Value::as_str)?;
+                    (!text.is_empty()).then(|| ContentBlock::text(text))
+                }
+                "image_url" => {
+                    let url = part
+                        .get("image_url")
+                        .and_then(|image| image.get("url").or(Some(image)))
+                        .and_then(Value::as_str)?;
+                    Some(media::image_url_to_claude_image_block(url))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// 提取 content 中的全部文本
+fn content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Chat `tool_calls[]` 项 → Anthropic `tool_use`
+fn tool_call_to_tool_use(tool_call: &Value) -> Option<ContentBlock> {
+    let id = tool_call.get("id").and_then(Value::as_str)?;
+    let function = tool_call.get("function")?;
+    let name = function.get("name").and_then(Value::as_str)?;
+    let arguments = function
+        .get("arguments")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let input = json_repair::parse_tool_arguments(arguments)
+        .map(|(input, _)| input)
+        .unwrap_or_default();
+    Some(ContentBlock::tool_use(id, name, Value::Object(input)))
+}
+
+/// tools / `tool_choice` / `parallel_tool_calls` → Anthropic tools / `tool_choice`
+fn apply_chat_tools(source: &Map<String, Value>, out: &mut MessagesRequest) {
+    out.tools = source
+        .get("tools")
+        .and_then(Value::as_array)
+        .map(|tools| {
+            tools
+                .iter()
+                .filter_map(|tool| {
+                    let function = tool.get("function")?;
+                    let name = function.get("name").and_then(Value::as_str)?;
+                    Some(Tool {
+                        name: name.to_string(),
+                        description: function
+                            .get("description")
+                            .and_then(Value::as_str)
+                            .map(str::to_string),
+                        input_schema: Some(
+                            function
+                                .get("parameters")
+                                .cloned()
+                                .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+                        ),
+                        ..Tool::default()
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if out.tools.is_empty() {
+        return;
+    }
+
+    let mut tool_choice = match source.get("tool_choice") {
+        Some(Value::String(choice)) => ToolChoice::new(match choice.as_str() {
+            "none" => ToolChoiceKind::None,
+            "required" => ToolChoiceKind::Any,
+            _ => ToolChoiceKind::Auto,
+        }),
+        Some(Value::Object(choice)) => choice
+            .get("function")
+            .and_then(|function| function.get("name"))
+            .and_then(Value::as_str)
+            .map_or_else(
+                || ToolChoice::new(ToolChoiceKind::Auto),
+                |name| ToolChoice {
+                    name: Some(name.to_string()),
+                    ..ToolChoice::new(ToolChoiceKind::Tool)
+                },
+            ),
+        _ => ToolChoice::new(ToolChoiceKind::Auto),
+    };
+    if source.get("parallel_tool_calls").and_then(Value::as_bool) == Some(false) {
+        tool_choice.disable_parallel_tool_use = Some(true);
+    }
+    out.tool_choice = Some(tool_choice);
+}
+
+/// `response_format` → Anthropic `output_format`
+fn chat_response_format(format: &Value) -> Option<Value> {
+    match format.get("type").and_then(Value::as_str)? {
+        "json_schema" => {
+            let schema = format.get("json_schema")?.get("schema")?;
+            Some(json!({ "type": "json_schema", "schema": schema }))
+        }
+        "json_object" => Some(json!({ "type": "json_object" })),
+        _ => None,
+    }
+}
+
+/// Anthropic Messages 响应 → Chat Completions 响应
+pub fn anthropic_response_to_chat(body: &[u8], info: &ChatRequestInfo) -> Result<Bytes, String> {
+    let response: MessagesResponse = serde_json::from_slice(body)
+        .map_err(|_| "Upstream response must be a JSON object.".to_string())?;
+
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in &response.content {
+        match block {
+            ContentBlock::Text(block) => text.push_str(&block.text),
+            ContentBlock::Thinking(block) => reasoning.push_str(&block.thinking),
+            ContentBlock::ToolUse(tool_use) => tool_calls.push(json!({
+                "id": tool_use.id,
+                "type": "function",
+                "function": {
+                    "name": tool_use.name,
+                    "arguments": tool_use.input.to_string()
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = Map::new();
+    message.insert("role".to_string(), json!("assistant"));
+    message.insert(
+        "content".to_string(),
+        if text.is_empty() && !tool_calls.is_empty() {
+            Value::Null
+        } else {
+            Value::String(text)
+        },
+    );
+    if !reasoning.is_empty() {
+        message.insert("reasoning_content".to_string(), Value::String(reasoning));
+    }
+    if !tool_calls.is_empty() {
+        message.insert("tool_calls".to_string(), Value::Array(tool_calls));
+    }
+
+    let id = if response.id.is_empty() {
+        "proxy"
+    } else {
+        response.id.as_str()
+    };
+    let model = if response.model.is_empty() {
+        info.model.as_str()
+    } else {
+        response.model.as_str()
+    };
+    let finish_reason = chat_finish_reason(response.stop_reason.as_deref());
+    let out = json!({
+        "id": format!("chatcmpl-{}", id.trim_start_matches("msg_")),
+        "object": "chat.completion",
+        "created": unix_timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason
+        }],
+        "usage": UsageTotals::from_anthropic(&response.usage).to_chat_usage()
+    });
+    serde_json::to_vec(&out)
+        .map(Bytes::from)
+        .map_err(|err| format!("Failed to serialize response: {err}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn convert(request: &Value) -> Value {
+        serde_json::to_value(
+            chat_request_to_anthropic(request.to_string().as_bytes())
+                .unwrap()
+                .0,
+        )
+        .unwrap()
+    }
+
+    /// 测试多轮工具调用对话转换为 Anthropic 消息
+    #[test]
+    fn test_chat_request_with_tools() {
+        let converted = convert(&json!({
+            "model": "gpt-4o",
+            "max_tokens": 1000,
+            "stop": "END",
+            "messages": [
+                {"role": "system", "content": "You are terse."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is in this file?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "read", "arguments": "{\"path\":\"a\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "read", "arguments": "{\"path\":\"b\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "A"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "B"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "read", "parameters": {"type": "object"}}}],
+            "tool_choice": "required",
+            "parallel_tool_calls": false
+        }));
+
+        assert_eq!(converted["system"], "You are terse.");
+        assert_eq!(converted["max_tokens"], 1000);
+        assert_eq!(converted["stop_sequences"], json!(["END"]));
+        let messages = converted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0]["content"][1]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(messages[1]["content"][1]["input"]["path"], "b");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "call_2");
+        assert_eq!(converted["tools"][0]["input_schema"]["type"], "object");
+        assert_eq!(
+            converted["tool_choice"],
+            json!({"type": "any", "disable_parallel_tool_use": true})
+        );
+    }
+
+    /// 测试 Anthropic 响应转换为 Chat 响应
+    #[test]
+    fn test_anthropic_response_to_chat() {
+        let response = json!({
+            "id": "msg_1",
+            "type": "message",
+            "model": "claude-test",
+            "content": [
+                {"type": "thinking", "thinking": "hmm"},
+                {"type": "text", "text": "Reading."},
+                {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {"path": "a"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "cache_read_input_tokens": 90, "output_tokens": 5}
+        });
+        let converted: Value = serde_json::from_slice(
+            &anthropic_response_to_chat(
+                response.to_string().as_bytes(),
+                &ChatRequestInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let message = &converted["choices"][0]["message"];
+        assert_eq!(converted["id"], "chatcmpl-1");
+        assert_eq!(message["content"], "Reading.");
+        assert_eq!(message["reasoning_content"], "hmm");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"path\":\"a\"}"
+        );
+        assert_eq!(converted["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(converted["usage"]["prompt_tokens"], 100);
+        assert_eq!(
+            converted["usage"]["prompt_tokens_details"]["cached_tokens"],
+            90
+        );
+    }
+}
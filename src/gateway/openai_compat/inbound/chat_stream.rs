@@ -0,0 +1,226 @@
+//! Anthropic SSE 事件 → Chat Completions 流式块
+//!
+//! - `message_start` → 带 `role` 的首个块
+//! - `text_delta` / `thinking_delta` → `delta.content` / `delta.reasoning_content`
+//! - `tool_use` 块 → `delta.tool_calls`（参数随 `input_json_delta` 增量下发）
+//! - `message_delta` → 带 `finish_reason` 的块
+//! - `message_stop` → 可选的 usage 块与 `data: [DONE]`
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use serde_json::{Value, json};
+
+use super::{UsageTotals, chat::ChatRequestInfo, chat_finish_reason, unix_timestamp};
+use crate::gateway::{
+    anthropic::{ContentBlock, Delta, StreamEvent},
+    sse::{SseDecoder, SseEvent, StreamConverter, encode_data},
+};
+
+/// Anthropic SSE → Chat SSE 的有状态转换器
+pub struct ChatStreamConverter {
+    decoder: SseDecoder,
+    info: ChatRequestInfo,
+    id: String,
+    created: u64,
+    /// Anthropic content block 索引 → Chat `tool_calls` 索引
+    tool_indexes: HashMap<usize, usize>,
+    usage: UsageTotals,
+    done: bool,
+}
+
+impl ChatStreamConverter {
+    #[must_use]
+    pub fn new(info: ChatRequestInfo) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            info,
+            id: "chatcmpl-proxy".to_string(),
+            created: unix_timestamp(),
+            tool_indexes: HashMap::new(),
+            usage: UsageTotals::default(),
+            done: false,
+        }
+    }
+
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Bytes {
+        encode_data(
+            &json!({
+                "id": self.id,
+                "object": "chat.completion.chunk",
+                "created": self.created,
+                "model": self.info.model,
+                "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+            })
+            .to_string(),
+        )
+    }
+
+    fn handle_event(&mut self, event: &SseEvent, out: &mut Vec<Bytes>) {
+        let Some(data) = event.parse::<StreamEvent>() else {
+            return;
+        };
+        match data {
+            StreamEvent::MessageStart { message } => {
+                if !message.id.is_empty() {
+                    self.id = format!("chatcmpl-{}", message.id.trim_start_matches("msg_"));
+                }
+                if !message.model.is_empty() {
+                    self.info.model = message.model;
+                }
+                self.usage = UsageTotals::from_anthropic(&message.usage);
+                out.push(self.chunk(&json!({ "role": "assistant", "content": "" }), None));
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block: ContentBlock::ToolUse(tool_use),
+            } => {
+                let tool_index = self.tool_indexes.len();
+                self.tool_indexes.insert(index, tool_index);
+                out.push(self.chunk(
+                    &json!({ "tool_calls": [{
+                        "index": tool_index,
+                        "id": tool_use.id,
+                        "type": "function",
+                        "function": { "name": tool_use.name, "arguments": "" }
+                    }] }),
+                    None,
+                ));
+            }
+            StreamEvent::ContentBlockDelta { index, delta } => {
+                if let Some(chunk) = self.delta_chunk(index, &delta) {
+                    out.push(chunk);
+                }
+            }
+            StreamEvent::MessageDelta { delta, usage } => {
+                self.usage.merge_delta(&usage);
+                let finish_reason = chat_finish_reason(delta.stop_reason.as_deref());
+                out.push(self.chunk(&json!({}), Some(finish_reason)));
+            }
+            StreamEvent::MessageStop => self.finish_into(out),
+            StreamEvent::Error { error } => {
+                out.push(encode_data(&json!({ "error": error }).to_string()));
+                self.finish_into(out);
+            }
+            _ => {}
+        }
+    }
+
+    fn delta_chunk(&self, index: usize, delta: &Delta) -> Option<Bytes> {
+        let chat_delta = match delta {
+            Delta::Text { text } => json!({ "content": text }),
+            Delta::Thinking { thinking } => json!({ "reasoning_content": thinking }),
+            Delta::InputJson { partial_json } => {
+                let tool_index = self.tool_indexes.get(&index)?;
+                json!({ "tool_calls": [{
+                    "index": tool_index,
+                    "function": { "arguments": partial_json }
+                }] })
+            }
+            _ => return None,
+        };
+        Some(self.chunk(&chat_delta, None))
+    }
+
+    fn finish_into(&mut self, out: &mut Vec<Bytes>) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        if self.info.include_usage {
+            out.push(encode_data(
+                &json!({
+                    "id": self.id,
+                    "object": "chat.completion.chunk",
+                    "created": self.created,
+                    "model": self.info.model,
+                    "choices": [],
+                    "usage": self.usage.to_chat_usage()
+                })
+                .to_string(),
+            ));
+        }
+        out.push(encode_data("[DONE]"));
+    }
+}
+
+impl StreamConverter for ChatStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event, &mut out);
+        }
+        self.finish_into(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+    use crate::gateway::{
+        anthropic::{DeltaUsage, Usage},
+        sse::AnthropicSseWriter,
+    };
+
+    /// 测试 Anthropic 流转换为 Chat 流式块（含工具调用与 usage）
+    #[test]
+    fn test_anthropic_stream_to_chat_chunks() {
+        let mut writer = AnthropicSseWriter::new("msg_9", "claude-test");
+        writer.start(Some(Usage::new(7, 0)));
+        writer.text_delta("Hi");
+        writer.full_block(ContentBlock::tool_use("toolu_1", "read", json!({"p": 1})));
+        writer.finish(
+            "tool_use",
+            Some(DeltaUsage {
+                output_tokens: 3,
+                ..DeltaUsage::default()
+            }),
+        );
+
+        let mut converter = ChatStreamConverter::new(ChatRequestInfo {
+            model: "gpt-4o".to_string(),
+            stream: true,
+            include_usage: true,
+        });
+        let mut out = writer
+            .take()
+            .iter()
+            .flat_map(|bytes| converter.convert(bytes))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+
+        let mut decoder = SseDecoder::default();
+        let events = out
+            .iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(events.last().unwrap().data, "[DONE]");
+        let chunks = events.iter().filter_map(SseEvent::json).collect::<Vec<_>>();
+
+        assert_eq!(chunks[0]["id"], "chatcmpl-9");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(
+            chunks[2]["choices"][0]["delta"]["tool_calls"][0]["function"]["name"],
+            "read"
+        );
+        assert_eq!(
+            chunks[3]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{\"p\":1}"
+        );
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[5]["usage"]["prompt_tokens"], 7);
+        assert_eq!(chunks[5]["usage"]["completion_tokens"], 3);
+        assert_eq!(chunks.len(), 6);
+    }
+}
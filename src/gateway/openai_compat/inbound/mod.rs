@@ -0,0 +1,161 @@
+//! 入站 `OpenAI` 格式请求
+//!
+//! 其他工具（编辑器插件、脚本等）以 `OpenAI` 格式调用代理时，
+//! 请求先转换为 Anthropic 格式，复用同一套 upstream 选择、mode 转换与统计，
+//! 响应再从 Anthropic 格式转换回 `OpenAI` 格式。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+use crate::gateway::anthropic::{
+    Content, ContentBlock, DeltaUsage, Message, Role, ThinkingConfig, ThinkingKind, Usage,
+};
+
+pub mod chat;
+pub mod chat_stream;
+pub mod responses;
+pub mod responses_stream;
+
+/// 未指定最大输出 token 时使用的默认值（Anthropic 要求必填）
+pub const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Anthropic usage 中的 token 计数
+///
+/// Anthropic 的 `input_tokens` 不含缓存命中与缓存写入部分，
+/// `OpenAI` 的 `prompt_tokens` / `input_tokens` 则是三者之和。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageTotals {
+    pub input: u64,
+    pub output: u64,
+    pub cached: u64,
+}
+
+impl UsageTotals {
+    /// Anthropic usage（完整响应或流式 `message_start`）
+    #[must_use]
+    pub fn from_anthropic(usage: &Usage) -> Self {
+        let cache_read = usage.cache_read_input_tokens.unwrap_or(0);
+        Self {
+            input: usage.input_tokens + cache_read + usage.cache_creation_input_tokens.unwrap_or(0),
+            output: usage.output_tokens,
+            cached: cache_read,
+        }
+    }
+
+    /// 合并流式 `message_delta` 中的累计 usage（输入部分只在出现时覆盖）
+    pub fn merge_delta(&mut self, usage: &DeltaUsage) {
+        let uncached = usage.input_tokens;
+        let cache_read = usage.cache_read_input_tokens;
+        let cache_creation = usage.cache_creation_input_tokens;
+        if uncached.is_some() || cache_read.is_some() || cache_creation.is_some() {
+            self.input =
+                uncached.unwrap_or(0) + cache_read.unwrap_or(0) + cache_creation.unwrap_or(0);
+            self.cached = cache_read.unwrap_or(0);
+        }
+        self.output = usage.output_tokens;
+    }
+
+    /// Chat Completions 风格的 usage
+    #[must_use]
+    pub fn to_chat_usage(self) -> Value {
+        json!({
+            "prompt_tokens": self.input,
+            "completion_tokens": self.output,
+            "total_tokens": self.input + self.output,
+            "prompt_tokens_details": { "cached_tokens": self.cached }
+        })
+    }
+
+    /// Responses API 风格的 usage
+    #[must_use]
+    pub fn to_responses_usage(self) -> Value {
+        json!({
+            "input_tokens": self.input,
+            "input_tokens_details": { "cached_tokens": self.cached },
+            "output_tokens": self.output,
+            "output_tokens_details": { "reasoning_tokens": 0 },
+            "total_tokens": self.input + self.output
+        })
+    }
+}
+
+/// Anthropic `stop_reason` → Chat `finish_reason`
+#[must_use]
+pub fn chat_finish_reason(stop_reason: Option<&str>) -> &'static str {
+    match stop_reason {
+        Some("max_tokens" | "model_context_window_exceeded") => "length",
+        Some("tool_use") => "tool_calls",
+        Some("refusal") => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// 当前 Unix 时间戳（秒），用于 `created` 字段
+#[must_use]
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// 任意上游错误响应 → `OpenAI` 错误格式
+///
+/// Anthropic 与 `OpenAI` 的错误体都形如 `{ "error": { "type", "message" } }`，
+/// 无法解析时把原始响应体作为错误信息。
+#[must_use]
+pub fn openai_error_body(body: &[u8], default_type: &str) -> Bytes {
+    let value = serde_json::from_slice::<Value>(body).ok();
+    let error = value.as_ref().and_then(|value| value.get("error"));
+    let message = error
+        .and_then(|error| error.get("message"))
+        .and_then(Value::as_str)
+        .map_or_else(
+            || String::from_utf8_lossy(body).into_owned(),
+            str::to_string,
+        );
+    let error_type = error
+        .and_then(|error| error.get("type"))
+        .and_then(Value::as_str)
+        .unwrap_or(default_type);
+    Bytes::from(
+        json!({
+            "error": { "message": message, "type": error_type, "param": null, "code": null }
+        })
+        .to_string(),
+    )
+}
+
+/// 追加内容块；与上一条消息角色相同时合并，满足 Anthropic 的角色交替要求
+pub fn push_blocks(messages: &mut Vec<Message>, role: Role, blocks: Vec<ContentBlock>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut()
+        && last.role == role
+        && let Content::Blocks(content) = &mut last.content
+    {
+        content.extend(blocks);
+        return;
+    }
+    messages.push(Message::new(role, blocks));
+}
+
+/// `reasoning_effort` → Anthropic thinking 预算（需小于 `max_tokens`）
+#[must_use]
+pub fn thinking_for_effort(effort: &str, max_tokens: u64) -> Option<ThinkingConfig> {
+    let budget: u64 = match effort {
+        "minimal" | "low" => 1024,
+        "medium" => 4096,
+        "high" => 16384,
+        _ => return None,
+    };
+    let budget = budget.min(max_tokens.saturating_sub(1));
+    // Anthropic 要求 budget_tokens ≥ 1024
+    (budget >= 1024).then(|| ThinkingConfig {
+        kind: ThinkingKind::Enabled,
+        budget_tokens: Some(budget),
+        extra: Map::new(),
+    })
+}
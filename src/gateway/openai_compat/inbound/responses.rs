@@ -0,0 +1,508 @@
+//! `OpenAI` Responses API ↔ Anthropic Messages
+//!
+//! 与 `openai_compat` 的 Claude → Responses 转换方向相反：
+//!
+//! 请求：
+//! - instructions / system / developer 消息 → system
+//! - message（`input_text` / `input_image` / `output_text`）→ 对应角色的内容块
+//! - `function_call` → assistant 的 `tool_use`，`function_call_output` → user 的 `tool_result`
+//! - `web_search` / `code_interpreter` 内置工具 → Anthropic 服务端工具
+//!
+//! 响应：
+//! - thinking → reasoning 项，text → message 项，`tool_use` → `function_call` 项
+//! - `server_tool_use`（`web_search`）→ `web_search_call` 项
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+use super::{DEFAULT_MAX_TOKENS, UsageTotals, push_blocks, thinking_for_effort, unix_timestamp};
+use crate::gateway::{
+    anthropic::{
+        Content, ContentBlock, MediaBlock, Message, MessagesRequest, MessagesResponse, Role,
+        Source, Tool, ToolChoice, ToolChoiceKind,
+    },
+    openai_compat::{json_repair, media},
+};
+
+/// 响应转换需要的请求信息
+#[derive(Debug, Clone, Default)]
+pub struct ResponsesRequestInfo {
+    pub model: String,
+    pub stream: bool,
+}
+
+/// Responses 请求 → Anthropic Messages 请求
+pub fn responses_request_to_anthropic(
+    body: &[u8],
+) -> Result<(MessagesRequest, ResponsesRequestInfo), String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|_| "Request body must be JSON.".to_string())?;
+    let Some(object) = value.as_object() else {
+        return Err("Request body must be a JSON object.".to_string());
+    };
+    let model = object
+        .get("model")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "Request must include model.".to_string())?;
+    let stream = object
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if object
+        .get("previous_response_id")
+        .is_some_and(|id| !id.is_null())
+    {
+        tracing::warn!("⚠️ 代理不保存会话状态，忽略 previous_response_id");
+    }
+
+    let mut system = object
+        .get("instructions")
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(|text| vec![text.to_string()])
+        .unwrap_or_default();
+    let messages = match object.get("input") {
+        Some(Value::String(text)) => vec![Message::new(Role::User, text.as_str())],
+        Some(Value::Array(items)) => responses_input_to_messages(items, &mut system),
+        _ => return Err("Request must include input.".to_string()),
+    };
+    let max_tokens = object
+        .get("max_output_tokens")
+        .and_then(Value::as_u64)
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut out = MessagesRequest {
+        model: model.to_string(),
+        messages,
+        system: (!system.is_empty()).then(|| Content::Text(system.join("\n\n"))),
+        max_tokens: Some(max_tokens),
+        stream: Some(stream),
+        temperature: object.get("temperature").and_then(Value::as_f64),
+        top_p: object.get("top_p").and_then(Value::as_f64),
+        thinking: object
+            .get("reasoning")
+            .and_then(|reasoning| reasoning.get("effort"))
+            .and_then(Value::as_str)
+            .and_then(|effort| thinking_for_effort(effort, max_tokens)),
+        ..MessagesRequest::default()
+    };
+    if let Some(user) = object.get("user").and_then(Value::as_str) {
+        out.extra
+            .insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+    if let Some(format) = object
+        .get("text")
+        .and_then(|text| text.get("format"))
+        .and_then(text_format_to_output_format)
+    {
+        out.extra.insert("output_format".to_string(), format);
+    }
+    apply_responses_tools(object, &mut out);
+
+    let info = ResponsesRequestInfo {
+        model: model.to_string(),
+        stream,
+    };
+    Ok((out, info))
+}
+
+/// Responses input[] → Anthropic messages，system / developer 消息收集到 `system`
+fn responses_input_to_messages(items: &[Value], system: &mut Vec<String>) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for item in items {
+        let item_type = item
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or("message");
+        match item_type {
+            "message" => {
+                let role = item.get("role").and_then(Value::as_str).unwrap_or("user");
+                let blocks = responses_content_to_blocks(item.get("content"));
+                match role {
+                    "system" | "developer" => system.extend(
+                        blocks
+                            .iter()
+                            .filter_map(ContentBlock::as_text)
+                            .map(str::to_string),
+                    ),
+                    "assistant" => push_blocks(&mut messages, Role::Assistant, blocks),
+                    _ => push_blocks(&mut messages, Role::User, blocks),
+                }
+            }
+            "function_call" => {
+                let call_id = item
+                    .get("call_id")
+                    .or_else(|| item.get("id"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let name = item.get("name").and_then(Value::as_str).unwrap_or("");
+                let arguments = item.get("arguments").and_then(Value::as_str).unwrap_or("");
+                let input = json_repair::parse_tool_arguments(arguments)
+                    .map(|(input, _)| input)
+                    .unwrap_or_default();
+                let block = ContentBlock::tool_use(call_id, name, Value::Object(input));
+                push_blocks(&mut messages, Role::Assistant, vec![block]);
+            }
+            "function_call_output" => {
+                let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
+                let content = match item.get("output") {
+                    Some(Value::String(text)) => Content::Text(text.clone()),
+                    output => Content::Blocks(responses_content_to_blocks(output)),
+                };
+                let block = ContentBlock::tool_result(call_id, content);
+                push_blocks(&mut messages, Role::User, vec![block]);
+            }
+            // reasoning 项缺少 Anthropic 要求的签名，无法回传
+            other => tracing::debug!("⚠️ 忽略 input 项: {}", other),
+        }
+    }
+    messages
+}
+
+/// Responses 消息 content → Anthropic 内容块
+fn responses_content_to_blocks(content: Option<&Value>) -> Vec<ContentBlock> {
+    match content {
+        Some(Value::String(text)) if !text.is_empty() => vec![ContentBlock::text(text.as_str())],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(Value::as_str)? {
+                "input_text" | "output_text" | "text" => {
+                    let text = part.get("text").and_then(Value::as_str)?;
+                    (!text.is_empty()).then(|| ContentBlock::text(text))
+                }
+                "input_image" => {
+                    let url = part.get("image_url").and_then(Value::as_str)?;
+                    Some(media::image_url_to_claude_image_block(url))
+                }
+                "input_file" => {
+                    let data_url = part
+                        .get("file_data")
+                        .or_else(|| part.get("file_url"))
+                        .and_then(Value::as_str)?;
+                    let (media_type, data) = data_url
+                        .strip_prefix("data:")
+                        .and_then(|rest| rest.split_once(";base64,"))?;
+                    Some(ContentBlock::Document(MediaBlock::new(Source::base64(
+                        media_type, data,
+                    ))))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// tools / `tool_choice` / `parallel_tool_calls` → Anthropic tools / `tool_choice`
+fn apply_responses_tools(source: &Map<String, Value>, out: &mut MessagesRequest) {
+    out.tools = source
+        .get("tools")
+        .and_then(Value::as_array)
+        .map(|tools| {
+            tools
+                .iter()
+                .filter_map(responses_tool_to_anthropic)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if out.tools.is_empty() {
+        return;
+    }
+
+    let mut tool_choice = match source.get("tool_choice") {
+        Some(Value::String(choice)) => ToolChoice::new(match choice.as_str() {
+            "none" => ToolChoiceKind::None,
+            "required" => ToolChoiceKind::Any,
+            _ => ToolChoiceKind::Auto,
+        }),
+        Some(Value::Object(choice)) => choice.get("name").and_then(Value::as_str).map_or_else(
+            || ToolChoice::new(ToolChoiceKind::Auto),
+            |name| ToolChoice {
+                name: Some(name.to_string()),
+                ..ToolChoice::new(ToolChoiceKind::Tool)
+            },
+        ),
+        _ => ToolChoice::new(ToolChoiceKind::Auto),
+    };
+    if source.get("parallel_tool_calls").and_then(Value::as_bool) == Some(false) {
+        tool_choice.disable_parallel_tool_use = Some(true);
+    }
+    out.tool_choice = Some(tool_choice);
+}
+
+/// Responses 工具定义 → Anthropic 工具定义
+fn responses_tool_to_anthropic(tool: &Value) -> Option<Tool> {
+    let server_tool = |kind: &str, name: &str| Tool {
+        kind: Some(kind.to_string()),
+        name: name.to_string(),
+        ..Tool::default()
+    };
+    match tool.get("type").and_then(Value::as_str)? {
+        "function" => {
+            let name = tool.get("name").and_then(Value::as_str)?;
+            Some(Tool {
+                name: name.to_string(),
+                description: tool
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                input_schema: Some(
+                    tool.get("parameters")
+                        .cloned()
+                        .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+                ),
+                ..Tool::default()
+            })
+        }
+        "web_search" | "web_search_preview" => {
+            Some(server_tool("web_search_20250305", "web_search"))
+        }
+        "code_interpreter" => Some(server_tool("code_execution_20250522", "code_execution")),
+        other => {
+            tracing::debug!("⚠️ 忽略不支持的 Responses 内置工具: {}", other);
+            None
+        }
+    }
+}
+
+/// `text.format` → Anthropic `output_format`
+fn text_format_to_output_format(format: &Value) -> Option<Value> {
+    match format.get("type").and_then(Value::as_str)? {
+        "json_schema" => {
+            let schema = format.get("schema")?;
+            Some(json!({ "type": "json_schema", "schema": schema }))
+        }
+        "json_object" => Some(json!({ "type": "json_object" })),
+        _ => None,
+    }
+}
+
+/// Responses 对象中的 id：去掉 Anthropic 前缀后加上 Responses 前缀
+#[must_use]
+pub fn prefixed_id(prefix: &str, id: &str) -> String {
+    let bare = id
+        .trim_start_matches("msg_")
+        .trim_start_matches("toolu_")
+        .trim_start_matches("srvtoolu_");
+    format!("{prefix}_{bare}")
+}
+
+/// assistant message 输出项
+#[must_use]
+pub fn message_item(id: &str, text: &str, status: &str) -> Value {
+    json!({
+        "type": "message",
+        "id": id,
+        "status": status,
+        "role": "assistant",
+        "content": [{ "type": "output_text", "text": text, "annotations": [] }]
+    })
+}
+
+/// reasoning 输出项
+#[must_use]
+pub fn reasoning_item(id: &str, text: &str) -> Value {
+    json!({
+        "type": "reasoning",
+        "id": id,
+        "summary": [{ "type": "summary_text", "text": text }]
+    })
+}
+
+/// `function_call` 输出项
+#[must_use]
+pub fn function_call_item(
+    id: &str,
+    call_id: &str,
+    name: &str,
+    arguments: &str,
+    status: &str,
+) -> Value {
+    json!({
+        "type": "function_call",
+        "id": id,
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments,
+        "status": status
+    })
+}
+
+/// 完整的 Responses 对象
+#[must_use]
+pub fn response_object(
+    id: &str,
+    model: &str,
+    created_at: u64,
+    stop_reason: Option<&str>,
+    output: &[Value],
+    usage: Option<UsageTotals>,
+) -> Value {
+    let incomplete = matches!(
+        stop_reason,
+        Some("max_tokens" | "model_context_window_exceeded")
+    );
+    let status = match stop_reason {
+        None => "in_progress",
+        Some(_) if incomplete => "incomplete",
+        Some(_) => "completed",
+    };
+    json!({
+        "id": id,
+        "object": "response",
+        "created_at": created_at,
+        "status": status,
+        "model": model,
+        "output": output,
+        "incomplete_details": if incomplete { json!({ "reason": "max_output_tokens" }) } else { Value::Null },
+        "usage": usage.map(UsageTotals::to_responses_usage)
+    })
+}
+
+/// Anthropic Messages 响应 → Responses 对象
+pub fn anthropic_response_to_responses(
+    body: &[u8],
+    info: &ResponsesRequestInfo,
+) -> Result<Bytes, String> {
+    let response: MessagesResponse = serde_json::from_slice(body)
+        .map_err(|_| "Upstream response must be a JSON object.".to_string())?;
+    let id = if response.id.is_empty() {
+        "proxy"
+    } else {
+        response.id.as_str()
+    };
+    let model = if response.model.is_empty() {
+        info.model.as_str()
+    } else {
+        response.model.as_str()
+    };
+
+    let mut output = Vec::new();
+    let mut text = String::new();
+    for block in &response.content {
+        match block {
+            ContentBlock::Text(block) => text.push_str(&block.text),
+            ContentBlock::Thinking(block) => {
+                output.push(reasoning_item(&prefixed_id("rs", id), &block.thinking));
+            }
+            ContentBlock::ToolUse(tool_use) => output.push(function_call_item(
+                &prefixed_id("fc", &tool_use.id),
+                &tool_use.id,
+                &tool_use.name,
+                &tool_use.input.to_string(),
+                "completed",
+            )),
+            ContentBlock::Other(other)
+                if other.kind == "server_tool_use"
+                    && other.extra.get("name").and_then(Value::as_str) == Some("web_search") =>
+            {
+                let server_id = other.extra.get("id").and_then(Value::as_str).unwrap_or("");
+                output.push(json!({
+                    "type": "web_search_call",
+                    "id": prefixed_id("ws", server_id),
+                    "status": "completed",
+                    "action": {
+                        "type": "search",
+                        "query": other.extra.get("input").and_then(|input| input.get("query"))
+                    }
+                }));
+            }
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        output.push(message_item(&prefixed_id("msg", id), &text, "completed"));
+    }
+
+    let out = response_object(
+        &prefixed_id("resp", id),
+        model,
+        unix_timestamp(),
+        Some(response.stop_reason.as_deref().unwrap_or("end_turn")),
+        &output,
+        Some(UsageTotals::from_anthropic(&response.usage)),
+    );
+    serde_json::to_vec(&out)
+        .map(Bytes::from)
+        .map_err(|err| format!("Failed to serialize response: {err}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试 Codex 风格的 Responses 请求转换为 Anthropic 请求
+    #[test]
+    fn test_responses_request_to_anthropic() {
+        let request = json!({
+            "model": "gpt-5-codex",
+            "instructions": "You are Codex.",
+            "input": [
+                {"type": "message", "role": "developer", "content": [{"type": "input_text", "text": "Be brief."}]},
+                {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "list files"}]},
+                {"type": "reasoning", "id": "rs_1", "summary": []},
+                {"type": "function_call", "call_id": "call_1", "name": "shell", "arguments": "{\"command\":[\"ls\"]}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "a.rs\nb.rs"}
+            ],
+            "tools": [
+                {"type": "function", "name": "shell", "parameters": {"type": "object"}},
+                {"type": "web_search"},
+                {"type": "local_shell"}
+            ],
+            "tool_choice": "auto",
+            "parallel_tool_calls": false,
+            "max_output_tokens": 2000,
+            "reasoning": {"effort": "medium"},
+            "stream": true
+        });
+        let (converted, info) =
+            responses_request_to_anthropic(request.to_string().as_bytes()).unwrap();
+        let converted = serde_json::to_value(converted).unwrap();
+
+        assert!(info.stream);
+        assert_eq!(converted["system"], "You are Codex.\n\nBe brief.");
+        assert_eq!(converted["max_tokens"], 2000);
+        assert_eq!(converted["thinking"]["budget_tokens"], 1999);
+        let messages = converted["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0]["input"]["command"][0], "ls");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "call_1");
+        assert_eq!(converted["tools"].as_array().unwrap().len(), 2);
+        assert_eq!(converted["tools"][1]["name"], "web_search");
+        assert_eq!(converted["tool_choice"]["disable_parallel_tool_use"], true);
+    }
+
+    /// 测试 Anthropic 响应转换为 Responses 对象
+    #[test]
+    fn test_anthropic_response_to_responses() {
+        let response = json!({
+            "id": "msg_1",
+            "model": "claude-test",
+            "content": [
+                {"type": "text", "text": "Listing."},
+                {"type": "tool_use", "id": "toolu_7", "name": "shell", "input": {"command": ["ls"]}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 4, "output_tokens": 2}
+        });
+        let converted: Value = serde_json::from_slice(
+            &anthropic_response_to_responses(
+                response.to_string().as_bytes(),
+                &ResponsesRequestInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(converted["id"], "resp_1");
+        assert_eq!(converted["status"], "completed");
+        let output = converted["output"].as_array().unwrap();
+        assert_eq!(output[0]["type"], "function_call");
+        assert_eq!(output[0]["call_id"], "toolu_7");
+        assert_eq!(output[0]["arguments"], "{\"command\":[\"ls\"]}");
+        assert_eq!(output[1]["content"][0]["text"], "Listing.");
+        assert_eq!(converted["usage"]["total_tokens"], 6);
+    }
+}
@@ -0,0 +1,438 @@
+//! Anthropic SSE 事件 → Responses API 流式事件
+//!
+//! - `message_start` → `response.created` / `response.in_progress`
+//! - text 块 → message 项（`output_text.delta`）
+//! - thinking 块 → reasoning 项（`reasoning_summary_text.delta`）
+//! - `tool_use` 块 → `function_call` 项（`function_call_arguments.delta`）
+//! - `message_stop` → `response.completed`（携带完整的 output 与 usage）
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use serde_json::{Value, json};
+
+use super::{
+    UsageTotals,
+    responses::{
+        ResponsesRequestInfo, function_call_item, message_item, prefixed_id, reasoning_item,
+        response_object,
+    },
+    unix_timestamp,
+};
+use crate::gateway::{
+    anthropic::{ContentBlock, Delta, StreamEvent},
+    sse::{SseDecoder, SseEvent, StreamConverter, encode_event},
+};
+
+/// 进行中的输出项
+enum OpenItem {
+    Message {
+        id: String,
+        text: String,
+    },
+    Reasoning {
+        id: String,
+        text: String,
+    },
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+}
+
+/// Anthropic SSE → Responses SSE 的有状态转换器
+pub struct ResponsesEventStreamConverter {
+    decoder: SseDecoder,
+    info: ResponsesRequestInfo,
+    id: String,
+    message_id: String,
+    created_at: u64,
+    sequence_number: u64,
+    /// Anthropic content block 索引 → 进行中的输出项及其 `output_index`
+    open_items: HashMap<usize, (usize, OpenItem)>,
+    output: Vec<Value>,
+    usage: UsageTotals,
+    stop_reason: Option<String>,
+    started: bool,
+    done: bool,
+}
+
+impl ResponsesEventStreamConverter {
+    #[must_use]
+    pub fn new(info: ResponsesRequestInfo) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            info,
+            id: "resp_proxy".to_string(),
+            message_id: "msg_proxy".to_string(),
+            created_at: unix_timestamp(),
+            sequence_number: 0,
+            open_items: HashMap::new(),
+            output: Vec::new(),
+            usage: UsageTotals::default(),
+            stop_reason: None,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// 生成带 `type` 与递增 `sequence_number` 的事件
+    fn event(&mut self, event_type: &str, mut payload: Value, out: &mut Vec<Bytes>) {
+        if let Some(object) = payload.as_object_mut() {
+            object.insert("type".to_string(), json!(event_type));
+            object.insert("sequence_number".to_string(), json!(self.sequence_number));
+        }
+        self.sequence_number += 1;
+        out.push(encode_event(event_type, &payload));
+    }
+
+    fn response(&self, stop_reason: Option<&str>) -> Value {
+        response_object(
+            &self.id,
+            &self.info.model,
+            self.created_at,
+            stop_reason,
+            &self.output,
+            stop_reason.map(|_| self.usage),
+        )
+    }
+
+    fn start(&mut self, out: &mut Vec<Bytes>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let response = self.response(None);
+        self.event("response.created", json!({ "response": response }), out);
+        let response = self.response(None);
+        self.event("response.in_progress", json!({ "response": response }), out);
+    }
+
+    fn handle_event(&mut self, event: &SseEvent, out: &mut Vec<Bytes>) {
+        let Some(data) = event.parse::<StreamEvent>() else {
+            return;
+        };
+        match data {
+            StreamEvent::MessageStart { message } => {
+                if !message.id.is_empty() {
+                    self.id = prefixed_id("resp", &message.id);
+                    self.message_id = prefixed_id("msg", &message.id);
+                }
+                if !message.model.is_empty() {
+                    self.info.model = message.model;
+                }
+                self.usage = UsageTotals::from_anthropic(&message.usage);
+                self.start(out);
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                self.start(out);
+                self.open_item(index, &content_block, out);
+            }
+            StreamEvent::ContentBlockDelta { index, delta } => self.item_delta(index, &delta, out),
+            StreamEvent::ContentBlockStop { index } => self.close_item(index, out),
+            StreamEvent::MessageDelta { delta, usage } => {
+                self.usage.merge_delta(&usage);
+                if delta.stop_reason.is_some() {
+                    self.stop_reason = delta.stop_reason;
+                }
+            }
+            StreamEvent::MessageStop => self.finish_into(out),
+            StreamEvent::Error { error } => {
+                let message = if error.message.is_empty() {
+                    "Upstream stream error."
+                } else {
+                    &error.message
+                };
+                self.event(
+                    "error",
+                    json!({ "code": error.kind, "message": message, "param": null }),
+                    out,
+                );
+                self.done = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn open_item(&mut self, index: usize, block: &ContentBlock, out: &mut Vec<Bytes>) {
+        let item = match block {
+            ContentBlock::Text(_) => OpenItem::Message {
+                id: format!("{}_{index}", self.message_id),
+                text: String::new(),
+            },
+            ContentBlock::Thinking(_) => OpenItem::Reasoning {
+                id: format!("{}_{index}", prefixed_id("rs", &self.id)),
+                text: String::new(),
+            },
+            ContentBlock::ToolUse(tool_use) => OpenItem::FunctionCall {
+                id: prefixed_id("fc", &tool_use.id),
+                call_id: tool_use.id.clone(),
+                name: tool_use.name.clone(),
+                arguments: String::new(),
+            },
+            _ => return,
+        };
+        let output_index = self.output.len() + self.open_items.len();
+        let (item_json, part) = match &item {
+            OpenItem::Message { id, .. } => (
+                json!({
+                    "type": "message", "id": id, "status": "in_progress",
+                    "role": "assistant", "content": []
+                }),
+                Some(
+                    json!({ "item_id": id, "output_index": output_index, "content_index": 0,
+                    "part": { "type": "output_text", "text": "", "annotations": [] } }),
+                ),
+            ),
+            OpenItem::Reasoning { id, .. } => (
+                json!({ "type": "reasoning", "id": id, "summary": [] }),
+                Some(
+                    json!({ "item_id": id, "output_index": output_index, "summary_index": 0,
+                    "part": { "type": "summary_text", "text": "" } }),
+                ),
+            ),
+            OpenItem::FunctionCall {
+                id, call_id, name, ..
+            } => (
+                function_call_item(id, call_id, name, "", "in_progress"),
+                None,
+            ),
+        };
+        self.event(
+            "response.output_item.added",
+            json!({ "output_index": output_index, "item": item_json }),
+            out,
+        );
+        if let Some(part) = part {
+            let event_type = if matches!(item, OpenItem::Reasoning { .. }) {
+                "response.reasoning_summary_part.added"
+            } else {
+                "response.content_part.added"
+            };
+            self.event(event_type, part, out);
+        }
+        self.open_items.insert(index, (output_index, item));
+    }
+
+    fn item_delta(&mut self, index: usize, delta: &Delta, out: &mut Vec<Bytes>) {
+        let Some((output_index, item)) = self.open_items.get_mut(&index) else {
+            return;
+        };
+        let output_index = *output_index;
+        let (event_type, payload) = match (item, delta) {
+            (OpenItem::Message { id, text }, Delta::Text { text: chunk }) => {
+                text.push_str(chunk);
+                (
+                    "response.output_text.delta",
+                    json!({ "item_id": id, "output_index": output_index,
+                        "content_index": 0, "delta": chunk }),
+                )
+            }
+            (OpenItem::Reasoning { id, text }, Delta::Thinking { thinking: chunk }) => {
+                text.push_str(chunk);
+                (
+                    "response.reasoning_summary_text.delta",
+                    json!({ "item_id": id, "output_index": output_index,
+                        "summary_index": 0, "delta": chunk }),
+                )
+            }
+            (
+                OpenItem::FunctionCall { id, arguments, .. },
+                Delta::InputJson {
+                    partial_json: chunk,
+                },
+            ) => {
+                arguments.push_str(chunk);
+                (
+                    "response.function_call_arguments.delta",
+                    json!({ "item_id": id, "output_index": output_index, "delta": chunk }),
+                )
+            }
+            _ => return,
+        };
+        self.event(event_type, payload, out);
+    }
+
+    fn close_item(&mut self, index: usize, out: &mut Vec<Bytes>) {
+        let Some((output_index, item)) = self.open_items.remove(&index) else {
+            return;
+        };
+        let done_item = match item {
+            OpenItem::Message { id, text } => {
+                self.event(
+                    "response.output_text.done",
+                    json!({ "item_id": id, "output_index": output_index,
+                        "content_index": 0, "text": text }),
+                    out,
+                );
+                self.event(
+                    "response.content_part.done",
+                    json!({ "item_id": id, "output_index": output_index, "content_index": 0,
+                        "part": { "type": "output_text", "text": text, "annotations": [] } }),
+                    out,
+                );
+                message_item(&id, &text, "completed")
+            }
+            OpenItem::Reasoning { id, text } => {
+                self.event(
+                    "response.reasoning_summary_text.done",
+                    json!({ "item_id": id, "output_index": output_index,
+                        "summary_index": 0, "text": text }),
+                    out,
+                );
+                self.event(
+                    "response.reasoning_summary_part.done",
+                    json!({ "item_id": id, "output_index": output_index, "summary_index": 0,
+                        "part": { "type": "summary_text", "text": text } }),
+                    out,
+                );
+                reasoning_item(&id, &text)
+            }
+            OpenItem::FunctionCall {
+                id,
+                call_id,
+                name,
+                arguments,
+            } => {
+                let arguments = if arguments.is_empty() {
+                    "{}".to_string()
+                } else {
+                    arguments
+                };
+                self.event(
+                    "response.function_call_arguments.done",
+                    json!({ "item_id": id, "output_index": output_index, "arguments": arguments }),
+                    out,
+                );
+                function_call_item(&id, &call_id, &name, &arguments, "completed")
+            }
+        };
+        self.event(
+            "response.output_item.done",
+            json!({ "output_index": output_index, "item": done_item }),
+            out,
+        );
+        self.output.push(done_item);
+    }
+
+    fn finish_into(&mut self, out: &mut Vec<Bytes>) {
+        if self.done {
+            return;
+        }
+        self.start(out);
+        let mut indexes = self.open_items.keys().copied().collect::<Vec<_>>();
+        indexes.sort_unstable();
+        for index in indexes {
+            self.close_item(index, out);
+        }
+        self.done = true;
+        // 未收到 stop_reason 说明上游流被截断，按输出不完整处理
+        let stop_reason = self
+            .stop_reason
+            .clone()
+            .unwrap_or_else(|| "max_tokens".to_string());
+        let response = self.response(Some(&stop_reason));
+        let event_type = if response.get("status").and_then(Value::as_str) == Some("incomplete") {
+            "response.incomplete"
+        } else {
+            "response.completed"
+        };
+        self.event(event_type, json!({ "response": response }), out);
+    }
+}
+
+impl StreamConverter for ResponsesEventStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event, &mut out);
+        }
+        self.finish_into(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+    use crate::gateway::{
+        anthropic::{DeltaUsage, Usage},
+        sse::AnthropicSseWriter,
+    };
+
+    /// 测试 Anthropic 流转换为 Responses 事件（含推理、文本与函数调用）
+    #[test]
+    fn test_anthropic_stream_to_responses_events() {
+        let mut writer = AnthropicSseWriter::new("msg_3", "claude-test");
+        writer.start(Some(Usage::new(5, 0)));
+        writer.thinking_delta("plan");
+        writer.text_delta("Hello");
+        writer.full_block(ContentBlock::tool_use(
+            "toolu_2",
+            "shell",
+            json!({"cmd": "ls"}),
+        ));
+        writer.finish(
+            "tool_use",
+            Some(DeltaUsage {
+                output_tokens: 4,
+                ..DeltaUsage::default()
+            }),
+        );
+
+        let mut converter = ResponsesEventStreamConverter::new(ResponsesRequestInfo {
+            model: "gpt-5".to_string(),
+            stream: true,
+        });
+        let mut out = writer
+            .take()
+            .iter()
+            .flat_map(|bytes| converter.convert(bytes))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+
+        let mut decoder = SseDecoder::default();
+        let events = out
+            .iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .collect::<Vec<_>>();
+        let types = events
+            .iter()
+            .map(|event| event.event.clone().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        assert_eq!(types[0], "response.created");
+        assert_eq!(types[1], "response.in_progress");
+        assert!(types.contains(&"response.reasoning_summary_text.delta".to_string()));
+        assert!(types.contains(&"response.output_text.done".to_string()));
+        assert!(types.contains(&"response.function_call_arguments.done".to_string()));
+        assert_eq!(types.last().unwrap(), "response.completed");
+
+        let payloads = events.iter().filter_map(SseEvent::json).collect::<Vec<_>>();
+        for (expected, payload) in payloads.iter().enumerate() {
+            assert_eq!(payload["sequence_number"], expected);
+        }
+        let completed = &payloads.last().unwrap()["response"];
+        assert_eq!(completed["id"], "resp_3");
+        assert_eq!(completed["status"], "completed");
+        let output = completed["output"].as_array().unwrap();
+        assert_eq!(output[0]["summary"][0]["text"], "plan");
+        assert_eq!(output[1]["content"][0]["text"], "Hello");
+        assert_eq!(output[2]["arguments"], "{\"cmd\":\"ls\"}");
+        assert_eq!(completed["usage"]["total_tokens"], 9);
+    }
+}
@@ -0,0 +1,373 @@
+//! 工具调用参数的容错解析
+//!
+//! 较弱的模型经常输出不合法的 `arguments`：
+//! - 包在 Markdown 代码块里，或前后夹带说明文字
+//! - 尾随逗号、单引号字符串、Python 风格的 `True` / `False` / `None`
+//! - 字符串里出现 JSON 不允许的转义（如 `\'`）
+//!
+//! 这里先尝试严格解析，失败后做一次修复再解析；修复结果需通过工具
+//! `input_schema` 的校验才会被采用。因 `max_tokens` 截断而缺少结尾引号或括号的
+//! 参数不做补全：补出来的内容是残缺的（例如只写了一半的文件），只能作为修复失败处理。
+
+use serde_json::{Map, Value};
+
+/// 解析工具参数为 JSON 对象
+///
+/// 返回 `(对象, 是否经过修复)`；无法得到对象时返回错误描述。
+pub fn parse_tool_arguments(raw: &str) -> Result<(Map<String, Value>, bool), String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Ok((Map::new(), false));
+    }
+
+    match serde_json::from_str::<Value>(trimmed) {
+        Ok(Value::Object(object)) => return Ok((object, false)),
+        Ok(other) => {
+            // 部分模型把参数再序列化了一层
+            if let Value::String(inner) = &other
+                && let Ok(Value::Object(object)) = serde_json::from_str::<Value>(inner)
+            {
+                return Ok((object, true));
+            }
+        }
+        Err(_) => {}
+    }
+
+    let repaired = repair_json(trimmed)?;
+    match serde_json::from_str::<Value>(&repaired) {
+        Ok(Value::Object(object)) => Ok((object, true)),
+        Ok(_) => Err("arguments are not a JSON object".to_string()),
+        Err(e) => Err(format!("arguments are not valid JSON ({e})")),
+    }
+}
+
+/// 尽力把类 JSON 文本修复为合法 JSON
+///
+/// 文本在字符串或括号闭合前结束（被截断）时返回错误。
+pub fn repair_json(raw: &str) -> Result<String, String> {
+    let body = strip_code_fence(raw);
+    let body = body.find(['{', '[']).map_or(body, |start| &body[start..]);
+
+    let mut out = String::with_capacity(body.len() + 8);
+    let mut stack: Vec<char> = Vec::new();
+    // 当前字符串的起始引号（None 表示不在字符串内）
+    let mut quote: Option<char> = None;
+    let mut escaping = false;
+    let mut chars = body.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if let Some(open) = quote {
+            if escaping {
+                escaping = false;
+                match ch {
+                    // \' 在 JSON 中不是合法转义，直接写单引号
+                    '\'' => out.push(ch),
+                    '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' | 'u' => {
+                        out.push('\\');
+                        out.push(ch);
+                    }
+                    // 其他转义按字面反斜杠处理（如 Windows 路径 C:\dir）
+                    c => {
+                        out.push_str("\\\\");
+                        push_string_char(&mut out, c);
+                    }
+                }
+                continue;
+            }
+            match ch {
+                // 反斜杠在读到下一个字符时才输出，末尾悬空的反斜杠因此被丢弃
+                '\\' => escaping = true,
+                c if c == open => {
+                    out.push('"');
+                    quote = None;
+                }
+                c => push_string_char(&mut out, c),
+            }
+            continue;
+        }
+
+        match ch {
+            '"' | '\'' => {
+                quote = Some(ch);
+                out.push('"');
+            }
+            '{' => {
+                stack.push('}');
+                out.push(ch);
+            }
+            '[' => {
+                stack.push(']');
+                out.push(ch);
+            }
+            '}' | ']' => {
+                trim_trailing_comma(&mut out);
+                if stack.last() == Some(&ch) {
+                    stack.pop();
+                    out.push(ch);
+                }
+                if stack.is_empty() {
+                    // 顶层结构已闭合，忽略后面夹带的文字
+                    break;
+                }
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_ascii_alphanumeric() || next == '_' {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                // 未加引号的键：{path: "a"}
+                let is_key = chars.clone().find(|c| !c.is_whitespace()) == Some(':');
+                if is_key {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                } else {
+                    out.push_str(match word.as_str() {
+                        "True" => "true",
+                        "False" => "false",
+                        "None" | "undefined" => "null",
+                        other => other,
+                    });
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("arguments are truncated (unterminated string)".to_string());
+    }
+    if !stack.is_empty() {
+        return Err("arguments are truncated (unclosed object or array)".to_string());
+    }
+    Ok(out)
+}
+
+/// 向字符串内部追加一个字符，转义 JSON 字符串中不能直接出现的字符
+fn push_string_char(out: &mut String, ch: char) {
+    match ch {
+        '"' => out.push_str("\\\""),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c => out.push(c),
+    }
+}
+
+fn strip_code_fence(raw: &str) -> &str {
+    let trimmed = raw.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // 跳过语言标记（如 ```json）
+    let rest = rest.split_once('\n').map_or(rest, |(_, body)| body);
+    rest.trim_end().strip_suffix("```").unwrap_or(rest).trim()
+}
+
+fn trim_trailing_comma(out: &mut String) {
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+    if out.ends_with(',') {
+        out.pop();
+    }
+}
+
+/// 按 JSON Schema 的常用子集校验参数
+///
+/// 只检查 `type`、`required`、`enum` 与嵌套的 `properties` / `items`，
+/// 足以发现修复后丢字段、类型错位等问题。
+pub fn validate_against_schema(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    if let Some(expected) = schema.get("type")
+        && !type_matches(value, expected)
+    {
+        return Err(format!("`{path}` should be of type {expected}"));
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array)
+        && !options.contains(value)
+    {
+        return Err(format!(
+            "`{path}` must be one of {}",
+            Value::Array(options.clone())
+        ));
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("missing required field `{}`", join_path(path, key)));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property_schema) in properties {
+                if let Some(property) = object.get(key) {
+                    validate_against_schema(property, property_schema, &join_path(path, key))?;
+                }
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array()
+        && let Some(item_schema) = schema.get("items")
+    {
+        for (index, item) in items.iter().enumerate() {
+            validate_against_schema(item, item_schema, &format!("{path}[{index}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn type_matches(value: &Value, expected: &Value) -> bool {
+    match expected {
+        Value::String(name) => single_type_matches(value, name),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|name| single_type_matches(value, name)),
+        _ => true,
+    }
+}
+
+fn single_type_matches(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(raw: &str) -> Value {
+        Value::Object(parse_tool_arguments(raw).unwrap().0)
+    }
+
+    #[test]
+    fn test_valid_json_is_not_marked_repaired() {
+        let (object, repaired) = parse_tool_arguments(r#"{"path": "a.rs"}"#).unwrap();
+        assert!(!repaired);
+        assert_eq!(object["path"], "a.rs");
+    }
+
+    #[test]
+    fn test_repairs_trailing_commas_and_fences() {
+        assert_eq!(
+            parse("```json\n{\"a\": 1, \"b\": [1, 2,],}\n```"),
+            json!({"a": 1, "b": [1, 2]})
+        );
+    }
+
+    #[test]
+    fn test_repairs_single_quotes_and_python_literals() {
+        assert_eq!(
+            parse("{'command': 'echo \"hi\"', 'run_in_background': False, 'x': None}"),
+            json!({"command": "echo \"hi\"", "run_in_background": false, "x": null})
+        );
+    }
+
+    #[test]
+    fn test_rejects_truncated_json() {
+        let err = parse_tool_arguments(r#"{"file_path": "/tmp/a.txt", "content": "line1\nline"#)
+            .unwrap_err();
+        assert!(err.contains("truncated"));
+        assert!(parse_tool_arguments(r#"{"a": {"b": [1, 2"#).is_err());
+        assert!(parse_tool_arguments(r#"{"a": 1, "b":"#).is_err());
+    }
+
+    #[test]
+    fn test_escaped_single_quote_loses_backslash() {
+        assert_eq!(
+            parse(r#"{"msg": "it\'s", 'other': 'don\'t'}"#),
+            json!({"msg": "it's", "other": "don't"})
+        );
+    }
+
+    #[test]
+    fn test_invalid_escapes_become_literal_backslashes() {
+        assert_eq!(
+            parse(r"{'path': 'C:\dir\sub', 'tab': 'a\tb'}"),
+            json!({"path": "C:\\dir\\sub", "tab": "a\tb"})
+        );
+    }
+
+    #[test]
+    fn test_dangling_backslash_is_dropped_not_escaped() {
+        let err = repair_json(r#"{"path": "C:\dir\"#).unwrap_err();
+        assert!(err.contains("truncated"));
+    }
+
+    #[test]
+    fn test_repairs_unquoted_keys() {
+        assert_eq!(
+            parse("{path: 'a.rs', limit: 10}"),
+            json!({"path": "a.rs", "limit": 10})
+        );
+    }
+
+    #[test]
+    fn test_ignores_surrounding_prose() {
+        assert_eq!(
+            parse("Sure, here you go: {\"q\": \"rust\"} Hope this helps!"),
+            json!({"q": "rust"})
+        );
+    }
+
+    #[test]
+    fn test_non_object_is_error() {
+        assert!(parse_tool_arguments("[1, 2]").is_err());
+        assert!(parse_tool_arguments("not json at all").is_err());
+    }
+
+    #[test]
+    fn test_validate_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "limit": {"type": "integer"},
+                "mode": {"enum": ["a", "b"]}
+            },
+            "required": ["path"]
+        });
+
+        assert!(
+            validate_against_schema(&json!({"path": "x", "limit": 3}), &schema, "input").is_ok()
+        );
+        let err = validate_against_schema(&json!({"limit": 3}), &schema, "input").unwrap_err();
+        assert!(err.contains("path"));
+        assert!(validate_against_schema(&json!({"path": 1}), &schema, "input").is_err());
+        assert!(
+            validate_against_schema(&json!({"path": "x", "mode": "c"}), &schema, "input").is_err()
+        );
+    }
+}
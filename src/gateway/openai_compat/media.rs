@@ -3,20 +3,27 @@
 //! 图片和文档的格式转换：
 //! - Claude: { type: "image", source: { type: "base64", `media_type`, data } }
 //! - `OpenAI`: { type: "`input_image`", `image_url`: "data:xxx;base64,xxx" }
+//!
+//! 入站 `OpenAI` 请求中的图片按相反方向转换。
+
+use serde_json::{Value, json};
 
-use serde_json::{Map, Value, json};
+use crate::gateway::anthropic::{ContentBlock, MediaBlock, Source};
 
 /// Claude 图片块 → `OpenAI` `input_image`
-pub fn claude_image_block_to_input_image_part(block: &Map<String, Value>) -> Option<Value> {
-    let source = block.get("source").and_then(Value::as_object)?;
-    if source.get("type").and_then(Value::as_str) != Some("base64") {
-        return None;
+#[must_use]
+pub fn claude_image_block_to_input_image_part(block: &MediaBlock) -> Option<Value> {
+    let source = &block.source;
+    match source.kind.as_str() {
+        "base64" => {}
+        "url" => {
+            let url = source.url.as_deref()?;
+            return Some(json!({ "type": "input_image", "image_url": url }));
+        }
+        _ => return None,
     }
-    let media_type = source
-        .get("media_type")
-        .and_then(Value::as_str)
-        .unwrap_or("image/png");
-    let data = source.get("data").and_then(Value::as_str)?;
+    let media_type = source.media_type.as_deref().unwrap_or("image/png");
+    let data = source.data.as_deref()?;
     Some(json!({
         "type": "input_image",
         "image_url": format!("data:{media_type};base64,{data}")
@@ -24,18 +31,32 @@ pub fn claude_image_block_to_input_image_part(block: &Map<String, Value>) -> Opt
 }
 
 /// Claude 文档块 → `OpenAI` `input_file`
-pub fn claude_document_block_to_input_file_part(block: &Map<String, Value>) -> Option<Value> {
-    let source = block.get("source").and_then(Value::as_object)?;
-    if source.get("type").and_then(Value::as_str) != Some("base64") {
+#[must_use]
+pub fn claude_document_block_to_input_file_part(block: &MediaBlock) -> Option<Value> {
+    let source = &block.source;
+    if source.kind != "base64" {
         return None;
     }
     let media_type = source
-        .get("media_type")
-        .and_then(Value::as_str)
+        .media_type
+        .as_deref()
         .unwrap_or("application/octet-stream");
-    let data = source.get("data").and_then(Value::as_str)?;
+    let data = source.data.as_deref()?;
     Some(json!({
         "type": "input_file",
         "file_url": format!("data:{media_type};base64,{data}")
     }))
 }
+
+/// `OpenAI` 图片 URL（`data:` URL 或普通 URL）→ Claude 图片块
+#[must_use]
+pub fn image_url_to_claude_image_block(url: &str) -> ContentBlock {
+    let source = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .map_or_else(
+            || Source::url(url),
+            |(media_type, data)| Source::base64(media_type, data),
+        );
+    ContentBlock::Image(MediaBlock::new(source))
+}
@@ -3,25 +3,76 @@
 //! 功能：
 //! - Claude CLI 请求 → `OpenAI` Responses 请求
 //! - `OpenAI` Responses 响应 → Claude CLI 响应
+//! - `OpenAI` Responses 流式事件 → Claude CLI 流式事件
+//! - Claude CLI ↔ `OpenAI` Chat Completions 上游（见 [`chat`]）
+//! - 入站 `OpenAI` Chat Completions 请求 ↔ Anthropic 格式（见 [`inbound`]）
 //!
 //! 参考文档：`API_FORMAT_CONVERSION.md`
 
+use std::collections::HashMap;
+
 use bytes::Bytes;
+use serde_json::Value;
+
+use crate::{
+    config::UpstreamConfig,
+    gateway::anthropic::{MessagesRequest, Tool},
+};
 
+mod alias;
+pub mod chat;
+mod error;
+pub mod inbound;
+mod json_repair;
 mod media;
 mod request;
 mod response;
+mod schema;
+mod stream;
 mod tools;
 
+pub use self::{
+    alias::ToolAliases, error::error_body_to_anthropic, json_repair::parse_tool_arguments,
+    schema::sanitize_tool_schema, stream::ResponsesStreamConverter,
+};
+
+/// 请求转换时记录、响应转换时回查的上下文
+///
+/// 部分转换是有损的（例如把强制工具调用改写成结构化输出），
+/// 响应需要按客户端最初请求的形态还原。
+#[derive(Debug, Clone, Default)]
+pub struct ConversionContext {
+    /// 强制单工具调用被改写为 `text.format` 时的工具名，返回的 JSON 文本需还原为 `tool_use`
+    pub structured_tool: Option<String>,
+    /// 工具名 → 原始 `input_schema`，用于校验修复后的工具调用参数
+    pub tool_schemas: HashMap<String, Value>,
+    /// 请求中被改名的工具，响应中的函数名需还原
+    pub tool_aliases: alias::ToolAliases,
+    /// 工具调用通过提示词协议模拟（Ollama 不支持函数调用的模型），响应文本需解析出工具调用
+    pub prompt_tools: bool,
+    /// 请求发往 Bedrock `CountTokens`，响应需转换为 Anthropic `count_tokens` 格式
+    pub count_tokens: bool,
+}
+
 /// Claude 请求 → `OpenAI` Responses 请求
-pub fn anthropic_request_to_responses(body: &Bytes) -> Result<Bytes, String> {
-    request::anthropic_request_to_responses(body)
+pub fn anthropic_request_to_responses(
+    body: &MessagesRequest,
+    upstream: &UpstreamConfig,
+) -> Result<(Bytes, ConversionContext), String> {
+    request::anthropic_request_to_responses(body, upstream)
+}
+
+/// 判断 Anthropic 工具定义是否为服务端工具（`web_search` / `web_fetch` / `code_execution`）
+#[must_use]
+pub fn is_server_tool(tool: &Tool) -> bool {
+    tools::ServerTool::from_tool(tool).is_some()
 }
 
 /// `OpenAI` Responses 响应 → Claude 响应
 pub fn responses_response_to_anthropic(
     body: &Bytes,
     model_hint: Option<&str>,
+    context: &ConversionContext,
 ) -> Result<Bytes, String> {
-    response::responses_response_to_anthropic(body, model_hint)
+    response::responses_response_to_anthropic(body, model_hint, context)
 }
@@ -9,241 +9,292 @@
 //! - `tool_result` → `function_call_output`
 //! - `max_tokens` → `max_output_tokens`
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use bytes::Bytes;
 use rayon::prelude::*;
 use serde_json::{Map, Value, json};
 
-use super::{media, tools};
+use super::{ConversionContext, alias::ToolAliases, media, schema, tools};
+use crate::{
+    config::UpstreamConfig,
+    gateway::{
+        anthropic::{
+            Content, ContentBlock, Message, MessagesRequest, Role, Tool, ToolChoice,
+            ToolResultBlock,
+        },
+        hash::stable_hash_hex,
+    },
+};
 
 /// Anthropic Claude 请求 → `OpenAI` Responses 请求
-pub fn anthropic_request_to_responses(body: &Bytes) -> Result<Bytes, String> {
-    let value: Value =
-        serde_json::from_slice(body).map_err(|_| "Request body must be JSON.".to_string())?;
-    let Some(object) = value.as_object() else {
-        return Err("Request body must be a JSON object.".to_string());
-    };
-
-    let model = object
-        .get("model")
-        .and_then(Value::as_str)
-        .ok_or_else(|| "Request must include model.".to_string())?;
-
-    let stream = object
-        .get("stream")
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
+pub fn anthropic_request_to_responses(
+    request: &MessagesRequest,
+    upstream: &UpstreamConfig,
+) -> Result<(Bytes, ConversionContext), String> {
+    if request.model.is_empty() {
+        return Err("Request must include model.".to_string());
+    }
+    if request.messages.is_empty() {
+        return Err("Request must include messages.".to_string());
+    }
 
-    let max_output_tokens = object
-        .get("max_tokens")
-        .and_then(Value::as_i64)
+    let max_output_tokens = request
+        .max_tokens
         .filter(|value| *value > 0)
         .unwrap_or(4096);
 
     let mut instructions_texts = Vec::new();
-    if let Some(system) = object.get("system")
-        && let Some(text) = claude_system_to_text(system)
+    if let Some(text) = request.system.as_ref().and_then(claude_system_to_text)
         && !text.trim().is_empty()
     {
         instructions_texts.push(text);
     }
 
-    let Some(messages) = object.get("messages").and_then(Value::as_array) else {
-        return Err("Request must include messages.".to_string());
-    };
-    let per_message_items: Vec<Vec<Value>> = messages
+    let aliases = ToolAliases::from_request(request);
+    let per_message_items: Vec<Vec<Value>> = request
+        .messages
         .par_iter()
-        .map(claude_message_to_responses_input_items)
+        .map(|message| claude_message_to_responses_input_items(message, &aliases))
         .collect();
     let input_items: Vec<Value> = per_message_items.into_iter().flatten().collect();
 
     let mut out = Map::new();
-    out.insert("model".to_string(), Value::String(model.to_string()));
-    out.insert(
-        "max_output_tokens".to_string(),
-        Value::Number(max_output_tokens.into()),
-    );
-    out.insert("stream".to_string(), Value::Bool(stream));
+    out.insert("model".to_string(), json!(request.model));
+    out.insert("max_output_tokens".to_string(), json!(max_output_tokens));
+    out.insert("stream".to_string(), Value::Bool(request.is_stream()));
     out.insert("input".to_string(), Value::Array(input_items));
 
-    // 修复异常的 function_call_output 元素
-    let mut result_value = Value::Object(out);
-    fix_malformed_function_call_outputs(&mut result_value);
-
-    if let Some(instructions) = join_system_texts(&instructions_texts)
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert("instructions".to_string(), Value::String(instructions));
+    if let Some(instructions) = join_system_texts(&instructions_texts) {
+        out.insert("instructions".to_string(), Value::String(instructions));
     }
-
-    if let Some(temperature) = object.get("temperature")
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert("temperature".to_string(), temperature.clone());
+    if let Some(temperature) = request.temperature {
+        out.insert("temperature".to_string(), json!(temperature));
     }
-    if let Some(top_p) = object.get("top_p")
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert("top_p".to_string(), top_p.clone());
+    if let Some(top_p) = request.top_p {
+        out.insert("top_p".to_string(), json!(top_p));
     }
-
     if let Some(stop) =
-        tools::map_anthropic_stop_sequences_to_openai_stop(object.get("stop_sequences"))
-        && let Some(obj) = result_value.as_object_mut()
+        tools::map_anthropic_stop_sequences_to_openai_stop(request.stop_sequences.as_deref())
     {
-        obj.insert("stop".to_string(), stop);
+        out.insert("stop".to_string(), stop);
     }
-
-    if let Some(tools_value) = object.get("tools")
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert(
+    if !request.tools.is_empty() {
+        out.insert(
             "tools".to_string(),
-            tools::map_anthropic_tools_to_responses(tools_value),
+            tools::map_anthropic_tools_to_responses(&request.tools, upstream, &aliases),
         );
     }
 
     let (tool_choice, parallel_tool_calls) =
-        tools::map_anthropic_tool_choice_to_responses(object.get("tool_choice"));
-    if let Some(tool_choice) = tool_choice
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert("tool_choice".to_string(), tool_choice);
+        tools::map_anthropic_tool_choice_to_responses(request.tool_choice.as_ref(), &aliases);
+    if let Some(tool_choice) = tool_choice {
+        out.insert("tool_choice".to_string(), tool_choice);
     }
-    if let Some(parallel_tool_calls) = parallel_tool_calls
-        && let Some(obj) = result_value.as_object_mut()
-    {
-        obj.insert(
+    if let Some(parallel_tool_calls) = parallel_tool_calls {
+        out.insert(
             "parallel_tool_calls".to_string(),
             Value::Bool(parallel_tool_calls),
         );
     }
 
+    let mut context = ConversionContext {
+        tool_schemas: collect_tool_schemas(&request.tools),
+        tool_aliases: aliases,
+        ..ConversionContext::default()
+    };
+    apply_structured_output(request, &mut out, &mut context);
+
+    if let Some(cache_key) = derive_prompt_cache_key(request, &out) {
+        out.insert("prompt_cache_key".to_string(), Value::String(cache_key));
+    }
+
+    let result_value = Value::Object(out);
     serde_json::to_vec(&result_value)
-        .map(Bytes::from)
+        .map(|bytes| (Bytes::from(bytes), context))
         .map_err(|err| format!("Failed to serialize request: {err}"))
 }
 
-/// 修复 input 数组中异常的 `function_call_output` 元素
-///
-/// 检测 `function_call_output` 的 `output` 字段是否可解析为 JSON 数组
-/// 如果可以，将其转换为 `type: message, role: assistant, content: [...]` 格式
+/// 收集客户端工具的 `input_schema`，响应转换时用于校验工具参数
+#[must_use]
+pub fn collect_tool_schemas(tools: &[Tool]) -> HashMap<String, Value> {
+    tools
+        .iter()
+        .filter(|tool| !tool.name.is_empty())
+        .filter_map(|tool| Some((tool.name.clone(), tool.input_schema.clone()?)))
+        .collect()
+}
+
+/// Anthropic 结构化输出 → Responses `text.format`
 ///
-/// 这是处理上游发送异常数据的情况：某些孤立的 `function_call_output` 的 output
-/// 字段是字符串化的 JSON 数组，实际包含的是 assistant 消息内容
-pub fn fix_malformed_function_call_outputs(body: &mut Value) {
-    let Some(obj) = body.as_object_mut() else {
+/// - `output_format: { type: "json_schema", schema }`（以及 `response_format` 写法）直接映射
+/// - 只有一个工具且 `tool_choice` 强制调用它时，改用该工具的 `input_schema` 作为输出格式，
+///   返回的 JSON 文本由响应转换还原为 `tool_use`
+fn apply_structured_output(
+    request: &MessagesRequest,
+    out: &mut Map<String, Value>,
+    context: &mut ConversionContext,
+) {
+    if let Some(format) = request
+        .extra
+        .get("output_format")
+        .or_else(|| request.extra.get("response_format"))
+        .and_then(structured_output_format)
+    {
+        out.insert("text".to_string(), json!({ "format": format }));
         return;
-    };
+    }
 
-    let Some(input) = obj.get_mut("input").and_then(|v| v.as_array_mut()) else {
+    let Some(forced_name) = request
+        .tool_choice
+        .as_ref()
+        .and_then(ToolChoice::forced_tool)
+    else {
         return;
     };
+    let [tool] = request.tools.as_slice() else {
+        return;
+    };
+    if tool.name != forced_name {
+        return;
+    }
 
-    let mut fixed_count = 0;
-
-    // 遍历并修复异常的 function_call_output 元素
-    for item in input.iter_mut() {
-        let Some(item_obj) = item.as_object_mut() else {
-            continue;
-        };
-
-        let type_str = item_obj.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-        if type_str == "function_call_output" {
-            let Some(output_str) = item_obj.get("output").and_then(|v| v.as_str()) else {
-                continue;
-            };
-
-            // 尝试将 output 解析为 JSON 数组
-            if let Ok(mut parsed_output) = serde_json::from_str::<Value>(output_str)
-                && parsed_output.is_array()
-            {
-                // output 是 JSON 数组，需要转换为 message 格式
-                // 移除 function_call_output 的字段
-                item_obj.remove("call_id");
-                item_obj.remove("output");
-
-                // 处理 content 数组：
-                // 1. 移除第二个元素（索引1）
-                // 2. 将第一个元素（索引0）的 type 改为 "output_text"
-                if let Some(content_array) = parsed_output.as_array_mut() {
-                    // 移除索引1的元素（如果存在）
-                    if content_array.len() > 1 {
-                        content_array.remove(1);
-                    }
-                    // 修改索引0元素的 type 为 "output_text"
-                    if let Some(first_item) = content_array.first_mut()
-                        && let Some(first_obj) = first_item.as_object_mut()
-                    {
-                        first_obj.insert("type".to_string(), json!("output_text"));
-                    }
-                }
-
-                // 添加 message 字段
-                item_obj.insert("type".to_string(), json!("message"));
-                item_obj.insert("role".to_string(), json!("assistant"));
-                item_obj.insert("content".to_string(), parsed_output);
-
-                fixed_count += 1;
-                tracing::info!("✅ 修复异常的 function_call_output: 转换为 assistant message");
+    let schema = tool
+        .input_schema
+        .clone()
+        .unwrap_or_else(|| json!({ "type": "object" }));
+    out.remove("tools");
+    out.remove("tool_choice");
+    out.remove("parallel_tool_calls");
+    out.insert(
+        "text".to_string(),
+        json!({
+            "format": {
+                "type": "json_schema",
+                "name": json_schema_format_name(forced_name),
+                "schema": schema::to_strict_json_schema(&schema),
+                "strict": true
             }
+        }),
+    );
+    context.structured_tool = Some(forced_name.to_string());
+    tracing::debug!("🧩 强制工具 {} 改写为结构化输出", forced_name);
+}
+/// 解析 `output_format` / `response_format` 为 Responses 的 `text.format`
+fn structured_output_format(value: &Value) -> Option<Value> {
+    match value.get("type").and_then(Value::as_str)? {
+        "json_object" => Some(json!({ "type": "json_object" })),
+        "json_schema" => {
+            // OpenAI Chat 写法把 schema 包在 json_schema 里，Anthropic 写法直接放在 schema
+            let wrapper = value.get("json_schema");
+            let schema = wrapper
+                .and_then(|wrapper| wrapper.get("schema"))
+                .or_else(|| value.get("schema"))?;
+            let name = wrapper
+                .and_then(|wrapper| wrapper.get("name"))
+                .and_then(Value::as_str)
+                .unwrap_or("structured_output");
+            Some(json!({
+                "type": "json_schema",
+                "name": json_schema_format_name(name),
+                "schema": schema::to_strict_json_schema(schema),
+                "strict": true
+            }))
         }
+        _ => None,
     }
+}
+
+/// `text.format.name` 只允许 `[a-zA-Z0-9_-]`，最长 64 个字符
+fn json_schema_format_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect()
+}
 
-    if fixed_count > 0 {
-        tracing::info!("共修复 {} 个异常的 function_call_output 元素", fixed_count);
+/// 根据 Anthropic 的 `cache_control` 标记推导 `OpenAI` 的 `prompt_cache_key`
+///
+/// Responses API 没有逐块的缓存断点，只能按 key 路由到同一缓存分片。
+/// 客户端标记了缓存时，以 model + instructions + tools 这段稳定前缀的哈希作为 key，
+/// 同一会话的后续请求即可命中上游的前缀缓存。
+fn derive_prompt_cache_key(
+    request: &MessagesRequest,
+    converted: &Map<String, Value>,
+) -> Option<String> {
+    if !request_has_cache_control(request) {
+        return None;
     }
+
+    let model = converted.get("model").and_then(Value::as_str).unwrap_or("");
+    let instructions = converted
+        .get("instructions")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let tools = converted
+        .get("tools")
+        .map(|tools| serde_json::to_vec(tools).unwrap_or_default())
+        .unwrap_or_default();
+
+    Some(format!(
+        "cc-proxy-{}",
+        stable_hash_hex(&[model.as_bytes(), instructions.as_bytes(), &tools])
+    ))
 }
 
-fn claude_message_to_responses_input_items(message: &Value) -> Vec<Value> {
+/// 检查 system / tools / messages 中是否存在 `cache_control` 标记
+fn request_has_cache_control(request: &MessagesRequest) -> bool {
+    let blocks_have_marker = |content: &Content| {
+        content
+            .blocks()
+            .iter()
+            .any(|block| block.cache_control().is_some())
+    };
+
+    request.system.as_ref().is_some_and(blocks_have_marker)
+        || request
+            .tools
+            .iter()
+            .any(|tool| tool.cache_control.is_some())
+        || request
+            .messages
+            .iter()
+            .any(|message| blocks_have_marker(&message.content))
+}
+
+fn claude_message_to_responses_input_items(message: &Message, aliases: &ToolAliases) -> Vec<Value> {
     let mut input_items = Vec::new();
 
-    let Some(message) = message.as_object() else {
-        return input_items;
-    };
-    let role = message
-        .get("role")
-        .and_then(Value::as_str)
-        .unwrap_or("user");
+    let role = message.role.as_str();
     if role == "system" {
         return input_items;
     }
 
-    let content = message.get("content");
-    let blocks = claude_content_to_blocks(content);
+    let blocks = claude_content_to_blocks(&message.content);
 
     let mut message_parts = Vec::new();
-    let text_part_type = match role {
+    let text_part_type = match message.role {
         // OpenAI Responses schema expects assistant messages in `input` to use output types.
         // This avoids errors like: "Invalid value: 'input_text'. Supported values are: 'output_text' and 'refusal'."
-        "assistant" => "output_text",
+        Role::Assistant => "output_text",
         _ => "input_text",
     };
-    for block in &blocks {
-        let Some(block) = block.as_object() else {
-            continue;
-        };
-        let block_type = block.get("type").and_then(Value::as_str).unwrap_or("");
-        match block_type {
-            "text" => {
-                if let Some(text) = block.get("text").and_then(Value::as_str) {
-                    message_parts.push(json!({ "type": text_part_type, "text": text }));
-                }
-            }
-            "image" => {
-                if let Some(part) = media::claude_image_block_to_input_image_part(block) {
-                    message_parts.push(part);
-                }
-            }
-            "document" => {
-                if let Some(part) = media::claude_document_block_to_input_file_part(block) {
-                    message_parts.push(part);
-                }
+    for block in blocks.iter() {
+        let part = match block {
+            ContentBlock::Text(text) => Some(json!({ "type": text_part_type, "text": text.text })),
+            ContentBlock::Image(image) => media::claude_image_block_to_input_image_part(image),
+            ContentBlock::Document(document) => {
+                media::claude_document_block_to_input_file_part(document)
             }
-            _ => {}
-        }
+            _ => None,
+        };
+        message_parts.extend(part);
     }
     if !message_parts.is_empty() {
         input_items.push(json!({
@@ -253,77 +304,126 @@ fn claude_message_to_responses_input_items(message: &Value) -> Vec<Value> {
         }));
     }
 
-    for block in blocks {
-        let Some(block) = block.as_object() else {
-            continue;
-        };
-        let block_type = block.get("type").and_then(Value::as_str).unwrap_or("");
-        match block_type {
-            "tool_use" => {
-                let call_id = block
-                    .get("id")
-                    .and_then(Value::as_str)
-                    .unwrap_or("call_proxy");
-                let name = block.get("name").and_then(Value::as_str).unwrap_or("");
-                let input = block.get("input").cloned().unwrap_or_else(|| json!({}));
-                let arguments = serde_json::to_string(&input).unwrap_or_else(|_| "{}".to_string());
+    let mut tool_result_images = Vec::new();
+    for block in blocks.iter() {
+        match block {
+            ContentBlock::ToolUse(tool_use) => {
+                let call_id = if tool_use.id.is_empty() {
+                    "call_proxy"
+                } else {
+                    tool_use.id.as_str()
+                };
+                let arguments =
+                    serde_json::to_string(&tool_use.input).unwrap_or_else(|_| "{}".to_string());
                 input_items.push(json!({
                     "type": "function_call",
                     "call_id": call_id,
-                    "name": name,
+                    "name": aliases.alias(&tool_use.name),
                     "arguments": arguments
                 }));
             }
-            "tool_result" => {
-                let call_id = block
-                    .get("tool_use_id")
-                    .and_then(Value::as_str)
-                    .unwrap_or("");
-                let output_text: Cow<'_, str> = match block.get("content") {
-                    Some(Value::String(text)) => Cow::Borrowed(text.as_str()),
-                    Some(other) => Cow::Owned(serde_json::to_string(other).unwrap_or_default()),
-                    None => Cow::Borrowed(""),
-                };
-                let is_error = block
-                    .get("is_error")
-                    .and_then(Value::as_bool)
-                    .unwrap_or(false);
-
-                let mut item = Map::new();
-                item.insert("type".to_string(), json!("function_call_output"));
-                item.insert("call_id".to_string(), Value::String(call_id.to_string()));
-
-                // 注意：上游 OpenAI Responses API 不支持 is_error 字段
-                // 如果有错误，将错误信息包装在 output 文本中
-                let final_output = if is_error && !output_text.is_empty() {
-                    format!("[ERROR] {output_text}")
-                } else {
-                    output_text.into_owned()
-                };
-                item.insert("output".to_string(), Value::String(final_output));
-                input_items.push(Value::Object(item));
+            ContentBlock::ToolResult(result) => {
+                let (item, images) = claude_tool_result_to_function_call_output(result);
+                input_items.push(item);
+                if let Some(images) = images {
+                    tool_result_images.push(images);
+                }
             }
             _ => {}
         }
     }
 
+    // function_call_output 只能承载文本，工具返回的图片以紧随其后的 user 消息送给模型
+    for (call_id, images) in tool_result_images {
+        let mut parts = Vec::with_capacity(images.len() + 1);
+        parts.push(json!({
+            "type": "input_text",
+            "text": format!("Images returned by tool call {call_id}:")
+        }));
+        parts.extend(images);
+        input_items.push(json!({
+            "type": "message",
+            "role": "user",
+            "content": parts
+        }));
+    }
+
     input_items
 }
 
-fn claude_system_to_text(value: &Value) -> Option<String> {
-    match value {
-        Value::String(text) => Some(text.clone()),
-        Value::Array(items) => {
-            let texts = items
-                .iter()
-                .filter_map(|item| item.as_object())
-                .filter(|item| item.get("type").and_then(Value::as_str) == Some("text"))
-                .filter_map(|item| item.get("text").and_then(Value::as_str))
-                .map(std::string::ToString::to_string)
-                .collect::<Vec<_>>();
+/// Claude `tool_result` → `OpenAI` `function_call_output`
+///
+/// 返回的第二项为工具结果中的图片（附带 `call_id`），需由调用方追加为 user 消息。
+fn claude_tool_result_to_function_call_output(
+    block: &ToolResultBlock,
+) -> (Value, Option<(String, Vec<Value>)>) {
+    let call_id = block.tool_use_id.as_str();
+    let (output_text, images) = claude_tool_result_content_to_parts(block.content.as_ref());
+
+    let mut item = Map::new();
+    item.insert("type".to_string(), json!("function_call_output"));
+    item.insert("call_id".to_string(), Value::String(call_id.to_string()));
+
+    // 注意：上游 OpenAI Responses API 不支持 is_error 字段
+    // 如果有错误，将错误信息包装在 output 文本中
+    let final_output = if block.is_error() && !output_text.is_empty() {
+        format!("[ERROR] {output_text}")
+    } else if output_text.is_empty() && !images.is_empty() {
+        format!(
+            "[{} image(s) returned, attached in the next message]",
+            images.len()
+        )
+    } else {
+        output_text.into_owned()
+    };
+    item.insert("output".to_string(), Value::String(final_output));
+
+    let images = (!images.is_empty()).then(|| (call_id.to_string(), images));
+    (Value::Object(item), images)
+}
+
+/// 拆分 `tool_result.content`：文本部分拼接为纯文本，图片部分转换为 `input_image`
+///
+/// 不认识的块类型退化为其 JSON 文本，避免静默丢失工具输出。
+#[must_use]
+pub fn claude_tool_result_content_to_parts(
+    content: Option<&Content>,
+) -> (Cow<'_, str>, Vec<Value>) {
+    let items = match content {
+        Some(Content::Text(text)) => return (Cow::Borrowed(text.as_str()), Vec::new()),
+        Some(Content::Blocks(items)) => items,
+        None => return (Cow::Borrowed(""), Vec::new()),
+    };
+
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    for item in items {
+        match item {
+            ContentBlock::Text(text) => texts.push(Cow::Borrowed(text.text.as_str())),
+            ContentBlock::Image(image) => {
+                images.extend(media::claude_image_block_to_input_image_part(image));
+            }
+            _ => texts.push(Cow::Owned(serde_json::to_string(item).unwrap_or_default())),
+        }
+    }
+
+    let text = match texts.len() {
+        0 => Cow::Borrowed(""),
+        1 => texts.swap_remove(0),
+        _ => Cow::Owned(texts.join("\n")),
+    };
+    (text, images)
+}
+
+/// system 内容 → 纯文本（内容块数组中只取 text 块，逐块去除首尾空白后换行拼接）
+#[must_use]
+pub fn claude_system_to_text(system: &Content) -> Option<String> {
+    match system {
+        Content::Text(text) => Some(text.clone()),
+        Content::Blocks(_) => {
+            let texts = system.texts().map(str::to_string).collect::<Vec<_>>();
             join_system_texts(&texts)
         }
-        _ => None,
     }
 }
 
@@ -342,63 +442,12 @@ fn join_system_texts(texts: &[String]) -> Option<String> {
     }
 }
 
-fn claude_content_to_blocks(content: Option<&Value>) -> Vec<Value> {
-    let Some(content) = content else {
-        return Vec::new();
-    };
+/// 消息内容 → 内容块，字符串内容视为一个 text 块
+#[must_use]
+pub fn claude_content_to_blocks(content: &Content) -> Cow<'_, [ContentBlock]> {
     match content {
-        Value::String(text) => vec![json!({ "type": "text", "text": text })],
-        Value::Array(items) => items
-            .iter()
-            .cloned()
-            .map(|mut item| {
-                normalize_text_block_in_place(&mut item);
-                item
-            })
-            .collect(),
-        _ => Vec::new(),
-    }
-}
-
-fn normalize_text_block_in_place(block: &mut Value) {
-    let Some(object) = block.as_object_mut() else {
-        return;
-    };
-    let block_type = object.get("type").and_then(Value::as_str).unwrap_or("");
-    if block_type != "text" {
-        return;
-    }
-    let text_value = object.get("text");
-    let new_text = text_value.and_then(extract_text_value);
-    if let Some(new_text) = new_text {
-        if matches!(
-            (&new_text, text_value),
-            (Cow::Borrowed(_), Some(Value::String(_)))
-        ) {
-            return;
-        }
-        object.insert("text".to_string(), Value::String(new_text.into_owned()));
-        return;
-    }
-    // If text exists but is not convertible, coerce to empty string to satisfy schema.
-    if text_value.is_some() {
-        object.insert("text".to_string(), Value::String(String::new()));
-    }
-}
-
-fn extract_text_value(value: &Value) -> Option<Cow<'_, str>> {
-    match value {
-        Value::String(text) => Some(Cow::Borrowed(text.as_str())),
-        Value::Object(object) => {
-            if let Some(text) = object.get("text") {
-                return extract_text_value(text);
-            }
-            if let Some(text) = object.get("value") {
-                return extract_text_value(text);
-            }
-            None
-        }
-        _ => None,
+        Content::Text(text) => Cow::Owned(vec![ContentBlock::text(text.as_str())]),
+        Content::Blocks(blocks) => Cow::Borrowed(blocks),
     }
 }
 
@@ -409,230 +458,260 @@ mod tests {
 
     use super::*;
 
-    /// 测试修复异常的 `function_call_output`
+    fn input_items(message: Value) -> Vec<Value> {
+        let message: Message = serde_json::from_value(message).unwrap();
+        claude_message_to_responses_input_items(&message, &ToolAliases::default())
+    }
+
+    /// 测试 `tool_result` 中的文本块被还原为纯文本
     #[test]
-    fn test_fix_malformed_function_call_outputs() {
-        // 构造一个包含异常 function_call_output 的请求
-        let mut request: Value = serde_json::from_str(r#"
-{
-            "model": "test-model",
-            "max_output_tokens": 4096,
-            "stream": false,
-            "input": [
-                {
-                    "content": [{"text": "用户消息", "type": "input_text"}],
-                    "role": "user",
-                    "type": "message"
-                },
-                {
-                    "call_id": "call_dfff71beedfc4e05a1ca7100",
-                    "output": "[{\"text\":\"我这边先说明下：当前环境里终端命令调用被拒绝了，所以我暂时无法直接扫描你的本地仓库文件。\\n\\n你可以任选一种方式，我立刻继续做精确定位：\\n\\n- 方式 1（推荐）：把仓库根目录路径下的关键文件列表/目录结构贴给我（例如 `src/`, `app/`, `components/` 相关）\\n- 方式 2：直接把你怀疑相关的文件内容贴上来（比如包含“注释/annotation/comment/page/layout/number/index”的文件）\\n- 方式 3：你本地先跑以下命令，把结果发我（我会据此给出“所有相关文件 + 关键代码段 + 结论”）：\\n\\n```bash\\n# 1) 找候选文件\\nrg -n \\\"annotation|annot|comment|note|footnote|序号|编号|页|分页|layout|position|left|bottom|vertical|horizontal|direction\\\" src app components\\n\\n# 2) 如果是 React Native/前端项目，再补充样式方向相关\\nrg -n \\\"flexDirection|writingMode|position:\\\\\\\\s*'absolute'|left:\\\\\\\\s*|bottom:\\\\\\\\s*|transform|column|row\\\" src app components\\n```\\n\\n拿到这些结果后，我会按你关心的三点给出明确结论：\\n- 注释序号如何编号\\n- 分页是否重置编号\\n- 左下角注释是横排还是竖排（以及由哪段布局代码决定）\",\"type\":\"text\"},{\"text\":\"agentId: a883913cc85bbedd1 (for resuming to continue this agent's work if needed)\\n<usage>total_tokens: 11154\\ntool_uses: 1\\nduration_ms: 36320</usage>\",\"type\":\"text\"}]",
-                   "type": "function_call_output"
-                },
-                {
-                    "call_id": "call_normal456",
-                    "output": "No files found",
-                    "type": "function_call_output"
-                }
-            ]
-        }
-        "#).unwrap();
-
-        // 调用修复函数
-        fix_malformed_function_call_outputs(&mut request);
-
-        // 验证结果
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-
-        // 第一个元素应该是普通用户消息，保持不变
-        assert_eq!(input[0].get("type").unwrap().as_str().unwrap(), "message");
-        assert_eq!(input[0].get("role").unwrap().as_str().unwrap(), "user");
-
-        // 第二个元素：异常的 function_call_output 应该被转换为 assistant message
-        assert_eq!(input[1].get("type").unwrap().as_str().unwrap(), "message");
-        assert_eq!(input[1].get("role").unwrap().as_str().unwrap(), "assistant");
-        // 不应该再有 call_id 和 output 字段
-        assert!(input[1].get("call_id").is_none());
-        assert!(input[1].get("output").is_none());
-        // content 应该是解析后的数组（第二个元素已被移除，只剩一个）
-        let content = input[1].get("content").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(content.len(), 1);
-        // 验证第一个元素的 type 已被改为 "output_text"
-        assert_eq!(
-            content[0].get("type").unwrap().as_str().unwrap(),
-            "output_text"
-        );
-        // 验证 text 包含预期的内容
-        let first_text = content[0].get("text").unwrap().as_str().unwrap();
-        assert!(
-            first_text.contains("我这边先说明下：当前环境里终端命令调用被拒绝了"),
-            "First text should contain expected content, got: {first_text}"
-        );
+    fn test_tool_result_text_parts_become_plain_text() {
+        let message = json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": "call_1",
+                "content": [
+                    {"type": "text", "text": "first"},
+                    {"type": "text", "text": "second"}
+                ]
+            }]
+        });
 
-        // 第三个元素：正常的 function_call_output 应该保持不变
-        assert_eq!(
-            input[2].get("type").unwrap().as_str().unwrap(),
-            "function_call_output"
-        );
-        assert_eq!(
-            input[2].get("call_id").unwrap().as_str().unwrap(),
-            "call_normal456"
-        );
-        assert_eq!(
-            input[2].get("output").unwrap().as_str().unwrap(),
-            "No files found"
-        );
+        let items = input_items(message);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["type"], "function_call_output");
+        assert_eq!(items[0]["call_id"], "call_1");
+        assert_eq!(items[0]["output"], "first\nsecond");
     }
 
-    /// 测试不包含异常数据的情况
+    /// 测试 `tool_result` 中的图片转换为后续 user 消息里的 `input_image`
     #[test]
-    fn test_fix_with_no_malformed_outputs() {
-        let mut request = json!({
-            "model": "test-model",
-            "input": [
-                {
-                    "call_id": "call_123",
-                    "output": "Normal output",
-                    "type": "function_call_output"
-                }
-            ]
+    fn test_tool_result_images_become_input_image() {
+        let message = json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": "call_shot",
+                "content": [
+                    {"type": "text", "text": "Screenshot taken"},
+                    {
+                        "type": "image",
+                        "source": {"type": "base64", "media_type": "image/jpeg", "data": "QUJD"}
+                    }
+                ]
+            }]
         });
 
-        fix_malformed_function_call_outputs(&mut request);
+        let items = input_items(message);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["type"], "function_call_output");
+        assert_eq!(items[0]["output"], "Screenshot taken");
+
+        assert_eq!(items[1]["type"], "message");
+        assert_eq!(items[1]["role"], "user");
+        let parts = items[1]["content"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1]["type"], "input_image");
+        assert_eq!(parts[1]["image_url"], "data:image/jpeg;base64,QUJD");
+    }
 
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(
-            input[0].get("type").unwrap().as_str().unwrap(),
-            "function_call_output"
-        );
-        assert_eq!(
-            input[0].get("output").unwrap().as_str().unwrap(),
-            "Normal output"
-        );
+    /// 测试只有图片的 `tool_result` 仍然输出非空文本
+    #[test]
+    fn test_tool_result_image_only_has_placeholder_output() {
+        let message = json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": "call_img",
+                "content": [{
+                    "type": "image",
+                    "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}
+                }]
+            }]
+        });
+
+        let items = input_items(message);
+        assert_eq!(items.len(), 2);
+        let output = items[0]["output"].as_str().unwrap();
+        assert!(output.contains("1 image(s)"));
+        assert_eq!(items[1]["content"][1]["type"], "input_image");
     }
 
-    /// 测试空 input 数组
+    /// 测试字符串形式的 `tool_result` 与错误标记保持原有行为
     #[test]
-    fn test_fix_with_empty_input() {
-        let mut request = json!({
-            "model": "test-model",
-            "input": []
+    fn test_tool_result_string_and_error() {
+        let message = json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": "call_err",
+                "is_error": true,
+                "content": "No such file"
+            }]
         });
 
-        fix_malformed_function_call_outputs(&mut request);
+        let items = input_items(message);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["output"], "[ERROR] No such file");
+    }
+
+    fn parse(request: &Value) -> MessagesRequest {
+        serde_json::from_value(request.clone()).unwrap()
+    }
 
-        // 不应该 panic，input 应该仍然为空
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(input.len(), 0);
+    fn convert(request: &Value) -> Value {
+        let upstream = UpstreamConfig::default();
+        serde_json::from_slice(
+            &anthropic_request_to_responses(&parse(request), &upstream)
+                .unwrap()
+                .0,
+        )
+        .unwrap()
     }
 
-    /// 测试 output 是无效 JSON 的情况
+    /// 测试 `prompt_cache_key` 只依赖稳定前缀，与消息历史无关
     #[test]
-    fn test_fix_with_invalid_json_output() {
+    fn test_prompt_cache_key_is_stable_across_turns() {
         let mut request = json!({
-            "model": "test-model",
-            "input": [
-                {
-                    "call_id": "call_123",
-                    "output": "{invalid json}",
-                    "type": "function_call_output"
-                }
-            ]
+            "model": "gpt-test",
+            "system": [{
+                "type": "text",
+                "text": "You are helpful.",
+                "cache_control": {"type": "ephemeral"}
+            }],
+            "tools": [{"name": "Read", "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "hi"}]
         });
+        let first = convert(&request);
 
-        fix_malformed_function_call_outputs(&mut request);
+        request["messages"] = json!([
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "hello"},
+            {"role": "user", "content": "more"}
+        ]);
+        let second = convert(&request);
 
-        // 无效 JSON 应该保持原样
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(
-            input[0].get("type").unwrap().as_str().unwrap(),
-            "function_call_output"
-        );
-        assert_eq!(
-            input[0].get("output").unwrap().as_str().unwrap(),
-            "{invalid json}"
+        let key = first["prompt_cache_key"].as_str().unwrap();
+        assert!(key.starts_with("cc-proxy-"));
+        assert_eq!(first["prompt_cache_key"], second["prompt_cache_key"]);
+
+        request["system"][0]["text"] = json!("Different prompt.");
+        assert_ne!(
+            convert(&request)["prompt_cache_key"],
+            first["prompt_cache_key"]
         );
     }
 
-    /// 测试 output 是 JSON 对象而非数组的情况
+    /// 测试没有 `cache_control` 标记时不设置 `prompt_cache_key`
     #[test]
-    fn test_fix_with_json_object_output() {
-        let mut request = json!({
-            "model": "test-model",
-            "input": [
-                {
-                    "call_id": "call_123",
-                    "output": "{\"result\":\"success\"}",
-                    "type": "function_call_output"
-                }
-            ]
+    fn test_prompt_cache_key_requires_cache_control() {
+        let request = json!({
+            "model": "gpt-test",
+            "system": "plain",
+            "messages": [{"role": "user", "content": "hi"}]
         });
+        assert!(convert(&request).get("prompt_cache_key").is_none());
+    }
 
-        fix_malformed_function_call_outputs(&mut request);
+    /// 测试服务端工具映射为 Responses 内置工具，`web_fetch` 被移除
+    #[test]
+    fn test_server_tools_map_to_builtin_tools() {
+        let request = json!({
+            "model": "gpt-test",
+            "tools": [
+                {"type": "web_search_20250305", "name": "web_search", "max_uses": 5,
+                 "allowed_domains": ["docs.rs"]},
+                {"type": "web_fetch_20250910", "name": "web_fetch"},
+                {"type": "code_execution_20250825", "name": "code_execution"},
+                {"name": "Read", "input_schema": {"type": "object"}}
+            ],
+            "messages": [{"role": "user", "content": "hi"}]
+        });
 
-        // JSON 对象应该保持原样（只有数组才会被转换）
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(
-            input[0].get("type").unwrap().as_str().unwrap(),
-            "function_call_output"
-        );
-        assert_eq!(
-            input[0].get("output").unwrap().as_str().unwrap(),
-            "{\"result\":\"success\"}"
-        );
+        let converted = convert(&request);
+        let tools = converted["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 3);
+        assert_eq!(tools[0]["type"], "web_search");
+        assert_eq!(tools[0]["filters"]["allowed_domains"][0], "docs.rs");
+        assert_eq!(tools[1]["type"], "code_interpreter");
+        assert_eq!(tools[2]["type"], "function");
+        assert_eq!(tools[2]["name"], "Read");
+
+        let upstream = UpstreamConfig {
+            server_tools: false,
+            ..UpstreamConfig::default()
+        };
+        let converted: Value = serde_json::from_slice(
+            &anthropic_request_to_responses(&parse(&request), &upstream)
+                .unwrap()
+                .0,
+        )
+        .unwrap();
+        let tools = converted["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "Read");
     }
 
-    /// 测试缺少 input 字段的情况
+    /// 测试 `output_format` 映射为 strict 的 `text.format`
     #[test]
-    fn test_fix_without_input_field() {
-        let mut request = json!({
-            "model": "test-model"
+    fn test_output_format_maps_to_strict_text_format() {
+        let request = json!({
+            "model": "gpt-test",
+            "messages": [{"role": "user", "content": "extract"}],
+            "output_format": {
+                "type": "json_schema",
+                "schema": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "age": {"type": "integer"}
+                    },
+                    "required": ["name"]
+                }
+            }
         });
 
-        // 不应该 panic
-        fix_malformed_function_call_outputs(&mut request);
+        let converted = convert(&request);
+        let format = &converted["text"]["format"];
+        assert_eq!(format["type"], "json_schema");
+        assert_eq!(format["strict"], true);
+        assert_eq!(format["schema"]["additionalProperties"], false);
+        assert_eq!(format["schema"]["required"], json!(["age", "name"]));
+        assert_eq!(
+            format["schema"]["properties"]["age"]["type"],
+            json!(["integer", "null"])
+        );
     }
 
-    /// 测试包含真实场景的数据（类似于用户提供的示例）
+    /// 测试强制单工具调用改写为结构化输出
     #[test]
-    fn test_fix_real_world_example() {
-        let output_array = json!([
-            {"text": "我这边先说明下：当前环境里终端命令调用被拒绝了，所以我暂时无法直接扫描你的本地仓库文件。", "type": "text"},
-            {"text": "agentId: a883913cc85bbedd1 (for resuming to continue this agent's work if needed)\n<usage>total_tokens: 11154\ntool_uses: 1\nduration_ms: 36320</usage>", "type": "text"}
-        ]);
-
-        let mut request = json!({
-            "model": "test-model",
-            "input": [
-                {
-                    "call_id": "call_dfff71beedfc4e05a1ca7100",
-                    "output": serde_json::to_string(&output_array).unwrap(),
-                    "type": "function_call_output"
+    fn test_forced_single_tool_becomes_text_format() {
+        let request = json!({
+            "model": "gpt-test",
+            "messages": [{"role": "user", "content": "classify"}],
+            "tools": [{
+                "name": "record_label",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {"label": {"type": "string"}},
+                    "required": ["label"]
                 }
-            ]
+            }],
+            "tool_choice": {"type": "tool", "name": "record_label"}
         });
 
-        fix_malformed_function_call_outputs(&mut request);
-
-        let input = request.get("input").and_then(|v| v.as_array()).unwrap();
-        assert_eq!(input[0].get("type").unwrap().as_str().unwrap(), "message");
-        assert_eq!(input[0].get("role").unwrap().as_str().unwrap(), "assistant");
+        let (converted, context) =
+            anthropic_request_to_responses(&parse(&request), &UpstreamConfig::default()).unwrap();
+        let converted: Value = serde_json::from_slice(&converted).unwrap();
 
-        let content = input[0].get("content").and_then(|v| v.as_array()).unwrap();
-        // 第二个元素已被移除，只剩一个元素
-        assert_eq!(content.len(), 1);
-        // 第一个元素的 type 应该被改为 "output_text"
+        assert_eq!(context.structured_tool.as_deref(), Some("record_label"));
+        assert!(converted.get("tools").is_none());
+        assert!(converted.get("tool_choice").is_none());
+        assert_eq!(converted["text"]["format"]["name"], "record_label");
         assert_eq!(
-            content[0].get("type").unwrap().as_str().unwrap(),
-            "output_text"
-        );
-        assert!(
-            content[0]
-                .get("text")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .contains("终端命令调用被拒绝了")
+            converted["text"]["format"]["schema"]["required"],
+            json!(["label"])
         );
     }
 }
@@ -7,14 +7,20 @@
 //! - `function_call` → `tool_use`
 //! - `output_text` → text
 //! - `reasoning_text` → thinking
+//! - `web_search_call` / `code_interpreter_call` → `server_tool_use` + 工具结果块
+//! - `url_citation` 注释 → text 块的 citations
 
 use bytes::Bytes;
 use serde_json::{Map, Value, json};
 
+use super::{ConversionContext, json_repair, schema, tools::ServerTool};
+use crate::gateway::anthropic::{ContentBlock, MessagesResponse, TextBlock, Usage};
+
 /// `OpenAI` Responses 响应 → Anthropic 响应
 pub fn responses_response_to_anthropic(
     body: &Bytes,
     model_hint: Option<&str>,
+    context: &ConversionContext,
 ) -> Result<Bytes, String> {
     let raw_body_str = String::from_utf8_lossy(body);
     tracing::debug!("🔍 原始上游响应 JSON: {}", raw_body_str);
@@ -48,103 +54,375 @@ pub fn responses_response_to_anthropic(
         .and_then(Value::as_array)
         .map_or(&[], |items| items.as_slice());
     tracing::debug!("📤 output 数组长度: {}", output.len());
-    let mut combined_text = String::new();
-    let mut thinking_text = String::new();
-    let mut tool_uses = Vec::new();
-
+    let mut collected = CollectedOutput::default();
     for item in output {
         let Some(item) = item.as_object() else {
             tracing::debug!("⚠️ output 项不是对象");
             continue;
         };
+        collected.push_item(item, context);
+    }
+
+    // 强制工具调用被改写为结构化输出时，把 JSON 文本还原为客户端期望的 tool_use
+    if collected.tool_uses.is_empty()
+        && let Some(tool_use) = structured_text_to_tool_use(&collected.text, id, context)
+    {
+        collected.tool_uses.push(tool_use);
+        collected.text.clear();
+        collected.citations.clear();
+    }
+
+    let has_tool_uses = !collected.tool_uses.is_empty();
+    let content = collected.into_content();
+
+    let finish_reason = chat_finish_reason_from_response_object(object, has_tool_uses);
+    let stop_reason = anthropic_stop_reason_from_chat_finish_reason(finish_reason);
+
+    let out = MessagesResponse {
+        content,
+        stop_reason: Some(stop_reason.to_string()),
+        usage: usage.unwrap_or_default(),
+        ..MessagesResponse::new(id, model)
+    };
+
+    serde_json::to_vec(&out)
+        .map(Bytes::from)
+        .map_err(|err| format!("Failed to serialize response: {err}"))
+}
+
+/// 从 Responses output[] 中收集的各类内容
+#[derive(Default)]
+struct CollectedOutput {
+    text: String,
+    citations: Vec<Value>,
+    thinking: String,
+    server_tool_blocks: Vec<ContentBlock>,
+    tool_uses: Vec<ContentBlock>,
+}
+
+impl CollectedOutput {
+    fn push_item(&mut self, item: &Map<String, Value>, context: &ConversionContext) {
         let item_type = item.get("type").and_then(Value::as_str);
         tracing::debug!("📤 output 项类型: {:?}", item_type);
         match item_type {
-            Some("message") => {
-                if item.get("role").and_then(Value::as_str) != Some("assistant") {
-                    continue;
-                }
-                if let Some(content) = item.get("content").and_then(Value::as_array) {
-                    for part in content {
-                        let Some(part) = part.as_object() else {
-                            continue;
-                        };
-                        match part.get("type").and_then(Value::as_str) {
-                            Some("output_text") => {
-                                if let Some(text) = part.get("text").and_then(Value::as_str) {
-                                    combined_text.push_str(text);
-                                }
-                            }
-                            Some("reasoning_text") => {
-                                if let Some(text) = part.get("text").and_then(Value::as_str) {
-                                    thinking_text.push_str(text);
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+            Some("message") => append_message_content(
+                item,
+                &mut self.text,
+                &mut self.thinking,
+                &mut self.citations,
+            ),
+            Some("function_call") => match responses_function_call_to_tool_use(item, context) {
+                Some(Ok(tool_use)) => self.tool_uses.push(tool_use),
+                Some(Err(explanation)) => append_paragraph(&mut self.text, &explanation),
+                None => {}
+            },
+            Some("web_search_call") => self
+                .server_tool_blocks
+                .extend(web_search_call_to_anthropic_blocks(item)),
+            Some("code_interpreter_call") => self
+                .server_tool_blocks
+                .extend(code_interpreter_call_to_anthropic_blocks(item)),
+            _ => {}
+        }
+    }
+
+    /// 按 thinking → 服务端工具 → text → `tool_use` 的顺序组装 Anthropic content
+    fn into_content(self) -> Vec<ContentBlock> {
+        let mut content = Vec::new();
+        if !self.thinking.trim().is_empty() {
+            content.push(ContentBlock::thinking(self.thinking, None));
+        }
+        content.extend(self.server_tool_blocks);
+        if !self.text.trim().is_empty() || self.tool_uses.is_empty() {
+            let mut text_block = TextBlock::new(self.text);
+            if !self.citations.is_empty() {
+                text_block
+                    .extra
+                    .insert("citations".to_string(), Value::Array(self.citations));
+            }
+            content.push(ContentBlock::Text(text_block));
+        }
+        content.extend(self.tool_uses);
+        content
+    }
+}
+
+/// 结构化输出的 JSON 文本 → Anthropic `tool_use`
+///
+/// strict 模式下可选字段被改写为可空，上游填入的 `null` 按原始 schema 移除。
+#[must_use]
+pub fn structured_text_to_tool_use(
+    text: &str,
+    response_id: &str,
+    context: &ConversionContext,
+) -> Option<ContentBlock> {
+    let tool_name = context.structured_tool.as_deref()?;
+    let trimmed = text.trim();
+    let trimmed = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|inner| inner.strip_suffix("```"))
+        .map_or(trimmed, str::trim);
+    let mut input = serde_json::from_str::<Value>(trimmed)
+        .ok()
+        .filter(Value::is_object)?;
+    if let Some(schema) = context.tool_schemas.get(tool_name) {
+        schema::drop_optional_nulls(&mut input, schema);
+    }
+    Some(ContentBlock::tool_use(
+        format!("toolu_{}", response_id.trim_start_matches("resp_")),
+        tool_name,
+        input,
+    ))
+}
+
+/// 收集 assistant message 中的文本、推理文本与引用
+fn append_message_content(
+    item: &Map<String, Value>,
+    text_out: &mut String,
+    thinking_out: &mut String,
+    citations: &mut Vec<Value>,
+) {
+    if item.get("role").and_then(Value::as_str) != Some("assistant") {
+        return;
+    }
+    let Some(content) = item.get("content").and_then(Value::as_array) else {
+        return;
+    };
+    for part in content {
+        let Some(part) = part.as_object() else {
+            continue;
+        };
+        match part.get("type").and_then(Value::as_str) {
+            Some("output_text") => {
+                if let Some(text) = part.get("text").and_then(Value::as_str) {
+                    citations.extend(url_citations_to_anthropic(part, text));
+                    text_out.push_str(text);
                 }
             }
-            Some("function_call") => {
-                if let Some(tool_use) = responses_function_call_to_tool_use(item) {
-                    tool_uses.push(tool_use);
+            Some("reasoning_text") => {
+                if let Some(text) = part.get("text").and_then(Value::as_str) {
+                    thinking_out.push_str(text);
                 }
             }
             _ => {}
         }
     }
+}
 
-    let mut content = Vec::new();
-    if !thinking_text.trim().is_empty() {
-        content.push(json!({ "type": "thinking", "thinking": thinking_text }));
-    }
-    if !combined_text.trim().is_empty() || tool_uses.is_empty() {
-        content.push(json!({ "type": "text", "text": combined_text }));
-    }
-    let has_tool_uses = !tool_uses.is_empty();
-    content.extend(tool_uses);
+/// 服务端工具调用 id：Anthropic 约定使用 `srvtoolu_` 前缀
+fn server_tool_use_id(item: &Map<String, Value>) -> String {
+    let id = item.get("id").and_then(Value::as_str).unwrap_or("proxy");
+    format!(
+        "srvtoolu_{}",
+        id.trim_start_matches("ws_").trim_start_matches("ci_")
+    )
+}
 
-    let finish_reason = chat_finish_reason_from_response_object(object, has_tool_uses);
-    let stop_reason = anthropic_stop_reason_from_chat_finish_reason(finish_reason);
+/// Responses `web_search_call` → Anthropic `server_tool_use` + `web_search_tool_result`
+///
+/// `OpenAI` 不提供 Anthropic 签发的 `encrypted_content`，搜索结果省略该字段，
+/// 不以空字符串冒充（历史重放给 Anthropic 上游时空密文会被拒绝）。
+#[must_use]
+pub fn web_search_call_to_anthropic_blocks(item: &Map<String, Value>) -> [ContentBlock; 2] {
+    let tool_use_id = server_tool_use_id(item);
+    let action = item.get("action").and_then(Value::as_object);
+    let query = action
+        .and_then(|action| action.get("query"))
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let results = action
+        .and_then(|action| action.get("sources"))
+        .and_then(Value::as_array)
+        .map(|sources| {
+            sources
+                .iter()
+                .filter_map(|source| {
+                    let url = source.get("url").and_then(Value::as_str)?;
+                    let title = source.get("title").and_then(Value::as_str).unwrap_or(url);
+                    Some(json!({
+                        "type": "web_search_result",
+                        "url": url,
+                        "title": title,
+                        "page_age": null
+                    }))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
 
-    let out = json!({
-        "id": id,
-        "type": "message",
-        "role": "assistant",
-        "model": model,
-        "content": content,
-        "stop_reason": stop_reason,
-        "stop_sequence": null,
-        "usage": usage.unwrap_or_else(|| json!({ "input_tokens": 0, "output_tokens": 0 }))
-    });
+    [
+        ContentBlock::other(
+            "server_tool_use",
+            json!({
+                "id": tool_use_id,
+                "name": ServerTool::WebSearch.anthropic_name(),
+                "input": { "query": query }
+            }),
+        ),
+        ContentBlock::other(
+            "web_search_tool_result",
+            json!({ "tool_use_id": tool_use_id, "content": results }),
+        ),
+    ]
+}
 
-    serde_json::to_vec(&out)
-        .map(Bytes::from)
-        .map_err(|err| format!("Failed to serialize response: {err}"))
+/// Responses `code_interpreter_call` → Anthropic `server_tool_use` + `code_execution_tool_result`
+#[must_use]
+pub fn code_interpreter_call_to_anthropic_blocks(item: &Map<String, Value>) -> [ContentBlock; 2] {
+    let tool_use_id = server_tool_use_id(item);
+    let code = item.get("code").and_then(Value::as_str).unwrap_or("");
+    let stdout = item
+        .get("outputs")
+        .and_then(Value::as_array)
+        .map(|outputs| {
+            outputs
+                .iter()
+                .filter(|output| output.get("type").and_then(Value::as_str) == Some("logs"))
+                .filter_map(|output| output.get("logs").and_then(Value::as_str))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+    let failed = item.get("status").and_then(Value::as_str) == Some("failed");
+
+    [
+        ContentBlock::other(
+            "server_tool_use",
+            json!({
+                "id": tool_use_id,
+                "name": ServerTool::CodeExecution.anthropic_name(),
+                "input": { "code": code }
+            }),
+        ),
+        ContentBlock::other(
+            "code_execution_tool_result",
+            json!({
+                "tool_use_id": tool_use_id,
+                "content": {
+                    "type": "code_execution_result",
+                    "stdout": stdout,
+                    "stderr": "",
+                    "return_code": i32::from(failed),
+                    "content": []
+                }
+            }),
+        ),
+    ]
 }
 
-fn responses_function_call_to_tool_use(item: &Map<String, Value>) -> Option<Value> {
+/// `output_text.annotations[type=url_citation]` → Anthropic `web_search_result_location` 引用
+///
+/// 同样省略没有来源的 `encrypted_index`。
+fn url_citations_to_anthropic(part: &Map<String, Value>, text: &str) -> Vec<Value> {
+    let Some(annotations) = part.get("annotations").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    annotations
+        .iter()
+        .filter(|annotation| annotation.get("type").and_then(Value::as_str) == Some("url_citation"))
+        .filter_map(|annotation| {
+            let url = annotation.get("url").and_then(Value::as_str)?;
+            let title = annotation
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or(url);
+            let index = |key: &str| {
+                annotation
+                    .get(key)
+                    .and_then(Value::as_u64)
+                    .and_then(|v| usize::try_from(v).ok())
+            };
+            // start/end_index 按字符计数，逐字符截取以避免落在 UTF-8 边界中间
+            let cited_text = match (index("start_index"), index("end_index")) {
+                (Some(start), Some(end)) if start < end => text
+                    .chars()
+                    .skip(start)
+                    .take(end - start)
+                    .collect::<String>(),
+                _ => String::new(),
+            };
+            Some(json!({
+                "type": "web_search_result_location",
+                "url": url,
+                "title": title,
+                "cited_text": cited_text
+            }))
+        })
+        .collect()
+}
+
+/// Responses `function_call` → Anthropic `tool_use`
+///
+/// 参数不是合法 JSON 时先尝试修复，修复结果需通过工具 `input_schema` 校验；
+/// 仍然失败则返回 `Err(说明文字)`，由调用方以 text 块输出，避免客户端拿着坏参数调用工具。
+pub fn responses_function_call_to_tool_use(
+    item: &Map<String, Value>,
+    context: &ConversionContext,
+) -> Option<Result<ContentBlock, String>> {
     let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
     let item_id = item.get("id").and_then(Value::as_str).unwrap_or("");
     let id = if call_id.is_empty() { item_id } else { call_id };
     if id.is_empty() {
         return None;
     }
-    let name = item.get("name").and_then(Value::as_str).unwrap_or("");
+    let name = context
+        .tool_aliases
+        .original(item.get("name").and_then(Value::as_str).unwrap_or(""));
     let arguments = item.get("arguments").and_then(Value::as_str).unwrap_or("");
-    let input = serde_json::from_str::<Value>(arguments)
-        .ok()
-        .and_then(|v| v.as_object().cloned().map(Value::Object))
-        .unwrap_or_else(|| json!({ "_raw": arguments }));
-    Some(json!({
-        "type": "tool_use",
-        "id": id,
-        "name": name,
-        "input": input
-    }))
+
+    let schema = context.tool_schemas.get(name);
+    let mut input = match json_repair::parse_tool_arguments(arguments) {
+        Ok((input, false)) => Value::Object(input),
+        Ok((input, true)) => {
+            let input = Value::Object(input);
+            if let Some(schema) = schema
+                && let Err(reason) = json_repair::validate_against_schema(&input, schema, "input")
+            {
+                return Some(Err(tool_call_failure_text(name, arguments, &reason)));
+            }
+            tracing::info!("🩹 已修复工具 {} 的调用参数", name);
+            input
+        }
+        Err(reason) => return Some(Err(tool_call_failure_text(name, arguments, &reason))),
+    };
+    if let Some(schema) = schema {
+        schema::drop_optional_nulls(&mut input, schema);
+    }
+
+    Some(Ok(ContentBlock::tool_use(id, name, input)))
 }
 
-fn map_openai_usage_to_anthropic_usage(usage: &Map<String, Value>) -> Value {
+fn append_paragraph(text: &mut String, paragraph: &str) {
+    if !text.is_empty() {
+        text.push_str("\n\n");
+    }
+    text.push_str(paragraph);
+}
+
+/// 工具调用参数无法使用时给客户端的说明文字
+fn tool_call_failure_text(name: &str, arguments: &str, reason: &str) -> String {
+    const PREVIEW_CHARS: usize = 500;
+
+    tracing::warn!("⚠️ 工具 {} 的调用参数无法修复: {}", name, reason);
+    let preview = arguments.chars().take(PREVIEW_CHARS).collect::<String>();
+    let ellipsis = if arguments.chars().count() > PREVIEW_CHARS {
+        "…"
+    } else {
+        ""
+    };
+    format!(
+        "[cc-proxy] The model tried to call tool `{name}`, but its arguments could not be used: {reason}. Raw arguments: {preview}{ellipsis}"
+    )
+}
+
+/// `OpenAI` usage → Anthropic usage
+///
+/// `OpenAI` 的 `input_tokens` 包含命中缓存的部分，而 Anthropic 的 `input_tokens`
+/// 只统计未命中缓存的部分，命中部分单独记为 `cache_read_input_tokens`。
+/// `output_tokens` 已包含推理 token，推理部分额外放在 `output_tokens_details` 中。
+#[must_use]
+pub fn map_openai_usage_to_anthropic_usage(usage: &Map<String, Value>) -> Usage {
     let input_tokens = usage
         .get("input_tokens")
         .or_else(|| usage.get("prompt_tokens"))
@@ -155,14 +433,36 @@ fn map_openai_usage_to_anthropic_usage(usage: &Map<String, Value>) -> Value {
         .or_else(|| usage.get("completion_tokens"))
         .and_then(Value::as_u64)
         .unwrap_or(0);
-    json!({
-        "input_tokens": input_tokens,
-        "output_tokens": output_tokens
-    })
+    let cached_tokens = usage
+        .get("input_tokens_details")
+        .or_else(|| usage.get("prompt_tokens_details"))
+        .and_then(|details| details.get("cached_tokens"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let reasoning_tokens = usage
+        .get("output_tokens_details")
+        .or_else(|| usage.get("completion_tokens_details"))
+        .and_then(|details| details.get("reasoning_tokens"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    let mut out = Usage {
+        cache_creation_input_tokens: Some(0),
+        cache_read_input_tokens: Some(cached_tokens),
+        ..Usage::new(input_tokens.saturating_sub(cached_tokens), output_tokens)
+    };
+    if reasoning_tokens > 0 {
+        out.extra.insert(
+            "output_tokens_details".to_string(),
+            json!({ "reasoning_tokens": reasoning_tokens }),
+        );
+    }
+    out
 }
 
 /// 从 `OpenAI` Responses 响应对象推断 `finish_reason`
-fn chat_finish_reason_from_response_object(
+#[must_use]
+pub fn chat_finish_reason_from_response_object(
     object: &Map<String, Value>,
     has_tool_uses: bool,
 ) -> &str {
@@ -174,6 +474,15 @@ fn chat_finish_reason_from_response_object(
 
     match status {
         "incomplete" => {
+            // 生成内容被内容过滤拦截（Azure `OpenAI`）
+            if object
+                .get("incomplete_details")
+                .and_then(|details| details.get("reason"))
+                .and_then(Value::as_str)
+                == Some("content_filter")
+            {
+                return "refusal";
+            }
             // 检查是否因 max_tokens 而中断
             if let Some(error) = object.get("error").and_then(Value::as_object)
                 && error.get("code").and_then(Value::as_str) == Some("max_output_tokens")
@@ -182,23 +491,287 @@ fn chat_finish_reason_from_response_object(
             }
             "max_tokens"
         }
-        "completed" => {
-            if has_tool_uses {
-                "tool_use"
-            } else {
-                "end_turn"
-            }
-        }
+        "completed" if has_tool_uses => "tool_use",
         _ => "end_turn",
     }
 }
 
 /// Chat `finish_reason` → Anthropic `stop_reason`
-fn anthropic_stop_reason_from_chat_finish_reason(reason: &str) -> &str {
+#[must_use]
+pub fn anthropic_stop_reason_from_chat_finish_reason(reason: &str) -> &str {
     match reason {
         "tool_use" => "tool_use",
         "max_tokens" => "max_tokens",
         "stop_sequence" => "stop_sequence",
+        "refusal" => "refusal",
         _ => "end_turn",
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// 测试缓存命中与推理 token 映射到 Anthropic usage
+    #[test]
+    fn test_usage_maps_cached_and_reasoning_tokens() {
+        let usage = json!({
+            "input_tokens": 1200,
+            "input_tokens_details": {"cached_tokens": 1000},
+            "output_tokens": 300,
+            "output_tokens_details": {"reasoning_tokens": 120}
+        });
+
+        let mapped = serde_json::to_value(map_openai_usage_to_anthropic_usage(
+            usage.as_object().unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(mapped["input_tokens"], 200);
+        assert_eq!(mapped["cache_read_input_tokens"], 1000);
+        assert_eq!(mapped["cache_creation_input_tokens"], 0);
+        assert_eq!(mapped["output_tokens"], 300);
+        assert_eq!(mapped["output_tokens_details"]["reasoning_tokens"], 120);
+    }
+
+    /// 测试 Chat Completions 风格的 usage 字段
+    #[test]
+    fn test_usage_maps_chat_style_fields() {
+        let usage = json!({
+            "prompt_tokens": 50,
+            "prompt_tokens_details": {"cached_tokens": 20},
+            "completion_tokens": 10
+        });
+
+        let mapped = serde_json::to_value(map_openai_usage_to_anthropic_usage(
+            usage.as_object().unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(mapped["input_tokens"], 30);
+        assert_eq!(mapped["cache_read_input_tokens"], 20);
+        assert_eq!(mapped["output_tokens"], 10);
+        assert!(mapped.get("output_tokens_details").is_none());
+    }
+
+    /// 测试 `web_search_call` 与引用注释转换为 Anthropic 服务端工具块
+    #[test]
+    fn test_web_search_call_and_citations() {
+        let response = json!({
+            "id": "resp_1",
+            "model": "gpt-test",
+            "status": "completed",
+            "output": [
+                {
+                    "type": "web_search_call",
+                    "id": "ws_abc",
+                    "status": "completed",
+                    "action": {
+                        "type": "search",
+                        "query": "rust 2024 edition",
+                        "sources": [{"url": "https://blog.rust-lang.org/", "title": "Rust Blog"}]
+                    }
+                },
+                {
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [{
+                        "type": "output_text",
+                        "text": "Rust 2024 is out.",
+                        "annotations": [{
+                            "type": "url_citation",
+                            "url": "https://blog.rust-lang.org/",
+                            "title": "Rust Blog",
+                            "start_index": 0,
+                            "end_index": 9
+                        }]
+                    }]
+                }
+            ]
+        });
+        let body = Bytes::from(serde_json::to_vec(&response).unwrap());
+        let converted: Value = serde_json::from_slice(
+            &responses_response_to_anthropic(&body, None, &ConversionContext::default()).unwrap(),
+        )
+        .unwrap();
+
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content[0]["type"], "server_tool_use");
+        assert_eq!(content[0]["id"], "srvtoolu_abc");
+        assert_eq!(content[0]["name"], "web_search");
+        assert_eq!(content[0]["input"]["query"], "rust 2024 edition");
+        assert_eq!(content[1]["type"], "web_search_tool_result");
+        assert_eq!(content[1]["tool_use_id"], "srvtoolu_abc");
+        assert_eq!(
+            content[1]["content"][0]["url"],
+            "https://blog.rust-lang.org/"
+        );
+        assert!(content[1]["content"][0].get("encrypted_content").is_none());
+        assert_eq!(content[2]["type"], "text");
+        assert_eq!(content[2]["citations"][0]["cited_text"], "Rust 2024");
+        assert!(content[2]["citations"][0].get("encrypted_index").is_none());
+        assert_eq!(converted["stop_reason"], "end_turn");
+    }
+
+    /// 测试生成内容被内容过滤拦截时返回 refusal
+    #[test]
+    fn test_content_filter_is_refusal() {
+        let response = json!({
+            "id": "resp_cf",
+            "status": "incomplete",
+            "incomplete_details": {"reason": "content_filter"},
+            "output": [{
+                "type": "message",
+                "role": "assistant",
+                "content": [{"type": "output_text", "text": "Here is"}]
+            }]
+        });
+        let body = Bytes::from(serde_json::to_vec(&response).unwrap());
+        let converted: Value = serde_json::from_slice(
+            &responses_response_to_anthropic(&body, None, &ConversionContext::default()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(converted["stop_reason"], "refusal");
+        assert_eq!(converted["content"][0]["text"], "Here is");
+    }
+
+    /// 测试结构化输出的 JSON 文本被还原为强制调用的 `tool_use`
+    #[test]
+    fn test_structured_output_text_becomes_tool_use() {
+        let response = json!({
+            "id": "resp_42",
+            "status": "completed",
+            "output": [{
+                "type": "message",
+                "role": "assistant",
+                "content": [{"type": "output_text", "text": "{\"label\":\"bug\"}"}]
+            }]
+        });
+        let body = Bytes::from(serde_json::to_vec(&response).unwrap());
+        let context = ConversionContext {
+            structured_tool: Some("record_label".to_string()),
+            ..ConversionContext::default()
+        };
+        let converted: Value = serde_json::from_slice(
+            &responses_response_to_anthropic(&body, Some("gpt-test"), &context).unwrap(),
+        )
+        .unwrap();
+
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0]["type"], "tool_use");
+        assert_eq!(content[0]["name"], "record_label");
+        assert_eq!(content[0]["input"]["label"], "bug");
+        assert_eq!(converted["stop_reason"], "tool_use");
+    }
+
+    fn convert_output(output: &Value, context: &ConversionContext) -> Value {
+        let response = json!({"id": "resp_1", "status": "completed", "output": output});
+        let body = Bytes::from(serde_json::to_vec(&response).unwrap());
+        serde_json::from_slice(&responses_response_to_anthropic(&body, None, context).unwrap())
+            .unwrap()
+    }
+
+    /// 测试结构化输出中可选字段的 `null` 按原始 schema 移除
+    #[test]
+    fn test_structured_output_drops_optional_nulls() {
+        let context = ConversionContext {
+            structured_tool: Some("record_label".to_string()),
+            tool_schemas: HashMap::from([(
+                "record_label".to_string(),
+                json!({
+                    "type": "object",
+                    "properties": {"label": {"type": "string"}, "note": {"type": "string"}},
+                    "required": ["label"]
+                }),
+            )]),
+            ..ConversionContext::default()
+        };
+        let converted = convert_output(
+            &json!([{
+                "type": "message",
+                "role": "assistant",
+                "content": [{"type": "output_text", "text": "{\"label\":\"bug\",\"note\":null}"}]
+            }]),
+            &context,
+        );
+
+        assert_eq!(converted["content"][0]["type"], "tool_use");
+        assert_eq!(converted["content"][0]["input"], json!({"label": "bug"}));
+    }
+
+    /// 测试格式不规范的工具参数被修复为合法的 `tool_use`
+    #[test]
+    fn test_malformed_arguments_are_repaired() {
+        let output = json!([{
+            "type": "function_call",
+            "call_id": "call_1",
+            "name": "Read",
+            "arguments": "{'file_path': '/tmp/a.rs',}"
+        }]);
+        let context = ConversionContext {
+            tool_schemas: [(
+                "Read".to_string(),
+                json!({"type": "object", "properties": {"file_path": {"type": "string"}}, "required": ["file_path"]}),
+            )]
+            .into(),
+            ..ConversionContext::default()
+        };
+
+        let converted = convert_output(&output, &context);
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0]["type"], "tool_use");
+        assert_eq!(content[0]["input"]["file_path"], "/tmp/a.rs");
+        assert_eq!(converted["stop_reason"], "tool_use");
+    }
+
+    /// 测试截断的工具参数不做补全，以 text 块说明
+    #[test]
+    fn test_truncated_arguments_become_text() {
+        let output = json!([{
+            "type": "function_call",
+            "call_id": "call_1",
+            "name": "Write",
+            "arguments": "{\"file_path\": \"/tmp/a.txt\", \"content\": \"line1\\nline"
+        }]);
+
+        let converted = convert_output(&output, &ConversionContext::default());
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0]["type"], "text");
+        assert!(content[0]["text"].as_str().unwrap().contains("truncated"));
+        assert_eq!(converted["stop_reason"], "end_turn");
+    }
+
+    /// 测试修复后仍不满足 schema 的调用以 text 块说明，而不是输出坏的 `tool_use`
+    #[test]
+    fn test_unrepairable_arguments_become_text() {
+        let output = json!([{
+            "type": "function_call",
+            "call_id": "call_1",
+            "name": "Write",
+            "arguments": "{'content': 'partial'}"
+        }]);
+        let context = ConversionContext {
+            tool_schemas: [(
+                "Write".to_string(),
+                json!({"type": "object", "required": ["file_path", "content"]}),
+            )]
+            .into(),
+            ..ConversionContext::default()
+        };
+
+        let converted = convert_output(&output, &context);
+        let content = converted["content"].as_array().unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0]["type"], "text");
+        let text = content[0]["text"].as_str().unwrap();
+        assert!(text.contains("`Write`"));
+        assert!(text.contains("file_path"));
+        assert_eq!(converted["stop_reason"], "end_turn");
+    }
+}
@@ -0,0 +1,594 @@
+//! JSON Schema 转换
+//!
+//! `OpenAI` 结构化输出（`strict: true`）对 schema 的要求比 Anthropic 严格：
+//! - 每个 object 都必须声明 `additionalProperties: false`
+//! - `properties` 中的每个字段都必须出现在 `required` 中，可选字段改为可空
+//! - 不接受 `$schema` 等元信息关键字
+//!
+//! 工具 `input_schema` 按上游的 [`SchemaProfile`] 清洗：展开 `$ref`、
+//! 合并 `allOf`、把 `oneOf` 与嵌套的 `anyOf` 压平，再删除上游不支持的关键字。
+
+use std::cell::Cell;
+
+use serde_json::{Map, Value, json};
+
+use crate::config::SchemaProfile;
+
+/// 展开 `$ref` 的最大深度，超过后（通常是递归定义）退化为任意对象
+const MAX_REF_DEPTH: usize = 8;
+/// 单个 schema 展开 `$ref` 的总次数上限
+///
+/// 一个定义引用自身多次（如 `anyOf` 的两个分支都是自身）时，只限深度仍会按分支数指数增长，
+/// 用完后其余 `$ref` 同样退化为任意对象。
+const MAX_REF_EXPANSIONS: usize = 256;
+
+/// `OpenAI` strict 模式支持的关键字
+const STRICT_OPENAI_KEYWORDS: &[&str] = &[
+    "type",
+    "description",
+    "title",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "anyOf",
+    "enum",
+    "const",
+    "pattern",
+    "format",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "minItems",
+    "maxItems",
+];
+
+/// `OpenAI` strict 模式支持的 string `format`
+const STRICT_OPENAI_FORMATS: &[&str] = &[
+    "date-time",
+    "time",
+    "date",
+    "duration",
+    "email",
+    "hostname",
+    "ipv4",
+    "ipv6",
+    "uuid",
+];
+
+/// Gemini function declaration 支持的关键字（`OpenAPI` 3.0 子集）
+const GEMINI_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "description",
+    "title",
+    "nullable",
+    "enum",
+    "properties",
+    "required",
+    "items",
+    "anyOf",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "propertyOrdering",
+];
+
+/// 按上游 profile 清洗工具的 `input_schema`
+#[must_use]
+pub fn sanitize_tool_schema(schema: &Value, profile: SchemaProfile) -> Value {
+    let mut schema = schema.clone();
+    match profile {
+        SchemaProfile::Permissive => {
+            if let Some(object) = schema.as_object_mut() {
+                object.remove("$schema");
+            }
+        }
+        SchemaProfile::StrictOpenAI => {
+            let defs = RefDefs::collect(&schema);
+            sanitize_node(&mut schema, profile, &defs, 0);
+            strictify_in_place(&mut schema);
+        }
+        SchemaProfile::Gemini => {
+            let defs = RefDefs::collect(&schema);
+            sanitize_node(&mut schema, profile, &defs, 0);
+        }
+    }
+    schema
+}
+
+/// 可展开的 `$ref` 定义，以及剩余的展开次数
+struct RefDefs {
+    defs: Map<String, Value>,
+    remaining: Cell<usize>,
+}
+
+impl RefDefs {
+    /// 收集根节点的 `$defs` / `definitions`，键为对应的 `$ref` 路径
+    fn collect(schema: &Value) -> Self {
+        let mut defs = Map::new();
+        for key in ["$defs", "definitions"] {
+            if let Some(items) = schema.get(key).and_then(Value::as_object) {
+                for (name, def) in items {
+                    defs.insert(format!("#/{key}/{name}"), def.clone());
+                }
+            }
+        }
+        Self {
+            defs,
+            remaining: Cell::new(MAX_REF_EXPANSIONS),
+        }
+    }
+
+    /// 取出 `$ref` 指向的定义，并消耗一次展开次数；次数用完时返回 `None`
+    fn expand(&self, reference: &Value) -> Option<&Map<String, Value>> {
+        let target = reference
+            .as_str()
+            .and_then(|reference| self.defs.get(reference))
+            .and_then(Value::as_object)?;
+        let remaining = self.remaining.get().checked_sub(1)?;
+        self.remaining.set(remaining);
+        Some(target)
+    }
+}
+
+fn sanitize_node(node: &mut Value, profile: SchemaProfile, defs: &RefDefs, ref_depth: usize) {
+    let Some(object) = node.as_object_mut() else {
+        return;
+    };
+    let ref_depth = inline_ref(object, defs, ref_depth);
+    merge_all_of(object, profile, defs, ref_depth);
+    flatten_unions(object, profile, defs, ref_depth);
+    if profile == SchemaProfile::Gemini {
+        gemini_rewrite_types(object);
+    }
+
+    let keywords = match profile {
+        SchemaProfile::Gemini => GEMINI_KEYWORDS,
+        _ => STRICT_OPENAI_KEYWORDS,
+    };
+    object.retain(|key, _| keywords.contains(&key.as_str()));
+    if let Some(format) = object.get("format").and_then(Value::as_str)
+        && !supports_format(profile, object, format)
+    {
+        object.remove("format");
+    }
+
+    if let Some(properties) = object.get_mut("properties").and_then(Value::as_object_mut) {
+        for property in properties.values_mut() {
+            sanitize_node(property, profile, defs, ref_depth);
+        }
+    }
+    if let Some(items) = object.get_mut("items") {
+        // 元组形式的 items 两种 profile 都不支持，取第一个元素的 schema
+        if let Value::Array(tuple) = items {
+            *items = tuple.first().cloned().unwrap_or_else(|| json!({}));
+        }
+        sanitize_node(items, profile, defs, ref_depth);
+    }
+    if profile == SchemaProfile::Gemini {
+        gemini_fix_object(object);
+    }
+}
+
+/// 用 `$defs` 中的定义替换 `$ref`，返回展开后的深度
+fn inline_ref(object: &mut Map<String, Value>, defs: &RefDefs, depth: usize) -> usize {
+    let mut depth = depth;
+    while let Some(reference) = object.remove("$ref") {
+        let target = Some(&reference)
+            .filter(|_| depth < MAX_REF_DEPTH)
+            .and_then(|reference| defs.expand(reference));
+        let Some(target) = target else {
+            tracing::debug!("⚠️ 无法展开 $ref: {}", reference);
+            object
+                .entry("type")
+                .or_insert_with(|| Value::String("object".to_string()));
+            break;
+        };
+        // 与 $ref 并列的字段（如 description）优先
+        for (key, value) in target {
+            object.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        depth += 1;
+    }
+    depth
+}
+
+/// 把 `allOf` 的各分支合并到当前节点
+fn merge_all_of(
+    object: &mut Map<String, Value>,
+    profile: SchemaProfile,
+    defs: &RefDefs,
+    ref_depth: usize,
+) {
+    let Some(Value::Array(branches)) = object.remove("allOf") else {
+        return;
+    };
+    for mut branch in branches {
+        sanitize_node(&mut branch, profile, defs, ref_depth);
+        let Value::Object(branch) = branch else {
+            continue;
+        };
+        for (key, value) in branch {
+            match (object.get_mut(&key), value) {
+                (Some(Value::Object(existing)), Value::Object(extra)) if key == "properties" => {
+                    for (name, property) in extra {
+                        existing.entry(name).or_insert(property);
+                    }
+                }
+                (Some(Value::Array(existing)), Value::Array(extra)) if key == "required" => {
+                    for name in extra {
+                        if !existing.contains(&name) {
+                            existing.push(name);
+                        }
+                    }
+                }
+                (Some(_), _) => {}
+                (None, value) => {
+                    object.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+/// `oneOf` 改为 `anyOf`，并把嵌套的 `anyOf` 压平为一层
+fn flatten_unions(
+    object: &mut Map<String, Value>,
+    profile: SchemaProfile,
+    defs: &RefDefs,
+    ref_depth: usize,
+) {
+    let mut branches = Vec::new();
+    for key in ["anyOf", "oneOf"] {
+        if let Some(Value::Array(items)) = object.remove(key) {
+            branches.extend(items);
+        }
+    }
+    if branches.is_empty() {
+        return;
+    }
+
+    let mut flat: Vec<Value> = Vec::with_capacity(branches.len());
+    for mut branch in branches {
+        sanitize_node(&mut branch, profile, defs, ref_depth);
+        let nested = branch
+            .as_object()
+            .filter(|branch| {
+                branch
+                    .keys()
+                    .all(|key| matches!(key.as_str(), "anyOf" | "description" | "title"))
+            })
+            .and_then(|branch| branch.get("anyOf"))
+            .and_then(Value::as_array)
+            .cloned();
+        for candidate in nested.unwrap_or_else(|| vec![branch]) {
+            if !flat.contains(&candidate) {
+                flat.push(candidate);
+            }
+        }
+    }
+
+    if profile == SchemaProfile::Gemini
+        && let Some(position) = flat
+            .iter()
+            .position(|branch| branch.get("type").and_then(Value::as_str) == Some("null"))
+    {
+        flat.remove(position);
+        object.insert("nullable".to_string(), Value::Bool(true));
+    }
+
+    if flat.len() == 1 {
+        if let Some(Value::Object(only)) = flat.pop() {
+            for (key, value) in only {
+                object.entry(key).or_insert(value);
+            }
+        }
+    } else if !flat.is_empty() {
+        object.insert("anyOf".to_string(), Value::Array(flat));
+    }
+}
+
+/// Gemini 不支持类型数组与 `const`：`["string", "null"]` 改为 `nullable`，`const` 改为单值 `enum`
+fn gemini_rewrite_types(object: &mut Map<String, Value>) {
+    if let Some(constant) = object.remove("const") {
+        object
+            .entry("enum")
+            .or_insert_with(|| Value::Array(vec![constant]));
+    }
+    let Some(Value::Array(types)) = object.get("type") else {
+        return;
+    };
+    let mut non_null = types
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|type_name| *type_name != "null")
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if non_null.len() < types.len() {
+        object.insert("nullable".to_string(), Value::Bool(true));
+    }
+    match non_null.len() {
+        0 => {
+            object.insert("type".to_string(), json!("string"));
+        }
+        1 => {
+            object.insert("type".to_string(), Value::String(non_null.remove(0)));
+        }
+        _ => {
+            object.remove("type");
+            let variants = non_null
+                .into_iter()
+                .map(|type_name| json!({ "type": type_name }))
+                .collect();
+            object.insert("anyOf".to_string(), Value::Array(variants));
+        }
+    }
+}
+
+/// 上游是否支持该 `format`，Gemini 按类型区分
+fn supports_format(profile: SchemaProfile, object: &Map<String, Value>, format: &str) -> bool {
+    if profile != SchemaProfile::Gemini {
+        return STRICT_OPENAI_FORMATS.contains(&format);
+    }
+    match object.get("type").and_then(Value::as_str) {
+        Some("string") => matches!(format, "enum" | "date-time"),
+        Some("number") => matches!(format, "float" | "double"),
+        Some("integer") => matches!(format, "int32" | "int64"),
+        _ => false,
+    }
+}
+
+/// Gemini 的 object / enum 约束：required 只能引用已声明的属性，properties 不能为空，enum 只能是字符串
+fn gemini_fix_object(object: &mut Map<String, Value>) {
+    if object
+        .get("enum")
+        .and_then(Value::as_array)
+        .is_some_and(|options| !options.iter().all(Value::is_string))
+    {
+        object.remove("enum");
+    }
+
+    let property_names = object
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| properties.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    if property_names.is_empty() {
+        object.remove("properties");
+        object.remove("required");
+    } else if let Some(required) = object.get_mut("required").and_then(Value::as_array_mut) {
+        required.retain(|name| {
+            name.as_str()
+                .is_some_and(|name| property_names.iter().any(|known| known == name))
+        });
+    }
+}
+
+/// 删除模型为可选字段填入的 `null`
+///
+/// strict 模式把可选字段改成了"必填但可空"，模型常会为不需要的参数填 `null`，
+/// 而客户端的工具校验只接受"未提供"。原 schema 本身允许 `null` 的字段保持不变。
+pub fn drop_optional_nulls(value: &mut Value, schema: &Value) {
+    match value {
+        Value::Object(object) => {
+            let required = schema.get("required").and_then(Value::as_array);
+            let properties = schema.get("properties").and_then(Value::as_object);
+            object.retain(|key, property| {
+                !property.is_null()
+                    || required.is_some_and(|required| required.iter().any(|name| name == key))
+                    || properties
+                        .and_then(|properties| properties.get(key))
+                        .is_some_and(allows_null)
+            });
+            if let Some(properties) = properties {
+                for (key, property) in object.iter_mut() {
+                    if let Some(property_schema) = properties.get(key) {
+                        drop_optional_nulls(property, property_schema);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for item in items {
+                    drop_optional_nulls(item, item_schema);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn allows_null(schema: &Value) -> bool {
+    match schema.get("type") {
+        Some(Value::String(type_name)) => type_name == "null",
+        Some(Value::Array(types)) => types.iter().any(|t| t.as_str() == Some("null")),
+        _ => schema.get("nullable").and_then(Value::as_bool) == Some(true),
+    }
+}
+
+/// 将任意 JSON Schema 改写为满足 `OpenAI` strict 模式的 schema
+#[must_use]
+pub fn to_strict_json_schema(schema: &Value) -> Value {
+    let mut schema = schema.clone();
+    strictify_in_place(&mut schema);
+    schema
+}
+
+fn strictify_in_place(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    object.remove("$schema");
+
+    if object.get("properties").is_some_and(Value::is_object) {
+        let required = object
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        // 原先可选的字段改为可空，再统一放进 required
+        let properties = object
+            .remove("properties")
+            .and_then(|properties| match properties {
+                Value::Object(properties) => Some(properties),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut all_keys = Vec::with_capacity(properties.len());
+        let mut strict_properties = Map::with_capacity(properties.len());
+        for (key, mut property) in properties {
+            strictify_in_place(&mut property);
+            if !required.contains(&key) {
+                make_nullable(&mut property);
+            }
+            all_keys.push(Value::String(key.clone()));
+            strict_properties.insert(key, property);
+        }
+        object.insert("properties".to_string(), Value::Object(strict_properties));
+        object.insert("required".to_string(), Value::Array(all_keys));
+        object.insert("additionalProperties".to_string(), Value::Bool(false));
+    } else if object.get("type").and_then(Value::as_str) == Some("object") {
+        object.insert("properties".to_string(), json!({}));
+        object.insert("required".to_string(), json!([]));
+        object.insert("additionalProperties".to_string(), Value::Bool(false));
+    }
+
+    if let Some(items) = object.get_mut("items") {
+        strictify_in_place(items);
+    }
+    for key in ["anyOf", "oneOf", "allOf"] {
+        if let Some(variants) = object.get_mut(key).and_then(Value::as_array_mut) {
+            variants.iter_mut().for_each(strictify_in_place);
+        }
+    }
+    for key in ["$defs", "definitions"] {
+        if let Some(defs) = object.get_mut(key).and_then(Value::as_object_mut) {
+            defs.values_mut().for_each(strictify_in_place);
+        }
+    }
+}
+
+/// 给 schema 的 type 追加 `null`
+fn make_nullable(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    // 已是 anyOf 的直接追加 null 分支，避免再嵌套一层
+    if !object.contains_key("type")
+        && let Some(variants) = object.get_mut("anyOf").and_then(Value::as_array_mut)
+    {
+        if !variants
+            .iter()
+            .any(|v| v.get("type").and_then(Value::as_str) == Some("null"))
+        {
+            variants.push(json!({ "type": "null" }));
+        }
+        return;
+    }
+    match object.get_mut("type") {
+        Some(Value::String(type_name)) if type_name != "null" => {
+            let type_name = std::mem::take(type_name);
+            object.insert("type".to_string(), json!([type_name, "null"]));
+        }
+        Some(Value::Array(types)) => {
+            if !types.iter().any(|t| t.as_str() == Some("null")) {
+                types.push(json!("null"));
+            }
+        }
+        Some(_) => {}
+        None => {
+            // 没有 type 的（如 $ref）用 anyOf 包一层 null
+            let inner = Value::Object(std::mem::take(object));
+            object.insert("anyOf".to_string(), json!([inner, { "type": "null" }]));
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 可选字段的 null 被删除，必填与本身可空的字段保留
+    #[test]
+    fn test_drop_optional_nulls() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "limit": {"type": "integer"},
+                "cursor": {"type": ["string", "null"]},
+                "items": {"type": "array", "items": {"type": "object", "properties": {"x": {"type": "string"}}}}
+            },
+            "required": ["path"]
+        });
+        let mut input = json!({
+            "path": null,
+            "limit": null,
+            "cursor": null,
+            "items": [{"x": null}]
+        });
+        drop_optional_nulls(&mut input, &schema);
+        assert_eq!(input, json!({"path": null, "cursor": null, "items": [{}]}));
+    }
+
+    /// 分支递归的 `$ref` 按总次数限制展开，输出大小有上限
+    #[test]
+    fn test_branching_recursive_ref_is_bounded() {
+        fn count_values(node: &Value) -> usize {
+            1 + match node {
+                Value::Object(object) => object.values().map(count_values).sum(),
+                Value::Array(items) => items.iter().map(count_values).sum(),
+                _ => 0,
+            }
+        }
+        let tree = json!({
+            "type": "object",
+            "properties": {
+                "value": {"type": "string"},
+                "left": {"anyOf": [{"$ref": "#/$defs/Tree"}, {"type": "null"}]},
+                "right": {"anyOf": [{"$ref": "#/$defs/Tree"}, {"type": "null"}]},
+                "child": {"anyOf": [{"$ref": "#/$defs/Tree"}, {"$ref": "#/definitions/Tree"}]}
+            },
+            "required": ["value"]
+        });
+        let schema = json!({
+            "type": "object",
+            "$defs": {"Tree": tree},
+            "definitions": {"Tree": tree},
+            "properties": {"root": {"$ref": "#/$defs/Tree"}},
+            "required": ["root"]
+        });
+
+        for profile in [SchemaProfile::StrictOpenAI, SchemaProfile::Gemini] {
+            let sanitized = sanitize_tool_schema(&schema, profile);
+            assert!(!sanitized.to_string().contains("$ref"));
+            assert_eq!(
+                sanitized["properties"]["root"]["properties"]["value"]["type"],
+                "string"
+            );
+            let nodes = count_values(&sanitized);
+            assert!(
+                nodes < MAX_REF_EXPANSIONS * 64,
+                "{profile:?} expanded to {nodes} nodes"
+            );
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! 流式响应转换
+//!
+//! `OpenAI` Responses SSE 事件 → Anthropic Messages SSE 事件
+//!
+//! 主要转换：
+//! - `response.created` → `message_start`
+//! - `response.output_text.delta` → text 块的 `text_delta`
+//! - `response.reasoning_*.delta` → thinking 块的 `thinking_delta`
+//! - `response.output_item.done`（`function_call`）→ 完整的 `tool_use` 块
+//! - `response.completed` / `response.incomplete` → `message_delta` + `message_stop`
+//! - `response.failed` / `error`，以及上游在完成前断开 → `error`
+//!
+//! 函数调用参数在 `output_item.done` 时才整体下发，以便先还原工具别名、
+//! 修复并校验参数，避免客户端收到半截或无法使用的参数。
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use super::{ConversionContext, response};
+use crate::gateway::sse::{AnthropicSseWriter, SseDecoder, SseEvent, StreamConverter};
+
+/// Responses SSE → Anthropic SSE 的有状态转换器
+pub struct ResponsesStreamConverter {
+    decoder: SseDecoder,
+    writer: AnthropicSseWriter,
+    context: ConversionContext,
+    response_id: String,
+    /// `output_index` → 累积的函数调用参数（`output_item.done` 未携带参数时使用）
+    pending_arguments: HashMap<u64, String>,
+    /// 结构化输出模式下缓存的 JSON 文本
+    structured_text: String,
+    has_tool_uses: bool,
+}
+
+impl ResponsesStreamConverter {
+    #[must_use]
+    pub fn new(model_hint: Option<&str>, context: ConversionContext) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            writer: AnthropicSseWriter::new("msg_proxy", model_hint.unwrap_or("unknown")),
+            context,
+            response_id: "msg_proxy".to_string(),
+            pending_arguments: HashMap::new(),
+            structured_text: String::new(),
+            has_tool_uses: false,
+        }
+    }
+
+    fn handle_event(&mut self, event: &SseEvent) {
+        let Some(data) = event.json() else {
+            return;
+        };
+        let event_type = data
+            .get("type")
+            .and_then(Value::as_str)
+            .or(event.event.as_deref())
+            .unwrap_or("");
+
+        match event_type {
+            "response.created" | "response.in_progress" => {
+                if let Some(response) = data.get("response") {
+                    self.start(response);
+                }
+            }
+            "response.output_text.delta" => {
+                let delta = data.get("delta").and_then(Value::as_str).unwrap_or("");
+                if self.context.structured_tool.is_some() {
+                    self.structured_text.push_str(delta);
+                } else {
+                    self.writer.text_delta(delta);
+                }
+            }
+            "response.reasoning_text.delta" | "response.reasoning_summary_text.delta" => {
+                let delta = data.get("delta").and_then(Value::as_str).unwrap_or("");
+                self.writer.thinking_delta(delta);
+            }
+            "response.function_call_arguments.delta" => {
+                if let Some(index) = data.get("output_index").and_then(Value::as_u64) {
+                    let delta = data.get("delta").and_then(Value::as_str).unwrap_or("");
+                    self.pending_arguments
+                        .entry(index)
+                        .or_default()
+                        .push_str(delta);
+                }
+            }
+            "response.output_item.done" => {
+                let index = data.get("output_index").and_then(Value::as_u64);
+                if let Some(item) = data.get("item").and_then(Value::as_object) {
+                    self.output_item_done(item, index);
+                }
+            }
+            "response.completed" | "response.incomplete" => {
+                if let Some(response) = data.get("response").and_then(Value::as_object) {
+                    self.complete(response);
+                }
+            }
+            "response.failed" => {
+                let message = data
+                    .pointer("/response/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Upstream response failed.");
+                self.writer.error("api_error", message);
+            }
+            "error" => {
+                let message = data
+                    .get("message")
+                    .or_else(|| data.pointer("/error/message"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("Upstream stream error.");
+                self.writer.error("api_error", message);
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, response: &Value) {
+        let id = response.get("id").and_then(Value::as_str);
+        if let Some(id) = id {
+            id.clone_into(&mut self.response_id);
+        }
+        self.writer
+            .set_message_meta(id, response.get("model").and_then(Value::as_str));
+        self.writer.start(None);
+    }
+
+    fn output_item_done(&mut self, item: &Map<String, Value>, index: Option<u64>) {
+        match item.get("type").and_then(Value::as_str) {
+            Some("function_call") => {
+                let pending = index.and_then(|index| self.pending_arguments.remove(&index));
+                let has_arguments = item
+                    .get("arguments")
+                    .and_then(Value::as_str)
+                    .is_some_and(|arguments| !arguments.is_empty());
+                let mut item = item.clone();
+                if !has_arguments && let Some(arguments) = pending {
+                    item.insert("arguments".to_string(), Value::String(arguments));
+                }
+                match response::responses_function_call_to_tool_use(&item, &self.context) {
+                    Some(Ok(tool_use)) => {
+                        self.has_tool_uses = true;
+                        self.writer.full_block(tool_use);
+                    }
+                    Some(Err(explanation)) => {
+                        self.writer.close_block();
+                        self.writer.text_delta(&explanation);
+                        self.writer.close_block();
+                    }
+                    None => {}
+                }
+            }
+            Some("web_search_call") => {
+                for block in response::web_search_call_to_anthropic_blocks(item) {
+                    self.writer.full_block(block);
+                }
+            }
+            Some("code_interpreter_call") => {
+                for block in response::code_interpreter_call_to_anthropic_blocks(item) {
+                    self.writer.full_block(block);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn complete(&mut self, response: &Map<String, Value>) {
+        self.flush_structured_text();
+        let usage = response
+            .get("usage")
+            .and_then(Value::as_object)
+            .map(|usage| response::map_openai_usage_to_anthropic_usage(usage).into());
+        let finish_reason =
+            response::chat_finish_reason_from_response_object(response, self.has_tool_uses);
+        let stop_reason = response::anthropic_stop_reason_from_chat_finish_reason(finish_reason);
+        self.writer.finish(stop_reason, usage);
+    }
+
+    /// 强制工具调用被改写为结构化输出时，把缓存的 JSON 文本还原为 `tool_use`
+    fn flush_structured_text(&mut self) {
+        if self.context.structured_tool.is_none() {
+            return;
+        }
+        let text = std::mem::take(&mut self.structured_text);
+        if !self.has_tool_uses
+            && let Some(tool_use) =
+                response::structured_text_to_tool_use(&text, &self.response_id, &self.context)
+        {
+            self.has_tool_uses = true;
+            self.writer.full_block(tool_use);
+        } else {
+            self.writer.text_delta(&text);
+        }
+    }
+}
+
+impl StreamConverter for ResponsesStreamConverter {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event);
+        }
+        self.writer.take()
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event);
+        }
+        // 上游没有发送 response.completed 就断开时，响应是残缺的，
+        // 不能以 end_turn 收尾让客户端当成正常结束
+        if !self.writer.is_finished() {
+            self.writer.error(
+                "api_error",
+                "Upstream stream ended before the response was completed.",
+            );
+        }
+        self.writer.take()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sse(events: &[Value]) -> String {
+        events
+            .iter()
+            .map(|event| {
+                String::from_utf8(
+                    crate::gateway::sse::encode_event(event["type"].as_str().unwrap(), event)
+                        .to_vec(),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn run(converter: &mut ResponsesStreamConverter, input: &str) -> Vec<Value> {
+        // 按 7 字节切片输入，模拟任意的网络分包
+        let mut out = input
+            .as_bytes()
+            .chunks(7)
+            .flat_map(|chunk| converter.convert(chunk))
+            .collect::<Vec<_>>();
+        out.extend(converter.finish());
+        let mut decoder = SseDecoder::default();
+        out.iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .filter_map(|event| event.json())
+            .collect()
+    }
+
+    /// 文本、函数调用（含别名还原）与 usage 转换为 Anthropic 流式事件
+    #[test]
+    fn test_text_and_function_call_stream() {
+        let long_name = format!("mcp__{}__lookup", "server.name".repeat(6));
+        let request = serde_json::from_value(json!({
+            "model": "m",
+            "tools": [{"name": long_name, "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let (converted, context) = super::super::anthropic_request_to_responses(
+            &request,
+            &crate::config::UpstreamConfig::default(),
+        )
+        .unwrap();
+        let converted: Value = serde_json::from_slice(&converted).unwrap();
+        let alias = converted["tools"][0]["name"].as_str().unwrap().to_string();
+        assert_ne!(alias, long_name);
+
+        let input = sse(&[
+            json!({"type": "response.created", "response": {"id": "resp_1", "model": "gpt-test"}}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "你好"}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "，世界"}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 1, "delta": "{\"q\":"}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 1, "delta": "\"rust\"}"}),
+            json!({"type": "response.output_item.done", "output_index": 1, "item": {
+                "type": "function_call", "call_id": "call_1", "name": alias, "arguments": ""
+            }}),
+            json!({"type": "response.completed", "response": {
+                "id": "resp_1", "status": "completed",
+                "usage": {"input_tokens": 10, "output_tokens": 5}
+            }}),
+        ]);
+
+        let mut converter = ResponsesStreamConverter::new(Some("hint"), context);
+        let events = run(&mut converter, &input);
+        let types = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0]["message"]["id"], "resp_1");
+        assert_eq!(events[0]["message"]["model"], "gpt-test");
+        assert_eq!(events[2]["delta"]["text"], "你好");
+        assert_eq!(events[5]["content_block"]["name"], long_name.as_str());
+        assert_eq!(events[6]["delta"]["partial_json"], "{\"q\":\"rust\"}");
+        assert_eq!(events[8]["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[8]["usage"]["output_tokens"], 5);
+    }
+
+    /// 流式结构化输出还原为 `tool_use` 时移除可选字段的 `null`
+    #[test]
+    fn test_structured_output_stream_drops_optional_nulls() {
+        let context = ConversionContext {
+            structured_tool: Some("record_label".to_string()),
+            tool_schemas: HashMap::from([(
+                "record_label".to_string(),
+                json!({
+                    "type": "object",
+                    "properties": {"label": {"type": "string"}, "note": {"type": "string"}},
+                    "required": ["label"]
+                }),
+            )]),
+            ..ConversionContext::default()
+        };
+        let input = sse(&[
+            json!({"type": "response.created", "response": {"id": "resp_1"}}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "{\"label\":\"bug\","}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "delta": "\"note\":null}"}),
+            json!({"type": "response.completed", "response": {"id": "resp_1", "status": "completed"}}),
+        ]);
+
+        let mut converter = ResponsesStreamConverter::new(None, context);
+        let events = run(&mut converter, &input);
+        let tool_use = events
+            .iter()
+            .find(|event| event["type"] == "content_block_start")
+            .unwrap();
+        assert_eq!(tool_use["content_block"]["type"], "tool_use");
+        let input_json = events
+            .iter()
+            .find_map(|event| event["delta"]["partial_json"].as_str())
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(input_json).unwrap(),
+            json!({"label": "bug"})
+        );
+    }
+
+    /// 上游中途断开时以 `error` 事件结束，而不是 `end_turn`
+    #[test]
+    fn test_truncated_stream_ends_with_error() {
+        let input = sse(&[json!({"type": "response.output_text.delta", "delta": "partial"})]);
+        let mut converter = ResponsesStreamConverter::new(None, ConversionContext::default());
+        let events = run(&mut converter, &input);
+        assert_eq!(events.first().unwrap()["type"], "message_start");
+        assert!(events.iter().all(|event| event["type"] != "message_stop"));
+        let last = events.last().unwrap();
+        assert_eq!(last["type"], "error");
+        assert_eq!(last["error"]["type"], "api_error");
+    }
+}
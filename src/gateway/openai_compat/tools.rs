@@ -3,81 +3,429 @@
 //! Anthropic Messages API → `OpenAI` Responses API 的工具格式转换：
 //! - Anthropic: { name, description, `input_schema` }
 //! - `OpenAI`: { type: "function", function: { name, description, parameters } }
+//!
+//! Anthropic 服务端工具 → Responses 内置工具：
+//! - `web_search_*` → `web_search`
+//! - `code_execution_*` → `code_interpreter`
+//! - `web_fetch_*` 没有对应的内置工具，直接移除
+//!
+//! 客户端工具的 `input_schema` 按上游的 `schema_profile` 清洗后作为 `parameters`。
 
 use serde_json::{Map, Value, json};
 
+use super::{alias::ToolAliases, schema};
+use crate::{
+    config::{SchemaProfile, UpstreamConfig},
+    gateway::anthropic::{Tool, ToolChoice, ToolChoiceKind},
+};
+
+/// Anthropic 服务端工具（由服务商执行，而不是由客户端执行）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerTool {
+    WebSearch,
+    WebFetch,
+    CodeExecution,
+}
+
+impl ServerTool {
+    /// 识别带版本后缀的服务端工具类型，如 `web_search_20250305`
+    #[must_use]
+    pub fn from_tool(tool: &Tool) -> Option<Self> {
+        let tool_type = tool.kind.as_deref()?;
+        if tool_type.starts_with("web_search_") {
+            Some(Self::WebSearch)
+        } else if tool_type.starts_with("web_fetch_") {
+            Some(Self::WebFetch)
+        } else if tool_type.starts_with("code_execution_") {
+            Some(Self::CodeExecution)
+        } else {
+            None
+        }
+    }
+
+    /// Anthropic 响应中 `server_tool_use.name` 使用的名称
+    #[must_use]
+    pub const fn anthropic_name(self) -> &'static str {
+        match self {
+            Self::WebSearch => "web_search",
+            Self::WebFetch => "web_fetch",
+            Self::CodeExecution => "code_execution",
+        }
+    }
+}
+
 /// Anthropic tools → `OpenAI` Responses tools
-pub fn map_anthropic_tools_to_responses(value: &Value) -> Value {
-    let Some(tools) = value.as_array() else {
-        return Value::Array(Vec::new());
-    };
+///
+/// upstream 的 `server_tools` 为 false 时服务端工具被移除，而不是转换成内置工具。
+#[must_use]
+pub fn map_anthropic_tools_to_responses(
+    tools: &[Tool],
+    upstream: &UpstreamConfig,
+    aliases: &ToolAliases,
+) -> Value {
     let mapped = tools
         .iter()
-        .filter_map(map_anthropic_tool)
+        .filter_map(|tool| match ServerTool::from_tool(tool) {
+            Some(kind) if upstream.server_tools => map_server_tool(kind, tool),
+            Some(_) => None,
+            None => map_anthropic_tool(tool, aliases, upstream.schema_profile),
+        })
         .collect::<Vec<_>>();
     Value::Array(mapped)
 }
 
-fn map_anthropic_tool(value: &Value) -> Option<Value> {
-    let tool = value.as_object()?;
-    let name = tool.get("name").and_then(Value::as_str)?;
+/// Anthropic tools → `OpenAI` Chat Completions tools
+///
+/// Chat Completions 没有内置工具，服务端工具总是被移除；
+/// 函数定义嵌套在 `function` 字段中。
+#[must_use]
+pub fn map_anthropic_tools_to_chat(
+    tools: &[Tool],
+    upstream: &UpstreamConfig,
+    aliases: &ToolAliases,
+) -> Value {
+    let mapped = tools
+        .iter()
+        .filter(|tool| ServerTool::from_tool(tool).is_none())
+        .filter_map(|tool| map_anthropic_tool(tool, aliases, upstream.schema_profile))
+        .map(|mut function| {
+            if let Some(function) = function.as_object_mut() {
+                function.remove("type");
+            }
+            json!({ "type": "function", "function": function })
+        })
+        .collect::<Vec<_>>();
+    Value::Array(mapped)
+}
+
+/// Anthropic 服务端工具 → Responses 内置工具
+fn map_server_tool(kind: ServerTool, tool: &Tool) -> Option<Value> {
+    match kind {
+        ServerTool::WebSearch => {
+            let mut out = Map::new();
+            out.insert("type".to_string(), json!("web_search"));
+            if let Some(domains) = tool.extra.get("allowed_domains").and_then(Value::as_array)
+                && !domains.is_empty()
+            {
+                out.insert("filters".to_string(), json!({ "allowed_domains": domains }));
+            }
+            if let Some(location) = tool.extra.get("user_location").and_then(Value::as_object) {
+                let mut mapped = location.clone();
+                mapped.insert("type".to_string(), json!("approximate"));
+                out.insert("user_location".to_string(), Value::Object(mapped));
+            }
+            Some(Value::Object(out))
+        }
+        ServerTool::CodeExecution => Some(json!({
+            "type": "code_interpreter",
+            "container": { "type": "auto" }
+        })),
+        ServerTool::WebFetch => None,
+    }
+}
+
+fn map_anthropic_tool(tool: &Tool, aliases: &ToolAliases, profile: SchemaProfile) -> Option<Value> {
+    if tool.name.is_empty() {
+        return None;
+    }
     let mut out = Map::new();
     out.insert("type".to_string(), json!("function"));
-    out.insert("name".to_string(), Value::String(name.to_string()));
-    if let Some(description) = tool.get("description") {
-        out.insert("description".to_string(), description.clone());
+    out.insert(
+        "name".to_string(),
+        Value::String(aliases.alias(&tool.name).into_owned()),
+    );
+    if let Some(description) = &tool.description {
+        out.insert("description".to_string(), json!(description));
+    }
+    if let Some(input_schema) = &tool.input_schema {
+        out.insert(
+            "parameters".to_string(),
+            schema::sanitize_tool_schema(input_schema, profile),
+        );
     }
-    if let Some(input_schema) = tool.get("input_schema") {
-        out.insert("parameters".to_string(), input_schema.clone());
+    if profile == SchemaProfile::StrictOpenAI {
+        out.insert("strict".to_string(), Value::Bool(true));
     }
     Some(Value::Object(out))
 }
 
 /// Anthropic `tool_choice` → `OpenAI` `tool_choice`
+#[must_use]
 pub fn map_anthropic_tool_choice_to_responses(
-    tool_choice: Option<&Value>,
+    tool_choice: Option<&ToolChoice>,
+    aliases: &ToolAliases,
 ) -> (Option<Value>, Option<bool>) {
-    let Some(tool_choice) = tool_choice.and_then(Value::as_object) else {
+    let Some(tool_choice) = tool_choice else {
         return (None, None);
     };
 
-    let choice_type = tool_choice
-        .get("type")
-        .and_then(Value::as_str)
-        .unwrap_or("");
-    let mapped_choice = match choice_type {
-        "auto" => Some(json!("auto")),
-        "any" => Some(json!("required")),
-        "none" => Some(json!("none")),
-        "tool" => {
-            let name = tool_choice
-                .get("name")
-                .and_then(Value::as_str)
-                .unwrap_or("");
-            if name.is_empty() {
-                None
-            } else {
-                Some(json!({ "type": "function", "name": name }))
-            }
-        }
-        _ => None,
+    let mapped_choice = match &tool_choice.kind {
+        ToolChoiceKind::Auto => Some(json!("auto")),
+        ToolChoiceKind::Any => Some(json!("required")),
+        ToolChoiceKind::None => Some(json!("none")),
+        ToolChoiceKind::Tool => tool_choice
+            .name
+            .as_deref()
+            .filter(|name| !name.is_empty())
+            .map(|name| json!({ "type": "function", "name": aliases.alias(name) })),
+        ToolChoiceKind::Other(_) => None,
     };
 
     let parallel_tool_calls = tool_choice
-        .get("disable_parallel_tool_use")
-        .and_then(Value::as_bool)
+        .disable_parallel_tool_use
         .map(|disable| !disable);
 
     (mapped_choice, parallel_tool_calls)
 }
 
+/// Anthropic `tool_choice` → `OpenAI` Chat Completions `tool_choice`
+///
+/// 与 Responses 的区别只在指定工具的写法：`{ type: "function", function: { name } }`。
+#[must_use]
+pub fn map_anthropic_tool_choice_to_chat(
+    tool_choice: Option<&ToolChoice>,
+    aliases: &ToolAliases,
+) -> (Option<Value>, Option<bool>) {
+    let (choice, parallel_tool_calls) =
+        map_anthropic_tool_choice_to_responses(tool_choice, aliases);
+    let choice = choice.map(|choice| {
+        choice.get("name").map_or_else(
+            || choice.clone(),
+            |name| json!({ "type": "function", "function": { "name": name } }),
+        )
+    });
+    (choice, parallel_tool_calls)
+}
+
 /// Anthropic `stop_sequences` → `OpenAI` stop
-pub fn map_anthropic_stop_sequences_to_openai_stop(stop: Option<&Value>) -> Option<Value> {
-    let stop = stop?;
-    let items = stop.as_array()?;
-    match items.len() {
-        0 => None,
-        1 => Some(items[0].clone()),
-        _ => Some(Value::Array(items.clone())),
+#[must_use]
+pub fn map_anthropic_stop_sequences_to_openai_stop(stop: Option<&[String]>) -> Option<Value> {
+    match stop? {
+        [] => None,
+        [single] => Some(json!(single)),
+        items => Some(json!(items)),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Claude Code 实际发送的工具定义（节选）
+    fn claude_code_tools() -> Value {
+        json!([
+            {
+                "name": "Bash",
+                "description": "Executes a given bash command",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {
+                        "command": {"type": "string", "description": "The command to execute"},
+                        "timeout": {"type": "number", "description": "Optional timeout in milliseconds (max 600000)"},
+                        "description": {"type": "string"},
+                        "run_in_background": {"type": "boolean"}
+                    },
+                    "required": ["command"],
+                    "additionalProperties": false,
+                    "$schema": "http://json-schema.org/draft-07/schema#"
+                }
+            },
+            {
+                "name": "WebFetch",
+                "description": "Fetches content from a specified URL",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {
+                        "url": {"type": "string", "format": "uri", "description": "The URL to fetch content from"},
+                        "prompt": {"type": "string", "description": "The prompt to run on the fetched content"}
+                    },
+                    "required": ["url", "prompt"],
+                    "additionalProperties": false,
+                    "$schema": "http://json-schema.org/draft-07/schema#"
+                }
+            },
+            {
+                "name": "TodoWrite",
+                "description": "Update the todo list for the current session",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {
+                        "todos": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "content": {"type": "string", "minLength": 1},
+                                    "status": {"type": "string", "enum": ["pending", "in_progress", "completed"]},
+                                    "activeForm": {"type": "string", "minLength": 1}
+                                },
+                                "required": ["content", "status", "activeForm"],
+                                "additionalProperties": false
+                            },
+                            "description": "The updated todo list"
+                        }
+                    },
+                    "required": ["todos"],
+                    "additionalProperties": false,
+                    "$schema": "http://json-schema.org/draft-07/schema#"
+                }
+            },
+            {
+                "name": "mcp__db__query",
+                "description": "MCP tool generated from a pydantic model",
+                "input_schema": {
+                    "type": "object",
+                    "$defs": {
+                        "Filter": {
+                            "type": "object",
+                            "properties": {
+                                "column": {"type": "string"},
+                                "value": {"oneOf": [{"type": "string"}, {"type": "integer"}]}
+                            },
+                            "required": ["column", "value"]
+                        }
+                    },
+                    "properties": {
+                        "table": {"type": "string", "default": "users"},
+                        "filter": {"anyOf": [{"$ref": "#/$defs/Filter"}, {"type": "null"}]},
+                        "limit": {"anyOf": [{"anyOf": [{"type": "integer"}, {"type": "null"}]}]},
+                        "mode": {"const": "read"}
+                    },
+                    "required": ["table"]
+                }
+            }
+        ])
+    }
+
+    fn upstream(profile: SchemaProfile) -> UpstreamConfig {
+        UpstreamConfig {
+            schema_profile: profile,
+            ..UpstreamConfig::default()
+        }
+    }
+
+    fn map_tools(profile: SchemaProfile) -> Vec<Value> {
+        let tools: Vec<Tool> = serde_json::from_value(claude_code_tools()).unwrap();
+        let mapped =
+            map_anthropic_tools_to_responses(&tools, &upstream(profile), &ToolAliases::default());
+        mapped.as_array().unwrap().clone()
+    }
+
+    /// 遍历 schema 中所有节点的关键字
+    fn all_keys(schema: &Value, out: &mut Vec<String>) {
+        match schema {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    out.push(key.clone());
+                    all_keys(value, out);
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| all_keys(item, out)),
+            _ => {}
+        }
+    }
+
+    /// permissive 只移除 `$schema`
+    #[test]
+    fn test_permissive_profile_keeps_schema() {
+        let tools = map_tools(SchemaProfile::Permissive);
+        let bash = &tools[0]["parameters"];
+        assert!(bash.get("$schema").is_none());
+        assert_eq!(bash["additionalProperties"], false);
+        assert_eq!(tools[1]["parameters"]["properties"]["url"]["format"], "uri");
+        assert!(tools[3]["parameters"].get("$defs").is_some());
+        assert!(tools[0].get("strict").is_none());
+    }
+
+    /// gemini 删除不支持的关键字、展开 `$ref` 并把可空联合改为 `nullable`
+    #[test]
+    fn test_gemini_profile() {
+        let tools = map_tools(SchemaProfile::Gemini);
+        for tool in &tools {
+            let mut keys = Vec::new();
+            all_keys(&tool["parameters"], &mut keys);
+            for forbidden in [
+                "$schema",
+                "additionalProperties",
+                "oneOf",
+                "$ref",
+                "$defs",
+                "default",
+                "const",
+            ] {
+                assert!(
+                    !keys.iter().any(|key| key == forbidden),
+                    "{} still contains {forbidden}",
+                    tool["name"]
+                );
+            }
+        }
+
+        assert!(
+            tools[1]["parameters"]["properties"]["url"]
+                .get("format")
+                .is_none()
+        );
+        assert_eq!(
+            tools[2]["parameters"]["properties"]["todos"]["items"]["properties"]["content"]["minLength"],
+            1
+        );
+
+        let query = &tools[3]["parameters"]["properties"];
+        assert_eq!(query["filter"]["type"], "object");
+        assert_eq!(query["filter"]["nullable"], true);
+        assert_eq!(
+            query["filter"]["properties"]["value"]["anyOf"],
+            json!([{"type": "string"}, {"type": "integer"}])
+        );
+        assert_eq!(query["limit"], json!({"type": "integer", "nullable": true}));
+        assert_eq!(query["mode"]["enum"], json!(["read"]));
+    }
+
+    /// strict-openai 输出满足 strict 函数调用要求的 schema
+    #[test]
+    fn test_strict_openai_profile() {
+        let tools = map_tools(SchemaProfile::StrictOpenAI);
+        assert!(tools.iter().all(|tool| tool["strict"] == true));
+
+        let bash = &tools[0]["parameters"];
+        assert!(bash.get("$schema").is_none());
+        assert_eq!(bash["additionalProperties"], false);
+        assert_eq!(
+            bash["required"],
+            json!(["command", "description", "run_in_background", "timeout"])
+        );
+        assert_eq!(
+            bash["properties"]["timeout"]["type"],
+            json!(["number", "null"])
+        );
+
+        assert!(
+            tools[1]["parameters"]["properties"]["url"]
+                .get("format")
+                .is_none()
+        );
+        let todo_item = &tools[2]["parameters"]["properties"]["todos"]["items"];
+        assert_eq!(todo_item["additionalProperties"], false);
+        assert!(
+            todo_item["properties"]["content"]
+                .get("minLength")
+                .is_none()
+        );
+
+        let query = &tools[3]["parameters"];
+        assert!(query.get("$defs").is_none());
+        let filter = &query["properties"]["filter"]["anyOf"];
+        assert_eq!(filter[0]["type"], "object");
+        assert_eq!(filter[0]["additionalProperties"], false);
+        assert_eq!(filter[1]["type"], "null");
+        assert_eq!(
+            filter[0]["properties"]["value"]["anyOf"],
+            json!([{"type": "string"}, {"type": "integer"}])
+        );
+        assert!(query["properties"]["table"].get("default").is_none());
     }
 }
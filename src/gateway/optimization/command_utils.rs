@@ -1,5 +1,6 @@
 const EMPTY_FILEPATHS_XML: &str = "<filepaths>\n</filepaths>";
 
+#[must_use]
 pub fn extract_command_prefix(command: &str) -> String {
     if command.contains('`') || command.contains("$(") {
         return String::from("command_injection_detected");
@@ -44,6 +45,7 @@ pub fn extract_command_prefix(command: &str) -> String {
     }
 }
 
+#[must_use]
 pub fn extract_filepaths_from_command(command: &str, _output: &str) -> String {
     let parts = split_shell_words(command);
     if parts.is_empty() {
@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use serde_json::Value;
+use crate::gateway::anthropic::{Content, ContentBlock, Message, MessagesRequest, Role};
 
 const HISTORY_ANALYSIS_PARSE: &str = "You are an expert at analyzing git history.";
 const TITLE_GENERATION_PHRASE: &str = "Analyze if this message indicates a new conversation topic.";
@@ -8,33 +8,27 @@ const SUGGESTION_MODE_MARKER: &str = "[SUGGESTION MODE:";
 const COMMAND_MARKER: &str = "Command:";
 const OUTPUT_MARKER: &str = "Output:";
 
+#[must_use]
 pub fn is_count_tokens_url(url: &str) -> bool {
     url.to_ascii_lowercase().contains("count_tokens")
 }
 
-pub fn is_quota_check_request(request: &Value) -> bool {
-    if request.get("max_tokens").and_then(Value::as_i64) != Some(1) {
+#[must_use]
+pub fn is_quota_check_request(request: &MessagesRequest) -> bool {
+    if request.max_tokens != Some(1) {
         return false;
     }
 
-    let Some(messages) = get_messages(request) else {
+    let Some(message) = single_user_message(request) else {
         return false;
     };
-    if messages.len() != 1 || message_role(&messages[0]) != Some("user") {
-        return false;
-    }
 
-    let text = extract_message_text(&messages[0]);
-    text.to_lowercase().contains("count")
+    message.content.text().to_lowercase().contains("count")
 }
 
-pub fn detect_prefix_command(request: &Value) -> Option<String> {
-    let messages = get_messages(request)?;
-    if messages.len() != 1 || message_role(&messages[0]) != Some("user") {
-        return None;
-    }
-
-    let content = extract_message_text(&messages[0]);
+#[must_use]
+pub fn detect_prefix_command(request: &MessagesRequest) -> Option<String> {
+    let content = single_user_message(request)?.content.text();
     if !content.contains("<policy_spec>") || !content.contains(COMMAND_MARKER) {
         return None;
     }
@@ -43,58 +37,31 @@ pub fn detect_prefix_command(request: &Value) -> Option<String> {
     Some(content[start..].trim().to_owned())
 }
 
-pub fn is_historical_analysis_request(request: &Value) -> bool {
-    let Some(system) = get_system(request) else {
-        return false;
-    };
-
-    let Some(last_system) = system.last() else {
-        return false;
-    };
-
-    let text = extract_system_text(last_system);
-    text.contains(HISTORY_ANALYSIS_PARSE)
+#[must_use]
+pub fn is_historical_analysis_request(request: &MessagesRequest) -> bool {
+    last_system_text(request).is_some_and(|text| text.contains(HISTORY_ANALYSIS_PARSE))
 }
 
-pub fn is_title_generation_request(request: &Value) -> bool {
-    let Some(system) = get_system(request) else {
-        return false;
-    };
-
-    let Some(last_system) = system.last() else {
-        return false;
-    };
-
-    let text = extract_system_text(last_system);
-    text.contains(TITLE_GENERATION_PHRASE)
+#[must_use]
+pub fn is_title_generation_request(request: &MessagesRequest) -> bool {
+    last_system_text(request).is_some_and(|text| text.contains(TITLE_GENERATION_PHRASE))
 }
 
-pub fn is_suggestion_mode_request(request: &Value) -> bool {
-    let Some(messages) = get_messages(request) else {
-        return false;
-    };
-
-    messages.iter().any(|message| {
-        message_role(message) == Some("user")
-            && extract_message_text(message).contains(SUGGESTION_MODE_MARKER)
+#[must_use]
+pub fn is_suggestion_mode_request(request: &MessagesRequest) -> bool {
+    request.messages.iter().any(|message| {
+        message.role == Role::User && message.content.text().contains(SUGGESTION_MODE_MARKER)
     })
 }
 
-pub fn detect_filepath_extraction_request(request: &Value) -> Option<(String, String)> {
-    let messages = get_messages(request)?;
-    if messages.len() != 1 || message_role(&messages[0]) != Some("user") {
+#[must_use]
+pub fn detect_filepath_extraction_request(request: &MessagesRequest) -> Option<(String, String)> {
+    let message = single_user_message(request)?;
+    if !request.tools.is_empty() {
         return None;
     }
 
-    if request
-        .get("tools")
-        .and_then(Value::as_array)
-        .is_some_and(|tools| !tools.is_empty())
-    {
-        return None;
-    }
-
-    let content = extract_message_text(&messages[0]);
+    let content = message.content.text();
     if !content.contains(COMMAND_MARKER) || !content.contains(OUTPUT_MARKER) {
         return None;
     }
@@ -104,8 +71,9 @@ pub fn detect_filepath_extraction_request(request: &Value) -> Option<(String, St
         content_lower.contains("filepaths") || content_lower.contains("<filepaths>");
 
     let system_text = request
-        .get("system")
-        .map_or(Cow::Borrowed(""), extract_text_from_content);
+        .system
+        .as_ref()
+        .map_or(Cow::Borrowed(""), Content::text);
     let system_text_lower = system_text.to_lowercase();
     let system_has_extract = system_text_lower.contains("extract any file paths")
         || system_text_lower.contains("file paths that this command");
@@ -131,70 +99,62 @@ pub fn detect_filepath_extraction_request(request: &Value) -> Option<(String, St
     Some((command, output))
 }
 
-fn get_messages(request: &Value) -> Option<&[Value]> {
-    request.get("messages")?.as_array().map(Vec::as_slice)
-}
-
-fn get_system(request: &Value) -> Option<&[Value]> {
-    request.get("system")?.as_array().map(Vec::as_slice)
-}
-
-fn message_role(message: &Value) -> Option<&str> {
-    message.get("role").and_then(Value::as_str)
-}
-
-fn extract_message_text(message: &Value) -> Cow<'_, str> {
-    message
-        .get("content")
-        .map_or(Cow::Borrowed(""), extract_text_from_content)
-}
-
-fn extract_system_text(message: &Value) -> Cow<'_, str> {
-    message
-        .get("text")
-        .map_or(Cow::Borrowed(""), extract_text_from_content)
+/// 请求只有一条 user 消息时返回该消息
+fn single_user_message(request: &MessagesRequest) -> Option<&Message> {
+    match request.messages.as_slice() {
+        [message] if message.role == Role::User => Some(message),
+        _ => None,
+    }
 }
 
-fn extract_text_from_content(content: &Value) -> Cow<'_, str> {
-    match content {
-        Value::String(text) => Cow::Borrowed(text.as_str()),
-        Value::Array(blocks) => Cow::Owned(
-            blocks
-                .iter()
-                .filter_map(|block| {
-                    block
-                        .get("text")
-                        .and_then(Value::as_str)
-                        .or_else(|| block.get("thinking").and_then(Value::as_str))
-                })
-                .collect::<Vec<_>>()
-                .join(""),
-        ),
-        _ => Cow::Borrowed(""),
-    }
+/// system 内容块数组中最后一个块的文本
+fn last_system_text(request: &MessagesRequest) -> Option<&str> {
+    request
+        .system
+        .as_ref()?
+        .blocks()
+        .last()
+        .and_then(ContentBlock::as_text)
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
 mod tests {
-    use serde_json::json;
+    use serde_json::{Value, json};
 
     use super::*;
 
+    fn parse(body: Value) -> MessagesRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
     #[test]
     fn test_quota_check_request() {
-        let request = json!({
+        let request = parse(json!({
             "max_tokens": 1,
             "messages": [{"role": "user", "content": "count"}]
-        });
+        }));
         assert!(is_quota_check_request(&request));
 
-        let non_quota = json!({
+        let non_quota = parse(json!({
             "max_tokens": 1,
             "messages": [{"role": "user", "content": "hello"}]
-        });
+        }));
         assert!(!is_quota_check_request(&non_quota));
     }
 
+    #[test]
+    fn test_quota_check_ignores_thinking_text() {
+        let request = parse(json!({
+            "max_tokens": 1,
+            "messages": [{"role": "user", "content": [
+                {"type": "thinking", "thinking": "count the words", "signature": "sig"},
+                {"type": "text", "text": "hello"}
+            ]}]
+        }));
+        assert!(!is_quota_check_request(&request));
+    }
+
     #[test]
     fn test_count_tokens_url_detection() {
         assert!(is_count_tokens_url("/v1/messages/count_tokens"));
@@ -204,12 +164,12 @@ mod tests {
 
     #[test]
     fn test_prefix_command_detection() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [{
                 "role": "user",
                 "content": "<policy_spec>abc</policy_spec>\nCommand: git commit -m test"
             }]
-        });
+        }));
 
         assert_eq!(
             detect_prefix_command(&request),
@@ -219,10 +179,10 @@ mod tests {
 
     #[test]
     fn test_filepath_extraction_detected_by_system_prompt() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [{"role": "user", "content": "Command: ls\nOutput: src\nCargo.toml"}],
             "system": "Extract any file paths that this command reads or modifies."
-        });
+        }));
 
         let result = detect_filepath_extraction_request(&request);
         assert_eq!(
@@ -233,19 +193,19 @@ mod tests {
 
     #[test]
     fn test_suggestion_mode_detection() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [
                 {"role": "assistant", "content": "ignore"},
                 {"role": "user", "content": "hello\n[SUGGESTION MODE: on]\n"}
             ]
-        });
+        }));
 
         assert!(is_suggestion_mode_request(&request));
     }
 
     #[test]
     fn test_title_generation_detection() {
-        let request = json!({
+        let request = parse(json!({
             "system": [
                 {
                     "text": "x-anthropic-billing-header: cc_version=2.1.50.ae0; cc_entrypoint=cli; cch=00000;",
@@ -256,7 +216,7 @@ mod tests {
                     "type": "text"
                 }
             ]
-        });
+        }));
         assert!(is_title_generation_request(&request));
     }
 }
@@ -3,12 +3,11 @@ mod detection;
 mod response_builder;
 
 pub use response_builder::OptimizationResponse;
-use serde_json::Value;
 
-use crate::config::OptimizationConfig;
+use crate::{config::OptimizationConfig, gateway::anthropic::MessagesRequest};
 
 pub fn try_local_optimization(
-    body_bytes: &[u8],
+    request: Option<&MessagesRequest>,
     request_url: &str,
     flags: &OptimizationConfig,
 ) -> Option<OptimizationResponse> {
@@ -23,9 +22,9 @@ pub fn try_local_optimization(
         );
     }
 
-    let request: Value = serde_json::from_slice(body_bytes).ok()?;
+    let request = request?;
 
-    if flags.enable_network_probe_mock && detection::is_quota_check_request(&request) {
+    if flags.enable_network_probe_mock && detection::is_quota_check_request(request) {
         tracing::info!("Optimization: Intercepted and mocked quota probe");
         return response_builder::build_text_response(
             "unknown-model",
@@ -36,8 +35,7 @@ pub fn try_local_optimization(
         );
     }
 
-    if flags.enable_historical_analysis_mock && detection::is_historical_analysis_request(&request)
-    {
+    if flags.enable_historical_analysis_mock && detection::is_historical_analysis_request(request) {
         tracing::info!("Optimization: Skipped historical analysis request");
         return response_builder::build_text_response(
             "unknown-model",
@@ -49,7 +47,7 @@ pub fn try_local_optimization(
     }
 
     if flags.enable_fast_prefix_detection
-        && let Some(command) = detection::detect_prefix_command(&request)
+        && let Some(command) = detection::detect_prefix_command(request)
     {
         tracing::info!("Optimization: Handled fast prefix detection");
         let prefix = command_utils::extract_command_prefix(command.as_str());
@@ -62,7 +60,7 @@ pub fn try_local_optimization(
         );
     }
 
-    if flags.enable_title_generation_skip && detection::is_title_generation_request(&request) {
+    if flags.enable_title_generation_skip && detection::is_title_generation_request(request) {
         tracing::info!("Optimization: Skipped title generation request");
         return response_builder::build_text_response(
             "unknown-model",
@@ -73,7 +71,7 @@ pub fn try_local_optimization(
         );
     }
 
-    if flags.enable_suggestion_mode_skip && detection::is_suggestion_mode_request(&request) {
+    if flags.enable_suggestion_mode_skip && detection::is_suggestion_mode_request(request) {
         tracing::info!("Optimization: Skipped suggestion mode request");
         return response_builder::build_text_response(
             "unknown-model",
@@ -85,7 +83,7 @@ pub fn try_local_optimization(
     }
 
     if flags.enable_filepath_extraction_mock
-        && let Some((command, output)) = detection::detect_filepath_extraction_request(&request)
+        && let Some((command, output)) = detection::detect_filepath_extraction_request(request)
     {
         tracing::info!("Optimization: Mocked filepath extraction request");
         let filepaths =
@@ -104,14 +102,18 @@ pub fn try_local_optimization(
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
 mod tests {
     use serde_json::{Value, json};
 
     use super::try_local_optimization;
-    use crate::config::OptimizationConfig;
+    use crate::{
+        config::OptimizationConfig,
+        gateway::anthropic::{MessagesRequest, MessagesResponse},
+    };
 
-    fn to_json_bytes(value: &Value) -> Vec<u8> {
-        serde_json::to_vec(value).unwrap_or_default()
+    fn parse(body: Value) -> MessagesRequest {
+        serde_json::from_value(body).unwrap()
     }
 
     fn require_optimization_response(
@@ -125,28 +127,26 @@ mod tests {
     }
 
     fn get_text_from_optimization_response(response_body: &[u8]) -> String {
-        let payload: Value = serde_json::from_slice(response_body).unwrap_or_default();
-        payload
-            .get("content")
-            .and_then(Value::as_array)
-            .and_then(|content| content.first())
-            .and_then(|block| block.get("text"))
-            .and_then(Value::as_str)
+        serde_json::from_slice::<MessagesResponse>(response_body)
+            .ok()
+            .and_then(|payload| payload.content.first()?.as_text().map(str::to_owned))
             .unwrap_or_default()
-            .to_owned()
     }
 
     #[test]
     fn test_quota_probe_mock_hit() {
-        let request = json!({
+        let request = parse(json!({
             "model": "claude-test",
             "max_tokens": 1,
             "messages": [{"role": "user", "content": "count"}]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let response = require_optimization_response(
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default()),
+            try_local_optimization(
+                Some(&request),
+                "/v1/messages",
+                &OptimizationConfig::default(),
+            ),
             "quota probe should hit",
         );
 
@@ -159,17 +159,20 @@ mod tests {
 
     #[test]
     fn test_prefix_detection_hit() {
-        let request = json!({
+        let request = parse(json!({
             "model": "claude-test",
             "messages": [{
                 "role": "user",
                 "content": "<policy_spec>strict</policy_spec>\nCommand: git commit -m 'feat'"
             }]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let response = require_optimization_response(
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default()),
+            try_local_optimization(
+                Some(&request),
+                "/v1/messages",
+                &OptimizationConfig::default(),
+            ),
             "prefix optimization should hit",
         );
 
@@ -182,16 +185,19 @@ mod tests {
 
     #[test]
     fn test_title_generation_skip_hit() {
-        let request = json!({
+        let request = parse(json!({
             "system": [{
                 "text": "Analyze if this message indicates a new conversation topic.",
                 "type": "text"
             }]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let response = require_optimization_response(
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default()),
+            try_local_optimization(
+                Some(&request),
+                "/v1/messages",
+                &OptimizationConfig::default(),
+            ),
             "title optimization should hit",
         );
 
@@ -204,13 +210,16 @@ mod tests {
 
     #[test]
     fn test_suggestion_mode_skip_hit() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [{"role": "user", "content": "hi\n[SUGGESTION MODE: on]"}]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let response = require_optimization_response(
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default()),
+            try_local_optimization(
+                Some(&request),
+                "/v1/messages",
+                &OptimizationConfig::default(),
+            ),
             "suggestion optimization should hit",
         );
 
@@ -220,16 +229,19 @@ mod tests {
 
     #[test]
     fn test_filepath_extraction_mock_hit() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [{
                 "role": "user",
                 "content": "Command: cat foo.txt bar.md\nOutput: line1\nline2\n\nPlease extract <filepaths>."
             }]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let response = require_optimization_response(
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default()),
+            try_local_optimization(
+                Some(&request),
+                "/v1/messages",
+                &OptimizationConfig::default(),
+            ),
             "filepath optimization should hit",
         );
 
@@ -242,24 +254,25 @@ mod tests {
 
     #[test]
     fn test_non_optimization_request_returns_none() {
-        let request = json!({
+        let request = parse(json!({
             "messages": [{"role": "user", "content": "normal chat message"}]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
-        let response =
-            try_local_optimization(&body, "/v1/messages", &OptimizationConfig::default());
+        let response = try_local_optimization(
+            Some(&request),
+            "/v1/messages",
+            &OptimizationConfig::default(),
+        );
         assert!(response.is_none());
     }
 
     #[test]
     fn test_count_tokens_url_hit() {
-        let request = json!({"model": "claude-test"});
-        let body = to_json_bytes(&request);
+        let request = parse(json!({"model": "claude-test"}));
 
         let response = require_optimization_response(
             try_local_optimization(
-                &body,
+                Some(&request),
                 "/v1/messages/count_tokens?foo=bar",
                 &OptimizationConfig::default(),
             ),
@@ -275,11 +288,9 @@ mod tests {
 
     #[test]
     fn test_count_tokens_url_hit_with_invalid_json_body() {
-        let body = b"not json";
-
         let response = require_optimization_response(
             try_local_optimization(
-                body,
+                None,
                 "/v1/messages/count_tokens?foo=bar",
                 &OptimizationConfig::default(),
             ),
@@ -295,18 +306,17 @@ mod tests {
 
     #[test]
     fn test_optimization_can_be_disabled_by_flag() {
-        let request = json!({
+        let request = parse(json!({
             "max_tokens": 1,
             "messages": [{"role": "user", "content": "quota"}]
-        });
-        let body = to_json_bytes(&request);
+        }));
 
         let flags = OptimizationConfig {
             enable_network_probe_mock: false,
             ..OptimizationConfig::default()
         };
 
-        let response = try_local_optimization(&body, "/v1/messages", &flags);
+        let response = try_local_optimization(Some(&request), "/v1/messages", &flags);
         assert!(response.is_none());
     }
 }
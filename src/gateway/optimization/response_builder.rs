@@ -3,7 +3,7 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use serde_json::{Value, json};
+use crate::gateway::anthropic::{ContentBlock, MessagesResponse, Usage};
 
 static RESPONSE_SEQUENCE: AtomicU64 = AtomicU64::new(1);
 
@@ -12,6 +12,7 @@ pub struct OptimizationResponse {
     pub reason: &'static str,
 }
 
+#[must_use]
 pub fn build_text_response(
     model: &str,
     text: &str,
@@ -19,19 +20,17 @@ pub fn build_text_response(
     output_tokens: u64,
     reason: &'static str,
 ) -> Option<OptimizationResponse> {
-    let payload = json!({
-        "id": build_message_id(),
-        "type": "message",
-        "role": "assistant",
-        "model": if model.is_empty() { "unknown-model" } else { model },
-        "content": [{"type": "text", "text": text}],
-        "stop_reason": "end_turn",
-        "stop_sequence": Value::Null,
-        "usage": {
-            "input_tokens": input_tokens,
-            "output_tokens": output_tokens
-        }
-    });
+    let model = if model.is_empty() {
+        "unknown-model"
+    } else {
+        model
+    };
+    let payload = MessagesResponse {
+        content: vec![ContentBlock::text(text)],
+        stop_reason: Some("end_turn".to_string()),
+        usage: Usage::new(input_tokens, output_tokens),
+        ..MessagesResponse::new(build_message_id(), model)
+    };
 
     let body = serde_json::to_vec(&payload).ok()?;
     Some(OptimizationResponse { body, reason })
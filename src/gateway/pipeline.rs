@@ -0,0 +1,198 @@
+//! 请求体处理管线
+//!
+//! 请求体在入口解析一次，之后的每个处理阶段（注入系统提示词、过滤、覆盖 model、
+//! 签名清洗、能力调整、缓存断点、兼容性修补……）都以 [`Transform`] 的形式改写同一个
+//! [`MessagesRequest`]，转发前只序列化一次。没有阶段修改请求体时直接使用原始字节。
+//!
+//! 是合法 JSON 但不符合 [`MessagesRequest`] 结构的请求体（如消息缺少 `role`）不参与各阶段处理，
+//! 只记录解析错误：Anthropic 上游仍可在原始 JSON 上覆盖 model 后转发，需要格式转换的上游据此拒绝请求。
+
+use bytes::Bytes;
+
+use crate::gateway::anthropic::MessagesRequest;
+
+/// 请求体处理阶段
+pub trait Transform {
+    /// 阶段名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 改写 Anthropic 格式的请求体，返回是否有修改
+    fn apply(&self, body: &mut MessagesRequest) -> bool;
+}
+
+/// 解析后的请求体
+///
+/// 空请求体或不是 Anthropic Messages 请求的请求体不参与各阶段处理，原样转发。
+#[derive(Debug, Default)]
+pub struct RequestBody {
+    raw: Bytes,
+    value: Option<MessagesRequest>,
+    /// 是 JSON 但不符合 Messages 请求结构时的解析错误
+    parse_error: Option<String>,
+    modified: bool,
+}
+
+impl RequestBody {
+    /// 解析请求体
+    #[must_use]
+    pub fn parse(raw: Bytes) -> Self {
+        let (value, parse_error) = if raw.is_empty() {
+            (None, None)
+        } else {
+            match serde_json::from_slice(&raw) {
+                Ok(value) => (Some(value), None),
+                Err(e) if e.is_data() => {
+                    tracing::warn!("⚠️ 请求体不符合 Messages 请求结构，跳过各处理阶段: {}", e);
+                    (None, Some(e.to_string()))
+                }
+                Err(_) => (None, None),
+            }
+        };
+        Self {
+            raw,
+            value,
+            parse_error,
+            modified: false,
+        }
+    }
+
+    /// 由已构造的请求创建（如 `OpenAI` 入口转换得到的 Anthropic 请求）
+    #[must_use]
+    pub const fn from_request(request: MessagesRequest) -> Self {
+        Self {
+            raw: Bytes::new(),
+            value: Some(request),
+            parse_error: None,
+            modified: true,
+        }
+    }
+
+    #[must_use]
+    pub const fn value(&self) -> Option<&MessagesRequest> {
+        self.value.as_ref()
+    }
+
+    /// 请求体是 JSON 但不符合 Messages 请求结构时的解析错误
+    #[must_use]
+    pub fn parse_error(&self) -> Option<&str> {
+        self.parse_error.as_deref()
+    }
+
+    /// 改写未能解析为 Messages 请求的原始字节（如在原始 JSON 上覆盖 model），返回是否有修改
+    pub fn rewrite_raw(&mut self, rewrite: impl FnOnce(&[u8]) -> Option<Bytes>) -> bool {
+        if self.value.is_some() {
+            return false;
+        }
+        let Some(raw) = rewrite(&self.raw) else {
+            return false;
+        };
+        self.raw = raw;
+        true
+    }
+
+    /// 执行单个阶段
+    pub fn apply(&mut self, stage: &dyn Transform) -> bool {
+        let Some(value) = self.value.as_mut() else {
+            return false;
+        };
+        if !stage.apply(value) {
+            return false;
+        }
+        tracing::trace!("🔧 请求体处理阶段已修改请求体: {}", stage.name());
+        self.modified = true;
+        true
+    }
+
+    /// 依次执行多个阶段（请求处理中各阶段按条件单独执行，这里供测试与基准测试使用）
+    #[cfg(any(test, feature = "bench"))]
+    pub fn run(&mut self, stages: &[&dyn Transform]) {
+        for &stage in stages {
+            self.apply(stage);
+        }
+    }
+
+    /// 执行可能失败的改写（如上下文窗口裁剪），`Ok(true)` 表示有修改
+    pub fn try_update<E>(
+        &mut self,
+        update: impl FnOnce(&mut MessagesRequest) -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        let Some(value) = self.value.as_mut() else {
+            return Ok(false);
+        };
+        let changed = update(value)?;
+        self.modified |= changed;
+        Ok(changed)
+    }
+
+    /// 最终的请求体字节：有修改时序列化，否则使用原始字节
+    #[must_use]
+    pub fn into_bytes(self) -> Bytes {
+        match self.value {
+            Some(value) if self.modified => {
+                serde_json::to_vec(&value).map_or(self.raw, Bytes::from)
+            }
+            _ => self.raw,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    struct SetModel(&'static str);
+
+    impl Transform for SetModel {
+        fn name(&self) -> &'static str {
+            "set_model"
+        }
+
+        fn apply(&self, body: &mut MessagesRequest) -> bool {
+            if body.model == self.0 {
+                return false;
+            }
+            body.model = self.0.to_string();
+            true
+        }
+    }
+
+    /// 测试未修改时直接使用原始字节，修改后只序列化一次
+    #[test]
+    fn test_request_body_serializes_only_when_modified() {
+        let raw = Bytes::from_static(br#"{ "model" : "a" }"#);
+        let mut body = RequestBody::parse(raw.clone());
+        body.run(&[&SetModel("a")]);
+        assert_eq!(body.into_bytes(), raw);
+
+        let mut body = RequestBody::parse(raw);
+        body.run(&[&SetModel("a"), &SetModel("b")]);
+        assert_eq!(body.into_bytes(), Bytes::from_static(br#"{"model":"b"}"#));
+
+        // 非 JSON 请求体原样保留
+        let raw = Bytes::from_static(b"not json");
+        let mut body = RequestBody::parse(raw.clone());
+        assert!(!body.apply(&SetModel("b")));
+        assert!(body.parse_error().is_none());
+        assert_eq!(body.into_bytes(), raw);
+    }
+
+    /// 测试不符合 Messages 请求结构的 JSON 记录解析错误，只能改写原始字节
+    #[test]
+    fn test_invalid_request_keeps_raw_bytes() {
+        let raw = Bytes::from_static(br#"{"model":"a","messages":[{"content":"hi"}]}"#);
+        let mut body = RequestBody::parse(raw);
+        assert!(body.value().is_none());
+        assert!(body.parse_error().unwrap().contains("role"));
+        assert!(!body.apply(&SetModel("b")));
+
+        assert!(body.rewrite_raw(|raw| {
+            let raw = std::str::from_utf8(raw).unwrap();
+            Some(Bytes::from(raw.replace(r#""a""#, r#""b""#)))
+        }));
+        assert_eq!(
+            body.into_bytes(),
+            Bytes::from_static(br#"{"model":"b","messages":[{"content":"hi"}]}"#)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! `kimi_reasoning_content`：补全 assistant 消息的 `reasoning_content`
+//!
+//! Kimi 的 Anthropic 兼容接口在开启 thinking 后要求历史 assistant 消息携带 `reasoning_content`，
+//! 缺失时返回 400。
+
+use serde_json::Value;
+
+use crate::gateway::anthropic::{ContentBlock, Message, MessagesRequest, Role, ThinkingKind};
+
+/// 缺省的 `reasoning_content` 占位符
+const REASONING_PLACEHOLDER: &str = "[Previous reasoning not available in context]";
+
+/// 从 message.content 中提取 type=thinking 的 thinking 文本
+fn extract_thinking_text(message: &Message) -> Option<&str> {
+    message
+        .content
+        .blocks()
+        .iter()
+        .find_map(|block| match block {
+            ContentBlock::Thinking(thinking) => Some(thinking.thinking.trim()),
+            _ => None,
+        })
+        .filter(|text| !text.is_empty())
+}
+
+/// 当前的 `reasoning_content`（不是字符串时视为缺失）
+fn reasoning_content(message: &Message) -> Option<&str> {
+    message
+        .extra
+        .get("reasoning_content")
+        .and_then(Value::as_str)
+}
+
+/// 根据 thinking 文本补丁单条消息的 `reasoning_content`
+fn patch_message_reasoning_content(message: &mut Message, fallback_thinking: Option<&str>) -> bool {
+    // 已有且不是占位符时保留
+    let current = reasoning_content(message);
+    if current.is_some_and(|value| value != REASONING_PLACEHOLDER) {
+        return false;
+    }
+
+    let reasoning_value = extract_thinking_text(message)
+        .or(fallback_thinking)
+        .unwrap_or(REASONING_PLACEHOLDER)
+        .to_string();
+    if current == Some(reasoning_value.as_str()) {
+        return false;
+    }
+
+    message.extra.insert(
+        "reasoning_content".to_string(),
+        Value::String(reasoning_value),
+    );
+    true
+}
+
+/// 为 Kimi Thinking 模式补全缺失的 `reasoning_content`
+///
+/// 在 thinking 启用时：
+/// - 优先从 message.content[type=thinking].thinking 提取文本
+/// - 给 `assistant` 消息补上/替换 `reasoning_content`（缺失或为占位符时）
+pub fn apply(body: &mut MessagesRequest) -> bool {
+    // 检查是否启用了 thinking 模式
+    if body.thinking_kind() != Some(&ThinkingKind::Enabled) {
+        return false;
+    }
+
+    let messages = &mut body.messages;
+    let mut patched = false;
+
+    // 用于兜底：取最后一个可用的 thinking 文本
+    let latest_thinking = messages
+        .iter()
+        .rev()
+        .find_map(extract_thinking_text)
+        .map(str::to_string);
+
+    for message in messages
+        .iter_mut()
+        .filter(|message| message.role == Role::Assistant)
+    {
+        if patch_message_reasoning_content(message, latest_thinking.as_deref()) {
+            patched = true;
+        }
+    }
+
+    if patched {
+        tracing::debug!("Patched missing reasoning_content for thinking mode messages");
+    }
+    patched
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn patch(body: &mut Value) -> bool {
+        let mut request: MessagesRequest = serde_json::from_value(body.clone()).unwrap();
+        let changed = apply(&mut request);
+        *body = serde_json::to_value(&request).unwrap();
+        changed
+    }
+
+    /// 测试 thinking 开启时从 thinking 块补全 `reasoning_content`，缺失时使用兜底文本
+    #[test]
+    fn test_patch_reasoning_content() {
+        let mut body = json!({
+            "thinking": {"type": "enabled", "budget_tokens": 2048},
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "Plan A", "signature": "s"},
+                    {"type": "text", "text": "ok"}
+                ]},
+                {"role": "assistant", "content": [{"type": "text", "text": "again"}]}
+            ]
+        });
+        assert!(patch(&mut body));
+        assert!(body["messages"][0].get("reasoning_content").is_none());
+        assert_eq!(body["messages"][1]["reasoning_content"], "Plan A");
+        assert_eq!(body["messages"][2]["reasoning_content"], "Plan A");
+        // 已补全的请求不再修改
+        assert!(!patch(&mut body));
+    }
+
+    /// 测试未开启 thinking 时不修改请求
+    #[test]
+    fn test_thinking_disabled() {
+        let mut body = json!({
+            "messages": [{"role": "assistant", "content": "ok"}]
+        });
+        assert!(!patch(&mut body));
+        assert!(body["messages"][0].get("reasoning_content").is_none());
+    }
+}
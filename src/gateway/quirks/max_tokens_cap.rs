@@ -0,0 +1,79 @@
+//! `max_tokens_cap`：限制 `max_tokens` 上限
+//!
+//! Claude CLI 按 Claude 模型的输出上限发送 `max_tokens`（如 32000），
+//! 输出上限更小的上游会直接返回 400 而不是自动截断。
+
+use crate::gateway::anthropic::{MessagesRequest, ThinkingKind};
+
+/// Anthropic 要求的最小 thinking 预算
+const MIN_THINKING_BUDGET: u64 = 1024;
+
+/// 把 `max_tokens` 限制在 `cap` 以内；thinking 预算需小于 `max_tokens`，随之收紧，放不下时关闭 thinking
+pub fn apply(body: &mut MessagesRequest, cap: u64) -> bool {
+    let Some(max_tokens) = body.max_tokens else {
+        return false;
+    };
+    if max_tokens <= cap {
+        return false;
+    }
+    body.max_tokens = Some(cap);
+
+    let budget = body
+        .thinking
+        .as_ref()
+        .filter(|thinking| thinking.kind == ThinkingKind::Enabled)
+        .and_then(|thinking| thinking.budget_tokens);
+    if let Some(budget) = budget
+        && budget >= cap
+    {
+        if cap > MIN_THINKING_BUDGET {
+            if let Some(thinking) = body.thinking.as_mut() {
+                thinking.budget_tokens = Some(cap - 1);
+            }
+        } else {
+            body.thinking = None;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn cap(body: &Value, cap: u64) -> (bool, Value) {
+        let mut request = serde_json::from_value(body.clone()).unwrap();
+        let changed = apply(&mut request, cap);
+        (changed, serde_json::to_value(&request).unwrap())
+    }
+
+    /// 测试 `max_tokens` 与 thinking 预算随上限收紧
+    #[test]
+    fn test_cap_max_tokens() {
+        let (changed, body) = cap(
+            &json!({"max_tokens": 32000, "thinking": {"type": "enabled", "budget_tokens": 31999}}),
+            8192,
+        );
+        assert!(changed);
+        assert_eq!(body["max_tokens"], 8192);
+        assert_eq!(body["thinking"]["budget_tokens"], 8191);
+
+        let (changed, body) = cap(
+            &json!({"max_tokens": 4096, "thinking": {"type": "enabled", "budget_tokens": 2048}}),
+            8192,
+        );
+        assert!(!changed);
+        assert_eq!(body["max_tokens"], 4096);
+
+        // 上限放不下最小 thinking 预算时关闭 thinking
+        let (changed, body) = cap(
+            &json!({"max_tokens": 4096, "thinking": {"type": "enabled", "budget_tokens": 2048}}),
+            1000,
+        );
+        assert!(changed);
+        assert!(body.get("thinking").is_none());
+    }
+}
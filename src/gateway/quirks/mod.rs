@@ -0,0 +1,156 @@
+//! 上游提供商的兼容性修补（`[[upstream]] quirks = [...]`）
+//!
+//! 每项修补是一个独立的转换，在这里统一登记：
+//! - 请求体修补作用于 Anthropic 格式的请求体，在按 mode 转换格式之前执行
+//! - 查询参数修补作用于转发时的查询字符串
+//!
+//! 新增提供商的修补只需在 [`Quirk`] 中添加一项并在本模块登记，不需要改动 handler。
+
+mod kimi_reasoning_content;
+mod max_tokens_cap;
+mod no_cache_control;
+mod no_parallel_tools;
+mod strip_beta_query;
+
+use std::borrow::Cow;
+
+use crate::{
+    config::Quirk,
+    gateway::{anthropic::MessagesRequest, pipeline::Transform},
+};
+
+/// 对请求体执行单项修补，返回是否修改了请求体
+fn apply_to_body(quirk: Quirk, body: &mut MessagesRequest) -> bool {
+    match quirk {
+        Quirk::KimiReasoningContent => kimi_reasoning_content::apply(body),
+        Quirk::NoCacheControl => no_cache_control::apply(body),
+        Quirk::NoParallelTools => no_parallel_tools::apply(body),
+        Quirk::MaxTokensCap(cap) => max_tokens_cap::apply(body, cap),
+        Quirk::StripBetaQuery => false,
+    }
+}
+
+/// 对查询字符串执行单项修补
+fn apply_to_query(quirk: Quirk, query: Cow<'_, str>) -> Cow<'_, str> {
+    match quirk {
+        Quirk::StripBetaQuery => strip_beta_query::apply(&query).map_or(query, Cow::Owned),
+        _ => query,
+    }
+}
+
+/// 按 upstream 启用的修补改写请求体
+pub struct BodyQuirks<'a>(pub &'a [Quirk]);
+
+impl Transform for BodyQuirks<'_> {
+    fn name(&self) -> &'static str {
+        "body_quirks"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        let mut changed = false;
+        for &quirk in self.0 {
+            if apply_to_body(quirk, body) {
+                tracing::debug!("🩹 应用上游修补: {:?}", quirk);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// 把 `max_tokens` 限制在 `cap` 以内（thinking 预算随之收紧），供能力声明的 `max_output_tokens` 复用
+pub fn cap_max_tokens(body: &mut MessagesRequest, cap: u64) -> bool {
+    max_tokens_cap::apply(body, cap)
+}
+
+/// 移除请求中所有 `cache_control`，供能力声明的 `prompt_caching = false` 复用
+pub fn strip_cache_control(body: &mut MessagesRequest) -> bool {
+    no_cache_control::apply(body)
+}
+
+/// 按 upstream 启用的修补改写查询字符串
+#[must_use]
+pub fn apply_query_quirks<'a>(query: &'a str, quirks: &[Quirk]) -> Cow<'a, str> {
+    quirks.iter().fold(Cow::Borrowed(query), |query, &quirk| {
+        apply_to_query(quirk, query)
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::UpstreamConfig;
+
+    /// 测试 TOML 中的 quirks 写法（字符串与带参数的内联表）
+    #[test]
+    fn test_quirks_from_toml() {
+        let upstream: UpstreamConfig = toml::from_str(
+            r#"
+            endpoint = "https://api.moonshot.cn/anthropic"
+            quirks = ["kimi_reasoning_content", "strip_beta_query", { max_tokens_cap = 8192 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            upstream.quirks(),
+            [
+                Quirk::KimiReasoningContent,
+                Quirk::StripBetaQuery,
+                Quirk::MaxTokensCap(8192)
+            ]
+        );
+    }
+
+    /// 测试未配置 quirks 时按 mode 沿用原有行为，显式配置 `[]` 时全部关闭
+    #[test]
+    fn test_default_quirks_by_mode() {
+        let parse = |toml: &str| toml::from_str::<UpstreamConfig>(toml).unwrap();
+
+        let anthropic = parse(r#"endpoint = "https://api.moonshot.cn/anthropic""#);
+        assert_eq!(
+            anthropic.quirks(),
+            [Quirk::StripBetaQuery, Quirk::KimiReasoningContent]
+        );
+        for mode in ["openai_responses", "openai_chat"] {
+            let upstream = parse(&format!("endpoint = \"https://x\"\nmode = \"{mode}\""));
+            assert_eq!(upstream.quirks(), [Quirk::StripBetaQuery]);
+        }
+        let gemini = parse("endpoint = \"https://x\"\nmode = \"gemini\"");
+        assert!(gemini.quirks().is_empty());
+
+        let opted_out = parse("endpoint = \"https://x\"\nquirks = []");
+        assert!(opted_out.quirks().is_empty());
+    }
+
+    /// 测试多项修补依次作用于请求体与查询字符串，未启用时原样返回
+    #[test]
+    fn test_apply_quirks() {
+        let mut body: MessagesRequest = serde_json::from_value(json!({
+            "max_tokens": 32000,
+            "tools": [{"name": "Read", "cache_control": {"type": "ephemeral"}}],
+            "messages": []
+        }))
+        .unwrap();
+        let quirks = [
+            Quirk::NoCacheControl,
+            Quirk::NoParallelTools,
+            Quirk::MaxTokensCap(4096),
+        ];
+        let mut patched = body.clone();
+        assert!(BodyQuirks(&quirks).apply(&mut patched));
+        let patched = serde_json::to_value(&patched).unwrap();
+        assert_eq!(patched["max_tokens"], 4096);
+        assert!(patched["tools"][0].get("cache_control").is_none());
+        assert_eq!(patched["tool_choice"]["disable_parallel_tool_use"], true);
+        assert!(!BodyQuirks(&[]).apply(&mut body));
+
+        assert_eq!(
+            apply_query_quirks("beta=true", &[Quirk::StripBetaQuery]),
+            ""
+        );
+        assert_eq!(apply_query_quirks("beta=true", &quirks), "beta=true");
+    }
+}
@@ -0,0 +1,73 @@
+//! `no_cache_control`：移除请求中的 `cache_control` 标记
+//!
+//! 不支持提示词缓存的 Anthropic 兼容上游收到 `cache_control` 会返回 400。
+
+use crate::gateway::anthropic::{ContentBlock, MessagesRequest, ToolResultBlock};
+
+/// 移除 system、tools 与消息内容块（含 `tool_result` 的嵌套内容）中的 `cache_control`
+pub fn apply(body: &mut MessagesRequest) -> bool {
+    let mut removed = false;
+    if let Some(system) = body.system.as_mut() {
+        removed |= strip_blocks(system.blocks_mut());
+    }
+    for tool in &mut body.tools {
+        removed |= tool.cache_control.take().is_some();
+    }
+    for message in &mut body.messages {
+        removed |= strip_blocks(message.content.blocks_mut());
+    }
+    removed
+}
+
+fn strip_blocks(blocks: &mut [ContentBlock]) -> bool {
+    let mut removed = false;
+    for block in blocks {
+        if let Some(cache_control) = block.cache_control_mut() {
+            removed |= cache_control.take().is_some();
+        }
+        if let ContentBlock::ToolResult(ToolResultBlock {
+            content: Some(content),
+            ..
+        }) = block
+        {
+            removed |= strip_blocks(content.blocks_mut());
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 测试移除 system、tools、消息与 `tool_result` 嵌套内容中的 `cache_control`
+    #[test]
+    fn test_strip_cache_control() {
+        let marker = json!({"type": "ephemeral"});
+        let mut body: MessagesRequest = serde_json::from_value(json!({
+            "system": [{"type": "text", "text": "sys", "cache_control": marker}],
+            "tools": [{"name": "Read", "input_schema": {}, "cache_control": marker}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": [
+                        {"type": "text", "text": "out", "cache_control": marker}
+                    ]},
+                    {"type": "text", "text": "hi", "cache_control": marker}
+                ]},
+                {"role": "assistant", "content": "plain"}
+            ]
+        }))
+        .unwrap();
+        assert!(apply(&mut body));
+        let value = serde_json::to_value(&body).unwrap();
+        assert!(!value.to_string().contains("cache_control"));
+        assert_eq!(
+            value["messages"][0]["content"][0]["content"][0]["text"],
+            "out"
+        );
+        assert!(!apply(&mut body));
+    }
+}
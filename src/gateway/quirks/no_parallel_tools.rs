@@ -0,0 +1,62 @@
+//! `no_parallel_tools`：禁止并行工具调用
+//!
+//! 部分上游一次返回多个工具调用时会丢失或错配 `tool_result`。
+//! 在 Anthropic 格式的 `tool_choice` 上设置 `disable_parallel_tool_use`，
+//! 转换为其他格式时随之映射为 `parallel_tool_calls: false`。
+
+use crate::gateway::anthropic::{MessagesRequest, ToolChoice, ToolChoiceKind};
+
+/// 带工具的请求设置 `tool_choice.disable_parallel_tool_use = true`
+pub fn apply(body: &mut MessagesRequest) -> bool {
+    if body.tools.is_empty() {
+        return false;
+    }
+    let tool_choice = body
+        .tool_choice
+        .get_or_insert_with(|| ToolChoice::new(ToolChoiceKind::Auto));
+    // none 不会调用工具，Anthropic 也不允许在其上设置该字段
+    if tool_choice.kind == ToolChoiceKind::None
+        || tool_choice.disable_parallel_tool_use == Some(true)
+    {
+        return false;
+    }
+    tool_choice.disable_parallel_tool_use = Some(true);
+    true
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn request(body: Value) -> MessagesRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    /// 测试带工具的请求禁用并行调用，没有工具或 `tool_choice: none` 时不修改
+    #[test]
+    fn test_disable_parallel_tool_use() {
+        let mut body = request(json!({"tools": [{"name": "Read"}], "messages": []}));
+        assert!(apply(&mut body));
+        assert_eq!(
+            serde_json::to_value(&body.tool_choice).unwrap(),
+            json!({"type": "auto", "disable_parallel_tool_use": true})
+        );
+        assert!(!apply(&mut body));
+
+        let mut body =
+            request(json!({"tools": [{"name": "Read"}], "tool_choice": {"type": "any"}}));
+        assert!(apply(&mut body));
+        assert_eq!(body.tool_choice.unwrap().kind, ToolChoiceKind::Any);
+
+        let mut body =
+            request(json!({"tools": [{"name": "Read"}], "tool_choice": {"type": "none"}}));
+        assert!(!apply(&mut body));
+
+        let mut body = request(json!({"messages": []}));
+        assert!(!apply(&mut body));
+        assert!(body.tool_choice.is_none());
+    }
+}
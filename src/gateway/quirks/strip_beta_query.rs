@@ -0,0 +1,32 @@
+//! `strip_beta_query`：移除 Claude CLI 附加的 `?beta=true` 查询参数
+//!
+//! 部分 Anthropic 兼容上游不认识该参数，会返回 404 或 400。
+
+/// 从查询字符串中移除 `beta=true`，保留其余参数；不含该参数时返回 `None`
+pub fn apply(query: &str) -> Option<String> {
+    if !query.split('&').any(|pair| pair == "beta=true") {
+        return None;
+    }
+    Some(
+        query
+            .split('&')
+            .filter(|pair| *pair != "beta=true")
+            .collect::<Vec<_>>()
+            .join("&"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试只移除 `beta=true`，其余参数保持原样
+    #[test]
+    fn test_strip_beta_query() {
+        assert_eq!(apply("beta=true").as_deref(), Some(""));
+        assert_eq!(apply("beta=true&foo=1").as_deref(), Some("foo=1"));
+        assert_eq!(apply("foo=1&beta=true").as_deref(), Some("foo=1"));
+        assert_eq!(apply("foo=1"), None);
+        assert_eq!(apply("beta=false"), None);
+    }
+}
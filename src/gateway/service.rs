@@ -1,15 +1,28 @@
-use std::{borrow::Cow, sync::atomic::Ordering};
+use std::{borrow::Cow, io, sync::atomic::Ordering};
 
 use rayon::prelude::*;
-use serde_json::Value;
+use serde::Serialize;
 use tracing::{info, warn};
 
-use crate::gateway::RequestStats;
+use crate::gateway::{
+    RequestStats,
+    anthropic::{Content, ContentBlock, Message, MessagesRequest},
+};
 
-fn estimate_tokens(text: &str) -> u64 {
+#[must_use]
+pub fn estimate_tokens(text: &str) -> u64 {
+    estimate_tokens_for_len(text.len())
+}
+
+/// 按序列化后的长度估算 JSON 值的 token 数，不生成中间字符串
+#[must_use]
+pub fn estimate_json_tokens<T: Serialize + ?Sized>(value: &T) -> u64 {
+    estimate_tokens_for_len(json_len(value))
+}
+
+fn estimate_tokens_for_len(len: usize) -> u64 {
     // 整数运算避免浮点精度损失: (len * 2 + 6) / 7 ≈ len / 3.5
     // 使用 checked_mul 防止溢出
-    let len = text.len();
     // 在 usize 空间内计算，然后转换为 u64
     let result = len
         .checked_mul(2)
@@ -18,20 +31,72 @@ fn estimate_tokens(text: &str) -> u64 {
     result as u64
 }
 
-// 从 content 字段提取实际文本（处理字符串或数组格式）
-fn extract_text(content: &Value) -> Cow<'_, str> {
+/// JSON 值序列化后的字节数（只计数，不分配）
+fn json_len<T: Serialize + ?Sized>(value: &T) -> usize {
+    struct Counter(usize);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).map_or(0, |()| counter.0)
+}
+
+/// 估算时每张图片计入的 token 数（约 1.15 百万像素的图片）
+const IMAGE_TOKENS: u64 = 1600;
+
+/// 估算 Anthropic 内容块的 token 数；图片按固定数量计入，不按 base64 长度
+#[must_use]
+pub fn estimate_block_tokens(block: &ContentBlock) -> u64 {
+    match block {
+        ContentBlock::Image(_) => IMAGE_TOKENS,
+        ContentBlock::ToolResult(result) => {
+            result.content.as_ref().map_or(0, estimate_content_tokens)
+        }
+        _ => estimate_json_tokens(block),
+    }
+}
+
+/// 估算消息内容（字符串或内容块数组）的 token 数
+#[must_use]
+pub fn estimate_content_tokens(content: &Content) -> u64 {
     match content {
-        Value::String(s) => Cow::Borrowed(s.as_str()),
-        Value::Array(arr) => Cow::Owned(
-            arr.iter()
-                .filter_map(|item| item.get("text").and_then(|t| t.as_str()))
-                .collect::<Vec<_>>()
-                .join(""),
-        ),
-        _ => Cow::Owned(content.to_string()),
+        Content::Blocks(blocks) => blocks.iter().map(estimate_block_tokens).sum(),
+        Content::Text(_) => estimate_json_tokens(content),
     }
 }
 
+/// 估算 Anthropic 消息的 token 数
+#[must_use]
+pub fn estimate_message_tokens(message: &Message) -> u64 {
+    estimate_content_tokens(&message.content)
+}
+
+/// 估算 Anthropic 请求的输入 token 数（system + tools + messages）
+#[must_use]
+pub fn estimate_request_tokens(request: &MessagesRequest) -> u64 {
+    let system = request.system.as_ref().map_or(0, estimate_json_tokens);
+    let tools = if request.tools.is_empty() {
+        0
+    } else {
+        estimate_json_tokens(&request.tools)
+    };
+    let messages = request
+        .messages
+        .iter()
+        .map(estimate_message_tokens)
+        .sum::<u64>();
+    system + tools + messages
+}
+
 // 检查内容是否是 Claude Code 的 system-reminder（被放在 user message 里的系统提示）
 fn is_system_reminder(content: &str) -> bool {
     content.contains("<system-reminder>")
@@ -42,70 +107,57 @@ fn is_system_reminder(content: &str) -> bool {
 }
 
 // 返回: (total, user_new, user_history, assistant, system)
-pub fn analyze_request_body(body: &str) -> (u64, u64, u64, u64, u64) {
+#[must_use]
+pub fn analyze_request_body(request: &MessagesRequest) -> (u64, u64, u64, u64, u64) {
     let mut system_tokens = 0;
     let mut user_new_tokens = 0;
     let mut user_history_tokens = 0;
     let mut assistant_tokens = 0;
 
-    if let Ok(json) = serde_json::from_str::<Value>(body) {
-        // 统计独立的 system 字段
-        if let Some(system) = json.get("system") {
-            system_tokens += estimate_tokens(&system.to_string());
-        }
+    // 统计独立的 system 字段
+    if let Some(system) = &request.system {
+        system_tokens += estimate_json_tokens(system);
+    }
 
-        // 统计 OpenAI 格式的 instructions 字段
-        if let Some(instructions) = json.get("instructions") {
-            system_tokens += estimate_tokens(&instructions.to_string());
-        }
+    // 统计 tools
+    if !request.tools.is_empty() {
+        system_tokens += estimate_json_tokens(&request.tools);
+    }
 
-        // 统计 tools
-        if let Some(tools) = json.get("tools") {
-            system_tokens += estimate_tokens(&tools.to_string());
-        }
+    // 统计 messages：预处理所有消息，提取纯文本（不含 thinking 等其他块）和角色
+    let parsed_messages: Vec<(&str, Cow<'_, str>, u64)> = request
+        .messages
+        .par_iter()
+        .map(|msg| {
+            let text = msg.content.text();
+            let tokens = estimate_tokens(text.as_ref());
+            (msg.role.as_str(), text, tokens)
+        })
+        .collect();
+
+    // 找到最后一条真正的 user 消息（排除 system-reminder）
+    let last_real_user_idx = parsed_messages
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, (role, text, _))| *role == "user" && !is_system_reminder(text))
+        .map(|(idx, _)| idx);
 
-        // 统计 messages
-        if let Some(messages) = json.get("messages").and_then(|m| m.as_array()) {
-            // 预处理所有消息，提取纯文本和角色
-            let parsed_messages: Vec<(Cow<'_, str>, Cow<'_, str>, u64)> = messages
-                .par_iter()
-                .filter_map(|msg| {
-                    let role = Cow::Borrowed(msg.get("role")?.as_str()?);
-                    let content = msg.get("content")?;
-                    let text = extract_text(content);
-                    let tokens = estimate_tokens(text.as_ref());
-                    Some((role, text, tokens))
-                })
-                .collect();
-
-            // 找到最后一条真正的 user 消息（排除 system-reminder）
-            let last_real_user_idx = parsed_messages
-                .iter()
-                .enumerate()
-                .rev()
-                .find(|(_, (role, text, _))| role == "user" && !is_system_reminder(text))
-                .map(|(idx, _)| idx);
-
-            for (idx, (role, text, tokens)) in parsed_messages.iter().enumerate() {
-                match role.as_ref() {
-                    "user" => {
-                        if is_system_reminder(text) {
-                            system_tokens += tokens;
-                        } else if Some(idx) == last_real_user_idx {
-                            user_new_tokens += tokens;
-                        } else {
-                            user_history_tokens += tokens;
-                        }
-                    }
-                    "assistant" => assistant_tokens += tokens,
-                    "system" => system_tokens += tokens,
-                    _ => {}
+    for (idx, (role, text, tokens)) in parsed_messages.iter().enumerate() {
+        match *role {
+            "user" => {
+                if is_system_reminder(text) {
+                    system_tokens += tokens;
+                } else if Some(idx) == last_real_user_idx {
+                    user_new_tokens += tokens;
+                } else {
+                    user_history_tokens += tokens;
                 }
             }
+            "assistant" => assistant_tokens += tokens,
+            "system" => system_tokens += tokens,
+            _ => {}
         }
-    } else {
-        // JSON 解析失败，可能是二进制或非标准格式
-        user_new_tokens = estimate_tokens(body);
     }
 
     let total = system_tokens + user_new_tokens + user_history_tokens + assistant_tokens;
@@ -159,8 +211,8 @@ pub fn log_full_response(body: &str) {
     info!("=== 响应体结束 ===");
 }
 
-pub fn calculate_tokens(stats: &RequestStats, body_str: &str) {
-    let (total, user_new, user_hist, assistant, system) = analyze_request_body(body_str);
+pub fn calculate_tokens(stats: &RequestStats, body: &MessagesRequest) {
+    let (total, user_new, user_hist, assistant, system) = analyze_request_body(body);
 
     stats.total_tokens.fetch_add(total, Ordering::Relaxed);
     stats.user_new_tokens.fetch_add(user_new, Ordering::Relaxed);
@@ -0,0 +1,414 @@
+//! thinking 签名的来源标记与跨上游清洗
+//!
+//! thinking / `redacted_thinking` 块的签名只有签发它的上游认可。负载均衡把同一会话
+//! 轮转到其他上游时，历史中的外来签名会导致 400 "invalid signature"。
+//!
+//! - 响应：签名改写为 `ccp1.{上游标记}.{原始签名}` 后交给客户端；没有签名的 thinking 块
+//!   （`OpenAI` / Ollama 等上游）同样补上带标记的空签名，以便识别来源
+//! - 请求：转发前还原本上游签发的签名，移除其他上游签发的 thinking 块；
+//!   没有标记的签名来源未知，原样保留
+//!
+//! 上游标记由 mode、endpoint 与 model 哈希得到，重启后保持不变。
+
+use bytes::Bytes;
+
+use crate::{
+    config::UpstreamConfig,
+    gateway::{
+        anthropic::{
+            Content, ContentBlock, Delta, MessagesRequest, MessagesResponse, Role, StreamEvent,
+        },
+        hash::stable_hash_hex,
+        pipeline::Transform,
+        sse::{SseDecoder, SseEvent, StreamConverter, encode_stream_event},
+    },
+};
+
+/// 带来源标记的签名前缀
+const TAG_PREFIX: &str = "ccp1.";
+
+/// 移除 thinking 块后 assistant 消息为空时使用的占位文本
+const OMITTED_TEXT: &str = "[thinking omitted]";
+
+/// 上游标记
+#[must_use]
+pub fn upstream_tag(upstream: &UpstreamConfig) -> String {
+    let mode = format!("{:?}", upstream.mode);
+    stable_hash_hex(&[
+        mode.as_bytes(),
+        upstream.endpoint.as_bytes(),
+        upstream.model.as_bytes(),
+    ])
+}
+
+fn tag_signature(tag: &str, signature: &str) -> String {
+    format!("{TAG_PREFIX}{tag}.{signature}")
+}
+
+/// 签名的来源
+enum Origin<'a> {
+    /// 本上游签发，附带原始签名
+    Own(&'a str),
+    /// 其他上游签发
+    Foreign,
+    /// 没有来源标记
+    Unknown,
+}
+
+fn origin<'a>(signature: &'a str, tag: &str) -> Origin<'a> {
+    let Some((signature_tag, original)) = signature
+        .strip_prefix(TAG_PREFIX)
+        .and_then(|rest| rest.split_once('.'))
+    else {
+        return Origin::Unknown;
+    };
+    if signature_tag == tag {
+        Origin::Own(original)
+    } else {
+        Origin::Foreign
+    }
+}
+
+/// 签名所在的字段：thinking 为 `signature`（缺失时补上空签名），`redacted_thinking` 为 `data`
+fn signature_mut(block: &mut ContentBlock) -> Option<&mut String> {
+    match block {
+        ContentBlock::Thinking(thinking) => Some(thinking.signature.get_or_insert_default()),
+        ContentBlock::RedactedThinking(redacted) => Some(&mut redacted.data),
+        _ => None,
+    }
+}
+
+/// 为内容块中的 thinking 块加上来源标记，返回是否修改
+fn tag_blocks(content: &mut [ContentBlock], tag: &str) -> bool {
+    let mut changed = false;
+    for signature in content.iter_mut().filter_map(signature_mut) {
+        if signature.starts_with(TAG_PREFIX) {
+            continue;
+        }
+        *signature = tag_signature(tag, signature);
+        changed = true;
+    }
+    changed
+}
+
+/// 为完整的 Anthropic 响应体中的 thinking 签名加上来源标记
+#[must_use]
+pub fn tag_response_body(body: Bytes, tag: &str) -> Bytes {
+    let Ok(mut response) = serde_json::from_slice::<MessagesResponse>(&body) else {
+        return body;
+    };
+    if !tag_blocks(&mut response.content, tag) {
+        return body;
+    }
+    serde_json::to_vec(&response).map_or(body, Bytes::from)
+}
+
+/// 转发前清洗历史中的 thinking 块：还原本上游的签名，移除其他上游签发的块
+///
+/// 最后一条 assistant 消息的 thinking 被移除时，同时关闭本次请求的 thinking，
+/// 否则 Anthropic 会因该消息不以 thinking 块开头而拒绝请求。
+pub struct HistorySanitizer<'a>(pub &'a str);
+
+impl Transform for HistorySanitizer<'_> {
+    fn name(&self) -> &'static str {
+        "signature_sanitizer"
+    }
+
+    fn apply(&self, body: &mut MessagesRequest) -> bool {
+        sanitize_history(body, self.0)
+    }
+}
+
+fn sanitize_history(request: &mut MessagesRequest, tag: &str) -> bool {
+    let messages = &mut request.messages;
+    let last_assistant = messages
+        .iter()
+        .rposition(|message| message.role == Role::Assistant);
+    let mut changed = false;
+    let mut dropped_from_last = false;
+    for (index, message) in messages.iter_mut().enumerate() {
+        if message.role != Role::Assistant {
+            continue;
+        }
+        let Content::Blocks(content) = &mut message.content else {
+            continue;
+        };
+        let before = content.len();
+        content.retain_mut(|block| retain_block(block, tag, &mut changed));
+        if content.len() != before {
+            changed = true;
+            dropped_from_last |= Some(index) == last_assistant;
+            if content.is_empty() {
+                content.push(ContentBlock::text(OMITTED_TEXT));
+            }
+        }
+    }
+    if !changed {
+        return false;
+    }
+    if dropped_from_last {
+        request.thinking = None;
+    }
+    tracing::debug!("🧹 已清洗历史中的 thinking 签名");
+    true
+}
+
+/// 还原本上游的签名；其他上游签发的块返回 false 以移除
+fn retain_block(block: &mut ContentBlock, tag: &str, changed: &mut bool) -> bool {
+    let signature = match block {
+        ContentBlock::Thinking(thinking) => match thinking.signature.as_mut() {
+            Some(signature) => signature,
+            None => return true,
+        },
+        ContentBlock::RedactedThinking(redacted) => &mut redacted.data,
+        _ => return true,
+    };
+    match origin(signature, tag) {
+        Origin::Own(original) => {
+            *signature = original.to_string();
+            *changed = true;
+            true
+        }
+        Origin::Foreign => false,
+        Origin::Unknown => true,
+    }
+}
+
+/// 为 Anthropic SSE 流中的 thinking 签名加上来源标记
+///
+/// 一个 thinking 块的 `signature_delta` 先缓存，在块结束前合并为一个带标记的 `signature_delta`；
+/// 上游没有发送签名时同样补上带标记的空签名。
+pub struct SignatureTagger {
+    decoder: SseDecoder,
+    tag: String,
+    /// 当前 thinking 块的索引与累积的签名
+    thinking: Option<(usize, String)>,
+}
+
+impl SignatureTagger {
+    #[must_use]
+    pub fn new(tag: String) -> Self {
+        Self {
+            decoder: SseDecoder::default(),
+            tag,
+            thinking: None,
+        }
+    }
+
+    fn handle_event(&mut self, event: &SseEvent, out: &mut Vec<Bytes>) {
+        // 只有 content block 事件可能涉及签名
+        if !event.data.contains("content_block") {
+            out.push(encode_raw(event));
+            return;
+        }
+        let Some(mut data) = event.parse::<StreamEvent>() else {
+            out.push(encode_raw(event));
+            return;
+        };
+        match &mut data {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                match content_block {
+                    ContentBlock::Thinking(thinking) => {
+                        // 签名统一在块结束前下发
+                        let signature = thinking
+                            .signature
+                            .replace(String::new())
+                            .unwrap_or_default();
+                        self.thinking = Some((*index, signature));
+                    }
+                    ContentBlock::RedactedThinking(_) => {
+                        tag_blocks(std::slice::from_mut(content_block), &self.tag);
+                    }
+                    _ => {
+                        out.push(encode_raw(event));
+                        return;
+                    }
+                }
+                out.push(encode_stream_event(&data));
+            }
+            StreamEvent::ContentBlockDelta {
+                index,
+                delta: Delta::Signature { signature: delta },
+            } => {
+                if let Some((thinking_index, signature)) = self.thinking.as_mut()
+                    && thinking_index == index
+                {
+                    signature.push_str(delta);
+                } else {
+                    out.push(encode_raw(event));
+                }
+            }
+            StreamEvent::ContentBlockStop { index } => {
+                if let Some((thinking_index, signature)) = self
+                    .thinking
+                    .take_if(|(thinking_index, _)| thinking_index == index)
+                {
+                    out.push(encode_stream_event(&StreamEvent::ContentBlockDelta {
+                        index: thinking_index,
+                        delta: Delta::Signature {
+                            signature: tag_signature(&self.tag, &signature),
+                        },
+                    }));
+                }
+                out.push(encode_raw(event));
+            }
+            _ => out.push(encode_raw(event)),
+        }
+    }
+}
+
+/// 原样重新编码事件
+fn encode_raw(event: &SseEvent) -> Bytes {
+    let data = event.data.replace('\n', "\ndata: ");
+    let frame = event.event.as_ref().map_or_else(
+        || format!("data: {data}\n\n"),
+        |name| format!("event: {name}\ndata: {data}\n\n"),
+    );
+    Bytes::from(frame)
+}
+
+impl StreamConverter for SignatureTagger {
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        for event in self.decoder.feed(chunk) {
+            self.handle_event(&event, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        if let Some(event) = self.decoder.finish() {
+            self.handle_event(&event, &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::gateway::sse::encode_event;
+
+    fn sse(events: &[Value]) -> String {
+        events
+            .iter()
+            .map(|event| {
+                String::from_utf8(encode_event(event["type"].as_str().unwrap(), event).to_vec())
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// 测试流中的签名合并为一个带标记的 `signature_delta`，缺少签名时补上空签名
+    #[test]
+    fn test_tag_stream_signatures() {
+        let input = sse(&[
+            json!({"type": "message_start", "message": {"id": "msg_1"}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": "", "signature": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Hmm"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "abc"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "def"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "redacted_thinking", "data": "xyz"}}),
+            json!({"type": "content_block_stop", "index": 2}),
+            json!({"type": "message_stop"}),
+        ]);
+        let mut tagger = SignatureTagger::new("t1".to_string());
+        let mut out = input
+            .as_bytes()
+            .chunks(13)
+            .flat_map(|chunk| tagger.convert(chunk))
+            .collect::<Vec<_>>();
+        out.extend(tagger.finish());
+        let mut decoder = SseDecoder::default();
+        let events = out
+            .iter()
+            .flat_map(|bytes| decoder.feed(bytes))
+            .filter_map(|event| event.json())
+            .collect::<Vec<_>>();
+        let signatures = events
+            .iter()
+            .filter_map(|event| event.pointer("/delta/signature").and_then(Value::as_str))
+            .collect::<Vec<_>>();
+        assert_eq!(signatures, ["ccp1.t1.abcdef", "ccp1.t1."]);
+        assert_eq!(events[1]["content_block"]["signature"], "");
+        assert_eq!(events[3]["type"], "content_block_delta");
+        assert_eq!(events[4]["type"], "content_block_stop");
+        assert_eq!(events[8]["content_block"]["data"], "ccp1.t1.xyz");
+        assert_eq!(events.last().unwrap()["type"], "message_stop");
+    }
+
+    /// 测试完整响应体中的签名加上来源标记
+    #[test]
+    fn test_tag_response_body() {
+        let body = json!({
+            "content": [
+                {"type": "thinking", "thinking": "Hmm", "signature": "abc"},
+                {"type": "text", "text": "Hi"}
+            ]
+        });
+        let tagged: Value =
+            serde_json::from_slice(&tag_response_body(Bytes::from(body.to_string()), "t1"))
+                .unwrap();
+        assert_eq!(tagged["content"][0]["signature"], "ccp1.t1.abc");
+        assert!(tagged["content"][1].get("signature").is_none());
+    }
+
+    /// 测试还原本上游签名、移除外来签名，最后一条 assistant 消息被清洗时关闭 thinking
+    #[test]
+    fn test_sanitize_history() {
+        let body = json!({
+            "thinking": {"type": "enabled", "budget_tokens": 2048},
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "A", "signature": "ccp1.t1.sigA"},
+                    {"type": "text", "text": "one"}
+                ]},
+                {"role": "user", "content": "again"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "B", "signature": "ccp1.t2.sigB"},
+                    {"type": "thinking", "thinking": "C", "signature": "legacy"}
+                ]},
+                {"role": "user", "content": "more"},
+                {"role": "assistant", "content": [
+                    {"type": "redacted_thinking", "data": "ccp1.t2.xyz"},
+                    {"type": "tool_use", "id": "t", "name": "Read", "input": {}}
+                ]}
+            ]
+        });
+        let sanitize = |tag: &str| {
+            let mut request = serde_json::from_value(body.clone()).unwrap();
+            sanitize_history(&mut request, tag).then(|| serde_json::to_value(&request).unwrap())
+        };
+        let sanitized = sanitize("t1").unwrap();
+        let messages = sanitized["messages"].as_array().unwrap();
+        assert_eq!(messages[1]["content"][0]["signature"], "sigA");
+        assert_eq!(messages[3]["content"].as_array().unwrap().len(), 1);
+        assert_eq!(messages[3]["content"][0]["signature"], "legacy");
+        assert_eq!(messages[5]["content"][0]["type"], "tool_use");
+        assert!(sanitized.get("thinking").is_none());
+
+        // 回到 t2 时保留其签名，t1 的块被移除
+        let sanitized = sanitize("t2").unwrap();
+        let messages = sanitized["messages"].as_array().unwrap();
+        assert_eq!(messages[1]["content"][0]["type"], "text");
+        assert_eq!(messages[5]["content"][0]["data"], "xyz");
+        assert_eq!(sanitized["thinking"]["budget_tokens"], 2048);
+
+        // 没有带标记的签名时不修改
+        let mut plain =
+            serde_json::from_value(json!({"messages": [{"role": "user", "content": "hi"}]}))
+                .unwrap();
+        assert!(!sanitize_history(&mut plain, "t1"));
+    }
+}
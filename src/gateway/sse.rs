@@ -0,0 +1,412 @@
+//! 流式响应转换基础设施
+//!
+//! 上游的流式响应按任意字节边界分片到达，格式转换需要：
+//! 1. 把字节流切分为完整的事件（SSE 以空行分隔）
+//! 2. 逐个事件交给有状态的转换器，产出新的事件
+//! 3. 流结束时让转换器补齐收尾事件（如 `message_stop`）
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, stream};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+
+use crate::gateway::anthropic::{
+    ApiError, ContentBlock, Delta, DeltaUsage, MessageDelta, MessagesResponse, StreamEvent, Usage,
+};
+
+/// 一个完整的 SSE 事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// `event:` 字段，未提供时为 None
+    pub event: Option<String>,
+    /// 多行 `data:` 字段以换行拼接后的内容
+    pub data: String,
+}
+
+impl SseEvent {
+    /// 将 data 解析为 JSON，`[DONE]` 等非 JSON 内容返回 None
+    #[must_use]
+    pub fn json(&self) -> Option<Value> {
+        self.parse()
+    }
+
+    /// 将 data 解析为指定类型（如 [`StreamEvent`]），解析失败返回 None
+    #[must_use]
+    pub fn parse<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_str(&self.data).ok()
+    }
+}
+
+/// SSE 事件解码器，缓存不完整的事件直到分隔空行到达
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    /// 输入一段字节，返回其中已完整的事件
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some((end, sep_len)) = find_event_boundary(&self.buffer) {
+            let raw = self.buffer.drain(..end + sep_len).collect::<Vec<_>>();
+            if let Some(event) = parse_event(&raw[..end]) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// 流结束时取出缓冲区中剩余（没有以空行结尾）的事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let raw = std::mem::take(&mut self.buffer);
+        parse_event(&raw)
+    }
+}
+
+/// 查找第一个事件分隔（`\n\n` 或 `\r\n\r\n`），返回 (事件结束位置, 分隔长度)
+fn find_event_boundary(buffer: &[u8]) -> Option<(usize, usize)> {
+    let lf = buffer.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+    let crlf = buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| (i, 4));
+    match (lf, crlf) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn parse_event(raw: &[u8]) -> Option<SseEvent> {
+    let text = String::from_utf8_lossy(raw);
+    let mut event = None;
+    let mut data_lines = Vec::new();
+    for line in text.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            event = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data_lines.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    if event.is_none() && data_lines.is_empty() {
+        return None;
+    }
+    Some(SseEvent {
+        event,
+        data: data_lines.join("\n"),
+    })
+}
+
+/// 编码一个带 `event:` 名称的 SSE 事件
+#[must_use]
+pub fn encode_event(event: &str, data: &impl Serialize) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("event: {event}\ndata: {data}\n\n"))
+}
+
+/// 编码一个 Anthropic 流式事件，`event:` 名称与事件类型相同
+#[must_use]
+pub fn encode_stream_event(event: &StreamEvent) -> Bytes {
+    encode_event(event.name(), event)
+}
+
+/// 编码一个只有 `data:` 的 SSE 事件（`OpenAI` 风格）
+#[must_use]
+pub fn encode_data(data: &str) -> Bytes {
+    Bytes::from(format!("data: {data}\n\n"))
+}
+
+/// 有状态的流式格式转换器
+pub trait StreamConverter: Send + 'static {
+    /// 处理上游的一段字节，返回要发给客户端的字节
+    fn convert(&mut self, chunk: &[u8]) -> Vec<Bytes>;
+    /// 上游流结束，返回收尾字节
+    fn finish(&mut self) -> Vec<Bytes>;
+}
+
+/// 用转换器包装字节流
+pub fn convert_stream<S, C>(upstream: S, mut converter: C) -> impl Stream<Item = Bytes> + Send
+where
+    S: Stream<Item = Bytes> + Send + 'static,
+    C: StreamConverter,
+{
+    upstream
+        .map(Some)
+        .chain(stream::iter([None]))
+        .flat_map(move |chunk| {
+            let out = match chunk {
+                Some(chunk) => converter.convert(&chunk),
+                None => converter.finish(),
+            };
+            stream::iter(out)
+        })
+}
+
+/// 当前打开的 content block 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Text,
+    Thinking,
+    ToolUse,
+}
+
+/// Anthropic Messages 流式事件生成器
+///
+/// 负责 `message_start` → `content_block_*` → `message_delta` → `message_stop`
+/// 的事件顺序与 block 索引，各上游格式的流式转换器只需描述"产生了什么内容"。
+#[derive(Debug)]
+pub struct AnthropicSseWriter {
+    id: String,
+    model: String,
+    started: bool,
+    finished: bool,
+    next_index: usize,
+    open: Option<OpenBlock>,
+    out: Vec<Bytes>,
+}
+
+impl AnthropicSseWriter {
+    #[must_use]
+    pub fn new(id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            model: model.into(),
+            started: false,
+            finished: false,
+            next_index: 0,
+            open: None,
+            out: Vec::new(),
+        }
+    }
+
+    /// 是否已输出 `message_stop`
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// 在 `message_start` 之前更新消息 id 与模型名
+    pub fn set_message_meta(&mut self, id: Option<&str>, model: Option<&str>) {
+        if self.started {
+            return;
+        }
+        if let Some(id) = id.filter(|id| !id.is_empty()) {
+            self.id = id.to_string();
+        }
+        if let Some(model) = model.filter(|model| !model.is_empty()) {
+            self.model = model.to_string();
+        }
+    }
+
+    /// 输出 `message_start`（只会输出一次）
+    pub fn start(&mut self, usage: Option<Usage>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let message = MessagesResponse {
+            usage: usage.unwrap_or_default(),
+            ..MessagesResponse::new(self.id.as_str(), self.model.as_str())
+        };
+        self.emit(&StreamEvent::MessageStart { message });
+    }
+
+    fn emit(&mut self, event: &StreamEvent) {
+        self.out.push(encode_stream_event(event));
+    }
+
+    fn open_block(&mut self, kind: OpenBlock, content_block: ContentBlock) {
+        self.start(None);
+        self.close_block();
+        self.emit(&StreamEvent::ContentBlockStart {
+            index: self.next_index,
+            content_block,
+        });
+        self.open = Some(kind);
+    }
+
+    fn delta(&mut self, delta: Delta) {
+        self.emit(&StreamEvent::ContentBlockDelta {
+            index: self.next_index,
+            delta,
+        });
+    }
+
+    /// 关闭当前打开的 block
+    pub fn close_block(&mut self) {
+        if self.open.take().is_some() {
+            self.emit(&StreamEvent::ContentBlockStop {
+                index: self.next_index,
+            });
+            self.next_index += 1;
+        }
+    }
+
+    /// 追加文本，必要时打开新的 text block
+    pub fn text_delta(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.open != Some(OpenBlock::Text) {
+            self.open_block(OpenBlock::Text, ContentBlock::text(""));
+        }
+        self.delta(Delta::Text {
+            text: text.to_string(),
+        });
+    }
+
+    /// 追加推理文本，必要时打开新的 thinking block
+    pub fn thinking_delta(&mut self, thinking: &str) {
+        if thinking.is_empty() {
+            return;
+        }
+        if self.open != Some(OpenBlock::Thinking) {
+            self.open_block(OpenBlock::Thinking, ContentBlock::thinking("", None));
+        }
+        self.delta(Delta::Thinking {
+            thinking: thinking.to_string(),
+        });
+    }
+
+    /// 为 thinking block 追加签名，必要时打开空的 thinking block
+    pub fn signature_delta(&mut self, signature: &str) {
+        if signature.is_empty() {
+            return;
+        }
+        if self.open != Some(OpenBlock::Thinking) {
+            self.open_block(OpenBlock::Thinking, ContentBlock::thinking("", None));
+        }
+        self.delta(Delta::Signature {
+            signature: signature.to_string(),
+        });
+    }
+
+    /// 追加当前 `tool_use` block 的参数片段
+    pub fn input_json_delta(&mut self, partial_json: &str) {
+        if self.open == Some(OpenBlock::ToolUse) && !partial_json.is_empty() {
+            self.delta(Delta::InputJson {
+                partial_json: partial_json.to_string(),
+            });
+        }
+    }
+
+    /// 一次性输出一个完整的 block
+    ///
+    /// `tool_use` / `server_tool_use` 的 input 按协议通过 `input_json_delta` 下发，
+    /// 其余 block 直接放在 `content_block_start` 中。
+    pub fn full_block(&mut self, block: ContentBlock) {
+        match block {
+            ContentBlock::ToolUse(mut tool_use) => {
+                let input = std::mem::replace(&mut tool_use.input, json!({}));
+                self.open_block(OpenBlock::ToolUse, ContentBlock::ToolUse(tool_use));
+                self.input_json_delta(&input.to_string());
+            }
+            ContentBlock::Other(mut other) if other.kind == "server_tool_use" => {
+                let input = other
+                    .extra
+                    .insert("input".to_string(), json!({}))
+                    .unwrap_or_else(|| json!({}));
+                self.open_block(OpenBlock::ToolUse, ContentBlock::Other(other));
+                self.input_json_delta(&input.to_string());
+            }
+            ContentBlock::Text(text) => {
+                self.close_block();
+                self.text_delta(&text.text);
+            }
+            block => self.open_block(OpenBlock::Text, block),
+        }
+        self.close_block();
+    }
+
+    /// 输出 `message_delta` 与 `message_stop`（只会输出一次）
+    pub fn finish(&mut self, stop_reason: &str, usage: Option<DeltaUsage>) {
+        if self.finished {
+            return;
+        }
+        self.start(None);
+        self.close_block();
+        self.finished = true;
+        self.emit(&StreamEvent::MessageDelta {
+            delta: MessageDelta::stop(stop_reason),
+            usage: usage.unwrap_or_default(),
+        });
+        self.emit(&StreamEvent::MessageStop);
+    }
+
+    /// 输出 Anthropic `error` 事件并结束流
+    pub fn error(&mut self, error_type: &str, message: &str) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.emit(&StreamEvent::Error {
+            error: ApiError::new(error_type, message),
+        });
+    }
+
+    /// 取出已生成的事件
+    pub fn take(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_handles_split_events() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"event: a\ndata: {\"x\"").is_empty());
+        let events = decoder.feed(b":1}\n\nevent: b\r\ndata: 2\r\n\r\ndata: [DONE]");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.as_deref(), Some("a"));
+        assert_eq!(events[0].data, "{\"x\":1}");
+        assert_eq!(events[1].event.as_deref(), Some("b"));
+        assert_eq!(events[1].data, "2");
+
+        let last = decoder.finish();
+        assert_eq!(
+            last,
+            Some(SseEvent {
+                event: None,
+                data: "[DONE]".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_anthropic_writer_event_order() {
+        let mut writer = AnthropicSseWriter::new("msg_1", "model");
+        writer.thinking_delta("hmm");
+        writer.text_delta("hi");
+        writer.full_block(ContentBlock::tool_use("t1", "Read", json!({"a": 1})));
+        writer.finish("tool_use", None);
+        writer.finish("end_turn", None);
+
+        let events = writer
+            .take()
+            .iter()
+            .flat_map(|bytes| SseDecoder::default().feed(bytes))
+            .map(|event| event.event.unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+    }
+}
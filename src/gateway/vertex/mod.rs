@@ -0,0 +1,312 @@
+//! Google Vertex AI 上游（`mode = "vertex"`）
+//!
+//! - 请求：Anthropic Messages 请求体去掉 `model`，加上 `anthropic_version`；
+//!   发往 `projects/{project}/locations/{region}/publishers/anthropic/models/{model}:rawPredict`
+//!   （流式为 `:streamRawPredict`），使用服务账号签发的 OAuth 访问令牌鉴权
+//! - `count_tokens`：请求体保留 `model`、加上 `anthropic_version`，发往 `models/count-tokens:rawPredict`，
+//!   响应已是 Anthropic 格式
+//! - 响应：成功响应已是 Anthropic 格式（含 SSE 流）；
+//!   Google 风格的错误响应（`{ "error": { "code", "message", "status" } }`）转换为 Anthropic 错误格式
+
+pub mod auth;
+
+use std::{
+    env, mem,
+    sync::{Arc, LazyLock},
+};
+
+use bytes::Bytes;
+use http::{HeaderMap, Method, StatusCode};
+use http_body_util::Full;
+use hyper::Request as HyperRequest;
+use serde_json::{Value, json};
+
+use self::auth::TokenCache;
+use crate::{
+    config::{UpstreamConfig, VertexConfig},
+    gateway::{
+        HttpClient,
+        anthropic::{MessagesRequest, error_type_for_status},
+    },
+};
+
+/// Vertex AI 要求的 Anthropic API 版本
+const VERTEX_ANTHROPIC_VERSION: &str = "vertex-2023-10-16";
+/// `count_tokens` 请求使用的模型路径段（实际模型在请求体的 `model` 中）
+const COUNT_TOKENS_MODEL: &str = "count-tokens";
+/// 未配置区域时使用全局端点
+const DEFAULT_REGION: &str = "global";
+
+/// 进程内共享的访问令牌缓存
+static TOKEN_CACHE: LazyLock<TokenCache> = LazyLock::new(TokenCache::default);
+
+/// Anthropic 请求 → Vertex AI `rawPredict` 请求
+///
+/// 返回 (请求体, 模型 id, 是否流式)；`stream` 保留在请求体中。
+pub fn anthropic_request_to_vertex(body: &[u8]) -> Result<(Bytes, String, bool), String> {
+    let mut request: MessagesRequest =
+        serde_json::from_slice(body).map_err(|e| format!("invalid request body: {e}"))?;
+    let model = mem::take(&mut request.model);
+    if model.is_empty() {
+        return Err("request has no model".to_string());
+    }
+    let stream = request.is_stream();
+    request.extra.insert(
+        "anthropic_version".to_string(),
+        Value::String(VERTEX_ANTHROPIC_VERSION.to_string()),
+    );
+    let body = serde_json::to_vec(&request).map_err(|e| format!("serialize failed: {e}"))?;
+    Ok((Bytes::from(body), model, stream))
+}
+
+/// Anthropic `count_tokens` 请求 → Vertex AI `count-tokens:rawPredict` 请求
+///
+/// 与 `rawPredict` 不同，模型 id 保留在请求体的 `model` 中。
+pub fn anthropic_count_tokens_to_vertex(body: &[u8]) -> Result<Bytes, String> {
+    let mut request: MessagesRequest =
+        serde_json::from_slice(body).map_err(|e| format!("invalid request body: {e}"))?;
+    if request.model.is_empty() {
+        return Err("request has no model".to_string());
+    }
+    request.extra.insert(
+        "anthropic_version".to_string(),
+        Value::String(VERTEX_ANTHROPIC_VERSION.to_string()),
+    );
+    serde_json::to_vec(&request)
+        .map(Bytes::from)
+        .map_err(|e| format!("serialize failed: {e}"))
+}
+
+/// 构建带访问令牌的 Vertex AI 请求；`count_tokens` 为 true 时发往 `count-tokens:rawPredict`
+pub async fn build_request(
+    upstream: &UpstreamConfig,
+    body: &[u8],
+    client_headers: &HeaderMap,
+    client: &Arc<HttpClient>,
+    count_tokens: bool,
+) -> Result<HyperRequest<Full<Bytes>>, String> {
+    let (body, model, stream) = if count_tokens {
+        let body = anthropic_count_tokens_to_vertex(body)?;
+        (body, COUNT_TOKENS_MODEL.to_string(), false)
+    } else {
+        anthropic_request_to_vertex(body)?
+    };
+    let config = upstream.vertex.clone().unwrap_or_default();
+    let credentials_file = config
+        .credentials_file
+        .clone()
+        .filter(|path| !path.is_empty())
+        .or_else(|| {
+            env::var("GOOGLE_APPLICATION_CREDENTIALS")
+                .ok()
+                .filter(|path| !path.is_empty())
+        })
+        .ok_or(
+            "vertex credentials not configured (vertex.credentials_file or GOOGLE_APPLICATION_CREDENTIALS)",
+        )?;
+    let token = TOKEN_CACHE
+        .access_token(client, &credentials_file, config.token_url.as_deref())
+        .await?;
+    let project_id = config
+        .project_id
+        .clone()
+        .filter(|project| !project.is_empty())
+        .or(token.project_id)
+        .ok_or("vertex project not configured (vertex.project_id or key file project_id)")?;
+
+    let url = vertex_url(&upstream.endpoint, &config, &project_id, &model, stream);
+    let host = url_host(&url).to_string();
+    tracing::info!("Proxying to: {} (vertex)", url);
+
+    let mut builder = HyperRequest::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .header("host", host)
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token.token));
+    // Vertex AI 通过请求头接收 beta 特性
+    if let Some(beta) = client_headers.get("anthropic-beta") {
+        builder = builder.header("anthropic-beta", beta);
+    }
+    builder
+        .body(Full::new(body))
+        .map_err(|e| format!("failed to build request: {e}"))
+}
+
+/// 拼接 `rawPredict` / `streamRawPredict` 地址
+///
+/// `endpoint` 留空时按区域使用默认地址（`global` 为 `aiplatform.googleapis.com`，
+/// 其余为 `{region}-aiplatform.googleapis.com`），否则使用配置值（PSC 端点、本地替身服务等）。
+fn vertex_url(
+    endpoint: &str,
+    config: &VertexConfig,
+    project_id: &str,
+    model: &str,
+    stream: bool,
+) -> String {
+    let region = config
+        .region
+        .as_deref()
+        .filter(|region| !region.is_empty())
+        .unwrap_or(DEFAULT_REGION);
+    let base = if !endpoint.is_empty() {
+        endpoint.trim_end_matches('/').to_string()
+    } else if region == DEFAULT_REGION {
+        "https://aiplatform.googleapis.com".to_string()
+    } else {
+        format!("https://{region}-aiplatform.googleapis.com")
+    };
+    let action = if stream {
+        "streamRawPredict"
+    } else {
+        "rawPredict"
+    };
+    format!(
+        "{base}/v1/projects/{project_id}/locations/{region}/publishers/anthropic/models/{model}:{action}"
+    )
+}
+
+fn url_host(url: &str) -> &str {
+    let without_scheme = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    without_scheme.split('/').next().unwrap_or(without_scheme)
+}
+
+/// Google 错误状态 → Anthropic 错误类型
+fn anthropic_error_type(status: &str) -> Option<&'static str> {
+    Some(match status {
+        "INVALID_ARGUMENT" | "FAILED_PRECONDITION" | "OUT_OF_RANGE" => "invalid_request_error",
+        "UNAUTHENTICATED" => "authentication_error",
+        "PERMISSION_DENIED" => "permission_error",
+        "NOT_FOUND" => "not_found_error",
+        "RESOURCE_EXHAUSTED" => "rate_limit_error",
+        "UNAVAILABLE" => "overloaded_error",
+        "INTERNAL" | "UNKNOWN" | "DEADLINE_EXCEEDED" => "api_error",
+        _ => return None,
+    })
+}
+
+/// Vertex AI 错误响应 → Anthropic 错误格式
+///
+/// 已是 Anthropic 格式的错误（由模型侧返回）原样保留；
+/// Google 风格的错误可能包在数组中（`[{ "error": .. }]`）。
+#[must_use]
+pub fn error_body_to_anthropic(status: StatusCode, body: &[u8]) -> Bytes {
+    let value = serde_json::from_slice::<Value>(body).unwrap_or(Value::Null);
+    if value.get("type").and_then(Value::as_str) == Some("error") {
+        return Bytes::copy_from_slice(body);
+    }
+    let error = value
+        .get("error")
+        .or_else(|| value.get(0).and_then(|item| item.get("error")));
+    let message = error
+        .and_then(|error| error.get("message"))
+        .and_then(Value::as_str)
+        .map_or_else(
+            || String::from_utf8_lossy(body).into_owned(),
+            str::to_string,
+        );
+    let error_type = error
+        .and_then(|error| error.get("status"))
+        .and_then(Value::as_str)
+        .and_then(anthropic_error_type)
+        .unwrap_or_else(|| error_type_for_status(status));
+    Bytes::from(
+        json!({
+            "type": "error",
+            "error": { "type": error_type, "message": message }
+        })
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    /// 测试 Anthropic 请求体转换为 Vertex AI 请求体
+    #[test]
+    fn test_anthropic_request_to_vertex() {
+        let request = json!({
+            "model": "claude-sonnet-4-5@20250929",
+            "stream": true,
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let (body, model, stream) =
+            anthropic_request_to_vertex(request.to_string().as_bytes()).unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(model, "claude-sonnet-4-5@20250929");
+        assert!(stream);
+        assert!(body.get("model").is_none());
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["anthropic_version"], "vertex-2023-10-16");
+    }
+
+    /// 测试 `count_tokens` 请求体保留 model 并发往 `count-tokens:rawPredict`
+    #[test]
+    fn test_count_tokens_to_vertex() {
+        let request = json!({
+            "model": "claude-sonnet-4-5@20250929",
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let body = anthropic_count_tokens_to_vertex(request.to_string().as_bytes()).unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["model"], "claude-sonnet-4-5@20250929");
+        assert_eq!(body["anthropic_version"], "vertex-2023-10-16");
+        assert!(anthropic_count_tokens_to_vertex(br#"{"messages": []}"#).is_err());
+
+        let config = VertexConfig {
+            region: Some("us-east5".to_string()),
+            ..VertexConfig::default()
+        };
+        assert_eq!(
+            vertex_url("", &config, "proj", COUNT_TOKENS_MODEL, false),
+            "https://us-east5-aiplatform.googleapis.com/v1/projects/proj/locations/us-east5/publishers/anthropic/models/count-tokens:rawPredict"
+        );
+    }
+
+    /// 测试按区域与 endpoint 拼接 rawPredict 地址
+    #[test]
+    fn test_vertex_url() {
+        let mut config = VertexConfig::default();
+        assert_eq!(
+            vertex_url("", &config, "proj", "claude-sonnet-4-5@20250929", false),
+            "https://aiplatform.googleapis.com/v1/projects/proj/locations/global/publishers/anthropic/models/claude-sonnet-4-5@20250929:rawPredict"
+        );
+        config.region = Some("us-east5".to_string());
+        assert_eq!(
+            vertex_url("", &config, "proj", "m", true),
+            "https://us-east5-aiplatform.googleapis.com/v1/projects/proj/locations/us-east5/publishers/anthropic/models/m:streamRawPredict"
+        );
+        assert_eq!(
+            vertex_url("http://127.0.0.1:8080/", &config, "proj", "m", true),
+            "http://127.0.0.1:8080/v1/projects/proj/locations/us-east5/publishers/anthropic/models/m:streamRawPredict"
+        );
+    }
+
+    /// 测试 Google 风格错误转换为 Anthropic 错误，Anthropic 错误原样保留
+    #[test]
+    fn test_error_body_to_anthropic() {
+        let google =
+            br#"[{"error":{"code":429,"message":"Quota exceeded","status":"RESOURCE_EXHAUSTED"}}]"#;
+        let converted: Value = serde_json::from_slice(&error_body_to_anthropic(
+            StatusCode::TOO_MANY_REQUESTS,
+            google,
+        ))
+        .unwrap();
+        assert_eq!(converted["error"]["type"], "rate_limit_error");
+        assert_eq!(converted["error"]["message"], "Quota exceeded");
+
+        let anthropic =
+            br#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(
+            &error_body_to_anthropic(StatusCode::from_u16(529).unwrap(), anthropic)[..],
+            anthropic
+        );
+    }
+}
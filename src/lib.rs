@@ -0,0 +1,35 @@
+//! 代理服务的实现
+//!
+//! `main.rs` 只负责初始化日志与路由，对外只导出启动服务需要的入口，内部模块不公开。
+//! 基准测试需要直接调用请求体处理管线，相关类型只在启用 `bench` feature 时经
+//! [`bench`] 导出，不属于稳定的公开 API。
+
+mod config;
+mod gateway;
+
+pub use config::AtomicConfig;
+pub use gateway::{
+    GatewayHandler,
+    handler::{claude_proxy, openai_chat_completions, openai_models, openai_responses},
+};
+
+/// 供 `benches/` 使用的内部类型（`cargo bench --features bench`）
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::{
+        config::{Capabilities, ToolResultPolicy},
+        gateway::{
+            RequestStats,
+            cache_planner::CachePlanner,
+            capabilities::CapabilityAdapter,
+            handler::{
+                CUSTOM_SYSTEM_PROMPT, ContentTagFilter, CustomSystemPrompt, ModelOverride,
+                ReadDedup, SystemPromptFilter, ToolDescriptionFilter, ToolResultTruncation,
+            },
+            pipeline::{RequestBody, Transform},
+            quirks::BodyQuirks,
+            signature::HistorySanitizer,
+        },
+    };
+}
@@ -1,11 +1,10 @@
-mod config;
-mod gateway;
-
 use std::{fmt, io::IsTerminal, sync::Arc};
 
+use cc_proxy::{
+    AtomicConfig, GatewayHandler, claude_proxy, openai_chat_completions, openai_models,
+    openai_responses,
+};
 use chrono::Local;
-use config::AtomicConfig;
-use gateway::{GatewayHandler, handler::claude_proxy};
 use salvo::{affix_state, prelude::*};
 use tracing::info;
 use tracing_subscriber::{
@@ -61,9 +60,17 @@ async fn main() -> anyhow::Result<()> {
         .hoop(
             affix_state::inject(atomic_config)
                 .inject(Arc::clone(gateway.stats()))
-                .inject(Arc::clone(gateway.client())),
+                .inject(Arc::clone(gateway.client()))
+                .inject(Arc::clone(gateway.models())),
         )
-        .push(Router::with_path("claude/{**rest}").goal(claude_proxy));
+        .push(Router::with_path("claude/{**rest}").goal(claude_proxy))
+        .push(Router::with_path("openai/v1/chat/completions").post(openai_chat_completions))
+        .push(Router::with_path("openai/v1/responses").post(openai_responses))
+        .push(
+            Router::with_path("openai/v1/models")
+                .get(openai_models)
+                .push(Router::with_path("{id}").get(openai_models)),
+        );
 
     // 启动服务器
     let acceptor = TcpListener::new("0.0.0.0:9066").bind().await;